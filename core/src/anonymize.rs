@@ -0,0 +1,177 @@
+//! Preparing a replay to be shared publicly.
+//!
+//! A replay records everything said in the game's chat, and which seat each
+//! player took: the host is always player 0, and the others are numbered in
+//! the order they arrived. `rbattle anonymize FILE` writes a copy of a replay
+//! with the chat left out and the seats shuffled, so neither gives away who
+//! played. Each player keeps their source, but takes the color and number of
+//! the seat they're given.
+//!
+//! Shuffling the seats changes every turn's checksum, so we play the game
+//! through twice, as recorded and as renumbered, to compute the new ones, and
+//! check as we go that the two play out alike.
+
+use errors::*;
+use replay::Replay;
+use scheduler::CollectedActions;
+use state::{Action, Occupied, Player, State};
+
+use rand::Rng;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Return where to write the anonymized copy of the replay at `path`:
+/// `game.replay` becomes `game.anonymous.replay`.
+pub fn output_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}.anonymous.{}", stem,
+                                                       extension.to_string_lossy())),
+        None => path.with_file_name(format!("{}.anonymous", stem)),
+    }
+}
+
+/// Return a copy of `replay` with the chat left out, and the seats shuffled
+/// using `rng`.
+pub fn anonymize<R: Rng>(replay: &Replay, rng: &mut R) -> Result<Replay> {
+    let seats = State::from_serializable(replay.state.clone()).map.sources.len();
+    let mut renumbering: Vec<usize> = (0..seats).collect();
+    rng.shuffle(&mut renumbering);
+    renumber(replay, &renumbering)
+}
+
+/// Return a copy of `replay` with the chat left out, and each player `p`
+/// renumbered as `renumbering[p]`.
+fn renumber(replay: &Replay, renumbering: &[usize]) -> Result<Replay> {
+    let mut recorded = State::from_serializable(replay.state.clone());
+    let mut renumbered = renumber_state(&recorded, renumbering);
+    let start = renumbered.serializable();
+
+    let mut turns = Vec::with_capacity(replay.turns.len());
+    for turn in &replay.turns {
+        if !turn.try_apply_to(&mut recorded) {
+            bail!("turn {}: the replay's own checksum doesn't match", turn.turn);
+        }
+        let actions: Vec<Action> = turn.actions.iter()
+            .filter_map(|action| renumber_action(action, renumbering))
+            .collect();
+        for action in &actions {
+            renumbered.take_action(action);
+        }
+        renumbered.advance();
+        if renumber_state(&recorded, renumbering).checksum() != renumbered.checksum() {
+            bail!("turn {}: the game plays out differently with the seats shuffled",
+                  turn.turn);
+        }
+        turns.push(CollectedActions {
+            turn: turn.turn,
+            actions,
+            state_checksum: renumbered.checksum(),
+            turn_duration: turn.turn_duration,
+        });
+    }
+
+    Ok(Replay { state: start, turn_duration: replay.turn_duration, turns })
+}
+
+/// Return a copy of `state` with each player `p` renumbered as
+/// `renumbering[p]`. Sources move with their players; colors and patterns
+/// stay with the seats.
+fn renumber_state(state: &State, renumbering: &[usize]) -> State {
+    let mut state = state.clone();
+    let mut map = (*state.map).clone();
+    let mut joined = state.joined.clone();
+    for (player, &seat) in renumbering.iter().enumerate() {
+        map.sources[seat] = state.map.sources[player];
+        joined[seat] = state.joined[player];
+    }
+    state.map = Arc::new(map);
+    state.joined = joined;
+    renumber_nodes(&mut state.nodes, renumbering);
+    if let Some(ref mut start) = state.params.start {
+        renumber_nodes(start, renumbering);
+    }
+    state
+}
+
+/// Renumber the players holding `nodes`, as `renumber_state` does.
+fn renumber_nodes(nodes: &mut [Option<Occupied>], renumbering: &[usize]) {
+    for occupied in nodes.iter_mut().flatten() {
+        occupied.player = Player(renumbering[occupied.player.0]);
+    }
+}
+
+/// Return `action` with its player renumbered, or `None` if it's chat.
+fn renumber_action(action: &Action, renumbering: &[usize]) -> Option<Action> {
+    let seat = |player: Player| Player(renumbering[player.0]);
+    Some(match *action {
+        Action::ToggleOutflow { player, from, to } =>
+            Action::ToggleOutflow { player: seat(player), from, to },
+        Action::ClearOutflows { player, node } =>
+            Action::ClearOutflows { player: seat(player), node },
+        Action::OpenOutflows { player, node } =>
+            Action::OpenOutflows { player: seat(player), node },
+        Action::Join { player } => Action::Join { player: seat(player) },
+        Action::Surrender { player } => Action::Surrender { player: seat(player) },
+        Action::Restart => Action::Restart,
+        Action::Chat { .. } => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use storage::Format;
+
+    use rand::{SeedableRng, XorShiftRng};
+
+    use std::env;
+    use std::fs;
+
+    fn duel() -> Replay {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/replays/duel-rematch.replay");
+        Replay::load(&path).unwrap()
+    }
+
+    #[test]
+    fn anonymizes() {
+        let replay = duel();
+        assert!(replay.turns.iter().flat_map(|turn| &turn.actions)
+                .any(|action| matches!(*action, Action::Chat { .. })));
+
+        let anonymous = renumber(&replay, &[1, 0]).unwrap();
+        assert_eq!(anonymous.turns.len(), replay.turns.len());
+        assert!(anonymous.turns.iter().flat_map(|turn| &turn.actions)
+                .all(|action| !matches!(*action, Action::Chat { .. })));
+
+        // The anonymous replay plays out like the original, with the players'
+        // numbers swapped.
+        let mut original = State::from_serializable(replay.state.clone());
+        let mut state = State::from_serializable(anonymous.state.clone());
+        assert_eq!(state.map.sources, vec![original.map.sources[1], original.map.sources[0]]);
+        for (turn, anonymous_turn) in replay.turns.iter().zip(&anonymous.turns) {
+            turn.apply_to(&mut original);
+            anonymous_turn.apply_to(&mut state);
+        }
+        assert_eq!(state.node_counts(), vec![original.node_counts()[1],
+                                             original.node_counts()[0]]);
+
+        // Shuffling at random works too, and the result can be saved.
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        let anonymous = anonymize(&replay, &mut rng).unwrap();
+        let path = env::temp_dir().join(format!("rbattle-anonymous-{}.replay",
+                                                ::std::process::id()));
+        anonymous.save(&path, Format { binary: true, compressed: true }).unwrap();
+        assert_eq!(Replay::load(&path).unwrap().turns.len(), replay.turns.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn output_paths() {
+        assert_eq!(output_path(Path::new("games/duel.replay")),
+                   Path::new("games/duel.anonymous.replay"));
+        assert_eq!(output_path(Path::new("duel")), Path::new("duel.anonymous"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Computer-controlled players.
+//!
+//! A server can fill seats that no human has taken with bots, so that someone
+//! playing alone has an opponent. Bots exist only in the scheduler: each turn,
+//! it asks every bot for its actions and includes them in the turn alongside
+//! everyone else's. Other hosts see a bot's actions arrive just like any
+//! player's, so nothing about bots needs to be deterministic across hosts.
+//! Even so, each bot should draw its decisions from its own seeded random
+//! number generator, so that a game against bots can be reproduced.
+//!
+//! A bot is anything implementing the `Bot` trait. The host picks one for
+//! each seat by name from a `Registry`, which maps each name to a function
+//! making that kind of bot. The built-in bots, one for each `Difficulty`, are
+//! in the `heuristic` module; a new kind of bot can live in a module of its
+//! own, and needs only a line in `Registry::default`, or a call to
+//! `Registry::register` at run time, to be playable.
+
+use state::{Action, Player, State};
+
+pub use heuristic::{Difficulty, Heuristic};
+
+/// A computer-controlled player.
+pub trait Bot: Send {
+    /// Return the actions `player` should take on the turn following
+    /// `state`.
+    fn act(&mut self, state: &State, player: Player) -> Vec<Action>;
+}
+
+/// A function returning a bot to play for the given player. The number
+/// distinguishes games in a run of them, as in `Heuristic::seeded`. The
+/// scheduler may make bots from its timer thread, so factories must be safe
+/// to share.
+pub type Factory = Box<dyn Fn(Player, u64) -> Box<dyn Bot> + Send + Sync>;
+
+/// The name of the kind of bot we play when no other is asked for, and in
+/// place of any kind a saved game names that we don't know.
+pub const DEFAULT_KIND: &str = "medium";
+
+/// The kinds of bot a host can seat, by name.
+pub struct Registry {
+    kinds: Vec<(String, Factory)>,
+}
+
+impl Registry {
+    /// Make `factory` the way to make bots of the kind called `name`,
+    /// replacing whatever was called that before.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+        where F: Fn(Player, u64) -> Box<dyn Bot> + Send + Sync + 'static
+    {
+        self.kinds.retain(|(kind, _)| kind != name);
+        self.kinds.push((name.to_string(), Box::new(factory)));
+    }
+
+    /// Return the names of the kinds of bot registered, in the order they
+    /// were registered.
+    pub fn names(&self) -> Vec<&str> {
+        self.kinds.iter().map(|(kind, _)| kind.as_str()).collect()
+    }
+
+    /// Return true if there is a kind of bot called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.kinds.iter().any(|(kind, _)| kind == name)
+    }
+
+    /// Return a bot of the kind called `name` to play for `player` in the
+    /// given game of a run, or `None` if there is no such kind.
+    pub fn create(&self, name: &str, player: Player, game: u64) -> Option<Box<dyn Bot>> {
+        let (_, factory) = self.kinds.iter().find(|(kind, _)| kind == name)?;
+        Some(factory(player, game))
+    }
+}
+
+/// The built-in bots, named for their difficulties.
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry { kinds: vec![] };
+        for &difficulty in &Difficulty::ALL {
+            registry.register(&difficulty.to_string(), move |player, game| {
+                Box::new(Heuristic::seeded(player, difficulty, game))
+            });
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A bot that never does anything.
+    struct Idle;
+
+    impl Bot for Idle {
+        fn act(&mut self, _state: &State, _player: Player) -> Vec<Action> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.names(), vec!["easy", "medium", "hard"]);
+        assert!(registry.contains(DEFAULT_KIND));
+        assert!(registry.create("idle", Player(0), 0).is_none());
+
+        registry.register("idle", |_, _| Box::new(Idle));
+        assert!(registry.contains("idle"));
+        assert!(registry.create("idle", Player(0), 0).is_some());
+
+        // Registering a name again replaces it.
+        registry.register("easy", |_, _| Box::new(Idle));
+        assert_eq!(registry.names(), vec!["medium", "hard", "idle", "easy"]);
+    }
+}
@@ -0,0 +1,14 @@
+//! The FNV-1a hash.
+//!
+//! Wherever participants must agree on a hash of something they hold, such as
+//! a turn's checksum, a game's fingerprint, or a map's name, we use 64-bit
+//! FNV-1a. See `State::checksum` for why not `std::hash`.
+
+/// Return the 64-bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,268 @@
+//! Graphs of nodes, and searches over them.
+//!
+//! The board is a `Graph`, and much of what the game and its players need to
+//! know about it comes down to searching it breadth-first: the way from one
+//! node to another, how far every node is from some set of them, or which
+//! nodes a region connects. The functions here answer those questions for
+//! any `Graph`, so that bots, the flow macro, and checks on maps can share
+//! them. Those that talk about connected regions assume that every edge has
+//! a counterpart going the other way, as on a `SquareGrid`.
+
+use std::collections::VecDeque;
+
+/// The type of the index of a node in a `Grid`.
+pub type Node = usize;
+
+/// A directed graph of nodes and their neighbors.
+///
+/// Each node is identified by a `Node` index.
+pub trait Graph {
+    /// Return the number of nodes in this graph. The graph's nodes have indices
+    /// in the range 0..graph.nodes().
+    fn nodes(&self) -> Node;
+
+    /// Return the number of edges in this graph.
+    fn edges(&self) -> usize;
+
+    /// Return a vector of `node`'s neighbors.
+    fn neighbors(&self, node: Node) -> Vec<Node>;
+}
+
+/// Return the nodes along a shortest path in `graph` from `from` to `to`,
+/// including both ends, or `None` if `to` can't be reached.
+pub fn shortest_path<G: Graph + ?Sized>(graph: &G, from: Node, to: Node) -> Option<Vec<Node>> {
+    nearest_path(graph, &[from], |node| node == to)
+}
+
+/// Return the nodes along a shortest path in `graph` from any of `starts` to
+/// the nearest node for which `goal` returns true, including both ends, or
+/// `None` if there's no such node within reach.
+pub fn nearest_path<G, F>(graph: &G, starts: &[Node], goal: F) -> Option<Vec<Node>>
+    where G: Graph + ?Sized,
+          F: Fn(Node) -> bool
+{
+    // A breadth-first search, noting the node through which we first reached
+    // each one. Every start is on the queue to begin with, noted as its own
+    // predecessor.
+    let mut came_from = vec![None; graph.nodes()];
+    let mut queue = VecDeque::new();
+    for &start in starts {
+        came_from[start] = Some(start);
+        queue.push_back(start);
+    }
+    while let Some(node) = queue.pop_front() {
+        if goal(node) {
+            let mut path = vec![node];
+            let mut node = node;
+            while came_from[node] != Some(node) {
+                node = came_from[node].expect("node on path should have been reached");
+                path.push(node);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for neighbor in graph.neighbors(node) {
+            if came_from[neighbor].is_none() {
+                came_from[neighbor] = Some(node);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    None
+}
+
+/// Return how many steps each node of `graph` is from the nearest of
+/// `starts`, indexed by node, or `None` for nodes that can't be reached.
+pub fn distances<G: Graph + ?Sized>(graph: &G, starts: &[Node]) -> Vec<Option<usize>> {
+    let mut distances = vec![];
+    distances_into(graph, starts, &mut distances, &mut VecDeque::new());
+    distances
+}
+
+/// Like `distances`, but store the result in `distances`, and use `queue` for
+/// the search, reusing their storage. Callers that search often can keep both
+/// around to save allocating them every time.
+pub fn distances_into<G>(graph: &G, starts: &[Node],
+                         distances: &mut Vec<Option<usize>>, queue: &mut VecDeque<Node>)
+    where G: Graph + ?Sized
+{
+    distances.clear();
+    distances.resize(graph.nodes(), None);
+    queue.clear();
+    for &start in starts {
+        distances[start] = Some(0);
+        queue.push_back(start);
+    }
+    while let Some(node) = queue.pop_front() {
+        let next = distances[node].map(|distance| distance + 1);
+        for neighbor in graph.neighbors(node) {
+            if distances[neighbor].is_none() {
+                distances[neighbor] = next;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+}
+
+/// Return the nodes of `graph` reachable from `starts` by stepping only onto
+/// nodes for which `include` returns true, nearest first. The starts
+/// themselves are always included.
+pub fn flood_fill<G, F>(graph: &G, starts: &[Node], include: F) -> Vec<Node>
+    where G: Graph + ?Sized,
+          F: Fn(Node) -> bool
+{
+    let mut reached = vec![false; graph.nodes()];
+    let mut filled = vec![];
+    for &start in starts {
+        if !reached[start] {
+            reached[start] = true;
+            filled.push(start);
+        }
+    }
+
+    // `filled` doubles as the queue: everything after `next` has yet to have
+    // its neighbors looked at.
+    let mut next = 0;
+    while next < filled.len() {
+        for neighbor in graph.neighbors(filled[next]) {
+            if !reached[neighbor] && include(neighbor) {
+                reached[neighbor] = true;
+                filled.push(neighbor);
+            }
+        }
+        next += 1;
+    }
+    filled
+}
+
+/// Divide the nodes of `graph` for which `include` returns true into groups,
+/// each connected through such nodes alone, and return each node's group,
+/// indexed by node, or `None` for nodes left out. Groups are numbered from
+/// zero, in order of their lowest-numbered nodes.
+pub fn components<G, F>(graph: &G, include: F) -> Vec<Option<usize>>
+    where G: Graph + ?Sized,
+          F: Fn(Node) -> bool
+{
+    let mut groups = vec![None; graph.nodes()];
+    let mut count = 0;
+    for node in 0..graph.nodes() {
+        if groups[node].is_some() || !include(node) {
+            continue;
+        }
+        for member in flood_fill(graph, &[node], &include) {
+            groups[member] = Some(count);
+        }
+        count += 1;
+    }
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use square::SquareGrid;
+
+    use proptest::prelude::*;
+
+    #[test]
+    fn shortest_path() {
+        let grid = SquareGrid::new(3, 4);
+        assert_eq!(super::shortest_path(&grid, 5, 5), Some(vec![5]));
+
+        let path = super::shortest_path(&grid, 0, 11).unwrap();
+        assert_eq!(path.len(), 6);
+        assert_eq!((path[0], path[5]), (0, 11));
+        for step in path.windows(2) {
+            assert!(grid.neighbors(step[0]).contains(&step[1]));
+        }
+    }
+
+    #[test]
+    fn nearest_path() {
+        let grid = SquareGrid::new(3, 4);
+        assert_eq!(super::nearest_path(&grid, &[0, 11], |node| node == 10), Some(vec![11, 10]));
+        assert_eq!(super::nearest_path(&grid, &[4], |node| node == 4), Some(vec![4]));
+        assert_eq!(super::nearest_path(&grid, &[0], |_| false), None);
+
+        // The path starts from whichever start is nearest.
+        let path = super::nearest_path(&grid, &[1, 2], |node| node == 9).unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!((path[0], path[2]), (1, 9));
+    }
+
+    #[test]
+    fn flood_fill() {
+        // Nodes 0 through 3 are the bottom row; 4 through 7 the next.
+        let grid = SquareGrid::new(3, 4);
+        let walls = [1, 5, 9];
+        let filled = super::flood_fill(&grid, &[0], |node| !walls.contains(&node));
+        assert_eq!(filled, vec![0, 4, 8]);
+        assert_eq!(super::flood_fill(&grid, &[1], |_| false), vec![1]);
+
+        let groups = super::components(&grid, |node| !walls.contains(&node));
+        assert_eq!(groups[0], Some(0));
+        assert_eq!(groups[2], Some(1));
+        assert_eq!(groups[1], None);
+        assert!(groups.iter().flatten().all(|&group| group < 2));
+
+        let distances = super::distances(&grid, &[0, 11]);
+        assert_eq!(distances[0], Some(0));
+        assert_eq!(distances[3], Some(2));
+        assert_eq!(distances[5], Some(2));
+    }
+
+    /// A grid of up to 8 by 8 squares, a node on it, and which of its nodes
+    /// are open.
+    fn grid_node_and_mask() -> impl Strategy<Value = (SquareGrid, Node, Vec<bool>)> {
+        (1usize..8, 1usize..8).prop_flat_map(|(rows, cols)| {
+            (Just(SquareGrid::new(rows, cols)),
+             0..rows * cols,
+             prop::collection::vec(any::<bool>(), rows * cols))
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn distances_are_path_lengths((grid, start, _) in grid_node_and_mask()) {
+            let distances = super::distances(&grid, &[start]);
+            for node in 0..grid.nodes() {
+                // Every node on a grid is reachable, by a shortest path as
+                // long as its distance.
+                let distance = distances[node].unwrap();
+                let path = super::shortest_path(&grid, start, node).unwrap();
+                prop_assert_eq!(path.len(), distance + 1);
+
+                // Neighbors are at most a step apart.
+                for neighbor in grid.neighbors(node) {
+                    prop_assert!(distances[neighbor].unwrap() + 1 >= distance);
+                }
+            }
+        }
+
+        #[test]
+        fn fills_are_components((grid, start, open) in grid_node_and_mask()) {
+            let groups = super::components(&grid, |node| open[node]);
+            let mut filled = super::flood_fill(&grid, &[start], |node| open[node]);
+            filled.sort();
+
+            // Filling from an open node reaches just its group; from a closed
+            // one, it reaches its open neighbors' groups.
+            let expected: Vec<Node> = (0..grid.nodes())
+                .filter(|&node| {
+                    node == start || groups[node].is_some_and(|group| {
+                        groups[start] == Some(group) || grid.neighbors(start).into_iter()
+                            .any(|neighbor| groups[neighbor] == Some(group))
+                    })
+                })
+                .collect();
+            prop_assert_eq!(filled, expected);
+
+            // Groups are numbered in order of their lowest nodes.
+            let mut seen = 0;
+            for group in groups.iter().flatten() {
+                prop_assert!(*group <= seen);
+                seen = seen.max(group + 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! The built-in bots, which play by rules of thumb.
+//!
+//! Each makes a move every so often, reinforcing its nodes under pressure,
+//! expanding toward sources it doesn't hold, or failing those, stirring goop
+//! around its territory. How often it moves and how far it looks depends on
+//! its `Difficulty`, chosen by the host per seat. The registry lists one of
+//! these for each difficulty, named for it.
+
+use bot::Bot;
+use graph::{self, Graph, Node, nearest_path};
+use influence::InfluenceMap;
+use state::{Action, Player, State};
+use xorshift::XorShift128Plus;
+
+use rand::Rng;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How well a bot plays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Every difficulty, easiest first.
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    /// Return how many turns a bot at this difficulty waits between moves.
+    /// This keeps bots from playing at inhuman speed, and easier ones from
+    /// noticing a threat as soon as it appears.
+    pub fn move_turns(self) -> usize {
+        match self {
+            Difficulty::Easy => 60,
+            Difficulty::Medium => 30,
+            Difficulty::Hard => 15,
+        }
+    }
+
+    /// Return how many steps from its territory a bot at this difficulty
+    /// looks for a source to head for, or `None` if it looks all over the
+    /// board.
+    pub fn search_depth(self) -> Option<usize> {
+        match self {
+            Difficulty::Easy => Some(4),
+            Difficulty::Medium => Some(16),
+            Difficulty::Hard => None,
+        }
+    }
+
+    /// Return true if a bot at this difficulty makes macro moves: opening
+    /// every outflow along a path from its source at once, as the F key lets
+    /// a player do, rather than one outflow per move.
+    pub fn macro_moves(self) -> bool {
+        self == Difficulty::Hard
+    }
+
+    /// Return true if a bot at this difficulty heads first for sources it
+    /// would reach before anyone else, judging by the influence map, rather
+    /// than simply the nearest.
+    pub fn reads_influence(self) -> bool {
+        self == Difficulty::Hard
+    }
+
+    /// Return the difficulty after this one, for cycling through them.
+    pub fn next(self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Medium,
+            Difficulty::Medium => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+}
+
+/// Difficulties are written in lower case on the command line, as `easy`,
+/// `medium`, or `hard`.
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Difficulty, String> {
+        Difficulty::ALL.iter().cloned()
+            .find(|difficulty| difficulty.to_string() == text)
+            .ok_or_else(|| format!("no such bot difficulty: {}", text))
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        })
+    }
+}
+
+/// A bot that plays by rules of thumb, at some `Difficulty`.
+pub struct Heuristic {
+    /// How well this bot plays.
+    pub difficulty: Difficulty,
+
+    /// The source of this bot's decisions.
+    rng: XorShift128Plus,
+
+    /// Who is nearest to what, as of the last time we looked.
+    influence: InfluenceMap,
+}
+
+impl Heuristic {
+    /// Return a bot to play for `player` at `difficulty`.
+    pub fn new(player: Player, difficulty: Difficulty) -> Heuristic {
+        Heuristic::seeded(player, difficulty, 0)
+    }
+
+    /// Return a bot that plays as `new`'s would, but makes different choices
+    /// for each `game`, so that a run of games between the same bots doesn't
+    /// play the same game over and over.
+    pub fn seeded(player: Player, difficulty: Difficulty, game: u64) -> Heuristic {
+        // Derive the seed from the player number, so that each bot plays
+        // differently, but the same way every time. The seed must not be zero.
+        let seed = [0x9e37_79b9_7f4a_7c15 ^ player.0 as u64, 0x2545_f491_4f6c_dd1d ^ game];
+        Heuristic { difficulty, rng: XorShift128Plus::new(seed),
+                    influence: InfluenceMap::default() }
+    }
+
+    /// Return the move this bot would make for `player` in `state` if it were
+    /// time for it to move, unless it could only think to wander. Unlike
+    /// `act`, this doesn't wait for the bot's turn to move, and makes no
+    /// random choices, so it's suitable for suggesting moves to a person.
+    pub fn advice(&mut self, state: &State, player: Player) -> Option<Vec<Action>> {
+        self.considered(state, player, &holdings(state, player))
+    }
+
+    /// Return the first move of reinforcing or expanding that applies, if
+    /// either does.
+    fn considered(&mut self, state: &State, player: Player, owned: &[Node])
+                  -> Option<Vec<Action>>
+    {
+        self.reinforce(state, player, owned)
+            .or_else(|| self.expand(state, player, owned))
+    }
+
+    /// Send goop to our weakest node under threat from a stronger enemy
+    /// neighbor, if we have one and can.
+    fn reinforce(&mut self, state: &State, player: Player, owned: &[Node]) -> Option<Vec<Action>> {
+        let graph = &state.map.graph;
+        let weakest = owned.iter().cloned()
+            .filter(|&node| state.pressure_on(node) > 0)
+            .min_by_key(|&node| goop(state, node))?;
+
+        // Draw from whichever of our neighbors has the most goop to spare.
+        let helper = graph.neighbors(weakest).into_iter()
+            .filter(|&neighbor| owns(state, player, neighbor) && !flows(state, neighbor, weakest))
+            .max_by_key(|&neighbor| goop(state, neighbor))?;
+        Some(self.open(state, player, helper, weakest))
+    }
+
+    /// Pump goop toward the nearest source we don't hold, if there is one
+    /// within reach that we're not pumping toward already. If we read the
+    /// influence map, prefer the nearest we'd reach before anyone else.
+    fn expand(&mut self, state: &State, player: Player, owned: &[Node]) -> Option<Vec<Action>> {
+        let graph = &state.map.graph;
+        let sources = &state.map.sources;
+        let unheld = |node| sources.contains(&node) && !owns(state, player, node);
+        let uncontested = if self.difficulty.reads_influence() {
+            self.influence.update(state);
+            let influence = &self.influence;
+            nearest_path(graph, owned, |node| unheld(node) && influence.lead(player, node) > 0)
+        } else {
+            None
+        };
+        let path = uncontested.or_else(|| nearest_path(graph, owned, unheld))?;
+        if self.difficulty.search_depth().is_some_and(|depth| path.len() > depth + 1) {
+            return None;
+        }
+        let (from, to) = (path[0], *path.get(1)?);
+        let mut actions = vec![];
+        if self.difficulty.macro_moves() {
+            actions.extend(self.supply(state, player, from));
+        }
+        if !flows(state, from, to) {
+            actions.extend(self.open(state, player, from, to));
+        }
+        if actions.is_empty() {
+            return None;
+        }
+        Some(actions)
+    }
+
+    /// Return the actions opening the outflows we don't have open already
+    /// along a shortest path from our source to `node`, so goop flows there.
+    fn supply(&self, state: &State, player: Player, node: Node) -> Vec<Action> {
+        let source = state.map.sources[player.0];
+        let path = match graph::shortest_path(&state.map.graph, source, node) {
+            Some(path) => path,
+            None => return vec![],
+        };
+        path.windows(2)
+            .filter(|step| owns(state, player, step[0]) && !flows(state, step[0], step[1]))
+            .map(|step| Action::ToggleOutflow { player, from: step[0], to: step[1] })
+            .collect()
+    }
+
+    /// Pick one of our nodes at random and start pumping goop out of it into a
+    /// neighboring node we don't hold, if there is one. If all its neighbors
+    /// are our own, toggle one of them at random.
+    fn wander(&mut self, state: &State, player: Player, owned: &[Node]) -> Vec<Action> {
+        let from = owned[self.rng.gen_range(0, owned.len())];
+
+        let neighbors = state.map.graph.neighbors(from);
+        let frontier: Vec<Node> = neighbors.iter().cloned()
+            .filter(|&to| !owns(state, player, to) && !flows(state, from, to))
+            .collect();
+        let to = if !frontier.is_empty() {
+            frontier[self.rng.gen_range(0, frontier.len())]
+        } else if !neighbors.is_empty() {
+            neighbors[self.rng.gen_range(0, neighbors.len())]
+        } else {
+            return vec![];
+        };
+
+        vec![Action::ToggleOutflow { player, from, to }]
+    }
+
+    /// Return the actions to start goop flowing from `from` to `to`, stopping
+    /// any flow back the other way, which would only cancel it out.
+    fn open(&self, state: &State, player: Player, from: Node, to: Node) -> Vec<Action> {
+        let mut actions = vec![Action::ToggleOutflow { player, from, to }];
+        if flows(state, to, from) {
+            actions.push(Action::ToggleOutflow { player, from: to, to: from });
+        }
+        actions
+    }
+}
+
+impl Bot for Heuristic {
+    /// Every so often, depending on its difficulty, the bot makes one move,
+    /// trying these in turn:
+    ///
+    /// - If one of its nodes is next to an enemy node holding more goop, it
+    ///   reinforces the weakest such node, sending it goop from a neighbor of
+    ///   its own.
+    ///
+    /// - Otherwise, it expands toward the nearest source it doesn't hold,
+    ///   whether vacant or an enemy's, pumping goop from its territory into
+    ///   the next node along the way. Only hard bots look across the whole
+    ///   board, and they also open the outflows from their own source to
+    ///   where they're expanding from, to keep the goop coming. They head
+    ///   first for sources they'd reach before any rival, if there are any.
+    ///
+    /// - Failing both, it toggles an outflow at random, which keeps goop
+    ///   moving around inside its territory.
+    fn act(&mut self, state: &State, player: Player) -> Vec<Action> {
+        if !state.turn.is_multiple_of(self.difficulty.move_turns()) {
+            return vec![];
+        }
+
+        let owned = holdings(state, player);
+        if owned.is_empty() {
+            return vec![];
+        }
+        match self.considered(state, player, &owned) {
+            Some(actions) => actions,
+            None => self.wander(state, player, &owned),
+        }
+    }
+}
+
+/// Return the nodes `player` occupies.
+fn holdings(state: &State, player: Player) -> Vec<Node> {
+    (0..state.nodes.len()).filter(|&node| owns(state, player, node)).collect()
+}
+
+/// Return true if `player` occupies `node`.
+fn owns(state: &State, player: Player, node: Node) -> bool {
+    state.nodes[node].as_ref().is_some_and(|occupied| occupied.player == player)
+}
+
+/// Return the amount of goop at `node`, or zero if it's vacant.
+fn goop(state: &State, node: Node) -> usize {
+    state.nodes[node].as_ref().map_or(0, |occupied| occupied.goop)
+}
+
+/// Return true if `from` is occupied, and sends goop out to `to`.
+fn flows(state: &State, from: Node, to: Node) -> bool {
+    state.nodes[from].as_ref().is_some_and(|occupied| occupied.outflows.contains(&to))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::{GameParameters, Occupied};
+
+    fn duel() -> State {
+        let mut state = State::new(MapParameters {
+            size: (5, 5),
+            sources: vec![0, 24],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        state.advance();
+        while !state.turn.is_multiple_of(Difficulty::Hard.move_turns() * 4) {
+            state.advance();
+        }
+        state
+    }
+
+    #[test]
+    fn expands_toward_sources() {
+        // Against an idle opponent, a bot heads for their source, taking
+        // most of the board on the way.
+        let mut state = duel();
+        let mut bot = Heuristic::new(Player(1), Difficulty::Medium);
+        for _ in 0..40 * Difficulty::Medium.move_turns() {
+            for action in bot.act(&state, Player(1)) {
+                state.take_action(&action);
+            }
+            state.advance();
+        }
+        assert!(state.node_counts()[1] > state.nodes.len() / 2);
+        assert!(owns(&state, Player(1), 1) || owns(&state, Player(1), 5));
+    }
+
+    #[test]
+    fn reinforces() {
+        // Player 1 holds 12 and 13; player 0 presses on 12 from 11 with
+        // more goop.
+        let mut state = duel();
+        state.nodes[11] = Some(Occupied { player: Player(0), outflows: vec![12], goop: 100 });
+        state.nodes[12] = Some(Occupied { player: Player(1), outflows: vec![], goop: 10 });
+        state.nodes[13] = Some(Occupied { player: Player(1), outflows: vec![12], goop: 50 });
+        state.nodes[7] = Some(Occupied { player: Player(1), outflows: vec![], goop: 60 });
+
+        let mut bot = Heuristic::new(Player(1), Difficulty::Medium);
+        let actions = bot.act(&state, Player(1));
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(actions[0], Action::ToggleOutflow { player: Player(1), from: 7, to: 12 }));
+    }
+
+    #[test]
+    fn difficulties() {
+        // Player 1 holds a line running out from its source, toward player 0's.
+        let mut state = duel();
+        for &node in &[23, 22] {
+            state.nodes[node] = Some(Occupied { player: Player(1), outflows: vec![], goop: 50 });
+        }
+        let toggles = |difficulty| {
+            Heuristic::new(Player(1), difficulty).act(&state, Player(1)).into_iter()
+                .map(|action| match action {
+                    Action::ToggleOutflow { from, to, .. } => (from, to),
+                    otherwise => panic!("unexpected action {:?}", otherwise),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // A medium bot pushes out from the end of the line; a hard one also
+        // opens the line from its source, to feed it.
+        let medium = toggles(Difficulty::Medium);
+        assert_eq!(medium.len(), 1);
+        assert_eq!(medium[0].0, 22);
+        let hard = toggles(Difficulty::Hard);
+        assert_eq!(&hard[..2], &[(24, 23), (23, 22)]);
+        assert_eq!(&hard[2..], &medium[..]);
+
+        assert!(Difficulty::Easy.move_turns() > Difficulty::Hard.move_turns());
+        assert_eq!("hard".parse(), Ok(Difficulty::Hard));
+        assert!("impossible".parse::<Difficulty>().is_err());
+        for &difficulty in &Difficulty::ALL {
+            assert_eq!(difficulty.to_string().parse(), Ok(difficulty));
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Keeping the last few states a game has passed through.
+//!
+//! A participant only needs the current state to play, but several things want
+//! to look back a little: rolling back a prediction that turned out wrong,
+//! reporting where a divergence began, or showing what changed on the last
+//! turn. Rather than each keeping copies of its own, they all use a `History`,
+//! holding the states before each of the latest few turns applied.
+
+use state::State;
+
+use std::collections::VecDeque;
+
+/// The number of states a participant keeps.
+pub const DEFAULT_STATES: usize = 8;
+
+/// The states before each of the latest turns applied, oldest first.
+pub struct History {
+    states: VecDeque<State>,
+
+    /// The most states we keep. Pushing another drops the oldest.
+    capacity: usize,
+}
+
+impl History {
+    /// Return an empty history that keeps at most `capacity` states.
+    pub fn new(capacity: usize) -> History {
+        History { states: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Note that `state` is the one before the turn just applied. This must
+    /// be the turn after the last state pushed; if it isn't, the states in
+    /// between are missing, so we forget the earlier ones.
+    pub fn push(&mut self, state: State) {
+        if self.latest().is_some_and(|latest| latest.turn + 1 != state.turn) {
+            self.states.clear();
+        }
+        if self.states.len() >= self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+
+    /// Forget every state, as when the game jumps to a state unrelated to
+    /// these.
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+
+    /// Return the most recent state, the one before the turn last applied.
+    pub fn latest(&self) -> Option<&State> {
+        self.states.back()
+    }
+
+    /// Return the state as of `turn`, if we still have it.
+    pub fn get(&self, turn: usize) -> Option<&State> {
+        let oldest = self.states.front()?.turn;
+        self.states.get(turn.checked_sub(oldest)?)
+    }
+
+    /// Iterate over the states we have, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item=&State> {
+        self.states.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::GameParameters;
+
+    #[test]
+    fn keeps_latest() {
+        let mut state = State::new(MapParameters {
+            size: (2, 2),
+            sources: vec![0, 3],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+        let mut history = History::new(3);
+        assert!(history.latest().is_none());
+        for _ in 0..5 {
+            history.push(state.clone());
+            state.advance();
+        }
+        assert_eq!(history.iter().map(|state| state.turn).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(history.latest().unwrap().turn, 4);
+        assert_eq!(history.get(3).unwrap().turn, 3);
+        assert!(history.get(1).is_none());
+        assert!(history.get(5).is_none());
+
+        // A gap means the older states don't lead up to the newer ones.
+        state.advance();
+        history.push(state.clone());
+        assert_eq!(history.iter().count(), 1);
+
+        history.clear();
+        assert!(history.latest().is_none());
+    }
+}
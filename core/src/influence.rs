@@ -0,0 +1,140 @@
+//! Which players are nearest to which parts of the board.
+//!
+//! An `InfluenceMap` holds, for each player, how many steps every node is
+//! from the nearest node they hold. From that, it can say who would reach a
+//! node first, and by how far, which is what a bot deciding where to head
+//! wants to know, and what a heat map of the board would show.
+//!
+//! Bots consult this every time they move, and the board can be large, so
+//! updating the map reuses its storage rather than allocating afresh, and
+//! takes one `graph::distances_into` search per player: time proportional to
+//! the number of players times the number of edges.
+
+use graph::{self, Node};
+use state::{Player, State};
+
+use std::collections::VecDeque;
+
+/// Each player's distance from every node of the board.
+#[derive(Clone, Debug, Default)]
+pub struct InfluenceMap {
+    /// For each player, how many steps each node is from the nearest node
+    /// the player holds, indexed by player number and then by node, or
+    /// `None` if none of their nodes connect to it.
+    distances: Vec<Vec<Option<usize>>>,
+
+    /// The nodes each search starts from, and its queue, kept to save
+    /// allocating them again each time.
+    held: Vec<Node>,
+    queue: VecDeque<Node>,
+}
+
+impl InfluenceMap {
+    /// Return the influence map for `state`.
+    pub fn new(state: &State) -> InfluenceMap {
+        let mut map = InfluenceMap::default();
+        map.update(state);
+        map
+    }
+
+    /// Bring this map up to date with `state`.
+    pub fn update(&mut self, state: &State) {
+        self.distances.resize(state.max_players(), vec![]);
+        for (player, distances) in self.distances.iter_mut().enumerate() {
+            self.held.clear();
+            self.held.extend(state.nodes.iter().enumerate()
+                             .filter(|&(_, occupied)| {
+                                 occupied.as_ref().is_some_and(|occupied| occupied.player.0 == player)
+                             })
+                             .map(|(node, _)| node));
+            graph::distances_into(&state.map.graph, &self.held, distances, &mut self.queue);
+        }
+    }
+
+    /// Return how many steps `node` is from the nearest node `player` holds,
+    /// or `None` if they hold none that connect to it.
+    pub fn distance(&self, player: Player, node: Node) -> Option<usize> {
+        self.distances.get(player.0).and_then(|distances| distances[node])
+    }
+
+    /// Return the player nearest to `node`, or `None` if no one can reach it
+    /// or two or more players are equally near.
+    pub fn controller(&self, node: Node) -> Option<Player> {
+        let mut nearest = None;
+        let mut tied = false;
+        for (player, distances) in self.distances.iter().enumerate() {
+            let distance = match distances[node] {
+                Some(distance) => distance,
+                None => continue,
+            };
+            match nearest {
+                Some((_, best)) if distance > best => (),
+                Some((_, best)) if distance == best => tied = true,
+                _ => {
+                    nearest = Some((player, distance));
+                    tied = false;
+                }
+            }
+        }
+        match nearest {
+            Some((player, _)) if !tied => Some(Player(player)),
+            _ => None,
+        }
+    }
+
+    /// Return how many steps nearer `node` is to `player` than to any other
+    /// player: positive if `player` would get there first, negative if some
+    /// rival would, and zero for a tie. A node a player can't reach counts as
+    /// farther from them than any node they can.
+    pub fn lead(&self, player: Player, node: Node) -> isize {
+        let beyond = self.distances.first().map_or(0, |distances| distances.len());
+        let distance = |distances: &Vec<Option<usize>>| {
+            distances[node].unwrap_or(beyond) as isize
+        };
+        let rival = self.distances.iter()
+            .enumerate()
+            .filter(|&(rival, _)| rival != player.0)
+            .map(|(_, distances)| distance(distances))
+            .min()
+            .unwrap_or(beyond as isize);
+        let own = self.distances.get(player.0).map_or(beyond as isize, distance);
+        rival - own
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::{GameParameters, Occupied};
+
+    #[test]
+    fn influence() {
+        let mut state = State::new(MapParameters {
+            size: (1, 7),
+            sources: vec![0, 6],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff), (0, 0xff, 0)],
+        }, GameParameters::default());
+        let occupied = |player| Some(Occupied { player: Player(player), outflows: vec![], goop: 0 });
+        state.nodes[0] = occupied(0);
+        state.nodes[1] = occupied(0);
+        state.nodes[6] = occupied(1);
+
+        let mut map = InfluenceMap::new(&state);
+        assert_eq!(map.distance(Player(0), 4), Some(3));
+        assert_eq!(map.distance(Player(1), 4), Some(2));
+        assert_eq!(map.distance(Player(2), 4), None);
+        assert_eq!(map.controller(3), Some(Player(0)));
+        assert_eq!(map.controller(4), Some(Player(1)));
+        assert_eq!(map.lead(Player(0), 0), 6);
+        assert_eq!(map.lead(Player(0), 4), -1);
+        assert_eq!(map.lead(Player(2), 4), -5);
+
+        // Updating follows the board.
+        state.nodes[2] = occupied(0);
+        map.update(&state);
+        assert_eq!(map.distance(Player(0), 4), Some(2));
+        assert_eq!(map.controller(4), None);
+        assert_eq!(map.lead(Player(1), 4), 0);
+    }
+}
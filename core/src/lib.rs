@@ -0,0 +1,55 @@
+//! The rbattle simulation: the board, the rules, and the scheduler that plays
+//! turns, along with the messages participants exchange and the files games
+//! are saved and recorded in.
+//!
+//! This has nothing to do with windows, drawing, or networking, so bots,
+//! analysis tools, and replay viewers can play games out without the rest of
+//! rbattle. The `rbattle` program itself is built on this.
+
+// `error_chain!` can recurse deeply
+#![recursion_limit = "1024"]
+
+#[macro_use] extern crate error_chain;
+#[macro_use] extern crate serde_derive;
+extern crate rand;
+extern crate rmp_serde;
+extern crate serde;
+extern crate serde_json;
+extern crate wasmi;
+extern crate zstd;
+
+#[cfg(test)]
+extern crate proptest;
+#[cfg(test)]
+extern crate wat;
+
+#[cfg(test)]
+#[macro_use]
+mod test_utils;
+#[cfg(test)]
+mod regression;
+
+pub mod anonymize;
+pub mod bot;
+pub mod errors;
+pub mod fnv;
+pub mod graph;
+pub mod heuristic;
+pub mod history;
+pub mod influence;
+pub mod map;
+pub mod math;
+pub mod messages;
+pub mod replay;
+pub mod save;
+pub mod scheduler;
+pub mod simulate;
+pub mod square;
+pub mod state;
+pub mod storage;
+pub mod timestep;
+pub mod timing;
+pub mod version;
+pub mod visible_graph;
+pub mod wasm;
+pub mod xorshift;
@@ -0,0 +1,194 @@
+use errors::*;
+use fnv::fnv1a;
+use graph::{self, Graph, Node};
+use math::{compose, inverse, translate_transform, scale_transform};
+use visible_graph::{GraphPt, VisibleGraph};
+use square::SquareGrid;
+
+use serde_json;
+
+use std::fmt;
+
+/// A map on which an RBattle game is played.
+///
+/// A `Map` holds everything that does not change over the course of an RBattle
+/// game. This includes a graph, and a set of nodes that have goop sources.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    /// The graph of nodes comprising this map's territory.
+    pub graph: SquareGrid,
+
+    /// The nodes of `graph` that contain goop sources.
+    pub sources: Vec<Node>,
+
+    /// Coordinate transformation from graph space to game space.
+    pub graph_to_game: [[f32; 3]; 3],
+
+    /// Coordinate transformation from game space to graph space.
+    /// The inverse of the above.
+    pub game_to_graph: [[f32; 3]; 3],
+
+    /// The aspect ratio (width / height) of the game rectangle.
+    pub game_aspect: f32,
+
+    /// The color of each player's goop, indexed by player number.
+    pub player_colors: Vec<(u8, u8, u8)>,
+
+    /// The pattern drawn over each player's goop, indexed by player number.
+    /// Patterns let players tell each other apart without relying on color.
+    pub player_patterns: Vec<Pattern>,
+}
+
+/// A pattern drawn over a player's goop, in a paler shade of their color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    Solid,
+    Stripes,
+    Dots,
+    Rings,
+}
+
+impl Pattern {
+    /// The patterns to assign to players, in order. If there are more players
+    /// than patterns, we start over from the beginning.
+    const ALL: [Pattern; 4] = [Pattern::Solid, Pattern::Stripes, Pattern::Dots, Pattern::Rings];
+
+    /// Return the pattern that the `player`'th player on a map should use.
+    pub fn for_player(player: usize) -> Pattern {
+        Pattern::ALL[player % Pattern::ALL.len()]
+    }
+}
+
+impl Map {
+    pub fn new(params: MapParameters) -> Map {
+        let MapParameters { size, sources, player_colors } = params;
+        let graph = SquareGrid::new(size.0, size.1);
+
+        // Compute the transformation from graph space, where points run from
+        // (0, 0) to upper_right, to game space, where points run from (-1, -1)
+        // to (1,1).
+        let GraphPt(bounds) = graph.bounds();
+        let (width, height) = (bounds[0], bounds[1]); // dumb; see issue #23121
+        let game_aspect = width / height;
+        let graph_to_game =
+            compose(translate_transform(-1.0, -1.0),
+                    scale_transform(2.0 / width, 2.0 / height));
+
+        // A little margin inside the window is nice.
+        let graph_to_game = compose(scale_transform(0.95, 0.95), graph_to_game);
+
+        let game_to_graph = inverse(graph_to_game)
+            .expect("graph_to_game transformation should be invertible");
+
+        let player_patterns = (0..player_colors.len()).map(Pattern::for_player).collect();
+
+        Map { graph, sources, graph_to_game,
+              game_to_graph, game_aspect, player_colors, player_patterns }
+    }
+
+    /// Return this map's canonical serialization: compact JSON, with the
+    /// fields in the order `Map` declares them. Map files hold exactly this,
+    /// and it's what `hash` hashes.
+    pub fn canonical_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("maps always serialize")
+    }
+
+    /// Check that this map, which `what` gave us, can be played on: that
+    /// every player has a color, a pattern, and a source of their own on the
+    /// board, and that each source can reach all the others.
+    pub fn check(&self, what: &str) -> Result<()> {
+        let players = self.sources.len();
+        if self.player_colors.len() != players || self.player_patterns.len() != players {
+            bail!("{} has {} sources, but {} colors and {} patterns",
+                  what, players, self.player_colors.len(), self.player_patterns.len());
+        }
+        let nodes = self.graph.nodes();
+        for (player, &source) in self.sources.iter().enumerate() {
+            if source >= nodes {
+                bail!("{} puts player {}'s source at node {}, off the board", what, player, source);
+            }
+            if self.sources[..player].contains(&source) {
+                bail!("{} gives node {} to two players", what, source);
+            }
+        }
+        let groups = graph::components(&self.graph, |_| true);
+        if let Some(&first) = self.sources.first() {
+            if let Some(&cut_off) = self.sources.iter().find(|&&source| groups[source] != groups[first]) {
+                bail!("{} has no way from node {} to node {}", what, first, cut_off);
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the hash that names this map: the FNV-1a hash of its canonical
+    /// serialization. Unlike a game's fingerprint, this covers everything in
+    /// the map, colors and all, since it's how participants tell whether they
+    /// already have the map another is talking about.
+    pub fn hash(&self) -> MapHash {
+        MapHash(fnv1a(&self.canonical_json()))
+    }
+}
+
+/// The hash that names a map. See `Map::hash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MapHash(pub u64);
+
+/// A map's hash is written as sixteen hex digits, as in map file names.
+impl fmt::Display for MapHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// A set of parameters that can be used to initialize a map.
+pub struct MapParameters {
+    /// The dimensions of the board.
+    pub size: (usize, usize),
+
+    /// The position of the sources on the board. The number of players is the
+    /// length of this vector.
+    pub sources: Vec<Node>,
+
+    /// The color assigned to each player, as an RGB triplet. This must be the
+    /// same length as `sources`.
+    pub player_colors: Vec<(u8, u8, u8)>
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn board(player_colors: Vec<(u8, u8, u8)>) -> Map {
+        Map::new(MapParameters { size: (4, 3), sources: vec![0, 11], player_colors })
+    }
+
+    #[test]
+    fn hashes() {
+        let map = board(vec![(0xff, 0, 0), (0, 0, 0xff)]);
+        let hash = map.hash();
+        assert_eq!(hash.to_string().len(), 16);
+
+        // The hash survives a trip through a map file, even a pretty one.
+        let pretty = serde_json::to_string_pretty(&map).unwrap();
+        let reread: Map = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(reread.canonical_json(), map.canonical_json());
+        assert_eq!(reread.hash(), hash);
+
+        // Anything that changes the map changes its name.
+        assert_ne!(board(vec![(0xff, 0, 0), (0, 0xff, 0)]).hash(), hash);
+    }
+
+    #[test]
+    fn check() {
+        let mut map = board(vec![(0xff, 0, 0), (0, 0, 0xff)]);
+        assert!(map.check("board").is_ok());
+
+        map.sources[1] = 12;
+        assert!(map.check("board").is_err());
+        map.sources[1] = 0;
+        assert!(map.check("board").is_err());
+        map.sources[1] = 11;
+        map.player_colors.pop();
+        assert!(map.check("board").is_err());
+    }
+}
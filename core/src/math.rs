@@ -46,7 +46,7 @@ pub trait Homogeneous {
     fn into(self) -> [f32; 3];
 
     /// Convent a homogeneous vector to `self`.
-    fn from([f32; 3]) -> Self;
+    fn from(h: [f32; 3]) -> Self;
 }
 
 impl Homogeneous for [f32; 2] {
@@ -78,7 +78,7 @@ pub fn scale_inv(vec: [f32; 3], scalar: f32) -> [f32; 3] {
 pub fn add(lhs: [f32; 3], rhs: [f32; 3]) -> [f32; 3] {
     [lhs[0] + rhs[0],
      lhs[1] + rhs[1],
-     lhs[2] + rhs[02]]
+     lhs[2] + rhs[2]]
 }
 
 /// Compute the cross product or vector product of `lhs` and `rhs`.
@@ -93,6 +93,21 @@ pub fn midpoint(lhs: [f32; 2], rhs: [f32; 2]) -> [f32; 2] {
     [(lhs[0] + rhs[0]) / 2.0, (lhs[1] + rhs[1]) / 2.0]
 }
 
+/// Return the distance from `point` to the nearest point on the line segment
+/// from `start` to `end`.
+pub fn segment_distance(point: [f32; 2], start: [f32; 2], end: [f32; 2]) -> f32 {
+    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((point[0] - start[0]) * dx + (point[1] - start[1]) * dy) / length_squared)
+            .clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (nx, ny) = (start[0] + t * dx - point[0], start[1] + t * dy - point[1]);
+    (nx * nx + ny * ny).sqrt()
+}
+
 /// Apply the transformation `trans` to `vec`. The `vec` argument may be either
 /// an `[f32; 2]` or an `[f32; 3]`.
 pub fn apply<V: Homogeneous>(trans: Matrix, vec: V) -> V {
@@ -120,9 +135,9 @@ pub fn transpose(m: Matrix) -> Matrix {
 
 /// Return the determinant of `m`.
 pub fn determinant(m: Matrix) -> f32 {
-    (m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2]) -
-     m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2]) +
-     m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]))
+    m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2]) -
+    m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2]) +
+    m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2])
 }
 
 /// Return the inverse of `m`. In other words, return a matrix that undoes
@@ -165,6 +180,13 @@ mod test {
                    [12.0, 51.0]);
     }
 
+    #[test]
+    fn test_segment_distance() {
+        assert_eq!(segment_distance([1.0, 1.0], [0.0, 0.0], [2.0, 0.0]), 1.0);
+        assert_eq!(segment_distance([3.0, 0.0], [0.0, 0.0], [2.0, 0.0]), 1.0);
+        assert_eq!(segment_distance([-3.0, 4.0], [0.0, 0.0], [0.0, 0.0]), 5.0);
+    }
+
     #[test]
     fn test_inverse() {
         let scale = scale_transform(2.0, 8.0);
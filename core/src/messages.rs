@@ -0,0 +1,158 @@
+//! The messages rbattle participants exchange.
+//!
+//! A client sends the server `Request`s, and the server answers each with a
+//! `Response`. See the `protocol` module of the `rbattle` program for how the
+//! game proceeds, and the `jsonproto` module there for how these travel over
+//! the wire.
+
+use map::{Map, MapHash};
+use scheduler::{CollectedActions, DeltaSnapshot, GameStats, Notifier, PlayerActions, RejoinToken,
+                Snapshot};
+use state::Player;
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Requests the server receives from clients.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    Join,
+    Actions(PlayerActions),
+
+    /// Take up the seat of `.0` again, after losing the connection, giving
+    /// the token `.1` that came with the seat's `Welcome`. The client's state
+    /// is at turn `.2`, and it has kept a copy of the state as of the
+    /// checkpoint at turn `.3`; see `scheduler::is_checkpoint`. If the server
+    /// still has the turns since `.2`, and there aren't too many, it answers
+    /// with `Rejoined`. Otherwise, if it still has that checkpoint, it
+    /// answers with `Resynced`; failing both, it answers like `Join`.
+    Rejoin(Player, RejoinToken, usize, usize),
+
+    /// Ask for the game's current state, without joining. The server answers
+    /// with `Snapshot`.
+    Spectate,
+
+    /// Like `Spectate`, from a client that has kept a copy of the state as of
+    /// the checkpoint at turn `.0`, as a spectator that has fallen too far
+    /// behind to catch up with `Watch` might. If the server still has that
+    /// checkpoint, it answers with `Delta`; otherwise, it answers like
+    /// `Spectate`.
+    CatchUp(usize),
+
+    /// Ask for the turn after turn `.0`, without submitting any actions. This
+    /// is answered like `Actions`, so spectators can follow the game.
+    Watch(usize),
+
+    /// Once the game is over, ask for the first turn of the rematch, if the
+    /// host starts one. The client's state is at turn `.0`. This is answered
+    /// like `Watch`, but not until the rematch begins.
+    AwaitRematch(usize),
+
+    /// Ask for the collected actions of past turns `.0 .. .1`. This doesn't
+    /// require joining the game, so spectators and analysis tools that connect
+    /// mid-game can use it to backfill what they missed. The server returns at
+    /// most `scheduler::MAX_TURN_RANGE` turns per request.
+    TurnRange(usize, usize),
+
+    /// Player `.0`'s state didn't match the checksum for turn `.1`. The server
+    /// writes down its side of things, and answers with `Noted`. See the
+    /// `divergence` module. Only the connection seated as `.0` may send this,
+    /// and the server writes down at most one report per player per turn.
+    Diverged(Player, usize),
+
+    /// Ask for the map named `.0`, which a `Welcome` referred to but the
+    /// client doesn't have. The server answers with `Map`, or `UnknownMap`
+    /// if it's no longer playing on that map.
+    Map(MapHash),
+}
+
+/// The server's responses to those requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// The client has joined the game as `player`. The client can recreate
+    /// the game's current state from `snapshot`, and a turn will be played
+    /// every `turn_duration`.
+    ///
+    /// To keep this short, the snapshot's state comes without its map; `map`
+    /// names it instead. The client should attach the map by that name from
+    /// its cache, or if it hasn't got it, ask for it with `Request::Map`.
+    /// Then it should check that `fingerprint` matches the board and rules it
+    /// finds in `snapshot`; see `state::SerializableState::check_fingerprint`.
+    ///
+    /// The client must give `token` to take its seat back with `Rejoin`.
+    Welcome { player: Player, token: RejoinToken, snapshot: Box<Snapshot>,
+              turn_duration: Duration, fingerprint: u64, map: MapHash },
+
+    /// The client has taken its seat again. It can catch up by applying
+    /// `turns`, the turns it missed.
+    Rejoined { turns: Vec<CollectedActions>, turn_duration: Duration },
+
+    /// The client has taken its seat again, but missed too many turns to
+    /// apply them all. It can catch up by applying `snapshot` to the
+    /// checkpoint it named in its `Rejoin` request.
+    Resynced { snapshot: Box<DeltaSnapshot>, turn_duration: Duration },
+
+    /// The answer to `Spectate`.
+    Snapshot { snapshot: Box<Snapshot>, turn_duration: Duration },
+
+    /// The answer to `CatchUp`, to be applied to the checkpoint it named.
+    Delta { snapshot: Box<DeltaSnapshot>, turn_duration: Duration },
+
+    /// There's no seat for the client: the game is full, or the player named
+    /// in a `Rejoin` request isn't missing.
+    GameFull,
+    Turn(CollectedActions),
+
+    /// The turns requested by `Request::TurnRange`, oldest first. This also
+    /// answers an `Actions` or `Watch` request for a turn that has already
+    /// been played, with all the turns played since.
+    Turns(Vec<CollectedActions>),
+
+    /// The requested turn range was empty, or isn't in the server's history.
+    /// The server can provide turns `.0 .. .1`.
+    TurnRangeUnavailable(usize, usize),
+
+    /// The game is over, and `winner` has won. This is the server's reply to
+    /// the first `Actions` request submitted after the final turn. The client
+    /// may then close its connection, or send `AwaitRematch` to stay for
+    /// another game.
+    Result { winner: Player, stats: GameStats },
+
+    /// The game has paused for up to `timeout` to let `player` reconnect. This
+    /// answers an `Actions` request in place of the next turn; the client
+    /// should submit its actions for the same turn again, and wait.
+    Paused { player: Player, timeout: Duration },
+
+    /// The server has written down its side of a divergence.
+    Noted,
+
+    /// The map requested by `Request::Map`.
+    Map(Box<Map>),
+
+    /// The server isn't playing on the map named in a `Request::Map`.
+    UnknownMap(MapHash),
+}
+
+/// This impl allows `Scheduler` to send the actions collected for a turn to the
+/// local game.
+impl Notifier for mpsc::Sender<Response> {
+    fn notify(self: Box<Self>, turn: CollectedActions) {
+        self.send(Response::Turn(turn))
+            .expect("mpsc notifier receiver died");
+    }
+
+    fn catch_up(self: Box<Self>, turns: Vec<CollectedActions>) {
+        self.send(Response::Turns(turns))
+            .expect("mpsc notifier receiver died");
+    }
+
+    fn game_over(self: Box<Self>, winner: Player, stats: GameStats) {
+        self.send(Response::Result { winner, stats })
+            .expect("mpsc notifier receiver died");
+    }
+
+    fn paused(self: Box<Self>, player: Player, timeout: Duration) {
+        self.send(Response::Paused { player, timeout })
+            .expect("mpsc notifier receiver died");
+    }
+}
@@ -0,0 +1,143 @@
+//! Regression tests that play recorded games over again.
+//!
+//! `tests/replays` holds a few replays of whole games, recorded by bots, and
+//! `tests/replays/checksums.txt` lists the turn each ends on and the checksum
+//! of the state there. The `replays` test plays each through the simulation
+//! from its starting state; `CollectedActions::apply_to` checks every turn's
+//! checksum along the way, and we check the final one against the list. So
+//! any change to how goop flows, how the random number generator is used, or
+//! how checksums are computed, deliberate or not, fails here.
+//!
+//! When such a change is deliberate, bump `version::RULES`, and record the
+//! games afresh with:
+//!
+//!     cargo test -p rbattle-core record_replays -- --ignored
+//!
+//! which rewrites the replays and the list.
+
+use bot::{Bot, Difficulty, Heuristic};
+use map::MapParameters;
+use replay::{Replay, ReplayWriter};
+use scheduler::CollectedActions;
+use state::{Action, GameParameters, Player, State};
+use storage::{Format, Output};
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Return the directory holding the recorded games.
+fn directory() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("replays")
+}
+
+/// A game to record: its name, board, rules, and length, and any actions to
+/// take on particular turns besides the bots'.
+struct Game {
+    name: &'static str,
+    map: MapParameters,
+    params: GameParameters,
+    turns: usize,
+    script: Vec<(usize, Action)>,
+}
+
+fn games() -> Vec<Game> {
+    vec![
+        Game {
+            name: "four-bots",
+            map: MapParameters {
+                size: (15, 15),
+                sources: vec![32, 42, 182, 192],
+                player_colors: vec![(0x9f, 0x20, 0xb1), (0xe0, 0x6f, 0x3a),
+                                    (0x20, 0xb1, 0x21), (0x20, 0x67, 0xb1)],
+            },
+            params: GameParameters { warmup_turns: 100, ..GameParameters::default() },
+            turns: 2000,
+            script: vec![],
+        },
+        Game {
+            name: "duel-rematch",
+            map: MapParameters {
+                size: (7, 7),
+                sources: vec![8, 40],
+                player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+            },
+            params: GameParameters { warmup_turns: 20, ..GameParameters::default() },
+            turns: 900,
+            script: vec![
+                (250, Action::Chat { player: Player(0), text: "again?".to_string() }),
+                (300, Action::Restart),
+                (700, Action::Surrender { player: Player(1) }),
+                (750, Action::Restart),
+            ],
+        },
+    ]
+}
+
+#[test]
+fn replays() {
+    let list = fs::read_to_string(directory().join("checksums.txt"))
+        .expect("reading tests/replays/checksums.txt");
+    let mut played = 0;
+    for line in list.lines().filter(|line| !line.starts_with('#')) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (name, turn, checksum) = match fields[..] {
+            [name, turn, checksum] => (name, turn.parse::<usize>().unwrap(),
+                                       u64::from_str_radix(checksum, 16).unwrap()),
+            _ => panic!("malformed line in checksums.txt: {:?}", line),
+        };
+
+        let replay = Replay::load(&directory().join(name))
+            .unwrap_or_else(|error| panic!("loading {}: {}", name, error));
+        let mut state = State::from_serializable(replay.state);
+        for turn in &replay.turns {
+            turn.apply_to(&mut state);
+        }
+        assert_eq!(state.turn, turn, "{} ends on the wrong turn", name);
+        assert_eq!(state.checksum(), checksum, "{} ends in a different state", name);
+        played += 1;
+    }
+    assert_eq!(played, games().len());
+}
+
+#[test]
+#[ignore]
+fn record_replays() {
+    let mut list = String::from("# replay, final turn, final checksum\n");
+    for game in games() {
+        let name = format!("{}.replay", game.name);
+        let mut state = State::new(game.map, game.params);
+        let mut bots: Vec<Heuristic> = (0..state.max_players())
+            .map(|player| Heuristic::new(Player(player), Difficulty::default()))
+            .collect();
+        let format = Format { binary: false, compressed: true };
+        let mut writer = ReplayWriter::<Output>::create(&directory().join(&name), format,
+                                                        &state, Duration::from_millis(16))
+            .unwrap();
+
+        let mut actions: Vec<Action> = (0..bots.len())
+            .map(|player| Action::Join { player: Player(player) })
+            .collect();
+        while state.turn < game.turns {
+            for &(turn, ref action) in &game.script {
+                if turn == state.turn {
+                    actions.push(action.clone());
+                }
+            }
+            for action in &actions {
+                state.take_action(action);
+            }
+            state.advance();
+            writer.record(&CollectedActions { turn: state.turn, actions,
+                                              state_checksum: state.checksum(),
+                                              turn_duration: None })
+                .unwrap();
+            actions = bots.iter_mut()
+                .enumerate()
+                .flat_map(|(player, bot)| bot.act(&state, Player(player)))
+                .collect();
+        }
+        list.push_str(&format!("{} {} {:016x}\n", name, state.turn, state.checksum()));
+    }
+    fs::write(directory().join("checksums.txt"), list).unwrap();
+}
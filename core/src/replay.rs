@@ -0,0 +1,399 @@
+//! Recording whole games, and reading them back.
+//!
+//! Since the game is deterministic, a state and the actions collected for each
+//! turn after it are all it takes to play a game over again. Given
+//! `--record-game FILE`, a participant, whether hosting or not, writes just
+//! that: a header holding the state as of when recording began, and then each
+//! turn's `CollectedActions` as it's applied, one JSON object per line.
+//! `rbattle replay FILE` shows the game again from such a file.
+//!
+//! Given `--binary` or `--compress`, the header and turns are written in
+//! MessagePack, or compressed, as the `storage` module explains.
+//!
+//! Each turn is written out as soon as it's applied, so nothing is lost if the
+//! game exits abruptly. If a client reconnects and gets a fresh copy of the
+//! state, rather than the turns it missed, the recording can't be continued,
+//! and stops.
+
+use errors::*;
+use scheduler::CollectedActions;
+use state::{SerializableState, State};
+use storage::{self, Format, Input, Output};
+use version::Version;
+
+use serde_json;
+
+use std::io::{BufRead, ErrorKind, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// The first line of a replay file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// The versions of rbattle the replay was recorded by.
+    version: Version,
+
+    /// The fingerprint of the board and rules the game was played by.
+    /// Replays recorded before there were fingerprints lack this.
+    #[serde(default)]
+    fingerprint: Option<u64>,
+
+    /// The time between turns when recording began. Later changes are noted
+    /// in the turns themselves.
+    turn_duration: Duration,
+
+    /// The state as of when recording began.
+    state: SerializableState,
+}
+
+/// Writes turns to a replay file as they're applied.
+pub struct ReplayWriter<W: Write> {
+    out: W,
+
+    /// True if we're writing MessagePack, rather than JSON.
+    binary: bool,
+}
+
+impl ReplayWriter<Output> {
+    /// Start a replay of the game in the file at `path`, in `format`, from
+    /// `state`, with a turn every `turn_duration`.
+    pub fn create(path: &Path, format: Format, state: &State, turn_duration: Duration)
+                  -> Result<Self>
+    {
+        let out = Output::create(path, format)
+            .chain_err(|| format!("creating replay {}", path.display()))?;
+        ReplayWriter::new(out, format.binary, state, turn_duration)
+            .chain_err(|| format!("writing replay {}", path.display()))
+    }
+}
+
+impl<W: Write> ReplayWriter<W> {
+    fn new(mut out: W, binary: bool, state: &State, turn_duration: Duration)
+           -> Result<ReplayWriter<W>>
+    {
+        let header = Header {
+            version: Version::current(),
+            fingerprint: Some(state.fingerprint()),
+            turn_duration,
+            state: state.serializable(),
+        };
+        storage::write_versioned(&mut out, binary, &header)
+            .chain_err(|| "writing replay header")?;
+        out.flush()?;
+        Ok(ReplayWriter { out, binary })
+    }
+
+    /// Record `turn`, which follows the last turn recorded.
+    pub fn record(&mut self, turn: &CollectedActions) -> Result<()> {
+        storage::write_next(&mut self.out, self.binary, turn)
+            .chain_err(|| "writing replay")?;
+        self.out.flush()
+            .chain_err(|| "writing replay")
+    }
+}
+
+/// A recorded game, read back in.
+pub struct Replay {
+    /// The state the recording starts from.
+    pub state: SerializableState,
+
+    /// The time between turns when recording began.
+    pub turn_duration: Duration,
+
+    /// The turns played from `state` on, oldest first.
+    pub turns: Vec<CollectedActions>,
+}
+
+impl Replay {
+    /// Load the replay in the file at `path`.
+    pub fn load(path: &Path) -> Result<Replay> {
+        let input = Input::open(path)
+            .chain_err(|| format!("opening replay {}", path.display()))?;
+        Replay::read(input)
+            .chain_err(|| format!("reading replay {}", path.display()))
+    }
+
+    /// Write this replay to the file at `path`, in `format`.
+    pub fn save(&self, path: &Path, format: Format) -> Result<()> {
+        let state = State::from_serializable(self.state.clone());
+        let mut writer = ReplayWriter::create(path, format, &state, self.turn_duration)?;
+        for turn in &self.turns {
+            writer.record(turn)
+                .chain_err(|| format!("writing replay {}", path.display()))?;
+        }
+        writer.out.finish()
+            .chain_err(|| format!("writing replay {}", path.display()))
+    }
+
+    /// Read a replay from `input`. A compressed replay cut off by the game
+    /// exiting abruptly ends with the last turn written in full.
+    fn read(mut input: Input) -> Result<Replay> {
+        let header: Header = input.read_versioned("replay")?;
+        if let Some(fingerprint) = header.fingerprint {
+            header.state.check_fingerprint(fingerprint, "replay")?;
+        }
+        let mut turns = vec![];
+        if input.binary {
+            while !input.at_end()? {
+                let turn = input.read_next()
+                    .chain_err(|| format!("turn {}: malformed", turns.len() + 1))?;
+                turns.push(turn);
+            }
+        } else {
+            // The header's line counts as line 1.
+            for (number, line) in input.reader.lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(error) => return Err(error.into()),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let turn = serde_json::from_str(&line)
+                    .chain_err(|| format!("line {}: malformed turn", number + 1))?;
+                turns.push(turn);
+            }
+        }
+        Ok(Replay { state: header.state, turn_duration: header.turn_duration, turns })
+    }
+}
+
+/// How often, in turns, the viewer keeps a copy of the state to seek back to.
+const KEYFRAME_TURNS: usize = 300;
+
+/// Plays back a replay, able to jump to any turn in it.
+///
+/// As play proceeds, we keep a copy of the state every `KEYFRAME_TURNS` turns.
+/// To go back, we start from the latest copy at or before the turn we want,
+/// and play forward from there, so jumping anywhere takes at most
+/// `KEYFRAME_TURNS` turns' work once we've been past it.
+pub struct Viewer {
+    turns: Vec<CollectedActions>,
+
+    /// The states after every `KEYFRAME_TURNS` turns of the replay, starting
+    /// with the initial state, and the time between turns as of each.
+    keyframes: Vec<(State, Duration)>,
+
+    /// The state after the first `position` turns of the replay.
+    state: State,
+    position: usize,
+
+    /// The time between turns as of `position`.
+    turn_duration: Duration,
+
+    /// True if play is paused.
+    paused: bool,
+}
+
+impl Viewer {
+    pub fn new(replay: Replay) -> Viewer {
+        let state = State::from_serializable(replay.state);
+        Viewer {
+            turns: replay.turns,
+            keyframes: vec![(state.clone(), replay.turn_duration)],
+            state,
+            position: 0,
+            turn_duration: replay.turn_duration,
+            paused: false,
+        }
+    }
+
+    /// Return the state as of the current turn.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Return the time between turns as of the current turn.
+    pub fn turn_duration(&self) -> Duration {
+        self.turn_duration
+    }
+
+    /// Return the first and last turns of the replay.
+    pub fn turns(&self) -> (usize, usize) {
+        let first = self.keyframes[0].0.turn;
+        (first, first + self.turns.len())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause play if it's going, or resume it if it's paused.
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Play the next turn, unless we're paused or at the end. Return true if
+    /// we did.
+    pub fn play(&mut self) -> bool {
+        !self.paused && self.step()
+    }
+
+    /// Apply the next turn, keeping a keyframe if it's time. Return false if
+    /// there are no more.
+    fn step(&mut self) -> bool {
+        let turn = match self.turns.get(self.position) {
+            Some(turn) => turn,
+            None => return false,
+        };
+        turn.apply_to(&mut self.state);
+        if let Some(turn_duration) = turn.turn_duration {
+            self.turn_duration = turn_duration;
+        }
+        self.position += 1;
+        if self.position == self.keyframes.len() * KEYFRAME_TURNS {
+            self.keyframes.push((self.state.clone(), self.turn_duration));
+        }
+        true
+    }
+
+    /// Jump to `turn`, or as close as the replay goes.
+    pub fn seek(&mut self, turn: usize) {
+        let (first, last) = self.turns();
+        let position = turn.clamp(first, last) - first;
+        if position < self.position {
+            let index = (position / KEYFRAME_TURNS).min(self.keyframes.len() - 1);
+            let (ref state, turn_duration) = self.keyframes[index];
+            self.state = state.clone();
+            self.turn_duration = turn_duration;
+            self.position = index * KEYFRAME_TURNS;
+        }
+        while self.position < position {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::{Action, Player};
+    use zstd;
+
+    use std::io::Cursor;
+
+    /// Record `count` turns of a game to `out`, starting from `state`, and
+    /// return the checksum of the state after each turn.
+    fn record_to<W: Write>(out: W, binary: bool, state: &mut State, count: usize) -> Vec<u64> {
+        let mut checksums = vec![];
+        {
+            let mut writer = ReplayWriter::new(out, binary, state, Duration::from_millis(20))
+                .unwrap();
+            let mut actions = vec![Action::Join { player: Player(0) },
+                                   Action::Join { player: Player(1) }];
+            for i in 0..count {
+                for action in &actions {
+                    state.take_action(action);
+                }
+                state.advance();
+                let turn = CollectedActions { turn: state.turn, actions,
+                                              state_checksum: state.checksum(),
+                                              turn_duration: None };
+                writer.record(&turn).unwrap();
+                checksums.push(state.checksum());
+
+                // Toggle an outflow back and forth, so the board keeps
+                // changing.
+                actions = if i % 7 == 0 {
+                    vec![Action::ToggleOutflow { player: Player(0), from: 0, to: 1 }]
+                } else {
+                    vec![]
+                };
+            }
+        }
+        checksums
+    }
+
+    /// Record `count` turns of a game as JSON, starting from `state`, and
+    /// return the recording and the checksum of the state after each turn.
+    fn record(state: &mut State, count: usize) -> (Vec<u8>, Vec<u64>) {
+        let mut out = vec![];
+        let checksums = record_to(&mut out, false, state, count);
+        (out, checksums)
+    }
+
+    fn read(recording: &[u8]) -> Result<Replay> {
+        Replay::read(Input::detect(Cursor::new(recording.to_vec()))?)
+    }
+
+    fn state() -> State {
+        State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, Default::default())
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut state = state();
+        let start = state.clone();
+        let (out, _) = record(&mut state, 10);
+
+        // Playing the turns back from the recorded state gets us to the same
+        // place; `apply_to` checks each turn's checksum along the way.
+        let replay = read(&out).unwrap();
+        assert_eq!(replay.turn_duration, Duration::from_millis(20));
+        assert_eq!(replay.turns.len(), 10);
+        let mut replayed = State::from_serializable(replay.state);
+        assert_eq!(replayed.checksum(), start.checksum());
+        for turn in &replay.turns {
+            turn.apply_to(&mut replayed);
+        }
+        assert_eq!(replayed.checksum(), state.checksum());
+
+        assert!(read(b"").is_err());
+        assert!(read(&out[..out.len() - 5]).is_err());
+    }
+
+    #[test]
+    fn binary_and_compressed() {
+        let mut state = state();
+        let start = state.clone();
+        let mut out = storage::BINARY_MAGIC.to_vec();
+        let checksums = record_to(&mut out, true, &mut state, 50);
+        let replay = read(&out).unwrap();
+        assert_eq!(replay.turns.len(), 50);
+        assert_eq!(replay.turns[49].state_checksum, checksums[49]);
+
+        // A compressed recording that was never finished, because the game
+        // exited abruptly, still has every turn written.
+        let mut state = start;
+        let mut encoder = zstd::Encoder::new(vec![], 0).unwrap();
+        record_to(&mut encoder, false, &mut state, 50);
+        let replay = read(encoder.get_ref()).unwrap();
+        assert_eq!(replay.turns.len(), 50);
+        assert_eq!(replay.turns[49].state_checksum, checksums[49]);
+    }
+
+    #[test]
+    fn seek() {
+        let mut state = state();
+        let start = state.checksum();
+        let (out, checksums) = record(&mut state, KEYFRAME_TURNS * 3 + 10);
+        let mut viewer = Viewer::new(read(&out).unwrap());
+        assert_eq!(viewer.turns(), (0, checksums.len()));
+
+        // Jump forward, back past several keyframes, forward again, and
+        // off either end.
+        for &turn in &[KEYFRAME_TURNS * 2 + 5, 17, KEYFRAME_TURNS, KEYFRAME_TURNS * 3 + 1,
+                       KEYFRAME_TURNS - 1, 100_000] {
+            viewer.seek(turn);
+            let turn = turn.min(checksums.len());
+            assert_eq!(viewer.state().turn, turn);
+            assert_eq!(viewer.state().checksum(), checksums[turn - 1]);
+        }
+        viewer.seek(0);
+        assert_eq!(viewer.state().checksum(), start);
+
+        // Play stops at the end, or while paused.
+        assert!(viewer.play());
+        viewer.toggle_pause();
+        assert!(!viewer.play());
+        viewer.toggle_pause();
+        viewer.seek(checksums.len());
+        assert!(!viewer.play());
+    }
+}
@@ -0,0 +1,309 @@
+//! Saving a game to disk, and resuming it later.
+//!
+//! A saved game is everything the scheduler needs to carry on: the state, the
+//! turn it's on, and who sits in each seat. Bots' seats come back with fresh
+//! bots. Human seats come back empty, waiting for their players: a client
+//! still running from before reconnects to its own seat, asking for it by
+//! player number and rejoin token as it would after any dropped connection,
+//! and anyone joining afresh takes the lowest empty one. The host takes the first.
+//!
+//! The scheduler's history and the game's statistics so far aren't saved, so
+//! a resumed game's final statistics only cover the part played since.
+//!
+//! Given `--autosave TURNS`, the host also saves the game every TURNS turns on
+//! its own, next to where Ctrl+S would, with the turn number added to the
+//! name: `rbattle-save.json` becomes `rbattle-save.turn-1200.json`. Only the
+//! latest few are kept. The scheduler hands each save to a thread of its own
+//! to write, so the game doesn't stall while it does.
+//!
+//! Autosaves hold the whole state, rather than just what has changed since the
+//! one before, the way a reconnecting client is brought up to date (see
+//! `state::StateDelta`): only the latest few are kept, so a save holding
+//! changes could easily outlive the one they're relative to.
+//!
+//! Saves are JSON, unless `--binary` or `--compress` say otherwise; see the
+//! `storage` module.
+//!
+//! For chasing bugs, F9 dumps just the state, as readable JSON, from any
+//! participant. `rbattle sandbox FILE` starts a game of one from such a dump,
+//! with the player in the first seat and everyone else's goop left to sit.
+
+use errors::*;
+use scheduler::RejoinToken;
+use state::{SerializableState, State};
+use storage::{self, Format, Input, Output};
+use version::Version;
+
+use serde_json;
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Where the host's Ctrl+S saves the game, unless `--save` says otherwise.
+pub const DEFAULT_PATH: &str = "rbattle-save.json";
+
+/// How many autosaves to keep, unless `--autosave-keep` says otherwise.
+pub const DEFAULT_AUTOSAVES: usize = 3;
+
+/// Where F9 dumps the state, unless `--dump-state` says otherwise.
+pub const DEFAULT_DUMP_PATH: &str = "rbattle-state.json";
+
+/// Who sits in a seat of a saved game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Seat {
+    Human,
+    Bot,
+}
+
+/// A game in progress, as written to disk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedGame {
+    /// The versions of rbattle the game was saved by.
+    pub version: Version,
+
+    /// The fingerprint of the board and rules the game is played by, which
+    /// `load` checks against `state`. Games saved before there were
+    /// fingerprints lack this.
+    #[serde(default)]
+    pub fingerprint: Option<u64>,
+
+    /// The number of the last turn played.
+    pub turn: usize,
+
+    /// The time between turns.
+    pub turn_duration: Duration,
+
+    /// Who sits in each seat taken, indexed by player number.
+    pub roster: Vec<Seat>,
+
+    /// The kind of each bot in `roster`, in seat order, as the bot registry
+    /// names it. Games saved before bots had difficulties lack this, and
+    /// their bots come back as the default kind, as do bots of any kind this
+    /// host doesn't know. Older saves call this `difficulties`; each
+    /// difficulty names a built-in kind.
+    #[serde(default, alias = "difficulties")]
+    pub bots: Vec<String>,
+
+    /// The token each seat's player must give to take it back, indexed by
+    /// player number. Seats that lack one, as in games saved before there
+    /// were tokens, get a fresh one, which only a newcomer can take.
+    #[serde(default)]
+    pub tokens: Vec<RejoinToken>,
+
+    /// The state as of `turn`.
+    pub state: SerializableState,
+}
+
+impl SavedGame {
+    /// Return a game of one, picking up from `state`, with a turn every
+    /// `turn_duration`.
+    pub fn sandbox(state: SerializableState, turn_duration: Duration) -> SavedGame {
+        let turn = State::from_serializable(state.clone()).turn;
+        SavedGame { version: Version::current(), fingerprint: Some(state.fingerprint()), turn,
+                    turn_duration, roster: vec![Seat::Human], bots: vec![], tokens: vec![],
+                    state }
+    }
+
+    /// Read a saved game from the file at `path`, in whatever format it was
+    /// written.
+    pub fn load(path: &Path) -> Result<SavedGame> {
+        let mut input = Input::open(path)
+            .chain_err(|| format!("opening saved game {}", path.display()))?;
+        let what = format!("saved game {}", path.display());
+        let game: SavedGame = input.read_versioned(&what)?;
+        if let Some(fingerprint) = game.fingerprint {
+            game.state.check_fingerprint(fingerprint, &what)?;
+        }
+        Ok(game)
+    }
+
+    /// Write this game to the file at `path`, in `format`.
+    pub fn save(&self, path: &Path, format: Format) -> Result<()> {
+        let mut out = Output::create(path, format)
+            .chain_err(|| format!("creating saved game {}", path.display()))?;
+        storage::write_versioned(&mut out, format.binary, self)
+            .and_then(|()| Ok(out.finish()?))
+            .chain_err(|| format!("writing saved game {}", path.display()))
+    }
+}
+
+/// Saves the game every so often, keeping only the latest few saves.
+pub struct Autosave {
+    /// How many turns apart to save the game.
+    every: usize,
+
+    /// Where to send games for the writing thread to save.
+    sender: mpsc::Sender<SavedGame>,
+}
+
+impl Autosave {
+    /// Start saving the game every `every` turns, next to `path`, in `format`,
+    /// keeping the latest `keep` saves.
+    pub fn start(path: PathBuf, format: Format, every: usize, keep: usize) -> Autosave {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut written = VecDeque::new();
+            for game in receiver {
+                if let Err(error) = rotate(&path, format, &game, &mut written, keep) {
+                    eprintln!("rbattle: couldn't autosave: {}", error);
+                }
+            }
+        });
+        Autosave { every: every.max(1), sender }
+    }
+
+    /// Return true if it's time to save the game as of `turn`.
+    pub fn due(&self, turn: usize) -> bool {
+        turn.is_multiple_of(self.every)
+    }
+
+    /// Save `game`, without waiting for it to be written.
+    pub fn save(&self, game: SavedGame) {
+        // If the writing thread has died, it has already said why.
+        let _ = self.sender.send(game);
+    }
+}
+
+/// Return where to autosave the game as of `turn`, given the path Ctrl+S
+/// saves to.
+fn autosave_path(path: &Path, turn: usize) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path.extension().map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "json".to_string());
+    path.with_file_name(format!("{}.turn-{}.{}", stem, turn, extension))
+}
+
+/// Autosave `game` next to `path`, in `format`, and delete the oldest of the
+/// saves `written` so far, to keep only `keep`. Write to a temporary file
+/// first, so a crash partway through never leaves half a save behind.
+fn rotate(path: &Path, format: Format, game: &SavedGame, written: &mut VecDeque<PathBuf>,
+          keep: usize)
+          -> Result<()>
+{
+    let target = autosave_path(path, game.turn);
+    let mut temporary = target.clone().into_os_string();
+    temporary.push(".tmp");
+    game.save(Path::new(&temporary), format)?;
+    fs::rename(&temporary, &target)
+        .chain_err(|| format!("renaming autosave to {}", target.display()))?;
+    written.push_back(target);
+    while written.len() > keep {
+        if let Some(oldest) = written.pop_front() {
+            // It's only a stale save; if it's already gone, so much the better.
+            let _ = fs::remove_file(oldest);
+        }
+    }
+    Ok(())
+}
+
+/// Write `state` to the file at `path`, as pretty-printed JSON.
+pub fn dump_state(state: &State, path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .chain_err(|| format!("creating state dump {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &state.serializable())
+        .chain_err(|| format!("writing state dump {}", path.display()))
+}
+
+/// Read a state dumped by `dump_state` from the file at `path`.
+pub fn load_state(path: &Path) -> Result<SerializableState> {
+    let mut input = Input::open(path)
+        .chain_err(|| format!("opening state dump {}", path.display()))?;
+    input.read_versioned(&format!("state dump {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::{Action, Player};
+
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn dump_and_sandbox() {
+        let mut state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, Default::default());
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        for _ in 0..20 {
+            state.advance();
+        }
+
+        let path = env::temp_dir().join(format!("rbattle-dump-{}.json", ::std::process::id()));
+        dump_state(&state, &path).unwrap();
+        let loaded = load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(State::from_serializable(loaded.clone()).checksum(), state.checksum());
+
+        let sandbox = SavedGame::sandbox(loaded, Duration::from_millis(16));
+        assert_eq!(sandbox.turn, 20);
+        assert_eq!(sandbox.roster, vec![Seat::Human]);
+
+        assert!(load_state(&path).is_err());
+    }
+
+    #[test]
+    fn autosaves() {
+        let dir = env::temp_dir().join(format!("rbattle-autosave-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.json");
+        let state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0],
+            player_colors: vec![(0xff, 0, 0)],
+        }, Default::default());
+        let mut game = SavedGame::sandbox(state.serializable(), Duration::from_millis(16));
+
+        // Only the latest two saves are kept.
+        let mut written = VecDeque::new();
+        for &turn in &[100, 200, 300] {
+            game.turn = turn;
+            rotate(&path, Format::default(), &game, &mut written, 2).unwrap();
+        }
+        let mut names: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["game.turn-200.json", "game.turn-300.json"]);
+        assert_eq!(SavedGame::load(&dir.join("game.turn-300.json")).unwrap().turn, 300);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fingerprints() {
+        let path = env::temp_dir().join(format!("rbattle-fingerprint-{}.json",
+                                                ::std::process::id()));
+        let state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, Default::default());
+        let mut game = SavedGame::sandbox(state.serializable(), Duration::from_millis(16));
+        assert_eq!(game.fingerprint, Some(state.fingerprint()));
+
+        // A game whose board isn't what its fingerprint says is refused.
+        game.fingerprint = Some(state.fingerprint() ^ 1);
+        game.save(&path, Format::default()).unwrap();
+        let error = SavedGame::load(&path).err().unwrap();
+        assert!(error.to_string().contains("fingerprint"));
+
+        // A game saved before there were fingerprints is taken on trust.
+        game.fingerprint = None;
+        game.save(&path, Format::default()).unwrap();
+        assert!(SavedGame::load(&path).is_ok());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
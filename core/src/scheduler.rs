@@ -0,0 +1,1757 @@
+//! Scheduling game play.
+
+use bot::{self, Bot, Registry};
+use errors;
+use map::Map;
+use save::{Autosave, SavedGame, Seat};
+use state::Player;
+use state::{Action, Phase, SerializableState, State, StateDelta};
+use timestep::FixedTimestep;
+use timing::TimingLog;
+use version::Version;
+
+use rand;
+
+use std::collections::VecDeque;
+use std::mem::take;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time between turns, unless the host says otherwise.
+pub const DEFAULT_TURN_DURATION: Duration = Duration::from_millis(16);
+
+/// The fastest the host can make the game go.
+const MIN_TURN_DURATION: Duration = Duration::from_millis(1);
+
+/// The number of past turns the scheduler retains in its history. At 60 turns
+/// per second, this is a little over five minutes of play.
+const HISTORY_TURNS: usize = 20_000;
+
+/// How often the scheduler saves a copy of the state, to give to players who
+/// join later. Newcomers get the latest copy and the turns since, which must
+/// fit in the history.
+const CHECKPOINT_TURNS: usize = 600;
+
+/// How many of the latest checkpoints the scheduler keeps whole, to bring
+/// participants that kept one of their own up to date with a `StateDelta`.
+/// At 60 turns per second, this goes back a little under three minutes.
+const REFERENCE_CHECKPOINTS: usize = 16;
+
+/// The largest number of turns `Scheduler::turn_range` will return at once.
+/// Callers wanting more must ask again, starting where the last batch ended.
+pub const MAX_TURN_RANGE: usize = 1_000;
+
+/// A `Scheduler` collects actions from all players, and then broadcasts the
+/// full list when it's time to play the next turn.
+///
+/// When a player submits their moves, they provide a `Notifier` which
+/// `Scheduler` should send the full move list once it is available.
+pub struct Scheduler {
+    /// The number of the last turn we broadcast out.
+    turn: usize,
+
+    /// A scheduler actually maintains its own copy of the game state, for
+    /// generating checksums to send to clients.
+    state: State,
+
+    /// The actions each joined player has submitted for the next turn; the
+    /// `i`'th element is for `Player(i)`. When the next turn is played, we
+    /// apply all the actions to our state in a given order, compute the new
+    /// state's checksum, and then transmit the collected moves to all the
+    /// players.
+    pending_actions: Vec<Vec<Action>>,
+
+    /// The players waiting to hear about the next turn.
+    waiting: Vec<Box<dyn Notifier + Send>>,
+
+    /// Actions the scheduler itself has generated, like players joining, to be
+    /// included ahead of the players' actions in the next turn.
+    injected_actions: Vec<Action>,
+
+    /// How often we play turns.
+    pacing: Pacing,
+
+    /// How well we've been keeping to our schedule.
+    timing: TimingLog,
+
+    /// The most recent turns we've broadcast, oldest first. This holds at most
+    /// HISTORY_TURNS entries.
+    history: VecDeque<CollectedActions>,
+
+    /// A copy of the state as of some recent turn that is a multiple of
+    /// CHECKPOINT_TURNS, and the number of that turn. Rather than serializing
+    /// the state afresh for each newcomer, we send them this, along with the
+    /// turns since from our history.
+    checkpoint: (usize, SerializableState),
+
+    /// The states as of the latest few checkpoints, oldest first, the last
+    /// being the one in `checkpoint`. This holds at most REFERENCE_CHECKPOINTS
+    /// entries.
+    references: VecDeque<State>,
+
+    /// Parties interested in every turn, regardless of whether they play.
+    observers: Vec<Box<dyn Observer + Send>>,
+
+    /// The most nodes each player has held at once during the game proper,
+    /// indexed by player number.
+    peak_nodes: Vec<usize>,
+
+    /// What each player has done during the game proper, indexed by player
+    /// number.
+    player_stats: Vec<PlayerStats>,
+
+    /// How long the game proper has lasted, going by the time between turns.
+    played: Duration,
+
+    /// Samples of how each player has fared over the game proper.
+    timeline: Timeline,
+
+    /// Once the game has been won, the winner and the game's statistics. We
+    /// answer all further submissions with this.
+    result: Option<(Player, GameStats)>,
+
+    /// Whether each joined player is still connected, indexed by player
+    /// number.
+    connected: Vec<bool>,
+
+    /// The token each seat's player must give to take it back after losing
+    /// their connection, indexed by player number.
+    tokens: Vec<RejoinToken>,
+
+    /// The latest turn on which each player has reported their state
+    /// diverging from ours, indexed by player number.
+    divergences: Vec<Option<usize>>,
+
+    /// If set, how long to pause the game to let a player who has lost their
+    /// connection come back. If `None`, we carry on without them.
+    pause_on_drop: Option<Duration>,
+
+    /// The players we've paused the game for, and how long we'll wait for
+    /// each of them. No turns are played while this is non-empty.
+    pauses: Vec<(Player, Instant)>,
+
+    /// The computer-controlled players. Their actions are generated here, at
+    /// the start of each turn.
+    bots: Vec<SeatedBot>,
+
+    /// In a resumed game, the human players' seats no one has taken back yet,
+    /// lowest first. Newcomers get these before any fresh seat.
+    vacant: Vec<Player>,
+
+    /// If set, how to save the game every so often.
+    autosave: Option<Autosave>,
+
+    /// If set, the registry to make bots with, and the kind of bot to seat in
+    /// every seat still empty when the game proper begins.
+    fill_seats: Option<(Arc<Registry>, String)>,
+}
+
+/// A computer-controlled player in its seat.
+struct SeatedBot {
+    player: Player,
+
+    /// The name the registry gives this kind of bot, for saving the game.
+    kind: String,
+
+    bot: Box<dyn Bot>,
+}
+
+/// Statistics about a finished game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameStats {
+    /// The number of turns the game proper lasted, not counting warm-up.
+    pub turns: usize,
+
+    /// How long the game proper lasted, going by the time between turns
+    /// rather than the clock, so every host that plays the game over again
+    /// gets the same figure.
+    #[serde(default)]
+    pub duration: Duration,
+
+    /// The most nodes each player held at once, indexed by player number.
+    pub peak_nodes: Vec<usize>,
+
+    /// What each player did over the game proper, indexed by player number.
+    #[serde(default)]
+    pub players: Vec<PlayerStats>,
+
+    /// How each player fared over the course of the game, sampled at evenly
+    /// spaced turns, oldest first. The last sample is the final position.
+    pub samples: Vec<Sample>,
+}
+
+impl GameStats {
+    /// Return how many actions `player` took per minute of the game proper.
+    pub fn actions_per_minute(&self, player: Player) -> f64 {
+        let minutes = self.duration.as_secs_f64() / 60.0;
+        match self.players.get(player.0) {
+            Some(stats) if minutes > 0.0 => stats.actions as f64 / minutes,
+            _ => 0.0,
+        }
+    }
+}
+
+/// What one player did over the game proper. These are counted from the
+/// actions and the state alone, so they come out the same however the game
+/// is played back.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    /// The number of actions the player took, of any kind.
+    pub actions: usize,
+
+    /// The number of outflows the player toggled.
+    pub toggles: usize,
+
+    /// The number of nodes the player took from other players.
+    pub captures: usize,
+}
+
+/// Each player's holdings as of some turn of the game proper.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// The turn of the game proper, counting from zero at its start.
+    pub turn: usize,
+
+    /// The number of nodes each player held, indexed by player number.
+    pub nodes: Vec<usize>,
+
+    /// The total goop each player held, indexed by player number.
+    pub goop: Vec<usize>,
+}
+
+/// Something that can notify a player of a turn's actions when they have been
+/// collected.
+pub trait Notifier {
+    fn notify(self: Box<Self>, turn: CollectedActions);
+
+    /// Send the player several turns they missed at once, oldest first, so
+    /// they can catch up in a single step.
+    fn catch_up(self: Box<Self>, turns: Vec<CollectedActions>);
+
+    /// Tell the player that `winner` has won the game, instead of sending
+    /// another turn.
+    fn game_over(self: Box<Self>, winner: Player, stats: GameStats);
+
+    /// Tell the player that the game has paused for up to `timeout` to let
+    /// `player` reconnect. The next turn will come once it resumes.
+    fn paused(self: Box<Self>, player: Player, timeout: Duration);
+}
+
+/// Something that wants to see every turn as it is played, along with the
+/// resulting state.
+pub trait Observer {
+    fn observe(&mut self, turn: &CollectedActions, state: &State);
+}
+
+impl Scheduler {
+    pub fn new(initial_state: State, turn_duration: Duration) -> Scheduler {
+        let peak_nodes = vec![0; initial_state.max_players()];
+        let player_stats = vec![PlayerStats::default(); initial_state.max_players()];
+        let divergences = vec![None; initial_state.max_players()];
+        let checkpoint = (initial_state.turn, initial_state.serializable());
+        let references = VecDeque::from(vec![initial_state.clone()]);
+        Scheduler { turn: 0, state: initial_state, pending_actions: vec![],
+                    injected_actions: vec![],
+                    waiting: vec![],
+                    pacing: Pacing::new(turn_duration),
+                    timing: TimingLog::new(),
+                    history: VecDeque::new(),
+                    checkpoint,
+                    references,
+                    observers: vec![],
+                    peak_nodes,
+                    player_stats,
+                    played: Duration::from_secs(0),
+                    timeline: Timeline::new(),
+                    result: None,
+                    connected: vec![],
+                    tokens: vec![],
+                    divergences,
+                    pause_on_drop: None,
+                    pauses: vec![],
+                    bots: vec![],
+                    vacant: vec![],
+                    autosave: None,
+                    fill_seats: None,
+        }
+    }
+
+    /// Pick up the game `saved` where it left off. Bots take their seats right
+    /// away, made by `registry`; human players' seats wait for them to come
+    /// back.
+    pub fn resume(saved: SavedGame, registry: &Registry) -> Scheduler {
+        let mut scheduler = Scheduler::new(State::from_serializable(saved.state),
+                                           saved.turn_duration);
+        scheduler.turn = saved.turn;
+        let mut kinds = saved.bots.into_iter();
+        for (index, seat) in saved.roster.into_iter().enumerate() {
+            let player = Player(index);
+            scheduler.pending_actions.push(vec![]);
+            scheduler.tokens.push(saved.tokens.get(index).cloned()
+                                  .unwrap_or_else(RejoinToken::generate));
+            match seat {
+                Seat::Bot => {
+                    scheduler.connected.push(true);
+                    let kind = kinds.next()
+                        .filter(|kind| registry.contains(kind))
+                        .unwrap_or_else(|| bot::DEFAULT_KIND.to_string());
+                    let bot = registry.create(&kind, player, 0)
+                        .expect("the default kind of bot should always be registered");
+                    scheduler.bots.push(SeatedBot { player, kind, bot });
+                }
+                Seat::Human => {
+                    scheduler.connected.push(false);
+                    scheduler.vacant.push(player);
+                }
+            }
+        }
+        scheduler
+    }
+
+    /// Return this game as it stands, to be saved and resumed later.
+    pub fn save(&self) -> SavedGame {
+        let roster = (0..self.pending_actions.len())
+            .map(|index| {
+                if self.bots.iter().any(|bot| bot.player.0 == index) {
+                    Seat::Bot
+                } else {
+                    Seat::Human
+                }
+            })
+            .collect();
+        let mut bots: Vec<&SeatedBot> = self.bots.iter().collect();
+        bots.sort_by_key(|bot| bot.player.0);
+        SavedGame {
+            version: Version::current(),
+            fingerprint: Some(self.state.fingerprint()),
+            turn: self.turn,
+            turn_duration: self.pacing.base,
+            roster,
+            bots: bots.iter().map(|bot| bot.kind.clone()).collect(),
+            tokens: self.tokens.clone(),
+            state: self.state.serializable(),
+        }
+    }
+
+    /// Save the game as `autosave` says, from now on.
+    pub fn set_autosave(&mut self, autosave: Autosave) {
+        self.autosave = Some(autosave);
+    }
+
+    /// When the game proper begins, seat a bot of the kind `registry` calls
+    /// `kind` in every seat no one has taken, rather than leaving their
+    /// sources to sit. With warm-up, the bots join on its last turn, so they
+    /// take their sources when the board is set up; without it, the game
+    /// begins right away, so only the host is there to keep their seat. Each
+    /// rematch fills the seats again.
+    pub fn set_fill_seats(&mut self, registry: Arc<Registry>, kind: &str) {
+        self.fill_seats = Some((registry, kind.to_string()));
+    }
+
+    /// Show `observer` every turn from now on.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer + Send>) {
+        self.observers.push(observer);
+    }
+
+    /// Return the collected actions for the turns in `range`, as far as our
+    /// history allows. Each `CollectedActions` in the result produces the turn
+    /// whose number it carries, so asking for `1..3` returns the actions that
+    /// produced turns 1 and 2.
+    ///
+    /// At most `MAX_TURN_RANGE` turns are returned, and the range is clipped
+    /// at the most recent turn. If the range starts before the oldest turn we
+    /// still remember, or is empty, return an error holding the range of turns
+    /// we could provide.
+    pub fn turn_range(&self, range: Range<usize>)
+                      -> Result<Vec<CollectedActions>, Range<usize>>
+    {
+        let available = match self.history.front() {
+            Some(oldest) => oldest.turn .. self.turn + 1,
+            None => self.turn + 1 .. self.turn + 1,
+        };
+
+        if range.start < available.start || range.start >= available.end ||
+            range.end <= range.start
+        {
+            return Err(available);
+        }
+
+        let skip = range.start - available.start;
+        let count = (range.end - range.start).min(MAX_TURN_RANGE);
+        Ok(self.history.iter().skip(skip).take(count).cloned().collect())
+    }
+
+    /// Return the time between turns.
+    pub fn turn_duration(&self) -> Duration { self.pacing.current }
+
+    /// Return the map the game is played on.
+    pub fn map(&self) -> &Map { &self.state.map }
+
+    /// Play a turn every `turn_duration` from now on. In adaptive mode, this
+    /// is the fastest the game will go.
+    pub fn set_turn_duration(&mut self, turn_duration: Duration) {
+        let turn_duration = turn_duration.max(MIN_TURN_DURATION);
+        self.pacing.base = turn_duration;
+        self.pacing.change = Some(turn_duration);
+    }
+
+    /// Turn adaptive pacing on or off. In adaptive mode, the scheduler slows
+    /// the game down when players keep submitting their actions late, and
+    /// speeds it back up when they keep up.
+    pub fn set_adaptive(&mut self, adaptive: bool) {
+        self.pacing.adaptive = adaptive;
+        if !adaptive {
+            self.pacing.change = Some(self.pacing.base);
+        }
+    }
+
+    /// Return the turns played after `turn`, oldest first, if they're all
+    /// still in our history.
+    pub fn turns_since(&self, turn: usize) -> Option<Vec<CollectedActions>> {
+        if turn == self.turn {
+            return Some(vec![]);
+        }
+        // Check `turn` against ours first: it may come from a peer, and be
+        // too large to add to.
+        let oldest = self.history.front()?.turn;
+        if turn > self.turn || turn + 1 < oldest {
+            return None;
+        }
+        Some(self.history.iter().skip(turn + 1 - oldest).cloned().collect())
+    }
+
+    /// Return a representation of the current game state.
+    pub fn snapshot(&self) -> Snapshot {
+        let (turn, ref state) = self.checkpoint;
+        Snapshot {
+            state: state.clone(),
+            turns: self.turns_since(turn).expect("checkpoint older than history"),
+        }
+    }
+
+    /// Return a representation of the current game state for a participant
+    /// that has kept its state as of the checkpoint at turn `reference`, if
+    /// we still have that checkpoint too.
+    pub fn delta_snapshot(&self, reference: usize) -> Option<DeltaSnapshot> {
+        let base = self.references.iter().find(|state| state.turn == reference)?;
+        let latest = self.references.back().expect("scheduler has no checkpoints");
+        Some(DeltaSnapshot {
+            delta: latest.delta_since(base),
+            turns: self.turns_since(latest.turn).expect("checkpoint older than history"),
+        })
+    }
+
+    /// Return what a participant whose state is at `turn`, and which has kept
+    /// its state as of the checkpoint at `reference`, needs to catch up: the
+    /// turns since, if we have them and they're no more than a checkpoint
+    /// interval's worth; failing that, a `DeltaSnapshot`, if we still have
+    /// that checkpoint; failing that, the turns since, however many. Return
+    /// `None` if we have neither.
+    pub fn catch_up(&self, turn: usize, reference: usize) -> Option<CatchUp> {
+        let turns = self.turns_since(turn);
+        match turns {
+            Some(ref turns) if turns.len() <= CHECKPOINT_TURNS => {}
+            _ => if let Some(snapshot) = self.delta_snapshot(reference) {
+                return Some(CatchUp::Delta(snapshot));
+            }
+        }
+        turns.map(CatchUp::Turns)
+    }
+
+    // Add another player to the game. If there is room, return the player's
+    // number and a representation of the current game state. Return `None` if
+    // there is no room for more players.
+    //
+    // Players may join at any time. The new player's source is occupied by an
+    // `Action::Join` in the next turn, so that every host sees it happen at
+    // the same point in the game. In a resumed game, newcomers take the seats
+    // left vacant first, whose sources are occupied already.
+    pub fn player_join(&mut self) -> Option<(Player, Snapshot)> {
+        let player = if self.vacant.is_empty() {
+            self.take_seat()?
+        } else {
+            // The seat is someone else's now; its old token mustn't get it
+            // back.
+            let player = self.vacant.remove(0);
+            self.connected[player.0] = true;
+            self.tokens[player.0] = RejoinToken::generate();
+            player
+        };
+        Some((player, self.snapshot()))
+    }
+
+    /// Fill the next free seat with a bot of the kind `registry` calls
+    /// `kind`. Return the bot's player number, or `None` if there is no room
+    /// or no such kind of bot.
+    pub fn add_bot(&mut self, registry: &Registry, kind: &str) -> Option<Player> {
+        if !registry.contains(kind) {
+            return None;
+        }
+        let player = self.take_seat()?;
+        let bot = registry.create(kind, player, 0)?;
+        self.bots.push(SeatedBot { player, kind: kind.to_string(), bot });
+        Some(player)
+    }
+
+    /// Assign the next free seat to a new player, and arrange for them to join
+    /// the game on the next turn.
+    fn take_seat(&mut self) -> Option<Player> {
+        if self.pending_actions.len() >= self.state.max_players() {
+            return None;
+        }
+        let player = Player(self.pending_actions.len());
+        self.pending_actions.push(vec![]);
+        self.connected.push(true);
+        self.tokens.push(RejoinToken::generate());
+        self.injected_actions.push(Action::Join { player });
+        Some(player)
+    }
+
+    /// Pause the game for up to `timeout` whenever a player loses their
+    /// connection, to give them a chance to rejoin. If `timeout` is `None`,
+    /// just carry on without them.
+    pub fn set_pause_on_drop(&mut self, timeout: Option<Duration>) {
+        self.pause_on_drop = timeout;
+    }
+
+    /// Note that `player` has lost their connection. If we're pausing for
+    /// dropped players, tell everyone waiting for a turn.
+    pub fn player_dropped(&mut self, player: Player) {
+        self.connected[player.0] = false;
+        let timeout = match self.pause_on_drop {
+            Some(timeout) if self.result.is_none() => timeout,
+            _ => return,
+        };
+
+        self.pauses.push((player, Instant::now() + timeout));
+        for reply_to in take(&mut self.waiting) {
+            reply_to.paused(player, timeout);
+        }
+    }
+
+    /// Return the token `player` must give `player_rejoin` to take their seat
+    /// back, or `None` if no one has taken that seat.
+    pub fn rejoin_token(&self, player: Player) -> Option<RejoinToken> {
+        self.tokens.get(player.0).cloned()
+    }
+
+    /// Let `player`, who lost their connection, back into the game, if
+    /// `token` is the one their seat was given. Return false if `player`
+    /// isn't someone who has dropped out, or whose seat in a resumed game is
+    /// waiting for them, or if `token` is wrong.
+    pub fn player_rejoin(&mut self, player: Player, token: RejoinToken) -> bool {
+        if self.connected.get(player.0) != Some(&false) || self.tokens[player.0] != token {
+            return false;
+        }
+        self.connected[player.0] = true;
+        self.vacant.retain(|&vacant| vacant != player);
+        self.pauses.retain(|&(paused_for, _)| paused_for != player);
+        true
+    }
+
+    // Submit `actions` to be carried out in the next turn. When that turn is
+    // played, send it to `reply_to`. If the game is over, tell `reply_to` the
+    // result right away instead.
+    //
+    // If the player is behind, and `actions.turn` has already been played, we
+    // send `reply_to` every turn since from our history immediately, so that
+    // the player can catch up.
+    //
+    // Return an error if `actions` is for a turn that hasn't been played yet,
+    // or from a player who hasn't taken a seat, or if any of the actions is
+    // one only the scheduler may take, is taken on someone else's behalf, or
+    // names a node not on the board. None of these can come from a
+    // well-behaved participant.
+    pub fn submit_actions(&mut self,
+                          actions: PlayerActions,
+                          reply_to: Box<dyn Notifier + Send>) -> errors::Result<()> {
+        if let Some((winner, ref stats)) = self.result {
+            reply_to.game_over(winner, stats.clone());
+            return Ok(());
+        }
+
+        let PlayerActions { player, turn, actions } = actions;
+        if turn > self.turn {
+            return Err(format!("player {} submitted actions for future turn {}",
+                               player.0, turn).into());
+        }
+        for action in &actions {
+            if action.taken_by() != Some(player) {
+                return Err(format!("player {} submitted {:?}, which isn't theirs to take",
+                                   player.0, action).into());
+            }
+            if action.nodes().iter().any(|&node| node >= self.state.nodes.len()) {
+                return Err(format!("player {} submitted {:?}, naming a node off the board",
+                                   player.0, action).into());
+            }
+        }
+        let pending = match self.pending_actions.get_mut(player.0) {
+            Some(pending) => pending,
+            None => return Err(format!("actions from unseated player {}", player.0).into()),
+        };
+        pending.extend(actions);
+
+        if turn == self.turn {
+            self.timing.submitted(player);
+        } else {
+            self.pacing.late = true;
+        }
+
+        self.watch(turn, reply_to);
+        Ok(())
+    }
+
+    // Send `reply_to` the turn after `turn`: immediately from our history if
+    // it's been played, or otherwise when it is. Spectators use this to follow
+    // the game without taking part.
+    pub fn watch(&mut self, turn: usize, reply_to: Box<dyn Notifier + Send>) {
+        if let Some((winner, ref stats)) = self.result {
+            reply_to.game_over(winner, stats.clone());
+            return;
+        }
+
+        if turn >= self.turn {
+            self.waiting.push(reply_to);
+            return;
+        }
+
+        // Send everything they've missed, rather than one turn per round
+        // trip; a player who has fallen several turns behind would never
+        // catch up otherwise. If the turn has fallen out of our history,
+        // there's nothing we can do for this player; dropping `reply_to` will
+        // disconnect them.
+        if let Ok(missed) = self.turn_range(turn + 1 .. self.turn + 1) {
+            reply_to.catch_up(missed);
+        }
+    }
+
+    // Play a turn: apply all the actions that have arrived since the last
+    // turn, and return the result to be sent to everyone waiting for it. The
+    // timer thread started by `start_timer` calls this on schedule, and sends
+    // the `Broadcast` once it has released the scheduler's lock.
+    //
+    // If the game is paused waiting for a player to reconnect, do nothing,
+    // unless we've run out of patience.
+    pub fn play_turn(&mut self) -> Broadcast {
+        let started = Instant::now();
+        self.pauses.retain(|&(_, deadline)| deadline > started);
+        if self.result.is_some() || !self.pauses.is_empty() {
+            return Broadcast { turn: None, recipients: vec![] };
+        }
+
+        let turn_duration = self.turn_duration();
+
+        if self.beginning() {
+            if let Some((registry, kind)) = self.fill_seats.clone() {
+                while self.add_bot(&registry, &kind).is_some() {}
+            }
+        }
+
+        // Bots are never late.
+        for bot in &mut self.bots {
+            self.pending_actions[bot.player.0].extend(bot.bot.act(&self.state, bot.player));
+            self.timing.submitted(bot.player);
+        }
+
+        // Collect all the actions into a single vector, and apply them to our
+        // state. Actions the scheduler generated itself go first, followed by
+        // each player's in order.
+        let mut collected_actions = take(&mut self.injected_actions);
+        for actions in &mut self.pending_actions {
+            collected_actions.append(actions);
+        }
+        for action in &collected_actions {
+            self.state.take_action(action);
+        }
+
+        // Note who held each node, to count captures. Warm-up doesn't count,
+        // nor does the board being put back the way it started when it ends.
+        let owners: Option<Vec<Option<Player>>> = match self.state.phase() {
+            Phase::Play { .. } => Some(self.state.nodes.iter()
+                                       .map(|node| node.as_ref().map(|occupied| occupied.player))
+                                       .collect()),
+            Phase::WarmUp { .. } => None,
+        };
+        self.state.advance();
+
+        let state_checksum = self.state.checksum();
+
+        // We are now in the new turn.
+        self.turn += 1;
+
+        let collected = CollectedActions {
+            turn: self.turn,
+            actions: collected_actions,
+            state_checksum,
+            turn_duration: self.pacing.after_turn(),
+        };
+
+        for observer in &mut self.observers {
+            observer.observe(&collected, &self.state);
+        }
+
+        if let Phase::Play { turns } = self.state.phase() {
+            for (peak, count) in self.peak_nodes.iter_mut().zip(self.state.node_counts()) {
+                *peak = (*peak).max(count);
+            }
+            self.played += turn_duration;
+            if let Some(owners) = owners {
+                tally(&mut self.player_stats, &collected.actions, &owners, &self.state);
+            }
+            let winner = self.state.winner();
+            self.timeline.record(turns, &self.state, winner.is_some());
+            if let Some(winner) = winner {
+                let stats = GameStats { turns, duration: self.played,
+                                        peak_nodes: self.peak_nodes.clone(),
+                                        players: self.player_stats.clone(),
+                                        samples: self.timeline.samples.clone() };
+                self.result = Some((winner, stats));
+            }
+        }
+
+        if self.history.len() >= HISTORY_TURNS {
+            self.history.pop_front();
+        }
+        self.history.push_back(collected.clone());
+
+        if is_checkpoint(self.turn) {
+            self.checkpoint = (self.turn, self.state.serializable());
+            if self.references.len() >= REFERENCE_CHECKPOINTS {
+                self.references.pop_front();
+            }
+            self.references.push_back(self.state.clone());
+        }
+
+        if let Some(ref autosave) = self.autosave {
+            if autosave.due(self.turn) {
+                autosave.save(self.save());
+            }
+        }
+
+        self.timing.played(self.turn, self.pending_actions.len(), started, turn_duration);
+
+        Broadcast { turn: Some(collected), recipients: take(&mut self.waiting) }
+    }
+
+    /// Return true if the next turn is the last before the game proper
+    /// begins: the last turn of warm-up, or, without warm-up, the first turn.
+    fn beginning(&self) -> bool {
+        match self.state.phase() {
+            Phase::WarmUp { turns_left } => turns_left == 1,
+            Phase::Play { turns } => turns == 0 && self.state.params.warmup_turns == 0,
+        }
+    }
+
+    /// Note that `player` says their state diverged from ours on `turn`.
+    /// Return true if that's worth writing down: `turn` has been played, and
+    /// `player` hasn't reported it, or any later turn, already. Each report
+    /// costs a snapshot on disk, so a client can't have us write more than one
+    /// per turn.
+    pub fn note_divergence(&mut self, player: Player, turn: usize) -> bool {
+        if turn > self.turn {
+            return false;
+        }
+        match self.divergences.get_mut(player.0) {
+            Some(latest) if latest.is_none_or(|latest| turn > latest) => {
+                *latest = Some(turn);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Return true if the game is over.
+    pub fn is_over(&self) -> bool { self.result.is_some() }
+
+    /// Start a new game with the same players, if the current one is over.
+    /// The new game begins with the next turn, which everyone waiting in
+    /// `await_rematch` receives.
+    pub fn rematch(&mut self) {
+        if self.result.take().is_none() {
+            return;
+        }
+        for peak in &mut self.peak_nodes {
+            *peak = 0;
+        }
+        for stats in &mut self.player_stats {
+            *stats = PlayerStats::default();
+        }
+        self.played = Duration::from_secs(0);
+        self.timeline = Timeline::new();
+        self.injected_actions.push(Action::Restart);
+    }
+
+    /// Send `reply_to` the first turn of the next game, once the host starts a
+    /// rematch. Players who've been told the game is over use this to stay at
+    /// the table. If a rematch has already begun, this is just like `watch`.
+    pub fn await_rematch(&mut self, turn: usize, reply_to: Box<dyn Notifier + Send>) {
+        if self.result.is_some() {
+            self.waiting.push(reply_to);
+        } else {
+            self.watch(turn, reply_to);
+        }
+    }
+
+    /// Return a record of how well we've kept to our schedule lately.
+    pub fn timing(&self) -> &TimingLog { &self.timing }
+}
+
+/// Start a thread that plays a turn of `scheduler`'s game every
+/// `turn_duration`. Once the game is over, the thread keeps time but plays
+/// nothing, in case the host starts a rematch.
+///
+/// Turns are played on schedule whether or not every player has submitted
+/// their actions: a slow player's actions simply land in a later turn, and
+/// they catch up by fetching the turns they missed from the history. This
+/// way, no player can hold up the game for everyone else.
+///
+/// If the thread wakes up late, it plays the turns it owes back to back, so
+/// the game keeps to its pace on average; see `timestep::FixedTimestep`.
+pub fn start_timer(scheduler: Arc<Mutex<Scheduler>>) {
+    thread::spawn(move || {
+        let turn_duration = scheduler.lock().unwrap().turn_duration();
+        let mut timestep = FixedTimestep::new(turn_duration, Instant::now());
+        loop {
+            let due = timestep.advance(Instant::now());
+            let mut guard = scheduler.lock().unwrap();
+            let mut broadcasts = vec![];
+            for _ in 0..due {
+                broadcasts.push(guard.play_turn());
+                if guard.is_over() {
+                    break;
+                }
+            }
+            timestep.set_step(guard.turn_duration());
+            drop(guard);
+
+            // Send the turns out without holding the lock, so that players'
+            // submissions are never kept waiting on us.
+            for broadcast in broadcasts {
+                broadcast.send();
+            }
+
+            thread::sleep(timestep.until_next());
+        }
+    });
+}
+
+/// A turn that has been played, and the players waiting to hear about it.
+#[must_use = "a Broadcast does nothing until it is sent"]
+pub struct Broadcast {
+    /// The turn just played, or `None` if the game is paused.
+    turn: Option<CollectedActions>,
+    recipients: Vec<Box<dyn Notifier + Send>>,
+}
+
+impl Broadcast {
+    /// Send the turn to everyone waiting for it.
+    pub fn send(self) {
+        if let Some(turn) = self.turn {
+            for reply_to in self.recipients {
+                reply_to.notify(turn.clone());
+            }
+        }
+    }
+}
+
+/// A set of actions submitted by a single player on a single turn.
+/// A secret the scheduler gives each player when they take their seat. Only
+/// whoever holds it can take the seat back after losing their connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejoinToken(pub u64);
+
+impl RejoinToken {
+    /// Return a fresh token, which no one could guess.
+    pub fn generate() -> RejoinToken {
+        RejoinToken(rand::random())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerActions {
+    // The player submitting these actions.
+    pub player: Player,
+
+    // The turn number they believe they're on.
+    pub turn: usize,
+
+    // The actions they wish to submit.
+    pub actions: Vec<Action>,
+}
+
+/// A representation of the game state at some turn: a copy of the state at an
+/// earlier turn, and the turns played since.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub state: SerializableState,
+    pub turns: Vec<CollectedActions>,
+}
+
+impl Snapshot {
+    /// Reconstitute the state this snapshot represents.
+    pub fn into_state(self) -> State {
+        let mut state = State::from_serializable(self.state);
+        for turn in &self.turns {
+            turn.apply_to(&mut state);
+        }
+        state
+    }
+}
+
+/// A representation of the game state at some turn, for a participant that
+/// has kept a copy of the state as of an earlier checkpoint: how the latest
+/// checkpoint differs from that, and the turns played since.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeltaSnapshot {
+    pub delta: StateDelta,
+    pub turns: Vec<CollectedActions>,
+}
+
+impl DeltaSnapshot {
+    /// Recreate the state as of the checkpoint this snapshot starts from,
+    /// given `reference`, the participant's copy of the state as of the
+    /// earlier checkpoint `delta` is relative to. Apply `turns` to that to
+    /// reach the current state.
+    pub fn checkpoint(&self, reference: &State) -> errors::Result<State> {
+        self.delta.apply_to(reference)
+    }
+}
+
+/// What a participant that has fallen behind needs to catch up. See
+/// `Scheduler::catch_up`.
+pub enum CatchUp {
+    Turns(Vec<CollectedActions>),
+    Delta(DeltaSnapshot),
+}
+
+/// Return true if the scheduler checkpoints the state as of `turn`.
+/// Participants that keep their own copy of the state as of the latest
+/// checkpoint can be brought up to date with a `DeltaSnapshot`.
+pub fn is_checkpoint(turn: usize) -> bool {
+    turn.is_multiple_of(CHECKPOINT_TURNS)
+}
+
+/// A collection of all actions submitted by all players.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollectedActions {
+    // The turn these moves produce when applied.
+    pub turn: usize,
+
+    // The actions to apply to the prior state.
+    pub actions: Vec<Action>,
+
+    // The hash value of the State that should result, as a checksum.
+    pub state_checksum: u64,
+
+    // If the time between turns has changed, the new time, which takes effect
+    // after this turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_duration: Option<Duration>,
+}
+
+impl CollectedActions {
+    /// Apply these actions to `state`, which must be at the turn before the
+    /// one they produce, and check that we got the same state the scheduler
+    /// did.
+    pub fn apply_to(&self, state: &mut State) {
+        assert!(self.try_apply_to(state), "Game state checksums have diverged!");
+    }
+
+    /// Apply these actions to `state`, which must be at the turn before the
+    /// one they produce. Return true if we got the same state the scheduler
+    /// did, or false if the states have diverged.
+    pub fn try_apply_to(&self, state: &mut State) -> bool {
+        assert_eq!(state.turn + 1, self.turn);
+
+        for action in &self.actions {
+            state.take_action(action);
+        }
+        state.advance();
+
+        // We should have applied the same actions to the same state,
+        // and gotten the same checksum.
+        state.checksum() == self.state_checksum
+    }
+}
+
+/// Add to `stats` the `actions` taken on a turn of the game proper, and the
+/// nodes that changed hands between `owners`, who held each node before the
+/// turn, and `state`, the state after it.
+fn tally(stats: &mut [PlayerStats], actions: &[Action], owners: &[Option<Player>], state: &State) {
+    for action in actions {
+        if let Some(player) = action.taken_by() {
+            stats[player.0].actions += 1;
+            if let Action::ToggleOutflow { .. } = *action {
+                stats[player.0].toggles += 1;
+            }
+        }
+    }
+    for (before, after) in owners.iter().zip(&state.nodes) {
+        if let (&Some(before), Some(after)) = (before, after) {
+            if before != after.player {
+                stats[after.player.0].captures += 1;
+            }
+        }
+    }
+}
+
+/// The most samples a `Timeline` keeps. A graph a few hundred pixels wide
+/// can't show more than this anyway.
+const MAX_SAMPLES: usize = 256;
+
+/// Samples of each player's holdings over the game proper, for graphing once
+/// it's over.
+///
+/// We sample every `interval` turns. Whenever that fills up `MAX_SAMPLES`, we
+/// drop every other sample and double the interval, so a game of any length
+/// takes bounded space, and the samples stay evenly spaced.
+struct Timeline {
+    samples: Vec<Sample>,
+    interval: usize,
+}
+
+impl Timeline {
+    fn new() -> Timeline {
+        Timeline { samples: vec![], interval: 1 }
+    }
+
+    /// Note `state` as of `turn` of the game proper. If `last` is true, the
+    /// game has just ended, and we take a sample regardless of the interval.
+    fn record(&mut self, turn: usize, state: &State, last: bool) {
+        if !last && !turn.is_multiple_of(self.interval) {
+            return;
+        }
+        if self.samples.len() >= MAX_SAMPLES {
+            let mut index = 0;
+            self.samples.retain(|_| { index += 1; index % 2 == 1 });
+            self.interval *= 2;
+            if !last && !turn.is_multiple_of(self.interval) {
+                return;
+            }
+        }
+        let evaluations = state.evaluate();
+        self.samples.push(Sample {
+            turn,
+            nodes: evaluations.iter().map(|evaluation| evaluation.territory).collect(),
+            goop: evaluations.iter().map(|evaluation| evaluation.goop).collect(),
+        });
+    }
+}
+
+/// How many turns in a row must see late submissions before adaptive pacing
+/// slows the game down.
+const SLOW_DOWN_AFTER: usize = 10;
+
+/// How many turns in a row must go by without late submissions before
+/// adaptive pacing speeds the game back up.
+const SPEED_UP_AFTER: usize = 120;
+
+/// The slowest adaptive pacing will make the game.
+const MAX_TURN_DURATION: Duration = Duration::from_millis(200);
+
+/// The scheduler's choice of how long turns should take.
+struct Pacing {
+    /// The time between turns the host asked for. Adaptive pacing never goes
+    /// faster than this.
+    base: Duration,
+
+    /// The time between turns right now.
+    current: Duration,
+
+    /// True if we should adjust `current` to suit the players.
+    adaptive: bool,
+
+    /// If set, `current` should change to this after the turn being played.
+    change: Option<Duration>,
+
+    /// True if some player submitted actions late during this turn.
+    late: bool,
+
+    /// The number of consecutive turns that have had late submissions.
+    late_streak: usize,
+
+    /// The number of consecutive turns that have had no late submissions.
+    prompt_streak: usize,
+}
+
+impl Pacing {
+    fn new(turn_duration: Duration) -> Pacing {
+        Pacing {
+            base: turn_duration,
+            current: turn_duration,
+            adaptive: false,
+            change: None,
+            late: false,
+            late_streak: 0,
+            prompt_streak: 0,
+        }
+    }
+
+    /// Note that a turn has been played, and return the new time between
+    /// turns, if it should change.
+    fn after_turn(&mut self) -> Option<Duration> {
+        if take(&mut self.late) {
+            self.late_streak += 1;
+            self.prompt_streak = 0;
+        } else {
+            self.prompt_streak += 1;
+            self.late_streak = 0;
+        }
+
+        if self.adaptive && self.change.is_none() {
+            if self.late_streak >= SLOW_DOWN_AFTER && self.current < MAX_TURN_DURATION {
+                self.change = Some((self.current * 5 / 4).min(MAX_TURN_DURATION));
+            } else if self.prompt_streak >= SPEED_UP_AFTER && self.current > self.base {
+                self.change = Some((self.current * 4 / 5).max(self.base));
+            }
+        }
+
+        let change = self.change.take()?;
+        self.late_streak = 0;
+        self.prompt_streak = 0;
+        if change == self.current {
+            return None;
+        }
+        self.current = change;
+        Some(change)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use map::MapParameters;
+    use state::{GameParameters, Occupied, Player, State};
+    use super::*;
+    use std::sync::{Arc, Mutex, mpsc};
+
+    /// A `Notifier` that ignores the turns it's given.
+    struct Ignore;
+
+    impl Notifier for Ignore {
+        fn notify(self: Box<Self>, _turn: CollectedActions) { }
+        fn catch_up(self: Box<Self>, _turns: Vec<CollectedActions>) { }
+        fn game_over(self: Box<Self>, _winner: Player, _stats: GameStats) { }
+        fn paused(self: Box<Self>, _player: Player, _timeout: Duration) { }
+    }
+
+    /// A `Notifier` that records the winner it's told about, if any.
+    struct Winner(Arc<Mutex<Option<Player>>>);
+
+    impl Notifier for Winner {
+        fn notify(self: Box<Self>, _turn: CollectedActions) { }
+        fn catch_up(self: Box<Self>, _turns: Vec<CollectedActions>) { }
+        fn game_over(self: Box<Self>, winner: Player, _stats: GameStats) {
+            *self.0.lock().unwrap() = Some(winner);
+        }
+        fn paused(self: Box<Self>, _player: Player, _timeout: Duration) { }
+    }
+
+    /// A `Notifier` that records the players it's told the game paused for.
+    struct Pauses(Arc<Mutex<Vec<Player>>>);
+
+    impl Notifier for Pauses {
+        fn notify(self: Box<Self>, _turn: CollectedActions) { }
+        fn catch_up(self: Box<Self>, _turns: Vec<CollectedActions>) { }
+        fn game_over(self: Box<Self>, _winner: Player, _stats: GameStats) { }
+        fn paused(self: Box<Self>, player: Player, _timeout: Duration) {
+            self.0.lock().unwrap().push(player);
+        }
+    }
+
+    fn one_player_scheduler() -> Scheduler {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![4],
+            player_colors: vec![(0xff, 0, 0)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler
+    }
+
+    /// A `Notifier` that records the numbers of the turns it's told about.
+    struct Turns(Arc<Mutex<Vec<usize>>>);
+
+    impl Notifier for Turns {
+        fn notify(self: Box<Self>, turn: CollectedActions) {
+            self.0.lock().unwrap().push(turn.turn);
+        }
+        fn catch_up(self: Box<Self>, turns: Vec<CollectedActions>) {
+            self.0.lock().unwrap().extend(turns.iter().map(|turn| turn.turn));
+        }
+        fn game_over(self: Box<Self>, _winner: Player, _stats: GameStats) { }
+        fn paused(self: Box<Self>, _player: Player, _timeout: Duration) { }
+    }
+
+    /// Have every player submit an empty action list, and play a turn.
+    fn play_turn(scheduler: &mut Scheduler) {
+        let turn = scheduler.turn;
+        for player in 0..scheduler.pending_actions.len() {
+            scheduler.submit_actions(PlayerActions { player: Player(player), turn,
+                                                     actions: vec![] },
+                                     Box::new(Ignore)).unwrap();
+        }
+        scheduler.play_turn().send();
+    }
+
+    fn turns(collected: Vec<CollectedActions>) -> Vec<usize> {
+        collected.into_iter().map(|c| c.turn).collect()
+    }
+
+    #[test]
+    fn turn_range() {
+        let mut scheduler = one_player_scheduler();
+        assert_eq!(scheduler.turn_range(1..2).unwrap_err(), 1..1);
+
+        for _ in 0..5 {
+            play_turn(&mut scheduler);
+        }
+
+        assert_eq!(turns(scheduler.turn_range(1..6).unwrap()), vec![1, 2, 3, 4, 5]);
+        assert_eq!(turns(scheduler.turn_range(2..4).unwrap()), vec![2, 3]);
+
+        // Ranges running past the present are clipped.
+        assert_eq!(turns(scheduler.turn_range(4..100).unwrap()), vec![4, 5]);
+
+        // Ranges starting in the future, or before the first turn, or empty,
+        // are errors.
+        assert_eq!(scheduler.turn_range(6..10).unwrap_err(), 1..6);
+        assert_eq!(scheduler.turn_range(0..3).unwrap_err(), 1..6);
+        assert_eq!(scheduler.turn_range(3..3).unwrap_err(), 1..6);
+    }
+
+    #[test]
+    fn join_mid_game() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        assert_eq!(scheduler.player_join().unwrap().0, Player(0));
+
+        // The first turn carries the host's own arrival.
+        play_turn(&mut scheduler);
+        let first = &scheduler.turn_range(1..2).unwrap()[0];
+        assert!(matches!(first.actions[..], [Action::Join { player: Player(0) }]));
+        assert!(scheduler.state.nodes[8].is_none());
+
+        for _ in 0..10 {
+            play_turn(&mut scheduler);
+        }
+
+        // A latecomer gets the free source, and a state from before their
+        // arrival. Their arrival is played out in the next turn.
+        let (player, snapshot) = scheduler.player_join().unwrap();
+        assert_eq!(player, Player(1));
+        let mut state = snapshot.into_state();
+        assert_eq!(state.turn, 11);
+        assert!(state.nodes[8].is_none());
+
+        play_turn(&mut scheduler);
+        let joined = &scheduler.turn_range(12..13).unwrap()[0];
+        assert!(matches!(joined.actions[..], [Action::Join { player: Player(1) }]));
+        joined.apply_to(&mut state);
+        assert_eq!(state.nodes[8].as_ref().unwrap().player, Player(1));
+
+        // That was the last source.
+        assert!(scheduler.player_join().is_none());
+    }
+
+    #[test]
+    fn game_over() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler.player_join().unwrap();
+        play_turn(&mut scheduler);
+        assert!(scheduler.result.is_none());
+
+        // Wipe out player 1, and let the scheduler notice.
+        scheduler.state.nodes[8] = None;
+        play_turn(&mut scheduler);
+        let (winner, stats) = scheduler.result.clone().unwrap();
+        assert_eq!(winner, Player(0));
+        assert_eq!(stats.turns, 2);
+        assert_eq!(stats.peak_nodes, vec![1, 1]);
+        assert_eq!(stats.samples.iter().map(|sample| sample.turn).collect::<Vec<_>>(),
+                   vec![1, 2]);
+        assert_eq!(stats.samples[1].nodes, vec![1, 0]);
+
+        // Further submissions get the result, not another turn.
+        let told = Arc::new(Mutex::new(None));
+        let turn = scheduler.turn;
+        scheduler.submit_actions(PlayerActions { player: Player(1), turn, actions: vec![] },
+                                 Box::new(Winner(told.clone()))).unwrap();
+        assert_eq!(*told.lock().unwrap(), Some(Player(0)));
+        assert_eq!(scheduler.turn, turn);
+
+        // The timer keeps ticking, but no turns are played.
+        scheduler.play_turn().send();
+        assert_eq!(scheduler.turn, turn);
+
+        // Player 1 waits for a rematch, which begins with the next turn.
+        let rematch = Arc::new(Mutex::new(vec![]));
+        scheduler.await_rematch(turn, Box::new(Turns(rematch.clone())));
+        scheduler.rematch();
+        scheduler.play_turn().send();
+        assert_eq!(*rematch.lock().unwrap(), vec![turn + 1]);
+        assert!(scheduler.result.is_none());
+        assert_eq!(scheduler.state.nodes[8].as_ref().unwrap().player, Player(1));
+        assert_eq!(scheduler.state.phase(), Phase::Play { turns: 1 });
+    }
+
+    #[test]
+    fn player_stats() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (1, 3),
+            sources: vec![0, 2],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters { warmup_turns: 2, ..GameParameters::default() }),
+                                           DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler.player_join().unwrap();
+        let act = |scheduler: &mut Scheduler, player: usize, actions: Vec<Action>| {
+            let turn = scheduler.turn;
+            scheduler.submit_actions(PlayerActions { player: Player(player), turn, actions },
+                                     Box::new(Ignore)).unwrap();
+        };
+
+        // Warm-up doesn't count.
+        act(&mut scheduler, 0, vec![Action::Chat { player: Player(0), text: "hi".to_string() }]);
+        scheduler.play_turn().send();
+        assert_eq!(scheduler.player_stats[0], PlayerStats::default());
+        while let Phase::WarmUp { .. } = scheduler.state.phase() {
+            play_turn(&mut scheduler);
+        }
+        let played = scheduler.played;
+
+        act(&mut scheduler, 0, vec![
+            Action::ToggleOutflow { player: Player(0), from: 0, to: 1 },
+            Action::Chat { player: Player(0), text: "gg".to_string() },
+        ]);
+        act(&mut scheduler, 1, vec![Action::ClearOutflows { player: Player(1), node: 2 }]);
+        scheduler.play_turn().send();
+        assert_eq!(scheduler.player_stats[0],
+                   PlayerStats { actions: 2, toggles: 1, captures: 0 });
+        assert_eq!(scheduler.player_stats[1].actions, 1);
+        assert_eq!(scheduler.played, played + DEFAULT_TURN_DURATION);
+
+        // Taking a node from another player is a capture; taking a vacant one
+        // isn't.
+        let mut stats = vec![PlayerStats::default(); 2];
+        let mut state = scheduler.state.clone();
+        let owners = vec![Some(Player(0)), Some(Player(1)), None];
+        state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![], goop: 1 });
+        state.nodes[2] = Some(Occupied { player: Player(0), outflows: vec![], goop: 1 });
+        tally(&mut stats, &[], &owners, &state);
+        assert_eq!(stats[0].captures, 1);
+
+        let stats = GameStats { turns: 0, duration: Duration::from_secs(30), peak_nodes: vec![],
+                                players: scheduler.player_stats.clone(), samples: vec![] };
+        assert_eq!(stats.actions_per_minute(Player(0)), 4.0);
+    }
+
+    #[test]
+    fn stragglers() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler.player_join().unwrap();
+
+        // Player 0 is prompt, but player 1 is not. The turn goes ahead anyway.
+        let prompt = Arc::new(Mutex::new(vec![]));
+        scheduler.submit_actions(PlayerActions { player: Player(0), turn: 0, actions: vec![] },
+                                 Box::new(Turns(prompt.clone()))).unwrap();
+        scheduler.play_turn().send();
+        assert_eq!(*prompt.lock().unwrap(), vec![1]);
+
+        // When player 1's submission finally arrives, they get the turn they
+        // missed right away, and their actions go into the next turn.
+        let late = Arc::new(Mutex::new(vec![]));
+        let toggle = Action::ToggleOutflow { player: Player(1), from: 8, to: 7 };
+        scheduler.submit_actions(PlayerActions { player: Player(1), turn: 0,
+                                                 actions: vec![toggle] },
+                                 Box::new(Turns(late.clone()))).unwrap();
+        assert_eq!(*late.lock().unwrap(), vec![1]);
+
+        scheduler.submit_actions(PlayerActions { player: Player(1), turn: 1, actions: vec![] },
+                                 Box::new(Turns(late.clone()))).unwrap();
+        scheduler.play_turn().send();
+        assert_eq!(*late.lock().unwrap(), vec![1, 2]);
+        let second = &scheduler.turn_range(2..3).unwrap()[0];
+        assert!(matches!(second.actions[..], [Action::ToggleOutflow { from: 8, to: 7, .. }]));
+
+        // A player who falls several turns behind gets all of them at once.
+        for _ in 0..3 {
+            scheduler.play_turn().send();
+        }
+        let burst = Arc::new(Mutex::new(vec![]));
+        scheduler.submit_actions(PlayerActions { player: Player(1), turn: 2, actions: vec![] },
+                                 Box::new(Turns(burst.clone()))).unwrap();
+        assert_eq!(*burst.lock().unwrap(), vec![3, 4, 5]);
+    }
+
+    /// A `Notifier` that reports whether it could take the scheduler's lock
+    /// when told of a turn.
+    struct Unlocked(Arc<Mutex<Scheduler>>, mpsc::Sender<bool>);
+
+    impl Notifier for Unlocked {
+        fn notify(self: Box<Self>, _turn: CollectedActions) {
+            let unlocked = self.0.try_lock().is_ok();
+            self.1.send(unlocked).unwrap();
+        }
+        fn catch_up(self: Box<Self>, _turns: Vec<CollectedActions>) { }
+        fn game_over(self: Box<Self>, _winner: Player, _stats: GameStats) { }
+        fn paused(self: Box<Self>, _player: Player, _timeout: Duration) { }
+    }
+
+    #[test]
+    fn timer_sends_unlocked() {
+        // The timer thread tells players about each turn only once it has let
+        // go of the scheduler, so they can submit their next actions at once.
+        let scheduler = Arc::new(Mutex::new(one_player_scheduler()));
+        let (sender, receiver) = mpsc::channel();
+        let notifier = Unlocked(scheduler.clone(), sender);
+        scheduler.lock().unwrap()
+            .submit_actions(PlayerActions { player: Player(0), turn: 0, actions: vec![] },
+                            Box::new(notifier)).unwrap();
+        start_timer(scheduler);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+    }
+
+    #[test]
+    fn invalid_actions() {
+        let mut scheduler = one_player_scheduler();
+        play_turn(&mut scheduler);
+
+        // Actions for a turn that hasn't happened yet, or from a seat no one
+        // has taken, are refused, and the scheduler carries on unharmed.
+        let future = PlayerActions { player: Player(0), turn: 5, actions: vec![] };
+        assert!(scheduler.submit_actions(future, Box::new(Ignore)).is_err());
+        let unseated = PlayerActions { player: Player(7), turn: 1, actions: vec![] };
+        assert!(scheduler.submit_actions(unseated, Box::new(Ignore)).is_err());
+
+        // So are actions only the scheduler may take, actions on another
+        // player's behalf, and actions naming nodes off the board.
+        for action in [Action::Restart,
+                       Action::Join { player: Player(0) },
+                       Action::Surrender { player: Player(1) },
+                       Action::Chat { player: Player(9), text: "hi".to_string() },
+                       Action::ToggleOutflow { player: Player(0), from: 4, to: 1000 },
+                       Action::OpenOutflows { player: Player(0), node: 9 }] {
+            let actions = PlayerActions { player: Player(0), turn: 1, actions: vec![action] };
+            assert!(scheduler.submit_actions(actions, Box::new(Ignore)).is_err());
+        }
+
+        play_turn(&mut scheduler);
+        assert_eq!(scheduler.turn, 2);
+    }
+
+    #[test]
+    fn divergence_reports() {
+        let mut scheduler = one_player_scheduler();
+        play_turn(&mut scheduler);
+        play_turn(&mut scheduler);
+
+        // Each player's report on a turn that has been played is worth
+        // writing down once; repeats, earlier turns, turns yet to come and
+        // seats that don't exist aren't.
+        assert!(scheduler.note_divergence(Player(0), 1));
+        assert!(!scheduler.note_divergence(Player(0), 1));
+        assert!(!scheduler.note_divergence(Player(0), 0));
+        assert!(!scheduler.note_divergence(Player(0), 3));
+        assert!(!scheduler.note_divergence(Player(0), usize::MAX));
+        assert!(!scheduler.note_divergence(Player(usize::MAX), 1));
+        assert!(scheduler.note_divergence(Player(0), 2));
+    }
+
+    #[test]
+    fn adaptive_pacing() {
+        let mut scheduler = one_player_scheduler();
+        scheduler.set_adaptive(true);
+
+        // Submit late for a while, and the game slows down.
+        for _ in 0..SLOW_DOWN_AFTER {
+            scheduler.play_turn().send();
+            let turn = scheduler.turn;
+            scheduler.submit_actions(PlayerActions { player: Player(0), turn: turn - 1,
+                                                     actions: vec![] },
+                                     Box::new(Ignore)).unwrap();
+        }
+        scheduler.play_turn().send();
+        let slower = DEFAULT_TURN_DURATION * 5 / 4;
+        assert_eq!(scheduler.turn_duration(), slower);
+        let turn = scheduler.turn;
+        let change = &scheduler.turn_range(turn .. turn + 1).unwrap()[0];
+        assert_eq!(change.turn_duration, Some(slower));
+
+        // Keep up for a while, and it speeds back up, but no faster than the
+        // host asked for.
+        for _ in 0..SPEED_UP_AFTER {
+            play_turn(&mut scheduler);
+        }
+        assert_eq!(scheduler.turn_duration(), DEFAULT_TURN_DURATION);
+        for _ in 0..SPEED_UP_AFTER {
+            play_turn(&mut scheduler);
+        }
+        assert_eq!(scheduler.turn_duration(), DEFAULT_TURN_DURATION);
+
+        // The host can change the pace outright.
+        scheduler.set_turn_duration(Duration::from_millis(50));
+        play_turn(&mut scheduler);
+        assert_eq!(scheduler.turn_duration(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn timing() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler.player_join().unwrap();
+
+        play_turn(&mut scheduler);
+
+        // Only player 0 submits for turn 2.
+        scheduler.submit_actions(PlayerActions { player: Player(0), turn: 1, actions: vec![] },
+                                 Box::new(Ignore)).unwrap();
+        scheduler.play_turn().send();
+
+        let timings: Vec<_> = scheduler.timing().turns().collect();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[1].turn, 2);
+        assert!(timings[0].waits.iter().all(|wait| wait.is_some()));
+        assert!(timings[1].waits[0].is_some());
+        assert!(timings[1].waits[1].is_none());
+    }
+
+    #[test]
+    fn pause_on_drop() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.set_pause_on_drop(Some(Duration::from_secs(60)));
+        scheduler.player_join().unwrap();
+        scheduler.player_join().unwrap();
+        play_turn(&mut scheduler);
+
+        // Only players who've dropped can rejoin.
+        let token = scheduler.rejoin_token(Player(1)).unwrap();
+        assert_ne!(scheduler.rejoin_token(Player(0)), Some(token));
+        assert!(!scheduler.player_rejoin(Player(1), token));
+
+        // Player 0 is waiting for the next turn when player 1 drops.
+        let pauses = Arc::new(Mutex::new(vec![]));
+        scheduler.submit_actions(PlayerActions { player: Player(0), turn: 1, actions: vec![] },
+                                 Box::new(Pauses(pauses.clone()))).unwrap();
+        scheduler.player_dropped(Player(1));
+        assert_eq!(*pauses.lock().unwrap(), vec![Player(1)]);
+
+        // No turns are played while we wait.
+        scheduler.play_turn().send();
+        assert_eq!(scheduler.turn, 1);
+
+        // Only the seat's own token gets it back. When player 1 comes back,
+        // the game picks up where it left off.
+        assert!(!scheduler.player_rejoin(Player(1), RejoinToken(token.0.wrapping_add(1))));
+        assert!(scheduler.player_rejoin(Player(1), token));
+        assert_eq!(scheduler.turns_since(0).unwrap().len(), 1);
+        play_turn(&mut scheduler);
+        assert_eq!(scheduler.turn, 2);
+
+        // If a player stays away too long, the game goes on without them.
+        scheduler.set_pause_on_drop(Some(Duration::from_millis(0)));
+        scheduler.player_dropped(Player(1));
+        scheduler.play_turn().send();
+        assert_eq!(scheduler.turn, 3);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut scheduler = one_player_scheduler();
+        for _ in 0 .. CHECKPOINT_TURNS + 5 {
+            play_turn(&mut scheduler);
+        }
+
+        // The snapshot starts from the most recent checkpoint.
+        let snapshot = scheduler.snapshot();
+        assert_eq!(turns(snapshot.turns.clone()),
+                   (CHECKPOINT_TURNS + 1 ..= CHECKPOINT_TURNS + 5).collect::<Vec<_>>());
+        let state = snapshot.into_state();
+        assert_eq!(state.turn, scheduler.turn);
+        assert_eq!(state.checksum(), scheduler.state.checksum());
+
+        assert_eq!(scheduler.turns_since(scheduler.turn).unwrap().len(), 0);
+        assert_eq!(turns(scheduler.turns_since(scheduler.turn - 2).unwrap()),
+                   vec![scheduler.turn - 1, scheduler.turn]);
+        assert!(scheduler.turns_since(scheduler.turn + 1).is_none());
+        assert!(scheduler.turns_since(usize::MAX).is_none());
+        assert!(matches!(scheduler.catch_up(usize::MAX, 0), Some(CatchUp::Delta(_))));
+    }
+
+    #[test]
+    fn delta_snapshots() {
+        let mut scheduler = one_player_scheduler();
+        let reference = scheduler.state.clone();
+        for _ in 0 .. CHECKPOINT_TURNS + 5 {
+            play_turn(&mut scheduler);
+        }
+
+        // A participant that kept the first checkpoint gets just what has
+        // changed since, up to the latest one, and the turns after that.
+        let snapshot = scheduler.delta_snapshot(reference.turn).unwrap();
+        assert_eq!((snapshot.delta.base(), snapshot.delta.turn()), (0, CHECKPOINT_TURNS));
+        assert_eq!(snapshot.turns.len(), 5);
+        let mut state = snapshot.checkpoint(&reference).unwrap();
+        for turn in &snapshot.turns {
+            turn.apply_to(&mut state);
+        }
+        assert_eq!(state.checksum(), scheduler.state.checksum());
+
+        // We don't have states from turns that aren't checkpoints.
+        assert!(scheduler.delta_snapshot(1).is_none());
+
+        // A participant only a few turns behind gets the turns instead.
+        let behind = scheduler.turn - 3;
+        assert!(matches!(scheduler.catch_up(behind, 0), Some(CatchUp::Turns(ref turns))
+                         if turns.len() == 3));
+        assert!(matches!(scheduler.catch_up(1, 0), Some(CatchUp::Delta(_))));
+        assert!(matches!(scheduler.catch_up(1, 1), Some(CatchUp::Turns(_))));
+    }
+
+    #[test]
+    fn bots() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        let registry = Registry::default();
+        assert_eq!(scheduler.add_bot(&registry, "chess"), None);
+        assert_eq!(scheduler.add_bot(&registry, bot::DEFAULT_KIND), Some(Player(1)));
+        assert_eq!(scheduler.add_bot(&registry, bot::DEFAULT_KIND), None);
+
+        for _ in 0..100 {
+            play_turn(&mut scheduler);
+        }
+
+        // The bot has joined, and has been making moves on its own.
+        assert!(scheduler.state.joined[1]);
+        let moves = scheduler.turn_range(1..101).unwrap().iter()
+            .flat_map(|turn| turn.actions.clone())
+            .filter(|action| matches!(*action, Action::ToggleOutflow { player: Player(1), .. }))
+            .count();
+        assert!(moves > 0);
+    }
+
+    #[test]
+    fn fill_seats() {
+        let params = GameParameters { warmup_turns: 10, .. GameParameters::default() };
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 4, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff)],
+        }, params), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        scheduler.set_fill_seats(Arc::new(Registry::default()), "easy");
+
+        // During warm-up, the seats stay open for people to take.
+        for _ in 0..5 {
+            play_turn(&mut scheduler);
+        }
+        assert_eq!(scheduler.player_join().map(|(player, _)| player), Some(Player(1)));
+        assert!(scheduler.bots.is_empty());
+
+        // Once the game proper begins, a bot holds the last source.
+        while let Phase::WarmUp { .. } = scheduler.state.phase() {
+            play_turn(&mut scheduler);
+        }
+        assert_eq!(scheduler.bots.len(), 1);
+        assert_eq!(scheduler.bots[0].player, Player(2));
+        assert_eq!(scheduler.bots[0].kind, "easy");
+        assert!(scheduler.state.joined.iter().all(|&joined| joined));
+        assert!(matches!(scheduler.state.nodes[8], Some(Occupied { player: Player(2), .. })));
+        assert!(scheduler.player_join().is_none());
+    }
+
+    #[test]
+    fn timeline() {
+        let state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+
+        // However long the game runs, the samples stay bounded and evenly
+        // spaced, and the last turn is always included.
+        let mut timeline = Timeline::new();
+        for turn in 0..MAX_SAMPLES * 5 {
+            timeline.record(turn, &state, false);
+        }
+        timeline.record(MAX_SAMPLES * 5 + 3, &state, true);
+        let turns: Vec<usize> = timeline.samples.iter().map(|sample| sample.turn).collect();
+        assert!(turns.len() <= MAX_SAMPLES + 1);
+        assert!(turns.len() > MAX_SAMPLES / 2);
+        assert_eq!(turns[0], 0);
+        assert_eq!(*turns.last().unwrap(), MAX_SAMPLES * 5 + 3);
+        let step = turns[1] - turns[0];
+        assert!(turns[..turns.len() - 1].windows(2).all(|pair| pair[1] - pair[0] == step));
+    }
+
+    #[test]
+    fn save_and_resume() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 4, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        scheduler.player_join().unwrap();
+        let registry = Registry::default();
+        scheduler.add_bot(&registry, "hard").unwrap();
+        scheduler.player_join().unwrap();
+        for _ in 0..5 {
+            play_turn(&mut scheduler);
+        }
+
+        let saved = scheduler.save();
+        assert_eq!(saved.turn, 5);
+        assert_eq!(saved.roster, vec![Seat::Human, Seat::Bot, Seat::Human]);
+        let json = ::serde_json::to_string(&saved).unwrap();
+        let saved: SavedGame = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(saved.bots, vec!["hard"]);
+        assert_eq!(saved.tokens, scheduler.tokens);
+        let mut resumed = Scheduler::resume(saved, &registry);
+        assert_eq!(resumed.turn, 5);
+        assert_eq!(resumed.bots[0].kind, "hard");
+        assert_eq!(resumed.snapshot().into_state().checksum(), scheduler.state.checksum());
+
+        // Player 2 reconnects to their own seat with the token they were
+        // given before; a newcomer gets the other human seat, along with a
+        // fresh token, and then there's no room.
+        let token = scheduler.rejoin_token(Player(2)).unwrap();
+        assert!(resumed.player_rejoin(Player(2), token));
+        assert!(!resumed.player_rejoin(Player(2), token));
+        assert_eq!(resumed.player_join().map(|(player, _)| player), Some(Player(0)));
+        assert_ne!(resumed.rejoin_token(Player(0)), scheduler.rejoin_token(Player(0)));
+        assert!(resumed.player_join().is_none());
+
+        // Play carries on from where it stopped, without anyone joining
+        // again.
+        play_turn(&mut resumed);
+        assert_eq!(resumed.turn, 6);
+        assert!(resumed.turns_since(5).unwrap()[0].actions.iter()
+                .all(|action| !matches!(action, Action::Join { .. })));
+    }
+}
@@ -0,0 +1,340 @@
+//! Playing games between bots with no one watching.
+//!
+//! To see how bots of different difficulties fare against each other, or
+//! whether a change to one makes it play better, we can play many games
+//! between them as fast as the rules can be applied: no window, no network,
+//! and no scheduler keeping time. Each game's bots are seeded differently, so
+//! the games differ, but a run of them can be reproduced.
+//!
+//! A run can also write out what the bots saw and did, as training data for
+//! people experimenting with learned policies outside the crate. The file
+//! starts with a header naming the map and the kinds of bot in each seat, and
+//! then has an `Example` for each turn on which any bot acted. Turns on which
+//! none did are left out: bots move only every so often, and a line for every
+//! turn would mostly repeat the board.
+
+use bot::{Bot, Registry};
+use errors::*;
+use map::Map;
+use state::{Action, Evaluation, Occupied, Player, State};
+use storage::{self, Format, Output};
+use version::Version;
+
+use std::path::Path;
+
+/// How a single game turned out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// The player who won, or `None` if no one had by the turn limit.
+    pub winner: Option<Player>,
+
+    /// How many turns the game ran, warm-up included.
+    pub turns: usize,
+}
+
+/// One turn of a game, as written for training: the position the bots saw,
+/// what they chose to do about it, and how the game came out in the end.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Example {
+    /// Which game of the run this is from, counting from zero.
+    pub game: usize,
+
+    /// The turn the bots saw.
+    pub turn: usize,
+
+    /// How each player stood, indexed by player number.
+    pub evaluations: Vec<Evaluation>,
+
+    /// The board, indexed by node id.
+    pub nodes: Vec<Option<Occupied>>,
+
+    /// The actions the bots took on seeing it, for every player.
+    pub actions: Vec<Action>,
+
+    /// The player who won the game, or `None` if no one had by the turn
+    /// limit.
+    pub winner: Option<Player>,
+
+    /// How many turns the game ran, warm-up included.
+    pub turns: usize,
+}
+
+/// The first line of a training data file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: Version,
+
+    /// The map every game in the run was played on.
+    map: Map,
+
+    /// The kind of bot in each seat, indexed by player number.
+    bots: Vec<String>,
+}
+
+/// Writes examples to a training data file.
+pub struct TrainingWriter {
+    out: Output,
+
+    /// True if we're writing MessagePack, rather than JSON.
+    binary: bool,
+}
+
+impl TrainingWriter {
+    /// Start the training data file at `path`, in `format`, for games on
+    /// `map` between bots of `kinds`.
+    pub fn create(path: &Path, format: Format, map: &Map, kinds: &[String])
+                  -> Result<TrainingWriter>
+    {
+        let mut out = Output::create(path, format)
+            .chain_err(|| format!("creating training data {}", path.display()))?;
+        let header = Header { version: Version::current(), map: map.clone(), bots: kinds.to_vec() };
+        storage::write_versioned(&mut out, format.binary, &header)
+            .chain_err(|| format!("writing training data {}", path.display()))?;
+        Ok(TrainingWriter { out, binary: format.binary })
+    }
+
+    /// Write `example`.
+    pub fn record(&mut self, example: &Example) -> Result<()> {
+        storage::write_next(&mut self.out, self.binary, example)
+            .chain_err(|| "writing training data")
+    }
+
+    /// Write out everything recorded so far, and end the file.
+    pub fn finish(&mut self) -> Result<()> {
+        self.out.finish()
+            .chain_err(|| "writing training data")
+    }
+}
+
+/// Play `state` out between `bots`, who take the seats in order, each joining
+/// at the start, until someone wins or the game has run `max_turns` turns.
+pub fn play(state: State, bots: &mut [Box<dyn Bot>], max_turns: usize) -> Outcome {
+    play_recording(state, bots, max_turns, None)
+}
+
+/// Play as `play` does, pushing an example onto `examples` for each turn on
+/// which any bot acted, if it's given. The examples' `game`, `winner`, and
+/// `turns` are left for the caller to fill in.
+fn play_recording(mut state: State, bots: &mut [Box<dyn Bot>], max_turns: usize,
+                  mut examples: Option<&mut Vec<Example>>) -> Outcome
+{
+    let mut actions: Vec<Action> = (0..bots.len())
+        .map(|player| Action::Join { player: Player(player) })
+        .collect();
+    while state.turn < max_turns {
+        for action in &actions {
+            state.take_action(action);
+        }
+        state.advance();
+        if let Some(winner) = state.winner() {
+            return Outcome { winner: Some(winner), turns: state.turn };
+        }
+        actions = bots.iter_mut()
+            .enumerate()
+            .flat_map(|(player, bot)| bot.act(&state, Player(player)))
+            .collect();
+        if let Some(ref mut examples) = examples {
+            if !actions.is_empty() {
+                examples.push(Example {
+                    game: 0,
+                    turn: state.turn,
+                    evaluations: state.evaluate(),
+                    nodes: state.nodes.clone(),
+                    actions: actions.clone(),
+                    winner: None,
+                    turns: 0,
+                });
+            }
+        }
+    }
+    Outcome { winner: None, turns: state.turn }
+}
+
+/// The results of a run of games.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tally {
+    /// How many games were played.
+    pub games: usize,
+
+    /// How many games each player won, indexed by player number.
+    pub wins: Vec<usize>,
+
+    /// The total length in turns of the games someone won.
+    pub won_turns: usize,
+}
+
+impl Tally {
+    /// Count `outcome` in this tally.
+    pub fn record(&mut self, outcome: Outcome) {
+        self.games += 1;
+        if let Some(Player(winner)) = outcome.winner {
+            if self.wins.len() <= winner {
+                self.wins.resize(winner + 1, 0);
+            }
+            self.wins[winner] += 1;
+            self.won_turns += outcome.turns;
+        }
+    }
+
+    /// Return how many games no one won before the turn limit.
+    pub fn unfinished(&self) -> usize {
+        self.games - self.wins.iter().sum::<usize>()
+    }
+
+    /// Return the fraction of all games that `player` won.
+    pub fn win_rate(&self, player: Player) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.wins.get(player.0).cloned().unwrap_or(0) as f64 / self.games as f64
+    }
+
+    /// Return the average length in turns of the games someone won, if any.
+    pub fn average_turns(&self) -> Option<f64> {
+        let won = self.games - self.unfinished();
+        if won == 0 {
+            return None;
+        }
+        Some(self.won_turns as f64 / won as f64)
+    }
+}
+
+/// Play `games` games, each starting from a state `new_game` returns, with a
+/// bot of each of `kinds` from `registry` taking the seats in order, and tally
+/// the results. Each game ends after `max_turns` turns if no one has won. If
+/// `training` is given, write each game's examples to it as the game ends.
+pub fn simulate<F>(mut new_game: F, registry: &Registry, kinds: &[String], games: usize,
+                   max_turns: usize, mut training: Option<&mut TrainingWriter>)
+                   -> Result<Tally>
+    where F: FnMut() -> State
+{
+    if let Some(kind) = kinds.iter().find(|kind| !registry.contains(kind)) {
+        bail!("no such kind of bot: {}", kind);
+    }
+    let mut tally = Tally { wins: vec![0; kinds.len()], .. Tally::default() };
+    for game in 0..games {
+        let mut bots: Vec<Box<dyn Bot>> = kinds.iter()
+            .enumerate()
+            .map(|(player, kind)| {
+                registry.create(kind, Player(player), game as u64)
+                    .expect("bot kind should be registered")
+            })
+            .collect();
+        let mut examples = vec![];
+        let recording = if training.is_some() { Some(&mut examples) } else { None };
+        let outcome = play_recording(new_game(), &mut bots, max_turns, recording);
+        if let Some(ref mut training) = training {
+            for mut example in examples {
+                example.game = game;
+                example.winner = outcome.winner;
+                example.turns = outcome.turns;
+                training.record(&example)?;
+            }
+        }
+        tally.record(outcome);
+    }
+    Ok(tally)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bot::{Difficulty, Heuristic};
+    use map::MapParameters;
+    use state::GameParameters;
+    use storage::Input;
+
+    use serde_json;
+
+    use std::env;
+    use std::fs;
+    use std::io::BufRead;
+
+    fn duel() -> State {
+        State::new(MapParameters {
+            size: (5, 5),
+            sources: vec![0, 24],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default())
+    }
+
+    #[test]
+    fn simulate() {
+        // A hard bot beats an easy one on a small board, well before the
+        // turn limit.
+        let registry = Registry::default();
+        let kinds = vec!["easy".to_string(), "hard".to_string()];
+        let tally = super::simulate(duel, &registry, &kinds, 4, 20_000, None).unwrap();
+        assert_eq!(tally.games, 4);
+        assert_eq!(tally.unfinished(), 0);
+        assert!(tally.win_rate(Player(1)) > tally.win_rate(Player(0)));
+        assert!(tally.average_turns().unwrap() > 0.0);
+
+        // The same run plays the same games.
+        assert_eq!(super::simulate(duel, &registry, &kinds, 4, 20_000, None).unwrap(), tally);
+
+        let unknown = vec!["easy".to_string(), "chess".to_string()];
+        assert!(super::simulate(duel, &registry, &unknown, 1, 10, None).is_err());
+    }
+
+    #[test]
+    fn training_data() {
+        let path = env::temp_dir().join(format!("rbattle-training-{}.json",
+                                                ::std::process::id()));
+        let registry = Registry::default();
+        let kinds = vec!["medium".to_string(), "hard".to_string()];
+        let mut training = TrainingWriter::create(&path, Format::default(), &duel().map,
+                                                  &kinds).unwrap();
+        let tally = super::simulate(duel, &registry, &kinds, 2, 20_000,
+                                    Some(&mut training)).unwrap();
+        training.finish().unwrap();
+
+        let mut input = Input::open(&path).unwrap();
+        let header: Header = input.read_versioned("training data").unwrap();
+        assert_eq!(header.bots, kinds);
+        let examples: Vec<Example> = input.reader.lines()
+            .map(|line| line.unwrap())
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_str(&line).unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        // Every example has someone acting, and both games are there, each
+        // ending as the tally says.
+        assert!(examples.iter().all(|example| !example.actions.is_empty()));
+        assert!(examples.iter().all(|example| example.turn < example.turns));
+        assert_eq!(examples.first().unwrap().game, 0);
+        assert_eq!(examples.last().unwrap().game, 1);
+        for game in 0..2 {
+            let winners: Vec<Option<Player>> = examples.iter()
+                .filter(|example| example.game == game)
+                .map(|example| example.winner)
+                .collect();
+            assert!(winners.iter().all(|&winner| winner.is_some() && winner == winners[0]));
+        }
+        let wins = |player| examples.iter()
+            .filter(|example| example.winner == Some(player))
+            .map(|example| example.game)
+            .collect::<::std::collections::BTreeSet<_>>()
+            .len();
+        assert_eq!(wins(Player(0)), tally.wins[0]);
+        assert_eq!(wins(Player(1)), tally.wins[1]);
+    }
+
+    #[test]
+    fn turn_limit() {
+        // A game no one has won by the limit counts for no one.
+        let mut bots: Vec<Box<dyn Bot>> = vec![
+            Box::new(Heuristic::new(Player(0), Difficulty::Easy)),
+            Box::new(Heuristic::new(Player(1), Difficulty::Easy)),
+        ];
+        let outcome = play(duel(), &mut bots, 10);
+        assert_eq!(outcome, Outcome { winner: None, turns: 10 });
+
+        let mut tally = Tally::default();
+        tally.record(outcome);
+        assert_eq!(tally.unfinished(), 1);
+        assert_eq!(tally.win_rate(Player(0)), 0.0);
+        assert_eq!(tally.average_turns(), None);
+    }
+}
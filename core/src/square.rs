@@ -23,6 +23,11 @@ impl SquareGrid {
         SquareGrid { rows, cols }
     }
 
+    /// Return the number of rows and columns.
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
     /// Return the row and column of `node`.
     fn node_rc(&self, node: Node) -> (usize, usize) {
         assert!(node < self.nodes());
@@ -186,10 +191,7 @@ impl VisibleGraph for SquareGrid {
     /// A `SquareGrid` recognizes edge hits by dividing each square into four
     /// triangular quadrants: north, south, east, and west. Points very near the
     /// diagonals or grid lines are excluded as ambiguous.
-    fn edge_hit(&self, &GraphPt(point): &GraphPt) -> Option<(Node, Node)> {
-        // Exclude points closer than this to a grid line.
-        const TOLERANCE: f32 = 0.05;
-
+    fn edge_hit(&self, &GraphPt(point): &GraphPt, tolerance: f32) -> Option<(Node, Node)> {
         // Check how close `val` is to the nearest integer. If it is within
         // `distance`, return true.
         fn near(val: f32, distance: f32) -> bool {
@@ -207,7 +209,7 @@ impl VisibleGraph for SquareGrid {
         }
 
         // Exclude points near grid lines.
-        if near(point[0], TOLERANCE) || near(point[1], TOLERANCE) {
+        if near(point[0], tolerance) || near(point[1], tolerance) {
             return None;
         }
 
@@ -219,10 +221,10 @@ impl VisibleGraph for SquareGrid {
         let fract_y = point[1].fract();
 
         // Exclude points near diagonals.
-        if (fract_x - fract_y).abs() < TOLERANCE {
+        if (fract_x - fract_y).abs() < tolerance {
             return None;
         }
-        if (fract_x + fract_y).abs() < TOLERANCE {
+        if (fract_x + fract_y - 1.0).abs() < tolerance {
             return None;
         }
 
@@ -252,6 +254,25 @@ impl VisibleGraph for SquareGrid {
             None
         }
     }
+
+    fn node_hit(&self, &GraphPt(point): &GraphPt) -> Option<Node> {
+        let GraphPt(bounds) = self.bounds();
+        if point[0] < 0.0 || point[0] >= bounds[0] ||
+            point[1] < 0.0 || point[1] >= bounds[1]
+        {
+            return None;
+        }
+
+        // Only the node whose square contains `point` can be close enough.
+        let node = self.rc_node(point[1] as usize, point[0] as usize);
+        let GraphPt(center) = self.center(node);
+        let distance = (point[0] - center[0]).hypot(point[1] - center[1]);
+        if distance <= self.radius() {
+            Some(node)
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -345,61 +366,97 @@ mod square_grid_as_visible_graph {
     #[test]
     fn boundary_hit() {
         // These tests are not black-box: they know the general algorithm
-        // `boundary_hit` implements, and the value of TOLERANCE. But they
+        // `boundary_hit` implements, and the value of EDGE_TOLERANCE. But they
         // should mostly be okay with any reasonable hit definition.
 
         use super::SquareGrid;
+        use visible_graph::EDGE_TOLERANCE;
 
         let grid = SquareGrid::new(3, 4);
 
         // Wildly outside the grid.
-        assert_eq!(grid.edge_hit(&gp(-100.0, -100.0)), None);
-        assert_eq!(grid.edge_hit(&gp(-100.0, 1.5)),    None);
-        assert_eq!(grid.edge_hit(&gp(-100.0, 2000.0)), None);
+        assert_eq!(grid.edge_hit(&gp(-100.0, -100.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(-100.0, 1.5), EDGE_TOLERANCE),    None);
+        assert_eq!(grid.edge_hit(&gp(-100.0, 2000.0), EDGE_TOLERANCE), None);
 
-        assert_eq!(grid.edge_hit(&gp(2.0, -100.0)),    None);
-        assert_eq!(grid.edge_hit(&gp(2.0, 2000.0)),    None);
+        assert_eq!(grid.edge_hit(&gp(2.0, -100.0), EDGE_TOLERANCE),    None);
+        assert_eq!(grid.edge_hit(&gp(2.0, 2000.0), EDGE_TOLERANCE),    None);
 
-        assert_eq!(grid.edge_hit(&gp(2000.0, -100.0)), None);
-        assert_eq!(grid.edge_hit(&gp(2000.0, 1.5)),    None);
-        assert_eq!(grid.edge_hit(&gp(2000.0, 2000.0)), None);
+        assert_eq!(grid.edge_hit(&gp(2000.0, -100.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(2000.0, 1.5), EDGE_TOLERANCE),    None);
+        assert_eq!(grid.edge_hit(&gp(2000.0, 2000.0), EDGE_TOLERANCE), None);
 
         // Nearby outside.
-        assert_eq!(grid.edge_hit(&gp(2.0, -0.5)), None);
-        assert_eq!(grid.edge_hit(&gp(4.5,  1.5)), None);
-        assert_eq!(grid.edge_hit(&gp(2.0,  3.5)), None);
-        assert_eq!(grid.edge_hit(&gp(-0.5, 1.5)), None);
+        assert_eq!(grid.edge_hit(&gp(2.0, -0.5), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(4.5,  1.5), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(2.0,  3.5), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(-0.5, 1.5), EDGE_TOLERANCE), None);
 
         // On corners.
-        assert_eq!(grid.edge_hit(&gp(0.0, 0.0)), None);
-        assert_eq!(grid.edge_hit(&gp(4.0, 0.0)), None);
-        assert_eq!(grid.edge_hit(&gp(4.0, 3.0)), None);
-        assert_eq!(grid.edge_hit(&gp(0.0, 3.0)), None);
+        assert_eq!(grid.edge_hit(&gp(0.0, 0.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(4.0, 0.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(4.0, 3.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(0.0, 3.0), EDGE_TOLERANCE), None);
 
         // On sides.
-        assert_eq!(grid.edge_hit(&gp(3.5, 0.0)), None);
-        assert_eq!(grid.edge_hit(&gp(4.0, 2.3)), None);
-        assert_eq!(grid.edge_hit(&gp(1.7, 3.0)), None);
-        assert_eq!(grid.edge_hit(&gp(0.0, 1.2)), None);
+        assert_eq!(grid.edge_hit(&gp(3.5, 0.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(4.0, 2.3), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(1.7, 3.0), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(0.0, 1.2), EDGE_TOLERANCE), None);
 
         // Interior north.
-        assert_eq!(grid.edge_hit(&gp(0.5, 0.9)), Some((0, 4)));
-        assert_eq!(grid.edge_hit(&gp(3.6, 1.8)), Some((7, 11)));
-        assert_eq!(grid.edge_hit(&gp(1.4, 1.9)), Some((5, 9)));
+        assert_eq!(grid.edge_hit(&gp(0.5, 0.9), EDGE_TOLERANCE), Some((0, 4)));
+        assert_eq!(grid.edge_hit(&gp(3.6, 1.8), EDGE_TOLERANCE), Some((7, 11)));
+        assert_eq!(grid.edge_hit(&gp(1.4, 1.9), EDGE_TOLERANCE), Some((5, 9)));
 
         // Interior south.
-        assert_eq!(grid.edge_hit(&gp(0.5, 1.1)), Some((4, 0)));
-        assert_eq!(grid.edge_hit(&gp(3.6, 2.2)), Some((11, 7)));
-        assert_eq!(grid.edge_hit(&gp(1.4, 2.1)), Some((9, 5)));
+        assert_eq!(grid.edge_hit(&gp(0.5, 1.1), EDGE_TOLERANCE), Some((4, 0)));
+        assert_eq!(grid.edge_hit(&gp(3.6, 2.2), EDGE_TOLERANCE), Some((11, 7)));
+        assert_eq!(grid.edge_hit(&gp(1.4, 2.1), EDGE_TOLERANCE), Some((9, 5)));
 
         // Interior east
-        assert_eq!(grid.edge_hit(&gp(0.9, 0.4)), Some((0, 1)));
-        assert_eq!(grid.edge_hit(&gp(2.8, 2.5)), Some((10, 11)));
-        assert_eq!(grid.edge_hit(&gp(1.9, 1.5)), Some((5, 6)));
+        assert_eq!(grid.edge_hit(&gp(0.9, 0.4), EDGE_TOLERANCE), Some((0, 1)));
+        assert_eq!(grid.edge_hit(&gp(2.8, 2.5), EDGE_TOLERANCE), Some((10, 11)));
+        assert_eq!(grid.edge_hit(&gp(1.9, 1.5), EDGE_TOLERANCE), Some((5, 6)));
+
+        // A larger tolerance makes points near the boundary ambiguous.
+        assert_eq!(grid.edge_hit(&gp(1.9, 1.5), 4.0 * EDGE_TOLERANCE), None);
 
         // Interior west
-        assert_eq!(grid.edge_hit(&gp(1.1, 0.6)), Some((1, 0)));
-        assert_eq!(grid.edge_hit(&gp(3.2, 2.5)), Some((11, 10)));
-        assert_eq!(grid.edge_hit(&gp(2.1, 1.6)), Some((6, 5)));
+        assert_eq!(grid.edge_hit(&gp(1.1, 0.6), EDGE_TOLERANCE), Some((1, 0)));
+        assert_eq!(grid.edge_hit(&gp(3.2, 2.5), EDGE_TOLERANCE), Some((11, 10)));
+        assert_eq!(grid.edge_hit(&gp(2.1, 1.6), EDGE_TOLERANCE), Some((6, 5)));
+
+        // On diagonals.
+        assert_eq!(grid.edge_hit(&gp(1.3, 1.32), EDGE_TOLERANCE), None);
+        assert_eq!(grid.edge_hit(&gp(2.3, 0.72), EDGE_TOLERANCE), None);
+    }
+
+    #[test]
+    fn inscribed_radius() {
+        let grid = SquareGrid::new(3, 4);
+        for node in 0..12 {
+            assert_eq!(grid.inscribed_radius(node), 0.5);
+        }
+    }
+
+    #[test]
+    fn node_hit() {
+        let grid = SquareGrid::new(3, 4);
+
+        // Outside the grid.
+        assert_eq!(grid.node_hit(&gp(-0.5, 1.5)), None);
+        assert_eq!(grid.node_hit(&gp(4.5, 1.5)), None);
+        assert_eq!(grid.node_hit(&gp(1.5, 3.5)), None);
+
+        // At and near centers.
+        assert_eq!(grid.node_hit(&gp(0.5, 0.5)), Some(0));
+        assert_eq!(grid.node_hit(&gp(3.5, 2.5)), Some(11));
+        assert_eq!(grid.node_hit(&gp(1.3, 1.7)), Some(5));
+        assert_eq!(grid.node_hit(&gp(2.5, 1.9)), Some(6));
+
+        // In the corners of a square, too far from its center.
+        assert_eq!(grid.node_hit(&gp(1.05, 1.05)), None);
+        assert_eq!(grid.node_hit(&gp(2.95, 0.95)), None);
     }
 }
@@ -0,0 +1,1614 @@
+//! Module for the dynamic state of the game.
+//!
+//! This module defines the `State` type and its entourage. The data needed to
+//! run an RBattle game is split into three categories:
+//!
+//! - `State` holds all the significant varying state of a game. What changes as the game proceeds
+//!   are the amount of goop in each node, and the outflows the user has chosen;
+//!   these are the `State`.
+//!
+//! - The positions of nodes and their boundaries and the locations of goop
+//!   sources are all fixed, and are part of the `Map`.
+//!
+//! - Interface elements enter hover and active states as the user mouses
+//!   around, but those states are ephemeral; they are part of the `Input` type.
+//!   When the user actually completes an interaction with an interface element,
+//!   only then is the `State` affected.
+//!
+//! Every host must evolve the `State` identically, so the simulation code here
+//! and in `scheduler` must never depend on anything that varies from one
+//! process to the next. In particular, `HashMap` and `HashSet` iterate in a
+//! randomly seeded order; use `BTreeMap` and `BTreeSet` instead. A test below
+//! enforces this.
+
+use errors::*;
+use fnv::fnv1a;
+use graph::{Node, Graph};
+use map::{Map, MapParameters};
+use version::{self, Version};
+use xorshift::XorShift128Plus;
+
+use rand::Rng;
+
+use std::sync::Arc;
+
+/// The complete state of an RBattle game board.
+#[derive(Clone)]
+pub struct State {
+    /// The map being played on.
+    pub map: Arc<Map>,
+
+    /// The current turn number.
+    pub turn: usize,
+
+    /// The turn on which the current game began: zero, unless the players
+    /// have started a rematch since. Warm-up and the game proper are counted
+    /// from here.
+    pub started: usize,
+
+    /// Which nodes are occupied, and which are vacant. Indexed by node id.
+    pub nodes: Vec<Option<Occupied>>,
+
+    /// Which players have joined the game, indexed by player number. A
+    /// player's source stays vacant until they join.
+    pub joined: Vec<bool>,
+
+    /// The rules this game is played by.
+    pub params: GameParameters,
+
+    /// Events from the last `EVENT_TURNS` turns, oldest first. These are for
+    /// the display's benefit: they aren't part of the game proper, so they
+    /// aren't hashed, or sent to other hosts.
+    pub events: Vec<Event>,
+
+    /// The random number generator used to drive the goop flow algorithm.
+    rng: XorShift128Plus
+}
+
+/// How many turns' events a `State` keeps. The display may not draw every
+/// turn, so it needs a few turns' worth to catch up on.
+const EVENT_TURNS: usize = 64;
+
+/// Something that happened while advancing the state that can't be seen by
+/// comparing the states before and after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// On `turn`, an attack on `node` cleared its outflows, which went to
+    /// `outflows`.
+    OutflowsCleared { turn: usize, node: Node, outflows: Vec<Node> },
+
+    /// During `turn`, `player` said `text` to everyone.
+    Chat { turn: usize, player: Player, text: String },
+}
+
+/// The rules of an RBattle game, apart from the map it's played on. Like the
+/// map, these are fixed for the duration of the game.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GameParameters {
+    /// The number of turns of warm-up play before the game proper begins.
+    /// Players may act freely during warm-up, but when it ends, the board is
+    /// reset to its starting position. Zero means no warm-up.
+    pub warmup_turns: usize,
+
+    /// The position to start from, indexed by node id, if not the usual one
+    /// of each player holding just their source. This lets a position
+    /// captured from one game be played out again as a puzzle: each player
+    /// who joins takes their nodes in it, goop, outflows and all. Warm-up and
+    /// rematches put the board back to this position.
+    #[serde(default)]
+    pub start: Option<Vec<Option<Occupied>>>,
+
+    /// Each player's handicap, indexed by player number. Players past the
+    /// end of the list have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub handicaps: Vec<Handicap>,
+}
+
+impl GameParameters {
+    /// Return `player`'s handicap.
+    pub fn handicap(&self, player: Player) -> Handicap {
+        self.handicaps.get(player.0).cloned().unwrap_or_default()
+    }
+}
+
+/// A way of evening out a game between players of unequal strength, or
+/// against a bot, set by the host for each player.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handicap {
+    /// Sources this player holds produce goop on only one of every this many
+    /// of the turns they otherwise would: 2 halves their supply. Zero or one
+    /// means no handicap. This applies to every source the player holds,
+    /// their own and any they capture.
+    pub goop_every: usize,
+}
+
+/// The phases an RBattle game passes through.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Practice play. The board will be reset after `turns_left` more turns.
+    WarmUp { turns_left: usize },
+
+    /// The game proper. It has been going for `turns` turns.
+    Play { turns: usize },
+}
+
+/// A player id number.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Player(pub usize);
+
+/// The state of a node that is occupied by some player.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Occupied {
+    /// The player who controls this node.
+    pub player: Player,
+
+    /// Which neighbors of this node it sends goop out to.
+    pub outflows: Vec<Node>,
+
+    /// How much goop this node holds. Ranges from 0 to MAX_GOOP.
+    pub goop: usize,
+}
+
+pub const MAX_GOOP: usize = 120;
+
+/// How a player stands in some state, by the measures bots, hints, and the
+/// post-game graphs judge positions by. See `State::evaluate`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// The number of nodes the player occupies.
+    pub territory: usize,
+
+    /// The total goop in those nodes.
+    pub goop: usize,
+
+    /// The number of the player's nodes next to some other player's.
+    pub frontier: usize,
+
+    /// How hard other players are pressing on the player's frontier: the
+    /// sum of `State::pressure_on` over the player's nodes.
+    pub pressure: usize,
+
+    /// The number of sources the player occupies, their own included.
+    pub sources: usize,
+}
+
+/// Return a pair of mutable references to the `i`'th and `j`'th elements of
+/// `slice`, where `i != j`.
+fn index_mut_pair<T>(slice: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
+    if i < j {
+        let (left, right) = slice.split_at_mut(j);
+        (&mut left[i], &mut right[0])
+    } else if j < i {
+        let (left, right) = slice.split_at_mut(i);
+        (&mut right[0], &mut left[j])
+    } else {
+        panic!("can't borrow two mutable references to the same element");
+    }
+}
+
+impl State {
+    pub fn new(map_params: MapParameters, params: GameParameters) -> State {
+        State::on_map(Arc::new(Map::new(map_params)), params)
+    }
+
+    /// Return a new game on `position`'s map, starting from `position`'s board
+    /// rather than the usual one. The rules are otherwise as `params` says.
+    /// No one has joined yet, and the turn count starts over.
+    pub fn puzzle(position: SerializableState, mut params: GameParameters) -> State {
+        params.start = Some(position.nodes);
+        State::on_map(Arc::new(position.map.expect(DETACHED)), params)
+    }
+
+    fn on_map(map: Arc<Map>, params: GameParameters) -> State {
+        let joined = vec![false; map.sources.len()];
+        let nodes = starting_nodes(&map, &params, &joined);
+
+        const SEED: [u64; 2] = [0xcd9d5eaaf04bc9a7, 0x4602cc7098d01ef9];
+        State { map, turn: 0, started: 0, nodes, joined, params, events: vec![],
+                rng: XorShift128Plus::new(SEED) }
+    }
+
+    /// Return the phase of the game this state is in.
+    pub fn phase(&self) -> Phase {
+        let turns = self.turn - self.started;
+        if turns < self.params.warmup_turns {
+            Phase::WarmUp { turns_left: self.params.warmup_turns - turns }
+        } else {
+            Phase::Play { turns: turns - self.params.warmup_turns }
+        }
+    }
+
+    /// Return the winner of the game, if it has been decided. Once the game
+    /// proper is under way and at least two players have joined, the last
+    /// player left occupying any nodes wins.
+    pub fn winner(&self) -> Option<Player> {
+        if let Phase::WarmUp { .. } = self.phase() {
+            return None;
+        }
+        if self.joined.iter().filter(|&&joined| joined).count() < 2 {
+            return None;
+        }
+
+        let counts = self.node_counts();
+        let mut holding = (0..counts.len()).filter(|&player| counts[player] > 0);
+        match (holding.next(), holding.next()) {
+            (Some(player), None) => Some(Player(player)),
+            _ => None,
+        }
+    }
+
+    /// Return the number of players this map can accomodate.
+    pub fn max_players(&self) -> usize { self.map.player_colors.len() }
+
+    /// Return the number of nodes each player occupies, indexed by player
+    /// number.
+    pub fn node_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.max_players()];
+        for occupied in self.nodes.iter().flatten() {
+            counts[occupied.player.0] += 1;
+        }
+        counts
+    }
+
+    /// Return the total amount of goop each player holds, indexed by player
+    /// number.
+    pub fn goop_totals(&self) -> Vec<usize> {
+        let mut totals = vec![0; self.max_players()];
+        for occupied in self.nodes.iter().flatten() {
+            totals[occupied.player.0] += occupied.goop;
+        }
+        totals
+    }
+
+    /// Return how much more goop the strongest enemy neighbor of `node` holds
+    /// than `node` itself does, or zero if it has no stronger enemy neighbor,
+    /// or is vacant.
+    pub fn pressure_on(&self, node: Node) -> usize {
+        let occupied = match self.nodes[node] {
+            Some(ref occupied) => occupied,
+            None => return 0,
+        };
+        self.map.graph.neighbors(node).into_iter()
+            .filter_map(|neighbor| self.nodes[neighbor].as_ref())
+            .filter(|neighbor| neighbor.player != occupied.player)
+            .map(|neighbor| neighbor.goop.saturating_sub(occupied.goop))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Return how each player stands, indexed by player number.
+    pub fn evaluate(&self) -> Vec<Evaluation> {
+        let mut evaluations = vec![Evaluation::default(); self.max_players()];
+        for (node, occupied) in self.nodes.iter().enumerate() {
+            let occupied = match *occupied {
+                Some(ref occupied) => occupied,
+                None => continue,
+            };
+            let evaluation = &mut evaluations[occupied.player.0];
+            evaluation.territory += 1;
+            evaluation.goop += occupied.goop;
+            let bordered = self.map.graph.neighbors(node).into_iter()
+                .filter_map(|neighbor| self.nodes[neighbor].as_ref())
+                .any(|neighbor| neighbor.player != occupied.player);
+            if bordered {
+                evaluation.frontier += 1;
+                evaluation.pressure += self.pressure_on(node);
+            }
+            if self.map.sources.contains(&node) {
+                evaluation.sources += 1;
+            }
+        }
+        evaluations
+    }
+
+    /// Return the players who have joined, from first place to last: ranked
+    /// by the number of nodes they hold, then by their goop, then by player
+    /// number.
+    pub fn standings(&self) -> Vec<Player> {
+        let nodes = self.node_counts();
+        let goop = self.goop_totals();
+        let mut players: Vec<Player> = (0..self.max_players())
+            .filter(|&player| self.joined[player])
+            .map(Player)
+            .collect();
+        players.sort_by_key(|&Player(player)| {
+            (::std::cmp::Reverse(nodes[player]), ::std::cmp::Reverse(goop[player]), player)
+        });
+        players
+    }
+
+    /// Return a SerializableState that can be used to recreate this state.
+    pub fn serializable(&self) -> SerializableState {
+        SerializableState {
+            version: Version::current(),
+            map: Some((*self.map).clone()),
+            turn: self.turn,
+            started: self.started,
+            nodes: self.nodes.clone(),
+            joined: self.joined.clone(),
+            params: self.params.clone(),
+            rng: self.rng.clone()
+        }
+    }
+
+    /// Reconstitute a State from a SerializableState. This will not share the
+    /// map with the original, but that's just a space optimization; the map is
+    /// immutable anyway.
+    pub fn from_serializable(ser: SerializableState) -> State {
+        State {
+            map: Arc::new(ser.map.expect(DETACHED)),
+            turn: ser.turn,
+            started: ser.started,
+            nodes: ser.nodes,
+            joined: ser.joined,
+            params: ser.params,
+            events: vec![],
+            rng: ser.rng
+        }
+    }
+
+    /// Return how this state differs from `base`, an earlier state of the same
+    /// game, so that a participant holding `base` can recreate this one from
+    /// far less than a `SerializableState`. On a large board, most nodes
+    /// don't change from one turn to the next, or even over many.
+    pub fn delta_since(&self, base: &State) -> StateDelta {
+        assert_eq!(self.nodes.len(), base.nodes.len(), "delta between states of different games");
+        let nodes = (0..self.nodes.len())
+            .filter(|&node| self.nodes[node] != base.nodes[node])
+            .map(|node| (node, self.nodes[node].clone()))
+            .collect();
+        StateDelta {
+            base: base.turn,
+            base_checksum: base.checksum(),
+            turn: self.turn,
+            started: self.started,
+            nodes,
+            joined: self.joined.clone(),
+            rng: self.rng.clone(),
+            checksum: self.checksum(),
+        }
+    }
+
+    /// Let one unit of goop flow through each outflow.
+    ///
+    /// There are algorithms for finding the flow through a graph precisely, but
+    /// we need something simpler here. We just visit every outgoing edge in a
+    /// random order, and propagate a unit of goop if the destination permits
+    /// it.
+    ///
+    /// Return a list of the events that occurred.
+    fn flow(&mut self) -> Vec<Event> {
+        // Build a vector of (from, to) pairs.
+        let mut outflow_list = Vec::new();
+        for node in 0..self.map.graph.nodes() {
+            if let Some(ref occupied) = self.nodes[node] {
+                for &outflow in &occupied.outflows {
+                    outflow_list.push((node, outflow))
+                }
+            }
+        }
+
+        // Put the pairs in a random order.
+        self.rng.shuffle(&mut outflow_list);
+
+        let mut events = vec![];
+        while let Some((from_index, to_index)) = outflow_list.pop() {
+            let (from_node, to_node) = index_mut_pair(&mut self.nodes, from_index, to_index);
+
+            // If this flow is an attack, it will clear the defender's
+            // outflows. Note what they were first.
+            let defended = match (&*from_node, &*to_node) {
+                (Some(from), Some(to)) if from.player != to.player
+                                          && !to.outflows.is_empty() => Some(to.outflows.clone()),
+                _ => None,
+            };
+
+            let attacked = simulate_flow(from_node, to_node);
+
+            if attacked {
+                // `to_node` is being attacked. Disregard any outflows from it this turn.
+                outflow_list.retain(|&(from, _)| from != to_index);
+                if let Some(outflows) = defended {
+                    events.push(Event::OutflowsCleared { turn: self.turn, node: to_index,
+                                                         outflows });
+                }
+            }
+        }
+        events
+    }
+
+    /// Let sources generate new goop. Vacant sources, whose players haven't
+    /// joined yet, produce nothing, and handicapped players' sources produce
+    /// only on some of the turns this is called.
+    fn generate_goop(&mut self) {
+        // This is called every other turn; count those calls.
+        let production = self.turn / 2;
+        for &source in &self.map.sources {
+            match self.nodes[source] {
+                Some(Occupied { player, ref mut goop, .. })
+                    if *goop < MAX_GOOP &&
+                       production.is_multiple_of(self.params.handicap(player).goop_every.max(1))
+                    => *goop += 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// Advance `self` to the next state, and return a list of the events
+    /// that occurred along the way. These are also added to `self.events`.
+    pub fn advance(&mut self) -> Vec<Event> {
+        self.turn += 1;
+
+        let turn = self.turn;
+        self.events.retain(|event| event.turn() + EVENT_TURNS > turn);
+
+        // When warm-up ends, put the board back the way it started.
+        if self.params.warmup_turns > 0 &&
+           self.turn == self.started + self.params.warmup_turns
+        {
+            self.nodes = starting_nodes(&self.map, &self.params, &self.joined);
+            return vec![];
+        }
+
+        let events = self.flow();
+        if self.turn & 1 == 0 {
+            self.generate_goop();
+        }
+
+        self.events.extend(events.iter().cloned());
+        events
+    }
+
+    /// Apply `action` to this state.
+    pub fn take_action(&mut self, action: &Action) {
+        match *action {
+            Action::ToggleOutflow { player, from, to } => {
+                match self.nodes[from] {
+                    // This node is empty. Don't change it.
+                    None => (),
+
+                    // Some other player owns this node. Do nothing.
+                    Some(Occupied { player: p, .. }) if p != player => (),
+
+                    // We own this node. Toggle the given outflow.
+                    Some(Occupied { ref mut outflows, .. }) => {
+                        if outflows.contains(&to) {
+                            outflows.retain(|&dest| dest != to);
+                        } else {
+                            outflows.push(to);
+                        }
+                    }
+                }
+            }
+            Action::ClearOutflows { player, node } => {
+                match self.nodes[node] {
+                    Some(Occupied { player: p, ref mut outflows, .. }) if p == player => {
+                        outflows.clear();
+                    }
+                    // The node is empty, or someone else's. Do nothing.
+                    _ => (),
+                }
+            }
+            Action::OpenOutflows { player, node } => {
+                match self.nodes[node] {
+                    Some(Occupied { player: p, ref mut outflows, .. }) if p == player => {
+                        for neighbor in self.map.graph.neighbors(node) {
+                            if !outflows.contains(&neighbor) {
+                                outflows.push(neighbor);
+                            }
+                        }
+                    }
+                    // The node is empty, or someone else's. Do nothing.
+                    _ => (),
+                }
+            }
+            Action::Join { player } => {
+                if self.joined[player.0] {
+                    return;
+                }
+                self.joined[player.0] = true;
+
+                // The newcomer takes possession of their starting nodes, even
+                // if someone else's goop has wandered into them in the mean
+                // time.
+                for (node, occupied) in starting_holdings(&self.map, &self.params, player) {
+                    self.nodes[node] = Some(occupied);
+                }
+            }
+            Action::Restart => {
+                // The turn this action is part of is the new game's first.
+                self.started = self.turn;
+                self.nodes = starting_nodes(&self.map, &self.params, &self.joined);
+            }
+            Action::Surrender { player } => {
+                for node in &mut self.nodes {
+                    if node.as_ref().map(|occupied| occupied.player) == Some(player) {
+                        *node = None;
+                    }
+                }
+            }
+            Action::Chat { player, ref text } => {
+                self.events.push(Event::Chat { turn: self.turn, player, text: text.clone() });
+            }
+        }
+    }
+
+    /// Return the outflows that taking `actions` would open, beyond those
+    /// already open, as `(from, to)` pairs. This lets the interface show
+    /// actions the player has queued before the scheduler carries them out.
+    pub fn queued_outflows(&self, actions: &[Action]) -> Vec<(Node, Node)> {
+        // The outflows each node the actions affect would end up with.
+        let mut predicted: Vec<(Node, Vec<Node>)> = vec![];
+        for action in actions {
+            let (player, node) = match *action {
+                Action::ToggleOutflow { player, from, .. } => (player, from),
+                Action::ClearOutflows { player, node } |
+                Action::OpenOutflows { player, node } => (player, node),
+                _ => continue,
+            };
+            let current = match self.nodes[node] {
+                Some(ref occupied) if occupied.player == player => &occupied.outflows,
+                // Actions on nodes the player doesn't own do nothing.
+                _ => continue,
+            };
+            let index = match predicted.iter().position(|&(other, _)| other == node) {
+                Some(index) => index,
+                None => {
+                    predicted.push((node, current.clone()));
+                    predicted.len() - 1
+                }
+            };
+            let outflows = &mut predicted[index].1;
+            match *action {
+                Action::ToggleOutflow { to, .. } => {
+                    if outflows.contains(&to) {
+                        outflows.retain(|&dest| dest != to);
+                    } else {
+                        outflows.push(to);
+                    }
+                }
+                Action::ClearOutflows { .. } => outflows.clear(),
+                _ => {
+                    for neighbor in self.map.graph.neighbors(node) {
+                        if !outflows.contains(&neighbor) {
+                            outflows.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut opened = vec![];
+        for (node, outflows) in predicted {
+            let current = &self.nodes[node].as_ref().expect("predicted node is occupied").outflows;
+            opened.extend(outflows.into_iter()
+                          .filter(|to| !current.contains(to))
+                          .map(|to| (node, to)));
+        }
+        opened
+    }
+
+    /// Return a checksum over the contents of this state: everything but the
+    /// map, the turn number, and the events.
+    ///
+    /// Every host must compute the same checksum for the same state, whatever
+    /// its platform or compiler version, so this doesn't use `Hash`: neither
+    /// `DefaultHasher`'s algorithm nor the way `Hash` feeds it values is
+    /// promised to stay the same, and `usize` values hash differently on 32-
+    /// and 64-bit machines. Instead, we take the FNV-1a hash of
+    /// `canonical_bytes`.
+    pub fn checksum(&self) -> u64 {
+        fnv1a(&self.canonical_bytes())
+    }
+
+    /// Return a fingerprint of the board and rules this game is played by.
+    /// See `fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(&self.map, &self.params)
+    }
+
+    /// Return the bytes `checksum` hashes. Every number is written as eight
+    /// little-endian bytes, and every list is preceded by its length, in the
+    /// order: the turn the game started, whether each player has joined, each
+    /// node, and the random number generator's state. A vacant node is a zero
+    /// byte; an occupied one is a one byte followed by its player, goop, and
+    /// outflows.
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        number(&mut bytes, self.started as u64);
+        number(&mut bytes, self.joined.len() as u64);
+        bytes.extend(self.joined.iter().map(|&joined| joined as u8));
+        nodes(&mut bytes, &self.nodes);
+        for &word in &self.rng.state() {
+            number(&mut bytes, word);
+        }
+        bytes
+    }
+}
+
+/// Return a fingerprint of `map` and `params`, and of the version of the rules
+/// we play by: everything that decides how a game plays out, other than the
+/// state of the board. Every participant in a game must agree on this, and a
+/// saved game or replay must be played on what it was recorded on; checking
+/// the fingerprint catches a mismatch up front, rather than as a divergence
+/// some turns later.
+///
+/// Like `State::checksum`, this is the FNV-1a hash of a canonical encoding:
+/// the rules version, the board's width and height, each source, the number
+/// of seats, the number of warm-up turns, and the starting position, if
+/// there is one, written like the nodes in `State::canonical_bytes`, with a
+/// zero or one byte first to say whether it's there. If any players have
+/// handicaps, the list of them follows, each as its `goop_every`. The players'
+/// colors and patterns don't matter to the game, so they're left out.
+fn fingerprint(map: &Map, params: &GameParameters) -> u64 {
+    let mut bytes = vec![];
+    number(&mut bytes, version::RULES as u64);
+    let (width, height) = map.graph.size();
+    number(&mut bytes, width as u64);
+    number(&mut bytes, height as u64);
+    number(&mut bytes, map.sources.len() as u64);
+    for &source in &map.sources {
+        number(&mut bytes, source as u64);
+    }
+    number(&mut bytes, map.player_colors.len() as u64);
+    number(&mut bytes, params.warmup_turns as u64);
+    match params.start {
+        None => bytes.push(0),
+        Some(ref start) => {
+            bytes.push(1);
+            nodes(&mut bytes, start);
+        }
+    }
+    if !params.handicaps.is_empty() {
+        number(&mut bytes, params.handicaps.len() as u64);
+        for handicap in &params.handicaps {
+            number(&mut bytes, handicap.goop_every as u64);
+        }
+    }
+    fnv1a(&bytes)
+}
+
+/// Append `n` to `bytes` as eight little-endian bytes.
+fn number(bytes: &mut Vec<u8>, n: u64) {
+    bytes.extend_from_slice(&n.to_le_bytes());
+}
+
+/// Append `nodes` to `bytes`, preceded by their number. A vacant node is a zero
+/// byte; an occupied one is a one byte followed by its player, goop, and
+/// outflows.
+fn nodes(bytes: &mut Vec<u8>, nodes: &[Option<Occupied>]) {
+    number(bytes, nodes.len() as u64);
+    for node in nodes {
+        match *node {
+            None => bytes.push(0),
+            Some(ref occupied) => {
+                bytes.push(1);
+                number(bytes, occupied.player.0 as u64);
+                number(bytes, occupied.goop as u64);
+                number(bytes, occupied.outflows.len() as u64);
+                for &outflow in &occupied.outflows {
+                    number(bytes, outflow as u64);
+                }
+            }
+        }
+    }
+}
+
+impl Event {
+    /// Return the turn on which this event occurred.
+    pub fn turn(&self) -> usize {
+        match *self {
+            Event::OutflowsCleared { turn, .. } |
+            Event::Chat { turn, .. } => turn,
+        }
+    }
+}
+
+/// Return the contents of `map`'s nodes at the start of a game played by
+/// `params`: each player who has joined holds their starting nodes, and
+/// everything else is vacant.
+fn starting_nodes(map: &Map, params: &GameParameters, joined: &[bool])
+                  -> Vec<Option<Occupied>>
+{
+    let mut nodes: Vec<Option<Occupied>> = vec![None; map.graph.nodes()];
+    for player in (0..joined.len()).filter(|&player| joined[player]) {
+        for (node, occupied) in starting_holdings(map, params, Player(player)) {
+            nodes[node] = Some(occupied);
+        }
+    }
+    nodes
+}
+
+/// Return the nodes `player` holds at the start of a game played by
+/// `params`, and what they hold there: normally just their empty source, but
+/// in a puzzle, whatever they held in the starting position.
+fn starting_holdings(map: &Map, params: &GameParameters, player: Player)
+                     -> Vec<(Node, Occupied)>
+{
+    match params.start {
+        Some(ref start) => {
+            start.iter().enumerate()
+                .filter_map(|(node, occupied)| match *occupied {
+                    Some(ref occupied) if occupied.player == player => {
+                        Some((node, occupied.clone()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        }
+        None => vec![(map.sources[player.0], Occupied { player, outflows: vec![], goop: 0 })],
+    }
+}
+
+/// Simulate goop flow from a given cell `from_node` to another cell, `to_node`.
+///
+/// This only simulates flow in that particular direction;
+/// if two neighboring cells are both trying to ooze into each other,
+/// then the State::flow() function will helpfully call this twice,
+/// once for each direction.
+///
+/// See the tests to learn the detailed rules of this function's behavior.
+///
+/// Return true if `from_node` attacked `to_node`—that is, if the two nodes are
+/// occupied by two different players, and any goop flowed. (The caller needs to
+/// know about this, because in this case it must stop `to_node`'s outflows.)
+///
+fn simulate_flow(from_node: &mut Option<Occupied>, to_node: &mut Option<Occupied>) -> bool {
+    match (from_node, to_node) {
+        // We shouldn't have generated a pair for an empty source, and
+        // when we clear a node we're supposed to remove pairs from
+        // `outflow` that originate there.
+        (&mut None, _) => panic!("outflow from empty node"),
+
+        // Source has no goop. No effect.
+        (&mut Some(Occupied { goop: 0, .. }), _) => false,
+
+        // Goop flowing into an unoccupied node. New player claims ownership.
+        (&mut Some(Occupied { player, ref mut goop, .. }),
+         &mut ref mut to @ None) => {
+            *goop -= 1;
+            *to = Some(Occupied { player, outflows: vec![], goop: 1 });
+            false
+        },
+
+        // Goop flowing into a node occupied by the same player.
+        (&mut Some(Occupied { player: from_player, goop: ref mut from_goop, .. }),
+         &mut Some(Occupied { player: to_player,   goop: ref mut to_goop, .. }))
+            if from_player == to_player =>
+        {
+            if *from_goop > 0 && *to_goop < MAX_GOOP {
+                *from_goop -= 1;
+                *to_goop += 1;
+            }
+            false
+        }
+
+        // Goop flowing into a node occupied by another player, but
+        // doesn't clear it. All outflow from destination stopped.
+        (&mut Some(Occupied { goop: ref mut from_goop, .. }),
+         &mut Some(Occupied { outflows: ref mut to_outflows,
+                               goop:     ref mut to_goop, .. }))
+            if *to_goop > 1 =>
+        {
+            *from_goop -= 1;
+            *to_goop -= 1;
+            to_outflows.clear();
+            true
+        },
+
+        // Goop flowing into an occupied node, succeeds in clearing it.
+        (&mut Some(Occupied { player, goop: ref mut from_goop, .. }),
+         &mut Some(ref mut target)) => {
+            *from_goop -= 1;
+            target.player = player;
+            target.outflows.clear();
+            target.goop = 1 - target.goop;
+            true
+        }
+    }
+}
+
+#[test]
+fn test_flow_into_unoccupied_cell() {
+    // The kingdom of Florin is invading the kingdom of Guilder and flooding it with goop.
+    let mut florin = Some(Occupied { player: Player(1), outflows: vec![2], goop: 15 });
+    let mut guilder = None;
+
+    // This isn't considered an attack, since Guilder was completely unoccupied.
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+
+    // One unit of goop flowed. The newly occupied territory now belongs to player 1.
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 14 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 1 }));
+}
+
+#[test]
+fn test_flow_empties_cell() {
+    // Same scenario, except this time there is just one unit of goop in Florin.
+    let mut florin = Some(Occupied { player: Player(1), outflows: vec![2], goop: 1 });
+    let mut guilder = None;
+
+    // As above, this isn't considered an attack.
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+
+    // One unit of goop flowed, leaving Florin emptied of goop (but still
+    // considered occupied by player 1).
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 1 }));
+
+    // In the next tick of the game, no more goop flows, because Florin is now empty.
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+
+    // The state after this second simulated step is therefore exactly the same as before.
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 1 }));
+}
+
+#[test]
+fn test_no_flow_from_empty_cell() {
+    // Florin, alas, has ambitions of conquest but no goop to carry them out with.
+    let mut florin = Some(Occupied { player: Player(1), outflows: vec![2, 3, 4], goop: 0 });
+
+    // Florin can try to flow into a cell that's never been occupied, but since
+    // Florin has no goop, the cell does *not* become occupied.
+    let mut zolot = None;
+    assert!(!simulate_flow(&mut florin, &mut zolot));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3, 4], goop: 0 }));
+    assert_eq!(zolot, None);
+
+    // Nor can Florin attack a cell occupied by an opponent with goop.
+    let mut guilder = Some(Occupied { player: Player(2), outflows: vec![5, 6], goop: 1 });
+    // The attempt does not count as an attack, since no goop flowed.
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3, 4], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(2), outflows: vec![5, 6], goop: 1 }));
+
+    // Same deal even if Guilder also has no goop.
+    guilder = Some(Occupied { player: Player(2), outflows: vec![5, 6], goop: 0 });
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3, 4], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(2), outflows: vec![5, 6], goop: 0 }));
+
+    // Nor can Florin reinforce Guilder if they happen to be occupied by the same player.
+    guilder = Some(Occupied { player: Player(1), outflows: vec![5, 6], goop: 0 });
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3, 4], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![5, 6], goop: 0 }));
+}
+
+#[test]
+fn test_friendly_flow() {
+    // Florin is sending goop into the friendly neighboring province of Krugerrand.
+    let mut florin     = Some(Occupied { player: Player(1), outflows: vec![2], goop: 8 });
+    let mut krugerrand = Some(Occupied { player: Player(1), outflows: vec![3], goop: 0 });
+    // This isn't an attack, since the same player occupies both.
+    assert!(!simulate_flow(&mut florin, &mut krugerrand));
+    // A unit of goop actually flowed.
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 7 }));
+    // Krugerrand received the goop, and its outflows are unaffected.
+    assert_eq!(krugerrand, Some(Occupied { player: Player(1), outflows: vec![3], goop: 1 }));
+
+    // It works even if the destination already has more goop than Florin.
+    krugerrand.as_mut().unwrap().goop = 9;
+    assert!(!simulate_flow(&mut florin, &mut krugerrand));
+    assert_eq!(florin.unwrap().goop, 6);
+    assert_eq!(krugerrand.unwrap().goop, 10);
+}
+
+#[test]
+fn test_friendly_flow_empties_cell() {
+    // Florin can send its only unit of goop to a neighboring empty or nonempty friendly cell.
+    let mut florin = Some(Occupied { player: Player(1), outflows: vec![2, 3], goop: 1 });
+    let mut guilder = Some(Occupied { player: Player(1), outflows: vec![4], goop: 0 });
+    // This isn't an attack, since the same player occupies both.
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+    // A unit of goop actually flowed.
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3], goop: 0 }));
+    // Guilder received the goop, and its outflows are unaffected.
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![4], goop: 1 }));
+
+    // Restore Florin's 1 unit of goop and try again.
+    florin.as_mut().unwrap().goop = 1;
+    assert!(!simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2, 3], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![4], goop: 2 }));
+}
+
+#[test]
+fn test_friendly_flow_max_goop() {
+    // Florin can't pump any more goop into its friendly neighbor Pfennig,
+    // which has the maximum amount already.
+    let mut florin  = Some(Occupied { player: Player(1), outflows: vec![2], goop: 3 });
+    let mut pfennig = Some(Occupied { player: Player(1), outflows: vec![4], goop: MAX_GOOP });
+
+    assert!(!simulate_flow(&mut florin, &mut pfennig));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 3 }));
+    assert_eq!(pfennig, Some(Occupied { player: Player(1), outflows: vec![4], goop: MAX_GOOP }));
+}
+
+#[test]
+fn test_attack_empty_cell() {
+    // Florin siezes the opportunity to invade Guilder, which is left unguarded.
+    let mut florin  = Some(Occupied { player: Player(1), outflows: vec![2], goop: 3 });
+    let mut guilder = Some(Occupied { player: Player(2), outflows: vec![1], goop: 0 });
+
+    // This is an attack!
+    assert!(simulate_flow(&mut florin, &mut guilder));
+    // Afterwards, player 1 controls Guilder. Note that Guilder's `.outflows`
+    // field is cleared. Since Guilder is being attacked, flow through it is inhibited.
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 2 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 1 }));
+
+    // The same thing happens even if Florin invades with its last unit of goop.
+    florin.as_mut().unwrap().goop = 1;
+    guilder = Some(Occupied { player: Player(2), outflows: vec![1], goop: 0 });
+    assert!(simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin, Some(Occupied { player: Player(1), outflows: vec![2], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 1 }));
+}
+
+#[test]
+fn test_attack_occupied_cell() {
+    // Florin can attack Guilder when both cells have positive amounts of goop.
+    let mut florin  = Some(Occupied { player: Player(1), outflows: vec![2], goop: 2 });
+    let mut guilder = Some(Occupied { player: Player(2), outflows: vec![1], goop: 2 });
+
+    assert!(simulate_flow(&mut florin, &mut guilder));
+    // In this case, the outcome is that one unit of Player 1 goop flows into
+    // Guilder, *cancelling out* one unit of Player 2 goop. Again, Guilder's
+    // `.outflows` field is cleared.
+    assert_eq!(florin,  Some(Occupied { player: Player(1), outflows: vec![2], goop: 1 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(2), outflows: vec![], goop: 1 }));
+
+    // Now Player 2 quickly clicks on the boundary, populating `.outflows`
+    // again, in an attempt to counter-attack.
+    guilder.as_mut().unwrap().outflows = vec![1];
+
+    // In the next tick of the game, the same thing happens again. This time,
+    // Guilder is reduced to 0 goop, so the attacker (Player 1) is considered
+    // victorious and gains control.
+    assert!(simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin,  Some(Occupied { player: Player(1), outflows: vec![2], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(1), outflows: vec![], goop: 0 }));
+}
+
+#[test]
+fn test_attack_occupied_cell_losing() {
+    // Florin is again attacking Guilder, but this time it's a losing battle.
+    let mut florin  = Some(Occupied { player: Player(1), outflows: vec![2], goop: 1 });
+    let mut guilder = Some(Occupied { player: Player(2), outflows: vec![1], goop: MAX_GOOP });
+
+    // This still counts as an attack, and Guilder's outflows are still inhibited.
+    assert!(simulate_flow(&mut florin, &mut guilder));
+    assert_eq!(florin,  Some(Occupied { player: Player(1), outflows: vec![2], goop: 0 }));
+    assert_eq!(guilder, Some(Occupied { player: Player(2), outflows: vec![], goop: MAX_GOOP - 1 }));
+}
+
+#[test]
+#[should_panic]
+fn test_flow_from_unoccupied_cell() {
+    // This should never happen, because the simulator won't pass an unoccupied
+    // cell to simulate_flow().
+    let mut zolot = None;
+    let mut zorkmid = None;
+    simulate_flow(&mut zolot, &mut zorkmid);
+}
+
+/// Actions that can be taken on a `State`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Action {
+    /// The `player` has requested to toggle the outflow
+    /// from `from` to `to`.
+    ToggleOutflow { player: Player, from: Node, to: Node },
+
+    /// The `player` has requested to close all of `node`'s outflows.
+    ClearOutflows { player: Player, node: Node },
+
+    /// The `player` has requested to open outflows from `node` to all its
+    /// neighbors.
+    OpenOutflows { player: Player, node: Node },
+
+    /// The `player` has joined the game, and takes possession of their
+    /// source. Only the scheduler generates these.
+    Join { player: Player },
+
+    /// Start a new game with the same players: put the board back the way
+    /// it started, and begin counting turns afresh, warm-up included. Only the
+    /// scheduler generates these, when the host asks for a rematch.
+    Restart,
+
+    /// The `player` gives up: their goop vanishes from the board.
+    Surrender { player: Player },
+
+    /// The `player` says `text` to everyone. This leaves the board alone; it
+    /// only adds an `Event::Chat` to the state's events.
+    Chat { player: Player, text: String },
+}
+
+impl Action {
+    /// Return the player who took this action, or `None` if the scheduler
+    /// generated it.
+    pub fn taken_by(&self) -> Option<Player> {
+        match *self {
+            Action::ToggleOutflow { player, .. } |
+            Action::ClearOutflows { player, .. } |
+            Action::OpenOutflows { player, .. } |
+            Action::Surrender { player } |
+            Action::Chat { player, .. } => Some(player),
+            Action::Join { .. } | Action::Restart => None,
+        }
+    }
+
+    /// Return the nodes this action names.
+    pub fn nodes(&self) -> Vec<Node> {
+        match *self {
+            Action::ToggleOutflow { from, to, .. } => vec![from, to],
+            Action::ClearOutflows { node, .. } |
+            Action::OpenOutflows { node, .. } => vec![node],
+            Action::Join { .. } | Action::Restart |
+            Action::Surrender { .. } | Action::Chat { .. } => vec![],
+        }
+    }
+}
+
+/// The message for using a `SerializableState` whose map was detached, and
+/// never attached again.
+const DETACHED: &str = "state's map was detached and never put back";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerializableState {
+    version: Version,
+
+    /// The map, unless it's been detached to send on its own; see
+    /// `detach_map`. States in files always have their maps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    map: Option<Map>,
+
+    turn: usize,
+    started: usize,
+    nodes: Vec<Option<Occupied>>,
+    joined: Vec<bool>,
+    params: GameParameters,
+    rng: XorShift128Plus
+}
+
+impl SerializableState {
+    /// Return the versions of rbattle this state was written by.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Return a fingerprint of the board and rules this state is played by.
+    /// See `fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(self.map.as_ref().expect(DETACHED), &self.params)
+    }
+
+    /// Take this state's map out of it, so that it can be sent as just its
+    /// hash; see `Map::hash`. Return `None` if it's already been taken.
+    pub fn detach_map(&mut self) -> Option<Map> {
+        self.map.take()
+    }
+
+    /// Put back a map taken out by `detach_map`.
+    pub fn attach_map(&mut self, map: Map) {
+        self.map = Some(map);
+    }
+
+    /// Return this state's map, unless it's been detached.
+    pub fn map(&self) -> Option<&Map> {
+        self.map.as_ref()
+    }
+
+    /// Check that `expected`, the fingerprint `what` gave for this state,
+    /// matches the one we compute. If it doesn't, the board or rules we read
+    /// from `what` aren't the ones it meant.
+    pub fn check_fingerprint(&self, expected: u64, what: &str) -> Result<()> {
+        let actual = self.fingerprint();
+        if actual != expected {
+            bail!("{} doesn't match its own fingerprint ({:016x}, expected {:016x}); \
+                   it may have been written by an incompatible rbattle", what, actual, expected);
+        }
+        Ok(())
+    }
+}
+
+/// How a state differs from an earlier one of the same game. See
+/// `State::delta_since`.
+///
+/// Only the nodes that changed are listed; everything else but the map and the
+/// rules, which stay the same throughout a game, is small enough to send
+/// whole.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateDelta {
+    /// The turn of the state this is relative to, and that state's checksum.
+    base: usize,
+    base_checksum: u64,
+
+    turn: usize,
+    started: usize,
+
+    /// The nodes that differ from the base state's, and what they hold now.
+    nodes: Vec<(Node, Option<Occupied>)>,
+
+    joined: Vec<bool>,
+    rng: XorShift128Plus,
+
+    /// The checksum of the state this recreates.
+    checksum: u64,
+}
+
+impl StateDelta {
+    /// Return the turn of the state this is relative to.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Return the turn of the state this recreates.
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    /// Return the number of nodes this lists as changed.
+    pub fn changed_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Recreate the state this delta was taken of, from `base`. Fail if
+    /// `base` isn't the state it was taken relative to, or if the result
+    /// doesn't match the original's checksum.
+    pub fn apply_to(&self, base: &State) -> Result<State> {
+        if base.turn != self.base || base.checksum() != self.base_checksum {
+            bail!("state delta is relative to turn {} ({:016x}), not turn {} ({:016x})",
+                  self.base, self.base_checksum, base.turn, base.checksum());
+        }
+        let mut state = base.clone();
+        state.turn = self.turn;
+        state.started = self.started;
+        for &(node, ref occupied) in &self.nodes {
+            match state.nodes.get_mut(node) {
+                Some(slot) => *slot = occupied.clone(),
+                None => bail!("state delta changes node {}, which isn't on the board", node),
+            }
+        }
+        state.joined = self.joined.clone();
+        state.events = vec![];
+        state.rng = self.rng.clone();
+        if state.checksum() != self.checksum {
+            bail!("state delta for turn {} doesn't recreate the state it was taken of",
+                  self.turn);
+        }
+        Ok(state)
+    }
+}
+
+#[test]
+fn test_warmup_resets_board() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0],
+        player_colors: vec![(0xff, 0, 0)],
+    }, GameParameters { warmup_turns: 10, ..GameParameters::default() });
+    state.take_action(&Action::Join { player: Player(0) });
+    let start = state.nodes.clone();
+
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    while state.turn < 9 {
+        assert_eq!(state.phase(), Phase::WarmUp { turns_left: 10 - state.turn });
+        state.advance();
+    }
+
+    // Warm-up play has spread goop around.
+    assert_ne!(state.nodes, start);
+
+    // When the game proper starts, the board is put back as it was.
+    state.advance();
+    assert_eq!(state.phase(), Phase::Play { turns: 0 });
+    assert_eq!(state.nodes, start);
+}
+
+#[test]
+fn test_join() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0, 2],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    while state.nodes[1].is_none() {
+        state.advance();
+    }
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 1, to: 2 });
+
+    // Player 1's source stays vacant, and generates no goop, until they join.
+    while state.nodes[2].is_none() {
+        state.advance();
+    }
+    assert_eq!(state.nodes[2].as_ref().unwrap().player, Player(0));
+    assert_eq!(state.node_counts(), vec![3, 0]);
+    let goop: usize = state.nodes.iter().flatten().map(|occupied| occupied.goop).sum();
+    assert_eq!(state.goop_totals(), vec![goop, 0]);
+
+    // When they do join, they take their source back.
+    state.take_action(&Action::Join { player: Player(1) });
+    assert_eq!(state.nodes[2], Some(Occupied { player: Player(1), outflows: vec![], goop: 0 }));
+    assert_eq!(state.joined, vec![true, true]);
+}
+
+#[test]
+fn test_standings() {
+    let mut state = State::new(MapParameters {
+        size: (1, 5),
+        sources: vec![0, 2, 4],
+        player_colors: vec![(0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    assert_eq!(state.standings(), vec![]);
+
+    // Players who haven't joined aren't ranked.
+    state.take_action(&Action::Join { player: Player(2) });
+    state.take_action(&Action::Join { player: Player(1) });
+    assert_eq!(state.standings(), vec![Player(1), Player(2)]);
+
+    // More goop breaks a tie in nodes.
+    state.nodes[4].as_mut().unwrap().goop = 5;
+    assert_eq!(state.standings(), vec![Player(2), Player(1)]);
+
+    // But nodes count for more than goop.
+    state.nodes[1] = Some(Occupied { player: Player(1), outflows: vec![], goop: 1 });
+    assert_eq!(state.standings(), vec![Player(1), Player(2)]);
+}
+
+#[test]
+fn test_evaluate() {
+    let mut state = State::new(MapParameters {
+        size: (1, 5),
+        sources: vec![0, 4],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    assert_eq!(state.evaluate(), vec![Evaluation::default(); 2]);
+
+    // Player 0 holds 0 through 2, pressed on at 2 by player 1's stronger 3.
+    for (node, goop) in [(0, 30), (1, 20), (2, 10)] {
+        state.nodes[node] = Some(Occupied { player: Player(0), outflows: vec![], goop });
+    }
+    state.nodes[3] = Some(Occupied { player: Player(1), outflows: vec![], goop: 25 });
+    assert_eq!(state.pressure_on(2), 15);
+    assert_eq!(state.pressure_on(3), 0);
+    assert_eq!(state.pressure_on(4), 0);
+    assert_eq!(state.evaluate(), vec![
+        Evaluation { territory: 3, goop: 60, frontier: 1, pressure: 15, sources: 1 },
+        Evaluation { territory: 1, goop: 25, frontier: 1, pressure: 0, sources: 0 },
+    ]);
+}
+
+#[test]
+fn test_handicap() {
+    let params = GameParameters {
+        handicaps: vec![Handicap { goop_every: 2 }],
+        .. GameParameters::default()
+    };
+    let mut state = State::new(MapParameters {
+        size: (1, 5),
+        sources: vec![0, 4],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, params);
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+    for _ in 0..8 {
+        state.advance();
+    }
+
+    // Player 0's source produced on only half the turns player 1's did.
+    let goop = |node: Node| state.nodes[node].as_ref().unwrap().goop;
+    assert_eq!(goop(4), 4);
+    assert_eq!(goop(0), 2);
+
+    // Handicaps change how the game plays, so they change the fingerprint.
+    let mut plain = state.clone();
+    plain.params.handicaps.clear();
+    assert_ne!(state.fingerprint(), plain.fingerprint());
+}
+
+#[test]
+fn test_simulation_containers_are_ordered() {
+    // The modules that `State::advance` and the `Scheduler` depend on, which
+    // must all behave identically on every host.
+    let sources = [
+        ("state.rs", include_str!("state.rs")),
+        ("scheduler.rs", include_str!("scheduler.rs")),
+        ("map.rs", include_str!("map.rs")),
+        ("graph.rs", include_str!("graph.rs")),
+        ("square.rs", include_str!("square.rs")),
+        ("xorshift.rs", include_str!("xorshift.rs")),
+    ];
+
+    // Spell the names out piecemeal, so this test doesn't trip over itself.
+    let forbidden = [format!("Hash{}", "Map"), format!("Hash{}", "Set")];
+    for &(file, source) in &sources {
+        let code = source.lines().filter(|line| !line.trim_start().starts_with("//"));
+        for (line, name) in code.flat_map(|line| forbidden.iter().map(move |name| (line, name))) {
+            assert!(!line.contains(name.as_str()),
+                    "{} uses {}, whose iteration order differs between hosts; \
+                     use the BTree equivalent instead", file, name);
+        }
+    }
+}
+
+#[test]
+fn test_winner() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0, 2],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+
+    // Holding the board alone doesn't count until someone else shows up.
+    state.take_action(&Action::Join { player: Player(0) });
+    assert_eq!(state.winner(), None);
+
+    state.take_action(&Action::Join { player: Player(1) });
+    assert_eq!(state.winner(), None);
+
+    state.nodes[2] = None;
+    assert_eq!(state.winner(), Some(Player(0)));
+
+    // Surrendering gives up every node.
+    state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![], goop: 3 });
+    state.nodes[2] = Some(Occupied { player: Player(1), outflows: vec![], goop: 3 });
+    state.take_action(&Action::Surrender { player: Player(0) });
+    assert_eq!(state.node_counts(), vec![0, 1]);
+    assert_eq!(state.winner(), Some(Player(1)));
+}
+
+#[test]
+fn test_restart() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0, 2],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters { warmup_turns: 5, ..GameParameters::default() });
+    state.take_action(&Action::Join { player: Player(0) });
+    while state.phase() != (Phase::Play { turns: 0 }) {
+        state.advance();
+    }
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    while state.turn < 20 {
+        state.advance();
+    }
+    assert!(state.nodes[1].is_some());
+
+    // A restart puts the board back, and the new game gets its own warm-up.
+    state.take_action(&Action::Restart);
+    state.advance();
+    assert_eq!(state.phase(), Phase::WarmUp { turns_left: 4 });
+    assert!(state.nodes[1].is_none());
+    assert!(state.nodes[0].as_ref().unwrap().outflows.is_empty());
+    while state.phase() != (Phase::Play { turns: 0 }) {
+        state.advance();
+    }
+    assert_eq!(state.turn, 25);
+}
+
+#[test]
+fn test_outflows_cleared_event() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0, 2],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+
+    // Player 1 holds the middle node, and flows back into their source.
+    state.nodes[0].as_mut().unwrap().goop = 10;
+    state.nodes[1] = Some(Occupied { player: Player(1), outflows: vec![2], goop: 10 });
+    assert_eq!(state.advance(), vec![]);
+
+    // When player 0 attacks it, the defender's outflows are cleared, and we
+    // hear about it.
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    let cleared = Event::OutflowsCleared { turn: state.turn + 1, node: 1, outflows: vec![2] };
+    assert_eq!(state.advance(), vec![cleared.clone()]);
+    assert_eq!(state.events, vec![cleared]);
+
+    // The attack goes on, but there are no more outflows to clear.
+    assert_eq!(state.advance(), vec![]);
+
+    // Old events are forgotten eventually.
+    for _ in 0..EVENT_TURNS {
+        state.advance();
+    }
+    assert_eq!(state.events, vec![]);
+}
+
+#[test]
+fn test_clear_outflows() {
+    let mut state = State::new(MapParameters {
+        size: (1, 3),
+        sources: vec![0, 2],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![0, 2], goop: 3 });
+
+    // Only the node's owner can close its outflows.
+    state.take_action(&Action::ClearOutflows { player: Player(1), node: 1 });
+    assert_eq!(state.nodes[1].as_ref().unwrap().outflows, vec![0, 2]);
+    state.take_action(&Action::ClearOutflows { player: Player(0), node: 1 });
+    assert!(state.nodes[1].as_ref().unwrap().outflows.is_empty());
+
+    // Clearing an empty node does nothing.
+    state.take_action(&Action::ClearOutflows { player: Player(0), node: 2 });
+    assert!(state.nodes[2].is_none());
+}
+
+#[test]
+fn test_open_outflows() {
+    let mut state = State::new(MapParameters {
+        size: (3, 3),
+        sources: vec![4, 8],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 4, to: 5 });
+
+    // Only the node's owner can open its outflows.
+    state.take_action(&Action::OpenOutflows { player: Player(1), node: 4 });
+    assert_eq!(state.nodes[4].as_ref().unwrap().outflows, vec![5]);
+    state.take_action(&Action::OpenOutflows { player: Player(0), node: 4 });
+    let mut outflows = state.nodes[4].as_ref().unwrap().outflows.clone();
+    outflows.sort();
+    assert_eq!(outflows, vec![1, 3, 5, 7]);
+}
+
+#[test]
+fn test_queued_outflows() {
+    let mut state = State::new(MapParameters {
+        size: (3, 3),
+        sources: vec![4, 8],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 4, to: 5 });
+
+    let toggle = |player, from, to| Action::ToggleOutflow { player: Player(player), from, to };
+    assert!(state.queued_outflows(&[]).is_empty());
+
+    // Toggling twice cancels out, and toggling an open outflow closes it.
+    assert_eq!(state.queued_outflows(&[toggle(0, 4, 1), toggle(0, 4, 3), toggle(0, 4, 1),
+                                       toggle(0, 4, 5)]),
+               vec![(4, 3)]);
+
+    // Toggles on other players' nodes don't count.
+    assert!(state.queued_outflows(&[toggle(1, 4, 1), toggle(0, 8, 7)]).is_empty());
+
+    let mut opened = state.queued_outflows(&[Action::OpenOutflows { player: Player(0), node: 4 }]);
+    opened.sort();
+    assert_eq!(opened, vec![(4, 1), (4, 3), (4, 7)]);
+    assert_eq!(state.queued_outflows(&[Action::ClearOutflows { player: Player(0), node: 4 },
+                                       toggle(0, 4, 7)]),
+               vec![(4, 7)]);
+}
+
+#[test]
+fn test_puzzle() {
+    let mut state = State::new(MapParameters {
+        size: (1, 5),
+        sources: vec![0, 4],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+    state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![2], goop: 30 });
+    state.nodes[3] = Some(Occupied { player: Player(1), outflows: vec![], goop: 7 });
+    let position = state.nodes.clone();
+
+    // A puzzle starts over from the captured position, with no one joined.
+    let params = GameParameters { warmup_turns: 3, ..GameParameters::default() };
+    let mut puzzle = State::puzzle(state.serializable(), params);
+    assert_eq!(puzzle.turn, 0);
+    assert!(puzzle.nodes.iter().all(Option::is_none));
+
+    // Each player who joins takes their nodes in it.
+    puzzle.take_action(&Action::Join { player: Player(0) });
+    assert_eq!(puzzle.nodes[1], position[1]);
+    assert!(puzzle.nodes[3].is_none());
+    puzzle.take_action(&Action::Join { player: Player(1) });
+    assert_eq!(puzzle.nodes, position);
+
+    // Warm-up and rematches put the board back to the position.
+    while puzzle.phase() != (Phase::Play { turns: 0 }) {
+        puzzle.advance();
+    }
+    assert_eq!(puzzle.nodes, position);
+    for _ in 0..10 {
+        puzzle.advance();
+    }
+    assert_ne!(puzzle.nodes, position);
+    puzzle.take_action(&Action::Restart);
+    assert_eq!(puzzle.nodes, position);
+}
+
+#[test]
+fn test_checksum_is_stable() {
+    let mut state = State::new(MapParameters {
+        size: (2, 3),
+        sources: vec![0, 5],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    for _ in 0..40 {
+        state.advance();
+    }
+
+    // Every build, on every platform, must agree on this. If it changes, so
+    // must `version::RULES`.
+    assert_eq!(state.checksum(), 0xac8f_1236_03bd_2c39);
+}
+
+#[test]
+fn test_fingerprint() {
+    fn board(sources: Vec<Node>, player_colors: Vec<(u8, u8, u8)>) -> MapParameters {
+        MapParameters { size: (2, 3), sources, player_colors }
+    }
+    let colors = vec![(0xff, 0, 0), (0, 0, 0xff)];
+    let state = State::new(board(vec![0, 5], colors.clone()), GameParameters::default());
+    let fingerprint = state.fingerprint();
+    assert_eq!(state.serializable().fingerprint(), fingerprint);
+
+    // Play doesn't change the fingerprint, and neither do the colors.
+    let mut played = state.clone();
+    played.take_action(&Action::Join { player: Player(0) });
+    played.advance();
+    assert_eq!(played.fingerprint(), fingerprint);
+    let recolored = State::new(board(vec![0, 5], vec![(0, 0xff, 0), (0xff, 0xff, 0)]),
+                               GameParameters::default());
+    assert_eq!(recolored.fingerprint(), fingerprint);
+
+    // But the board and rules do.
+    let moved = State::new(board(vec![0, 4], colors.clone()), GameParameters::default());
+    assert_ne!(moved.fingerprint(), fingerprint);
+    let warmup = State::new(board(vec![0, 5], colors),
+                            GameParameters { warmup_turns: 10, ..GameParameters::default() });
+    assert_ne!(warmup.fingerprint(), fingerprint);
+    let puzzle = State::puzzle(played.serializable(), GameParameters::default());
+    assert_ne!(puzzle.fingerprint(), fingerprint);
+
+    assert!(state.serializable().check_fingerprint(fingerprint, "game").is_ok());
+    assert!(state.serializable().check_fingerprint(fingerprint ^ 1, "game").is_err());
+}
+
+#[test]
+fn test_delta() {
+    let mut state = State::new(MapParameters {
+        size: (5, 5),
+        sources: vec![0, 24],
+        player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+    }, GameParameters::default());
+    state.take_action(&Action::Join { player: Player(0) });
+    state.take_action(&Action::Join { player: Player(1) });
+    state.advance();
+    let base = state.clone();
+
+    state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+    for _ in 0..20 {
+        state.advance();
+    }
+
+    // Only the nodes that changed are sent, and they bring the base up to date.
+    let delta = state.delta_since(&base);
+    assert_eq!((delta.base(), delta.turn()), (base.turn, state.turn));
+    assert!(delta.changed_nodes() > 0);
+    assert!(delta.changed_nodes() < state.nodes.len());
+    let recreated = delta.apply_to(&base).unwrap();
+    assert_eq!(recreated.turn, state.turn);
+    assert_eq!(recreated.checksum(), state.checksum());
+
+    // A delta only applies to the state it was taken relative to.
+    assert!(delta.apply_to(&state).is_err());
+    assert_eq!(state.delta_since(&state).changed_nodes(), 0);
+}
@@ -0,0 +1,263 @@
+//! The encodings saved games and replays can be written in.
+//!
+//! By default these files are JSON, which is easy to read and to poke at. But
+//! at thirty turns a second, a long game's replay runs to many megabytes. So
+//! given `--binary`, we write MessagePack instead, and given `--compress`, we
+//! run whatever we write through zstd. Reading takes any of these without
+//! being told: a zstd frame starts with its own magic number, and a binary
+//! file starts with `BINARY_MAGIC`, which no JSON text does.
+//!
+//! A binary file's version comes first, on its own, so that we can check it
+//! before trying to make sense of the rest, as we do for JSON. See the
+//! `version` module.
+
+use errors::*;
+use version::{self, Version};
+
+use rmp_serde;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use zstd;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+/// The first bytes of a binary file.
+pub const BINARY_MAGIC: &[u8] = b"\0rbattle\n";
+
+/// The first bytes of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// How we write saved games and replays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Format {
+    /// Write MessagePack, rather than JSON.
+    pub binary: bool,
+
+    /// Compress what we write with zstd.
+    pub compressed: bool,
+}
+
+/// A file being written in some `Format`.
+pub enum Output {
+    Plain(BufWriter<File>),
+    Compressed(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    /// Create the file at `path`, to be written in `format`.
+    pub fn create(path: &Path, format: Format) -> io::Result<Output> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut output = if format.compressed {
+            Output::Compressed(zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?)
+        } else {
+            Output::Plain(file)
+        };
+        if format.binary {
+            output.write_all(BINARY_MAGIC)?;
+        }
+        Ok(output)
+    }
+
+    /// Write out everything written so far, and end the compressed stream, if
+    /// any. Dropping an `Output` does this too, but can't report errors.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match *self {
+            Output::Plain(ref mut file) => file.flush(),
+            Output::Compressed(ref mut encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Output::Plain(ref mut file) => file.write(buf),
+            Output::Compressed(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    /// Write out everything written so far. A compressed stream isn't ended,
+    /// but what has been flushed can be read back even if it never is.
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Output::Plain(ref mut file) => file.flush(),
+            Output::Compressed(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// A file being read, decompressed if need be.
+pub struct Input {
+    pub reader: Box<dyn BufRead>,
+
+    /// True if the file is MessagePack, rather than JSON.
+    pub binary: bool,
+}
+
+impl Input {
+    /// Open the file at `path`, and figure out how it was written.
+    pub fn open(path: &Path) -> io::Result<Input> {
+        Input::detect(BufReader::new(File::open(path)?))
+    }
+
+    /// Figure out how the data `reader` produces was written.
+    pub fn detect<R: BufRead + 'static>(mut reader: R) -> io::Result<Input> {
+        let mut reader: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(ZSTD_MAGIC) {
+            Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        } else {
+            Box::new(reader)
+        };
+        let binary = reader.fill_buf()?.starts_with(BINARY_MAGIC);
+        if binary {
+            reader.consume(BINARY_MAGIC.len());
+        }
+        Ok(Input { reader, binary })
+    }
+
+    /// Return true if there's nothing more to read. A compressed stream that
+    /// stops short of its end, as it does if the program writing it exited
+    /// abruptly, ends at the last point it was flushed.
+    pub fn at_end(&mut self) -> io::Result<bool> {
+        match self.reader.fill_buf() {
+            Ok(buf) => Ok(buf.is_empty()),
+            Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => Ok(true),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Read a `what`, checking its version first. This reads no further than
+    /// the end of the value, so other values may follow it.
+    pub fn read_versioned<T: DeserializeOwned>(&mut self, what: &str) -> Result<T> {
+        if self.binary {
+            let version: Version = rmp_serde::from_read(&mut self.reader)
+                .chain_err(|| format!("{} has a malformed version", what))?;
+            version.check(what)?;
+            rmp_serde::from_read(&mut self.reader)
+                .chain_err(|| format!("malformed {} ({})", what, version))
+        } else {
+            let value = match serde_json::Deserializer::from_reader(&mut self.reader)
+                .into_iter::<Value>().next()
+            {
+                Some(value) => value.chain_err(|| format!("malformed {}", what))?,
+                None => bail!("{} is empty", what),
+            };
+            version::from_value(value, what)
+        }
+    }
+
+    /// Read the next value, in a stream of them following a versioned one.
+    /// This is only for binary input; JSON streams are one value per line.
+    pub fn read_next<T: DeserializeOwned>(&mut self) -> Result<T> {
+        rmp_serde::from_read(&mut self.reader)
+            .chain_err(|| "malformed value")
+    }
+}
+
+/// Write `value`, which carries our version, to `out`, in binary if `binary`
+/// is set, or as a line of JSON otherwise.
+pub fn write_versioned<W: Write, T: Serialize>(out: &mut W, binary: bool, value: &T)
+                                               -> Result<()>
+{
+    if binary {
+        rmp_serde::encode::write_named(out, &Version::current())
+            .chain_err(|| "serializing version")?;
+    }
+    write_next(out, binary, value)
+}
+
+/// Write `value`, following a versioned one, to `out`.
+pub fn write_next<W: Write, T: Serialize>(out: &mut W, binary: bool, value: &T) -> Result<()> {
+    if binary {
+        rmp_serde::encode::write_named(out, value)
+            .chain_err(|| "serializing value")
+    } else {
+        serde_json::to_writer(&mut *out, value)
+            .chain_err(|| "serializing value")?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Thing {
+        version: Version,
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        n: Option<u32>,
+    }
+
+    fn thing(n: Option<u32>) -> Thing {
+        Thing { version: Version::current(), name: "goop".to_string(), n }
+    }
+
+    /// Write `things` in `format` to an in-memory file, and read them back.
+    fn round_trip(format: Format, things: &[Thing]) -> Vec<Thing> {
+        let mut out = vec![];
+        if format.binary {
+            out.extend_from_slice(BINARY_MAGIC);
+        }
+        write_versioned(&mut out, format.binary, &things[0]).unwrap();
+        for thing in &things[1..] {
+            write_next(&mut out, format.binary, thing).unwrap();
+        }
+        if format.compressed {
+            out = zstd::encode_all(&out[..], 0).unwrap();
+        }
+
+        let mut input = Input::detect(Cursor::new(out)).unwrap();
+        assert_eq!(input.binary, format.binary);
+        let mut read = vec![input.read_versioned("thing").unwrap()];
+        if input.binary {
+            while !input.at_end().unwrap() {
+                read.push(input.read_next().unwrap());
+            }
+        } else {
+            for line in input.reader.lines() {
+                let line = line.unwrap();
+                if !line.is_empty() {
+                    read.push(serde_json::from_str(&line).unwrap());
+                }
+            }
+        }
+        read
+    }
+
+    #[test]
+    fn formats() {
+        for &binary in &[false, true] {
+            for &compressed in &[false, true] {
+                let format = Format { binary, compressed };
+                let things = vec![thing(Some(1)), thing(None), thing(Some(3))];
+                assert_eq!(round_trip(format, &things), things, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_version_checked_first() {
+        let mut out = BINARY_MAGIC.to_vec();
+        let old = Version { format: version::FORMAT, rules: version::RULES + 1 };
+        rmp_serde::encode::write_named(&mut out, &old).unwrap();
+        out.extend_from_slice(b"garbage");
+        let mut input = Input::detect(Cursor::new(out)).unwrap();
+        let error = input.read_versioned::<Thing>("thing").err().unwrap();
+        assert!(error.to_string().contains("rules version"));
+    }
+}
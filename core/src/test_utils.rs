@@ -1,4 +1,4 @@
-/// Utilities for tests.
+//! Utilities for tests.
 
 use graph::Node;
 use visible_graph::{GraphPt,IndexedSegment};
@@ -52,10 +52,7 @@ pub struct EqPoint(pub GraphPt);
 
 /// Return the bit pattern of `f`.
 fn f32_bits(f: f32) -> u32 {
-    unsafe {
-        // This is safe because every bit pattern is a valid `u32` value.
-        ::std::mem::transmute::<f32, u32>(f)
-    }
+    f.to_bits()
 }
 
 /// Bit-for-bit comparison on f32 values.
@@ -128,7 +125,7 @@ impl SegmentWithPoints {
     pub fn new(line: &Range<GraphPt>, neighbor: Option<Node>) -> SegmentWithPoints {
         SegmentWithPoints {
             line: order_segment(line),
-            neighbor: neighbor
+            neighbor
         }
     }
 
@@ -153,9 +150,9 @@ pub fn into_points(segments: &[IndexedSegment], endpoints: &[GraphPt]) -> Vec<Se
 
 impl PartialEq<SegmentWithPoints> for SegmentWithPoints {
     fn eq(&self, other: &SegmentWithPoints) -> bool {
-        (EqPoint(self.line.start) == EqPoint(other.line.start) &&
-         EqPoint(self.line.end) == EqPoint(other.line.end) &&
-         self.neighbor == other.neighbor)
+        EqPoint(self.line.start) == EqPoint(other.line.start) &&
+        EqPoint(self.line.end) == EqPoint(other.line.end) &&
+        self.neighbor == other.neighbor
     }
 }
 
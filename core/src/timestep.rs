@@ -0,0 +1,145 @@
+//! Running something at a fixed rate, regardless of how promptly we wake up.
+//!
+//! The scheduler plays turns at a fixed interval, and nothing else in the game
+//! should affect that interval: not how long the window takes to draw a
+//! frame, nor the monitor's refresh rate, nor a sleep that runs long. A
+//! `FixedTimestep` keeps track of how much time has passed and tells its
+//! caller how many steps are due, so that a late wakeup is made up for by
+//! taking several steps at once, rather than by letting the game drift slower.
+//!
+//! Drawing is the opposite case: there's no point in drawing frames we missed,
+//! so a `FrameLimiter` simply keeps the render loop from running faster than
+//! a given rate.
+
+use std::time::{Duration, Instant};
+
+/// The most steps `FixedTimestep::advance` will ask for at once. If we fall
+/// further behind than this, say because the machine was suspended, we drop
+/// the excess rather than racing through a burst of turns.
+const MAX_STEPS: usize = 5;
+
+pub struct FixedTimestep {
+    /// The time between steps.
+    step: Duration,
+
+    /// Time that has passed but not yet been used up by steps.
+    accumulated: Duration,
+
+    /// When we last accounted for the passage of time.
+    last: Instant,
+}
+
+impl FixedTimestep {
+    /// Return a `FixedTimestep` taking a step every `step`, starting at `now`.
+    pub fn new(step: Duration, now: Instant) -> FixedTimestep {
+        FixedTimestep { step, accumulated: Duration::from_secs(0), last: now }
+    }
+
+    /// Take a step every `step` from now on.
+    pub fn set_step(&mut self, step: Duration) {
+        self.step = step;
+    }
+
+    /// Return the number of steps that have come due by `now`.
+    pub fn advance(&mut self, now: Instant) -> usize {
+        self.accumulated += now.saturating_duration_since(self.last);
+        self.last = now;
+
+        let mut steps = 0;
+        while self.accumulated >= self.step {
+            self.accumulated -= self.step;
+            steps += 1;
+        }
+
+        if steps > MAX_STEPS {
+            self.accumulated = Duration::from_secs(0);
+            steps = MAX_STEPS;
+        }
+        steps
+    }
+
+    /// Return how long after the last call to `advance` the next step is due.
+    pub fn until_next(&self) -> Duration {
+        self.step - self.accumulated
+    }
+}
+
+/// Keeps a loop from running more than a given number of times per second.
+pub struct FrameLimiter {
+    /// The shortest time between frames, or `None` if there's no limit.
+    interval: Option<Duration>,
+
+    /// When the next frame may start.
+    next: Instant,
+}
+
+impl FrameLimiter {
+    /// Return a limiter allowing at most `fps` frames per second, starting at
+    /// `now`. If `fps` is zero, there is no limit.
+    pub fn new(fps: u32, now: Instant) -> FrameLimiter {
+        let interval = if fps > 0 { Some(Duration::from_secs(1) / fps) } else { None };
+        FrameLimiter { interval, next: now }
+    }
+
+    /// Return how long to wait before starting the next frame, given that the
+    /// current one finished at `now`. If we're running behind, don't try to
+    /// make up for it by rushing the following frames.
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return Duration::from_secs(0),
+        };
+        self.next += interval;
+        if self.next > now {
+            self.next - now
+        } else {
+            self.next = now;
+            Duration::from_secs(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut timestep = FixedTimestep::new(ms(10), start);
+
+        assert_eq!(timestep.advance(start + ms(5)), 0);
+        assert_eq!(timestep.until_next(), ms(5));
+        assert_eq!(timestep.advance(start + ms(10)), 1);
+
+        // A late wakeup is made up with extra steps, and the remainder
+        // carries over.
+        assert_eq!(timestep.advance(start + ms(34)), 2);
+        assert_eq!(timestep.until_next(), ms(6));
+
+        // Falling far behind doesn't produce an unbounded burst.
+        assert_eq!(timestep.advance(start + ms(1000)), MAX_STEPS);
+        assert_eq!(timestep.until_next(), ms(10));
+
+        timestep.set_step(ms(20));
+        assert_eq!(timestep.advance(start + ms(1030)), 1);
+    }
+
+    #[test]
+    fn frame_limiter() {
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+
+        let mut limiter = FrameLimiter::new(50, start);
+        assert_eq!(limiter.delay(start + ms(5)), ms(15));
+        assert_eq!(limiter.delay(start + ms(20)), ms(20));
+
+        // A slow frame gets no delay, and doesn't make later frames hurry.
+        assert_eq!(limiter.delay(start + ms(100)), ms(0));
+        assert_eq!(limiter.delay(start + ms(105)), ms(15));
+
+        let mut unlimited = FrameLimiter::new(0, start);
+        assert_eq!(unlimited.delay(start), ms(0));
+    }
+}
@@ -0,0 +1,382 @@
+//! Measuring how well the scheduler keeps to its schedule.
+//!
+//! When a game feels sluggish, it's hard to tell from the outside whether the
+//! server is slow to play turns, the timer is waking up late, or some player
+//! is slow to respond. The scheduler records a `TurnTiming` for each turn it
+//! plays, keeping the most recent few in a `TimingLog`, which can summarize
+//! them for logging or display.
+//!
+//! Likewise, when the picture stutters, it helps to know how long frames are
+//! really taking to draw. The render loop records each frame's duration in a
+//! `FrameTimes`, for logging, and in a `PerfMeter`, for the performance
+//! overlay.
+
+use state::Player;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// The number of turns' timings a `TimingLog` retains.
+const TIMING_TURNS: usize = 600;
+
+/// How long the scheduler took over one turn.
+#[derive(Clone, Debug)]
+pub struct TurnTiming {
+    /// The turn these timings are for.
+    pub turn: usize,
+
+    /// How far the time since the previous turn differed from the intended
+    /// time between turns, in either direction.
+    pub jitter: Duration,
+
+    /// How long it took to apply the turn's actions and advance the state.
+    pub processing: Duration,
+
+    /// For each player, how long after the previous turn their actions for
+    /// this turn arrived, or `None` if they didn't arrive in time. Indexed by
+    /// player number.
+    pub waits: Vec<Option<Duration>>,
+}
+
+/// A record of the scheduler's timing over recent turns.
+pub struct TimingLog {
+    /// The timings of recent turns, oldest first.
+    turns: VecDeque<TurnTiming>,
+
+    /// When the previous turn was played.
+    last_played: Instant,
+
+    /// When each player's actions for the coming turn arrived, measured from
+    /// `last_played`. Indexed by player number.
+    arrivals: Vec<Option<Duration>>,
+}
+
+impl TimingLog {
+    pub fn new() -> TimingLog {
+        TimingLog {
+            turns: VecDeque::new(),
+            last_played: Instant::now(),
+            arrivals: vec![],
+        }
+    }
+
+    /// Note that `player`'s actions for the coming turn have arrived.
+    pub fn submitted(&mut self, player: Player) {
+        if self.arrivals.len() <= player.0 {
+            self.arrivals.resize(player.0 + 1, None);
+        }
+        let arrival = &mut self.arrivals[player.0];
+        if arrival.is_none() {
+            *arrival = Some(self.last_played.elapsed());
+        }
+    }
+
+    /// Record the timing of `turn`, which we began playing at `started`,
+    /// having meant to play it `turn_duration` after the previous one. The
+    /// game has `players` players.
+    pub fn played(&mut self, turn: usize, players: usize,
+                  started: Instant, turn_duration: Duration) {
+        let now = Instant::now();
+        self.arrivals.resize(players, None);
+        let interval = started.saturating_duration_since(self.last_played);
+        let jitter = interval.abs_diff(turn_duration);
+
+        if self.turns.len() >= TIMING_TURNS {
+            self.turns.pop_front();
+        }
+        self.turns.push_back(TurnTiming {
+            turn,
+            jitter,
+            processing: now - started,
+            waits: self.arrivals.drain(..).collect(),
+        });
+        self.last_played = started;
+    }
+
+    /// Return the timings of recent turns, oldest first.
+    pub fn turns(&self) -> impl Iterator<Item = &TurnTiming> {
+        self.turns.iter()
+    }
+
+    /// Summarize the timings of recent turns.
+    pub fn summary(&self) -> TimingSummary {
+        let count = self.turns.len() as u32;
+        let mut summary = TimingSummary::default();
+        match (self.turns.front(), self.turns.back()) {
+            (Some(first), Some(last)) => summary.turns = first.turn .. last.turn + 1,
+            _ => return summary,
+        }
+
+        for timing in self.turns() {
+            summary.mean_jitter += timing.jitter / count;
+            summary.max_jitter = summary.max_jitter.max(timing.jitter);
+            summary.mean_processing += timing.processing / count;
+            summary.max_processing = summary.max_processing.max(timing.processing);
+
+            if summary.players.len() < timing.waits.len() {
+                summary.players.resize(timing.waits.len(), PlayerTiming::default());
+            }
+            for (player, wait) in summary.players.iter_mut().zip(&timing.waits) {
+                match *wait {
+                    Some(wait) => {
+                        player.arrived += 1;
+                        player.mean_wait += wait;
+                        player.max_wait = player.max_wait.max(wait);
+                    }
+                    None => player.missed += 1,
+                }
+            }
+        }
+        for player in &mut summary.players {
+            if player.arrived > 0 {
+                player.mean_wait /= player.arrived as u32;
+            }
+        }
+
+        summary
+    }
+}
+
+impl Default for TimingLog {
+    fn default() -> TimingLog {
+        TimingLog::new()
+    }
+}
+
+/// Averages and extremes of the scheduler's timing over recent turns.
+#[derive(Clone, Debug, Default)]
+pub struct TimingSummary {
+    /// The turns summarized.
+    pub turns: Range<usize>,
+
+    pub mean_jitter: Duration,
+    pub max_jitter: Duration,
+    pub mean_processing: Duration,
+    pub max_processing: Duration,
+
+    /// Statistics for each player, indexed by player number.
+    pub players: Vec<PlayerTiming>,
+}
+
+/// How promptly one player has been submitting their actions.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerTiming {
+    /// The number of turns for which the player's actions arrived in time.
+    pub arrived: usize,
+
+    /// The number of turns played without the player's actions.
+    pub missed: usize,
+
+    /// The average and longest time between a turn being played and the
+    /// player's actions for the next turn arriving.
+    pub mean_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "turns {}..{}: jitter {:?} mean, {:?} max; processing {:?} mean, {:?} max",
+               self.turns.start, self.turns.end, self.mean_jitter, self.max_jitter,
+               self.mean_processing, self.max_processing)?;
+        for (player, timing) in self.players.iter().enumerate() {
+            write!(f, "\n  player {}: wait {:?} mean, {:?} max; missed {} of {}",
+                   player, timing.mean_wait, timing.max_wait,
+                   timing.missed, timing.arrived + timing.missed)?;
+        }
+        Ok(())
+    }
+}
+
+/// The durations of frames drawn since the last summary.
+pub struct FrameTimes {
+    frames: Vec<Duration>,
+}
+
+impl FrameTimes {
+    pub fn new() -> FrameTimes {
+        FrameTimes { frames: vec![] }
+    }
+
+    /// Note that a frame took `duration` from start to finish.
+    pub fn record(&mut self, duration: Duration) {
+        self.frames.push(duration);
+    }
+
+    /// Summarize the frames recorded since the last call, and start afresh.
+    pub fn take_summary(&mut self) -> FrameSummary {
+        let frames = self.frames.len();
+        let total: Duration = self.frames.iter().sum();
+        let summary = FrameSummary {
+            frames,
+            mean: if frames > 0 { total / frames as u32 } else { Duration::from_secs(0) },
+            max: self.frames.iter().cloned().max().unwrap_or_default(),
+        };
+        self.frames.clear();
+        summary
+    }
+}
+
+impl Default for FrameTimes {
+    fn default() -> FrameTimes {
+        FrameTimes::new()
+    }
+}
+
+/// Statistics about recently drawn frames.
+#[derive(Clone, Debug, Default)]
+pub struct FrameSummary {
+    /// The number of frames drawn.
+    pub frames: usize,
+
+    pub mean: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} frames: {:?} mean, {:?} max", self.frames, self.mean, self.max)
+    }
+}
+
+/// How far back a `PerfMeter` looks.
+const PERF_WINDOW: Duration = Duration::from_secs(1);
+
+/// A running record of the last second's frames, for the performance overlay.
+pub struct PerfMeter {
+    /// When each recent frame finished, how long it took, and the turn it
+    /// showed, oldest first.
+    frames: VecDeque<(Instant, Duration, usize)>,
+}
+
+impl PerfMeter {
+    pub fn new() -> PerfMeter {
+        PerfMeter { frames: VecDeque::new() }
+    }
+
+    /// Note that a frame showing `turn` finished at `now`, having taken
+    /// `duration`.
+    pub fn record(&mut self, now: Instant, duration: Duration, turn: usize) {
+        while let Some(&(finished, _, _)) = self.frames.front() {
+            if now.saturating_duration_since(finished) <= PERF_WINDOW {
+                break;
+            }
+            self.frames.pop_front();
+        }
+        self.frames.push_back((now, duration, turn));
+    }
+
+    /// Summarize the frames of the last second. We were `turns_behind` the
+    /// server, as of its last word.
+    pub fn stats(&self, turns_behind: usize) -> PerfStats {
+        let mut stats = PerfStats { turns_behind, .. PerfStats::default() };
+        let (first, last) = match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return stats,
+        };
+
+        let count = self.frames.len() as u32;
+        for &(_, duration, _) in &self.frames {
+            stats.mean_frame += duration / count;
+            stats.max_frame = stats.max_frame.max(duration);
+        }
+
+        // Rates are measured between the first and last frames, so there must
+        // be some time between them.
+        let span = last.0.saturating_duration_since(first.0).as_secs_f32();
+        if span > 0.0 {
+            stats.frames_per_second = (count - 1) as f32 / span;
+            stats.turns_per_second = last.2.saturating_sub(first.2) as f32 / span;
+        }
+        stats
+    }
+}
+
+impl Default for PerfMeter {
+    fn default() -> PerfMeter {
+        PerfMeter::new()
+    }
+}
+
+/// Figures for the performance overlay.
+#[derive(Clone, Debug, Default)]
+pub struct PerfStats {
+    /// How long frames took to draw, on average and at worst.
+    pub mean_frame: Duration,
+    pub max_frame: Duration,
+
+    pub frames_per_second: f32,
+
+    /// How quickly the game is advancing, as we see it.
+    pub turns_per_second: f32,
+
+    /// How many turns behind the server we were, as of its last word.
+    pub turns_behind: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut log = TimingLog::new();
+        assert_eq!(log.summary().turns, 0..0);
+
+        log.submitted(Player(0));
+        log.submitted(Player(1));
+        log.played(1, 2, Instant::now(), Duration::from_millis(16));
+
+        // Player 1 misses turn 2. A second submission from player 0 doesn't
+        // change when their actions first arrived.
+        log.submitted(Player(0));
+        log.submitted(Player(0));
+        log.played(2, 2, Instant::now(), Duration::from_millis(16));
+
+        let turns: Vec<_> = log.turns().map(|timing| timing.turn).collect();
+        assert_eq!(turns, vec![1, 2]);
+        assert!(log.turns().last().unwrap().waits[0].is_some());
+
+        let summary = log.summary();
+        assert_eq!(summary.turns, 1..3);
+        assert_eq!(summary.players.len(), 2);
+        assert_eq!((summary.players[0].arrived, summary.players[0].missed), (2, 0));
+        assert_eq!((summary.players[1].arrived, summary.players[1].missed), (1, 1));
+    }
+
+    #[test]
+    fn frames() {
+        let mut times = FrameTimes::new();
+        times.record(Duration::from_millis(10));
+        times.record(Duration::from_millis(30));
+        let summary = times.take_summary();
+        assert_eq!(summary.frames, 2);
+        assert_eq!(summary.mean, Duration::from_millis(20));
+        assert_eq!(summary.max, Duration::from_millis(30));
+
+        assert_eq!(times.take_summary().frames, 0);
+    }
+
+    #[test]
+    fn perf() {
+        let mut meter = PerfMeter::new();
+        assert_eq!(meter.stats(3).turns_behind, 3);
+        assert_eq!(meter.stats(0).frames_per_second, 0.0);
+
+        // Ten frames a tenth of a second apart, two turns per frame.
+        let start = Instant::now();
+        for i in 0..10 {
+            meter.record(start + Duration::from_millis(i * 100),
+                         Duration::from_millis(5 + i), 2 * i as usize);
+        }
+        let stats = meter.stats(0);
+        assert!((stats.frames_per_second - 10.0).abs() < 0.01);
+        assert!((stats.turns_per_second - 20.0).abs() < 0.01);
+        assert_eq!(stats.max_frame, Duration::from_millis(14));
+
+        // Frames more than a second old are forgotten.
+        meter.record(start + Duration::from_millis(1500), Duration::from_millis(1), 30);
+        assert_eq!(meter.frames.len(), 6);
+    }
+}
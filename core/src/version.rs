@@ -0,0 +1,119 @@
+//! Telling which rbattle wrote a file, and whether we can read it.
+//!
+//! Saved games, replays and state dumps all carry a `Version`: the format
+//! version says how the file is laid out, and the rules version says which
+//! rules the state in it was played by. A replay from under different rules
+//! would play out differently than it did, and fail its checksums partway
+//! through; a save would carry on a game whose goop meant something else. So
+//! we refuse anything whose rules differ from ours, or whose format is newer
+//! than we understand.
+//!
+//! Readers check the version before deserializing the rest, so a file from
+//! another rbattle gets an error saying so, not a complaint about some field
+//! deep inside the state.
+//!
+//! Bump `FORMAT` when the layout of any of these files changes, and `RULES`
+//! when anything in `state` or `scheduler` changes how the game plays out.
+
+use errors::*;
+
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use std::fmt;
+
+/// The newest file format this rbattle reads, and the one it writes.
+pub const FORMAT: u32 = 3;
+
+/// The version of the rules this rbattle plays by.
+pub const RULES: u32 = 2;
+
+/// The versions a file was written with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub format: u32,
+    pub rules: u32,
+}
+
+impl Version {
+    /// Return the versions this rbattle writes.
+    pub fn current() -> Version {
+        Version { format: FORMAT, rules: RULES }
+    }
+
+    /// Check that we can read a `what` written with these versions.
+    pub fn check(&self, what: &str) -> Result<()> {
+        if self.format > FORMAT {
+            bail!("{} is in format version {}, but this rbattle only reads up to version {}; \
+                   it was written by a newer rbattle", what, self.format, FORMAT);
+        }
+        if self.rules != RULES {
+            bail!("{} was played under rules version {}, but this rbattle plays by version {}",
+                  what, self.rules, RULES);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "format {}, rules {}", self.format, self.rules)
+    }
+}
+
+/// Deserialize a `what` from `value`, a JSON object with a `version` field,
+/// after checking that we can read it.
+pub fn from_value<T: DeserializeOwned>(value: Value, what: &str) -> Result<T> {
+    let version = match value.get("version") {
+        Some(version) => serde_json::from_value::<Version>(version.clone())
+            .chain_err(|| format!("{} has a malformed version", what))?,
+        None => bail!("{} has no version; it was written by an older rbattle, \
+                       before files were versioned", what),
+    };
+    version.check(what)?;
+    serde_json::from_value(value)
+        .chain_err(|| format!("malformed {} ({})", what, version))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Thing {
+        version: Version,
+        n: u32,
+    }
+
+    fn read(text: &str) -> Result<Thing> {
+        from_value(serde_json::from_str(text).unwrap(), "thing")
+    }
+
+    fn message(result: Result<Thing>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn versions() {
+        let current = format!("{{\"format\": {}, \"rules\": {}}}", FORMAT, RULES);
+        let thing = read(&format!("{{\"version\": {}, \"n\": 7}}", current)).unwrap();
+        assert_eq!(thing.version, Version::current());
+        assert_eq!(thing.n, 7);
+
+        assert!(message(read("{\"n\": 7}")).contains("older rbattle"));
+        assert!(message(read(&format!("{{\"version\": {{\"format\": {}, \"rules\": {}}}}}",
+                                      FORMAT + 1, RULES)))
+                .contains("newer rbattle"));
+        assert!(message(read(&format!("{{\"version\": {{\"format\": {}, \"rules\": {}}}}}",
+                                      FORMAT, RULES + 1)))
+                .contains("rules version"));
+
+        // The version is checked before anything else, but the rest must
+        // still make sense.
+        assert!(message(read(&format!("{{\"version\": {}, \"n\": \"seven\"}}", current)))
+                .contains("malformed thing"));
+    }
+}
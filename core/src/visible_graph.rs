@@ -1,9 +1,18 @@
 //! The `VisibleGraph` trait, and types it refers to.
 
 use graph::{Graph, Node};
+use math::segment_distance;
 use std::fmt::Debug;
 use std::ops::Range;
 
+/// How close, in graph units, a point may come to a boundary between nodes,
+/// or to the line between two of a node's edges, before `edge_hit` calls it
+/// ambiguous, at a UI scale of 1 and with the camera zoomed all the way out.
+/// A larger UI scale widens this, so that an unsteady hand near a boundary
+/// doesn't toggle the wrong outflow. Players can choose a different value with
+/// `--hit-tolerance`.
+pub const EDGE_TOLERANCE: f32 = 0.05;
+
 /// A `Graph` that can be drawn on the screen.
 ///
 /// Each node of a `VisibleGraph` has a designated center position.
@@ -50,7 +59,6 @@ use std::ops::Range;
 /// OpenGL prefers to have actual points and drawable items separated in this
 /// way, because sharing vertex positions reduces the amount of data that must
 /// be moved from CPU to GPU to draw a given frame.
-
 pub trait VisibleGraph: Graph + Debug {
     /// Return the upper-right corner of the smallest axis-aligned
     /// bounding box that contains all nodes' areas.
@@ -77,8 +85,30 @@ pub trait VisibleGraph: Graph + Debug {
     /// node, rather than simply being an unordered pair of nodes.
     ///
     /// If the point does identify an outgoing graph edge, return the a pair
-    /// `(from, to)`.
-    fn edge_hit(&self, &GraphPt) -> Option<(Node, Node)>;
+    /// `(from, to)`. Points within `tolerance` of where one edge's area meets
+    /// another's are ambiguous, and identify nothing.
+    fn edge_hit(&self, point: &GraphPt, tolerance: f32) -> Option<(Node, Node)>;
+
+    /// Determine which node the given point lies over, judging by its distance
+    /// from the nodes' centers: a point within `radius()` of a node's center
+    /// hits that node. If the point is not that close to any node's center,
+    /// return `None`.
+    fn node_hit(&self, point: &GraphPt) -> Option<Node>;
+
+    /// Return the radius of the largest circle centered on `node`'s center
+    /// that fits within its area. Unlike `radius()`, this may vary from node
+    /// to node.
+    fn inscribed_radius(&self, node: Node) -> f32 {
+        let endpoints = self.endpoints();
+        let GraphPt(center) = self.center(node);
+        self.boundary(node).iter()
+            .map(|segment| {
+                let GraphPt(start) = endpoints[segment.line.start];
+                let GraphPt(end) = endpoints[segment.line.end];
+                segment_distance(center, start, end)
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
 }
 
 /// A point in the graph coordinate space.
@@ -0,0 +1,312 @@
+//! Bots written as WebAssembly modules.
+//!
+//! So that people can pit bots of their own against each other without the
+//! host having to trust their code, a bot can be a WebAssembly module, run by
+//! an interpreter that gives it nothing but a view of the game and a way to
+//! make moves. Each turn, the module gets a fixed amount of fuel, spent as it
+//! executes instructions; a module that runs out, or traps, makes no moves
+//! that turn, but is asked again the next. Its memory is limited too.
+//!
+//! A bot module must export:
+//!
+//! - `memory`: its linear memory.
+//!
+//! - `alloc(len: i32) -> i32`: return the address of `len` bytes of memory the
+//!   host may fill with the view of the game. This may be the same space every
+//!   turn.
+//!
+//! - `act(ptr: i32, len: i32)`: choose this turn's moves, given the view at
+//!   `ptr`, `len` bytes long.
+//!
+//! It may import, from the module `rbattle`:
+//!
+//! - `toggle_outflow(from: i32, to: i32)`: toggle the outflow from node
+//!   `from` to node `to`, as clicking it would. Only the first
+//!   `MAX_TOGGLES` toggles each turn count, and toggles between nodes that
+//!   aren't neighbors are ignored.
+//!
+//! The view is a sequence of little-endian 32-bit words:
+//!
+//! - the turn number;
+//! - the player the bot is playing for;
+//! - the number of sources, and each source's node, in player order;
+//! - the number of nodes, and then for each node, in order:
+//!   - its owner's player number, or `0xffffffff` if it's vacant,
+//!   - the goop it holds,
+//!   - the number of its neighbors, and each neighbor's node,
+//!   - the number of its open outflows, and the node each goes to.
+//!
+//! Nothing else about the game, like how many turns it takes goop to move,
+//! is in the view: a bot must learn that by watching.
+
+use bot::Bot;
+use errors::*;
+use graph::Graph;
+use state::{Action, Player, State};
+
+use wasmi::{Caller, Config, Engine, Linker, Memory, Module, Store, StoreLimits,
+            StoreLimitsBuilder, TypedFunc};
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// How much fuel a bot gets each turn, and to set itself up. Most of
+/// WebAssembly's instructions take one unit.
+pub const FUEL_PER_TURN: u64 = 1_000_000;
+
+/// The most memory a bot may have, in bytes.
+pub const MAX_MEMORY: usize = 16 << 20;
+
+/// The most outflows a bot may toggle in one turn.
+pub const MAX_TOGGLES: usize = 4;
+
+/// A compiled bot module, from which we can make as many bots as we like.
+#[derive(Clone)]
+pub struct WasmModule {
+    engine: Engine,
+    module: Arc<Module>,
+}
+
+impl WasmModule {
+    /// Compile the bot module `wasm`, and check that it has the exports and
+    /// imports a bot needs and no others, and sets itself up within its fuel.
+    pub fn new(wasm: &[u8]) -> Result<WasmModule> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)
+            .map_err(|error| format!("invalid bot module: {}", error))?;
+        let module = WasmModule { engine, module: Arc::new(module) };
+        module.instantiate()?;
+        Ok(module)
+    }
+
+    /// Load the bot module in the file at `path`.
+    pub fn load(path: &Path) -> Result<WasmModule> {
+        let wasm = fs::read(path)
+            .chain_err(|| format!("reading bot module {}", path.display()))?;
+        WasmModule::new(&wasm)
+            .chain_err(|| format!("loading bot module {}", path.display()))
+    }
+
+    /// Return a fresh bot running this module. Each has its own memory.
+    pub fn bot(&self) -> Box<dyn Bot> {
+        // `new` has already instantiated the module once, so this can't fail
+        // for want of an export. If it fails nonetheless, the bot just sits.
+        match self.instantiate() {
+            Ok(bot) => Box::new(bot),
+            Err(_) => Box::new(WasmBot { store: self.store(), exports: None }),
+        }
+    }
+
+    fn store(&self) -> Store<Host> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, Host { limits, toggles: vec![] });
+        store.limiter(|host| &mut host.limits);
+        store
+    }
+
+    fn instantiate(&self) -> Result<WasmBot> {
+        let mut store = self.store();
+        store.set_fuel(FUEL_PER_TURN).expect("fuel should be enabled");
+
+        let mut linker = Linker::new(&self.engine);
+        linker.func_wrap("rbattle", "toggle_outflow",
+                         |mut caller: Caller<Host>, from: u32, to: u32| {
+                             let toggles = &mut caller.data_mut().toggles;
+                             if toggles.len() < MAX_TOGGLES {
+                                 toggles.push((from, to));
+                             }
+                         })
+            .expect("a fresh linker should have no definitions");
+
+        let instance = linker.instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|error| format!("bot module failed to start: {}", error))?;
+        let memory = instance.get_memory(&store, "memory")
+            .ok_or("bot module doesn't export its memory")?;
+        let alloc = instance.get_typed_func(&store, "alloc")
+            .map_err(|error| format!("bot module has no usable `alloc` export: {}", error))?;
+        let act = instance.get_typed_func(&store, "act")
+            .map_err(|error| format!("bot module has no usable `act` export: {}", error))?;
+        Ok(WasmBot { store, exports: Some(Exports { memory, alloc, act }) })
+    }
+}
+
+/// What a bot module's instance can get at from outside.
+struct Host {
+    limits: StoreLimits,
+
+    /// The outflows the bot has asked to toggle this turn.
+    toggles: Vec<(u32, u32)>,
+}
+
+/// The exports a bot module must provide.
+struct Exports {
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    act: TypedFunc<(u32, u32), ()>,
+}
+
+/// A bot running a WebAssembly module.
+pub struct WasmBot {
+    store: Store<Host>,
+
+    /// The instance's exports, or `None` if it couldn't be instantiated.
+    exports: Option<Exports>,
+}
+
+impl Bot for WasmBot {
+    fn act(&mut self, state: &State, player: Player) -> Vec<Action> {
+        let store = &mut self.store;
+        let exports = match self.exports {
+            Some(ref exports) => exports,
+            None => return vec![],
+        };
+        store.data_mut().toggles.clear();
+        store.set_fuel(FUEL_PER_TURN).expect("fuel should be enabled");
+
+        let view = view(state, player);
+        let ran = exports.alloc.call(&mut *store, view.len() as u32)
+            .map_err(|_| ())
+            .and_then(|ptr| {
+                exports.memory.write(&mut *store, ptr as usize, &view).map_err(|_| ())?;
+                exports.act.call(&mut *store, (ptr, view.len() as u32)).map_err(|_| ())
+            });
+        if ran.is_err() {
+            return vec![];
+        }
+
+        let graph = &state.map.graph;
+        store.data().toggles.iter()
+            .map(|&(from, to)| (from as usize, to as usize))
+            .filter(|&(from, to)| from < state.nodes.len() && graph.neighbors(from).contains(&to))
+            .map(|(from, to)| Action::ToggleOutflow { player, from, to })
+            .collect()
+    }
+}
+
+/// Return the view of `state` a bot module playing for `player` gets.
+fn view(state: &State, player: Player) -> Vec<u8> {
+    let mut words = vec![state.turn as u32, player.0 as u32];
+    words.push(state.map.sources.len() as u32);
+    words.extend(state.map.sources.iter().map(|&source| source as u32));
+    words.push(state.nodes.len() as u32);
+    for (node, occupied) in state.nodes.iter().enumerate() {
+        match *occupied {
+            Some(ref occupied) => words.extend(&[occupied.player.0 as u32, occupied.goop as u32]),
+            None => words.extend(&[!0, 0]),
+        }
+        let neighbors = state.map.graph.neighbors(node);
+        words.push(neighbors.len() as u32);
+        words.extend(neighbors.iter().map(|&neighbor| neighbor as u32));
+        let outflows = occupied.as_ref().map_or(&[][..], |occupied| &occupied.outflows[..]);
+        words.push(outflows.len() as u32);
+        words.extend(outflows.iter().map(|&to| to as u32));
+    }
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::GameParameters;
+
+    fn duel() -> State {
+        let mut state = State::new(MapParameters {
+            size: (5, 5),
+            sources: vec![0, 24],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        state.advance();
+        state
+    }
+
+    fn module(wat: &str) -> Result<WasmModule> {
+        WasmModule::new(&::wat::parse_str(wat).unwrap())
+    }
+
+    /// A bot that pumps from its source to the next node up, and asks for a
+    /// toggle between nodes that aren't neighbors, which should be ignored.
+    const PUMP: &str = r#"
+        (module
+          (import "rbattle" "toggle_outflow" (func $toggle (param i32 i32)))
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "act") (param $ptr i32) (param $len i32)
+            (local $source i32)
+            ;; The player's source: the word at 12 + 4 * player.
+            (local.set $source
+              (i32.load
+                (i32.add (local.get $ptr)
+                         (i32.add (i32.const 12)
+                                  (i32.shl (i32.load offset=4 (local.get $ptr))
+                                           (i32.const 2))))))
+            (call $toggle (local.get $source) (i32.add (local.get $source) (i32.const 1)))
+            (call $toggle (local.get $source) (i32.const 1000))))
+    "#;
+
+    #[test]
+    fn plays() {
+        let module = module(PUMP).unwrap();
+        let state = duel();
+        let actions = module.bot().act(&state, Player(0));
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(actions[0], Action::ToggleOutflow { player: Player(0), from: 0, to: 1 }));
+
+        // Player 1's source is in the corner, so there's no node after it.
+        assert!(module.bot().act(&state, Player(1)).is_empty());
+    }
+
+    #[test]
+    fn fuel() {
+        // A bot that never finishes gets nowhere, but is asked again.
+        let module = module(r#"
+            (module
+              (import "rbattle" "toggle_outflow" (func $toggle (param i32 i32)))
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "act") (param i32 i32)
+                (call $toggle (i32.const 0) (i32.const 1))
+                (loop $forever (br $forever))))
+        "#).unwrap();
+        let mut bot = module.bot();
+        let state = duel();
+        assert!(bot.act(&state, Player(0)).is_empty());
+        assert!(bot.act(&state, Player(0)).is_empty());
+    }
+
+    #[test]
+    fn rejected() {
+        // Missing exports.
+        assert!(module(r#"(module (memory (export "memory") 1))"#).is_err());
+
+        // Asking for more than the host provides.
+        assert!(module(r#"
+            (module
+              (import "wasi_snapshot_preview1" "fd_write"
+                      (func (param i32 i32 i32 i32) (result i32)))
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "act") (param i32 i32)))
+        "#).is_err());
+
+        // More memory than we allow.
+        assert!(module(r#"
+            (module
+              (memory (export "memory") 1000)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "act") (param i32 i32)))
+        "#).is_err());
+
+        // Not WebAssembly at all.
+        assert!(WasmModule::new(b"not wasm").is_err());
+    }
+}
@@ -10,11 +10,11 @@ use rand::{Error, RngCore};
 ///
 /// That paper says:
 ///
-///     In particular, we propose a tightly coded xorshift128+ generator that
-///     does not fail systematically any test from the BigCrush suite of TestU01
-///     (even reversed) and generates 64 pseudorandom bits in 1.10 ns on an
-///     Intel(R) Core(TM) i7-4770 CPU @3.40GHz (Haswell). It is the fastest
-///     generator we are aware of with such empirical statistical properties.
+/// > In particular, we propose a tightly coded xorshift128+ generator that
+/// > does not fail systematically any test from the BigCrush suite of TestU01
+/// > (even reversed) and generates 64 pseudorandom bits in 1.10 ns on an
+/// > Intel(R) Core(TM) i7-4770 CPU @3.40GHz (Haswell). It is the fastest
+/// > generator we are aware of with such empirical statistical properties.
 ///
 /// The stream of numbers produced by this method repeats every 2**128 - 1 calls
 /// (i.e. never, for all practical purposes). Zero appears 2**64 - 1 times in
@@ -35,6 +35,11 @@ impl XorShift128Plus {
     pub fn new(seed: [u64; 2]) -> XorShift128Plus {
         XorShift128Plus { state: seed }
     }
+
+    /// Return the generator's internal state.
+    pub fn state(&self) -> [u64; 2] {
+        self.state
+    }
 }
 
 impl RngCore for XorShift128Plus {
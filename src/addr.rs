@@ -0,0 +1,76 @@
+//! Parsing network addresses given on the command line.
+//!
+//! Players shouldn't need to know their opponent's IP address, or remember
+//! which port rbattle uses. So wherever the command line wants an address, we
+//! accept any of:
+//!
+//! - `HOST:PORT`, where `HOST` is a hostname, an IPv4 address, or an IPv6
+//!   address in brackets, like `[::1]`,
+//!
+//! - `HOST` alone, meaning `DEFAULT_PORT` on that host, or
+//!
+//! - `PORT` alone, meaning that port on some host appropriate to the context:
+//!   every interface when we're listening, or this machine when we're
+//!   connecting.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+/// The port to use when an address doesn't specify one.
+pub const DEFAULT_PORT: u16 = 12345;
+
+/// Resolve `text` to a socket address. If `text` is only a port number, use
+/// `host` as the host.
+pub fn resolve(text: &str, host: IpAddr) -> io::Result<SocketAddr> {
+    if let Ok(addr) = text.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+
+    if let Ok(port) = text.parse::<u16>() {
+        return Ok(SocketAddr::new(host, port));
+    }
+
+    // A bare IP address. IPv6 addresses may or may not be bracketed.
+    let unbracketed = text.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DEFAULT_PORT));
+    }
+
+    // Anything else must be a hostname, with or without a port.
+    let mut addrs = if text.contains(':') {
+        text.to_socket_addrs()?
+    } else {
+        (text, DEFAULT_PORT).to_socket_addrs()?
+    };
+    addrs.next()
+        .ok_or_else(|| io::Error::other(format!("no addresses found for '{}'", text)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn forms() {
+        let any = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let addr = |text| resolve(text, any).unwrap().to_string();
+
+        assert_eq!(addr("10.0.0.1:4000"), "10.0.0.1:4000");
+        assert_eq!(addr("10.0.0.1"), "10.0.0.1:12345");
+        assert_eq!(addr("4000"), "0.0.0.0:4000");
+        assert_eq!(addr("[::]:4000"), "[::]:4000");
+        assert_eq!(addr("[::]"), "[::]:12345");
+        assert_eq!(addr("::1"), "[::1]:12345");
+
+        let local = resolve("localhost:4000", any).unwrap();
+        assert!(local.ip().is_loopback());
+        assert_eq!(local.port(), 4000);
+        assert_eq!(resolve("localhost", any).unwrap().port(), DEFAULT_PORT);
+
+        let ipv6 = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        assert_eq!(resolve("80", ipv6).unwrap().to_string(), "[::]:80");
+
+        assert!(resolve("no.such.host.invalid:80", any).is_err());
+    }
+}
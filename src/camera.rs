@@ -0,0 +1,154 @@
+//! Zooming and panning the view of the board.
+//!
+//! The drawer fits the whole board into the window; the camera then magnifies
+//! and shifts that picture, so players can get a closer look at a crowded part
+//! of a large board. The camera's transformation maps normalized device
+//! coordinates to normalized device coordinates, and the drawer applies it
+//! last, so everything drawn on the board, and the mouse's view of it, moves
+//! together. The heads-up display, the legend, and the buttons stay put.
+
+use math::{compose, scale_transform, translate_transform, Matrix};
+
+/// The closest the camera will zoom in.
+pub const MAX_ZOOM: f32 = 8.0;
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// The window's size, in pixels.
+    size: [f32; 2],
+
+    /// How many times its usual size the board appears.
+    zoom: f32,
+
+    /// How far the magnified board is shifted, in normalized device
+    /// coordinates.
+    offset: [f32; 2],
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera { size: [1.0, 1.0], zoom: 1.0, offset: [0.0, 0.0] }
+    }
+}
+
+impl Camera {
+    /// The window is now `width` by `height` pixels.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        self.size = [width.max(1.0), height.max(1.0)];
+    }
+
+    /// Return how many times its usual size the board appears.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Convert `point`, in window coordinates, to normalized device
+    /// coordinates.
+    fn to_device(&self, point: [f32; 2]) -> [f32; 2] {
+        [2.0 * point[0] / self.size[0] - 1.0,
+         1.0 - 2.0 * point[1] / self.size[1]]
+    }
+
+    /// Move the board by `delta`, in pixels.
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        self.offset[0] += 2.0 * delta[0] / self.size[0];
+        self.offset[1] -= 2.0 * delta[1] / self.size[1];
+        self.clamp();
+    }
+
+    /// Magnify the board by `factor`, keeping whatever is at `point`, in
+    /// window coordinates, where it is.
+    pub fn zoom_at(&mut self, point: [f32; 2], factor: f32) {
+        let zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+        let change = zoom / self.zoom;
+        let fixed = self.to_device(point);
+        for (offset, fixed) in self.offset.iter_mut().zip(&fixed) {
+            *offset = fixed + (*offset - fixed) * change;
+        }
+        self.zoom = zoom;
+        self.clamp();
+    }
+
+    /// Center the window on whatever is now at `point`, in window
+    /// coordinates, magnifying the board at least `zoom` times. Near the
+    /// board's edges, the point may end up off center, since the camera won't
+    /// pan the board out of view.
+    pub fn focus(&mut self, point: [f32; 2], zoom: f32) {
+        let device = self.to_device(point);
+        let zoom = self.zoom.max(zoom).min(MAX_ZOOM);
+        for (offset, device) in self.offset.iter_mut().zip(&device) {
+            // Where the point would be with the camera at rest.
+            let unmoved = (device - *offset) / self.zoom;
+            *offset = -unmoved * zoom;
+        }
+        self.zoom = zoom;
+        self.clamp();
+    }
+
+    /// Keep the window covered by the board's area, so the board can't be
+    /// panned out of view.
+    fn clamp(&mut self) {
+        let limit = self.zoom - 1.0;
+        for offset in &mut self.offset {
+            *offset = offset.clamp(-limit, limit);
+        }
+    }
+
+    /// Return the camera's transformation, from normalized device coordinates
+    /// as the drawer would lay out the board to where they appear now.
+    pub fn transform(&self) -> Matrix {
+        compose(translate_transform(self.offset[0], self.offset[1]),
+                scale_transform(self.zoom, self.zoom))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use math::apply;
+
+    #[test]
+    fn zoom_at() {
+        let mut camera = Camera::default();
+        camera.set_size(200.0, 100.0);
+        assert_eq!(apply(camera.transform(), [0.5, -0.5]), [0.5, -0.5]);
+
+        // Zooming in about a point leaves that point where it was.
+        camera.zoom_at([150.0, 25.0], 2.0);
+        assert_eq!(camera.zoom(), 2.0);
+        assert_eq!(apply(camera.transform(), [0.5, 0.5]), [0.5, 0.5]);
+        assert_eq!(apply(camera.transform(), [0.0, 0.0]), [-0.5, -0.5]);
+
+        // Panning moves everything, but not so far the board leaves the
+        // window.
+        camera.pan([-50.0, 0.0]);
+        assert_eq!(apply(camera.transform(), [0.0, 0.0]), [-1.0, -0.5]);
+        camera.pan([-50.0, -100.0]);
+        assert_eq!(apply(camera.transform(), [0.0, 0.0]), [-1.0, 1.0]);
+
+        // Zooming can't go below the board's usual size, and then there's no
+        // room to pan at all.
+        camera.zoom_at([0.0, 0.0], 0.1);
+        assert_eq!(camera.zoom(), 1.0);
+        assert_eq!(apply(camera.transform(), [0.5, -0.5]), [0.5, -0.5]);
+        camera.zoom_at([0.0, 0.0], 100.0);
+        assert_eq!(camera.zoom(), MAX_ZOOM);
+    }
+
+    #[test]
+    fn focus() {
+        let mut camera = Camera::default();
+        camera.set_size(200.0, 100.0);
+
+        // Focusing zooms in, and brings the point to the window's center.
+        camera.focus([125.0, 50.0], 2.0);
+        assert_eq!(camera.zoom(), 2.0);
+        assert_eq!(apply(camera.transform(), [0.25, 0.0]), [0.0, 0.0]);
+
+        // It never zooms out, and it still won't pan the board out of view:
+        // focusing on the board's corner leaves it in the window's corner.
+        camera.focus([200.0, 0.0], 1.0);
+        assert_eq!(camera.zoom(), 2.0);
+        assert_eq!(apply(camera.transform(), [1.0, 1.0]), [1.0, 1.0]);
+    }
+}
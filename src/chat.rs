@@ -0,0 +1,189 @@
+//! Chatting with the other players.
+//!
+//! A chat message travels like any other action: the client requests an
+//! `Action::Chat`, the scheduler collects it into a turn, and every
+//! participant takes it along with the rest of that turn's actions. Taking it
+//! changes nothing on the board; it only leaves an `Event::Chat` behind, which
+//! we copy out of each state we see into a short history of our own, since the
+//! state only keeps a few turns' worth of events.
+//!
+//! This module also holds the line the player is typing. Pressing Return opens
+//! it; while it's open, characters typed go into the line rather than being
+//! taken as hotkeys. Return sends the line, and Escape throws it away.
+
+use state::{Action, Event, Player, State};
+
+/// The most characters a chat message may have.
+pub const MAX_MESSAGE: usize = 80;
+
+/// How many messages we keep to show.
+const HISTORY: usize = 5;
+
+/// How many turns a message stays on screen: about ten seconds, at the default
+/// pace.
+const SHOW_TURNS: usize = 600;
+
+/// A message someone sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// The turn during which the message arrived.
+    pub turn: usize,
+    pub player: Player,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chat {
+    /// The line being typed, if the player is typing one.
+    line: Option<String>,
+
+    /// The latest messages, oldest first.
+    messages: Vec<Message>,
+
+    /// The first turn whose messages we haven't copied out of the state yet.
+    next_turn: usize,
+}
+
+impl Chat {
+    /// Return true if the player is typing a message.
+    pub fn typing(&self) -> bool {
+        self.line.is_some()
+    }
+
+    /// Return the line being typed, if any.
+    pub fn line(&self) -> Option<&str> {
+        self.line.as_deref()
+    }
+
+    /// Start typing a message.
+    pub fn open(&mut self) {
+        if self.line.is_none() {
+            self.line = Some(String::new());
+        }
+    }
+
+    /// The player has typed `ch`. Backspace deletes the last character; other
+    /// control characters, including those Return and Escape produce, are
+    /// left to `send` and `cancel`.
+    pub fn type_char(&mut self, ch: char) {
+        let line = match self.line {
+            Some(ref mut line) => line,
+            None => return,
+        };
+        match ch {
+            '\u{8}' => { line.pop(); }
+            _ if ch.is_control() => (),
+            _ if line.chars().count() >= MAX_MESSAGE => (),
+            _ => line.push(ch),
+        }
+    }
+
+    /// The player has pressed Return: stop typing, and return the action
+    /// that sends what they typed as `player`, unless it's blank.
+    pub fn send(&mut self, player: Player) -> Option<Action> {
+        let line = self.line.take()?;
+        let text = line.trim();
+        if text.is_empty() {
+            return None;
+        }
+        Some(Action::Chat { player, text: text.to_string() })
+    }
+
+    /// The player has pressed Escape: stop typing, and forget the line.
+    pub fn cancel(&mut self) {
+        self.line = None;
+    }
+
+    /// Copy any messages in `state`'s events we haven't seen yet into our
+    /// history.
+    pub fn note_messages(&mut self, state: &State) {
+        for event in &state.events {
+            if let Event::Chat { turn, player, ref text } = *event {
+                if turn >= self.next_turn {
+                    let text = text.chars().take(MAX_MESSAGE).collect();
+                    self.messages.push(Message { turn, player, text });
+                }
+            }
+        }
+        self.next_turn = state.turn;
+        let excess = self.messages.len().saturating_sub(HISTORY);
+        self.messages.drain(..excess);
+    }
+
+    /// Return the messages to show as of `turn`, oldest first.
+    pub fn recent(&self, turn: usize) -> &[Message] {
+        let shown = self.messages.iter()
+            .position(|message| message.turn + SHOW_TURNS > turn)
+            .unwrap_or(self.messages.len());
+        &self.messages[shown..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typing() {
+        let mut chat = Chat::default();
+
+        // Nothing goes anywhere until the line is open.
+        chat.type_char('x');
+        assert!(!chat.typing());
+        assert!(chat.send(Player(0)).is_none());
+
+        chat.open();
+        for ch in "hellp\u{8}o\r".chars() {
+            chat.type_char(ch);
+        }
+        assert_eq!(chat.line(), Some("hello"));
+        match chat.send(Player(1)) {
+            Some(Action::Chat { player: Player(1), ref text }) => assert_eq!(text, "hello"),
+            other => panic!("unexpected action: {:?}", other),
+        }
+        assert!(!chat.typing());
+
+        // Blank lines aren't sent, and Escape throws the line away.
+        chat.open();
+        chat.type_char(' ');
+        assert!(chat.send(Player(1)).is_none());
+        chat.open();
+        chat.type_char('x');
+        chat.cancel();
+        assert!(!chat.typing());
+
+        // Lines have a limit.
+        chat.open();
+        for _ in 0..MAX_MESSAGE + 10 {
+            chat.type_char('a');
+        }
+        assert_eq!(chat.line().map(|line| line.len()), Some(MAX_MESSAGE));
+    }
+
+    #[test]
+    fn history() {
+        let mut chat = Chat::default();
+        let mut state = State::new(::map::MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, Default::default());
+
+        state.take_action(&Action::Chat { player: Player(1), text: "hi".to_string() });
+        state.advance();
+        chat.note_messages(&state);
+        chat.note_messages(&state);
+        assert_eq!(chat.recent(state.turn),
+                   &[Message { turn: 0, player: Player(1), text: "hi".to_string() }]);
+
+        // Only the latest few messages are kept, and they fade after a while.
+        for i in 0..HISTORY + 2 {
+            state.take_action(&Action::Chat { player: Player(0), text: i.to_string() });
+            state.advance();
+            chat.note_messages(&state);
+        }
+        assert_eq!(chat.recent(state.turn).len(), HISTORY);
+        assert_eq!(chat.recent(state.turn)[HISTORY - 1].text, (HISTORY + 1).to_string());
+        assert!(chat.recent(state.turn + SHOW_TURNS).is_empty());
+    }
+}
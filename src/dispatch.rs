@@ -0,0 +1,128 @@
+//! Deciding which part of the game window gets each pointer event.
+//!
+//! The game window stacks a few layers of interface: the board at the bottom,
+//! with `Input` handling both it and the row of buttons along the top, since
+//! those share a notion of what a click is over; and the settings panel above
+//! them, when it's open. Ordinarily, pointer events go to the topmost layer.
+//! But once a mouse button or finger goes down, that layer captures the
+//! pointer: everything up to the last button or finger lifting goes to it,
+//! even if another layer has appeared or vanished in the mean time. Otherwise
+//! a drag that opened the panel partway through would never finish, or a
+//! click on the panel's CLOSE button would land its release on the board.
+//!
+//! Keyboard events aren't pointer events; the main loop decides where those
+//! go.
+
+use recording::{Button, InputEvent, Phase};
+
+/// A layer of the interface that can take pointer events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    /// The board, and the buttons along the top of the window.
+    Board,
+
+    /// The settings panel.
+    Panel,
+}
+
+/// Something that can be held down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pointer {
+    Button(Button),
+    Touch(u64),
+}
+
+#[derive(Debug, Default)]
+pub struct Dispatcher {
+    /// The layer that has captured the pointer, if any.
+    captured: Option<Layer>,
+
+    /// The buttons and fingers currently down.
+    held: Vec<Pointer>,
+}
+
+impl Dispatcher {
+    /// Return the layer that should handle `event`, when `top` is the topmost
+    /// layer. Return `None` if `event` isn't a pointer event.
+    pub fn route(&mut self, event: &InputEvent, top: Layer) -> Option<Layer> {
+        let layer = self.captured.unwrap_or(top);
+        match *event {
+            InputEvent::Button { button, pressed: true } => self.press(Pointer::Button(button), top),
+            InputEvent::Button { button, pressed: false } => self.lift(Pointer::Button(button)),
+            InputEvent::Touch { id, phase: Phase::Started, .. } =>
+                self.press(Pointer::Touch(id), top),
+            InputEvent::Touch { id, phase: Phase::Ended, .. } |
+            InputEvent::Touch { id, phase: Phase::Cancelled, .. } => self.lift(Pointer::Touch(id)),
+            InputEvent::Touch { phase: Phase::Moved, .. } |
+            InputEvent::CursorMoved { .. } |
+            InputEvent::Scroll { .. } |
+            InputEvent::Swipe { .. } => (),
+            InputEvent::Key { .. } |
+            InputEvent::Char { .. } => return None,
+        }
+        Some(layer)
+    }
+
+    /// `pointer` went down. If nothing else is held, `top` captures it.
+    fn press(&mut self, pointer: Pointer, top: Layer) {
+        if self.held.is_empty() {
+            self.captured = Some(top);
+        }
+        if !self.held.contains(&pointer) {
+            self.held.push(pointer);
+        }
+    }
+
+    /// `pointer` came up. If nothing else is held, release the capture.
+    fn lift(&mut self, pointer: Pointer) {
+        self.held.retain(|&held| held != pointer);
+        if self.held.is_empty() {
+            self.captured = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn button(pressed: bool) -> InputEvent {
+        InputEvent::Button { button: Button::Left, pressed }
+    }
+
+    fn touch(id: u64, phase: Phase) -> InputEvent {
+        InputEvent::Touch { id, phase, x: 0.0, y: 0.0 }
+    }
+
+    #[test]
+    fn capture() {
+        let mut dispatcher = Dispatcher::default();
+        let moved = InputEvent::CursorMoved { x: 1.0, y: 2.0 };
+
+        // Without anything held, events go to the top layer.
+        assert_eq!(dispatcher.route(&moved, Layer::Board), Some(Layer::Board));
+        assert_eq!(dispatcher.route(&moved, Layer::Panel), Some(Layer::Panel));
+
+        // A press on the board keeps everything there until the release,
+        // even if the panel opens.
+        assert_eq!(dispatcher.route(&button(true), Layer::Board), Some(Layer::Board));
+        assert_eq!(dispatcher.route(&moved, Layer::Panel), Some(Layer::Board));
+        assert_eq!(dispatcher.route(&button(false), Layer::Panel), Some(Layer::Board));
+        assert_eq!(dispatcher.route(&moved, Layer::Panel), Some(Layer::Panel));
+
+        // Likewise the other way around, and for fingers; the capture lasts
+        // until the last finger lifts.
+        assert_eq!(dispatcher.route(&touch(1, Phase::Started), Layer::Panel),
+                   Some(Layer::Panel));
+        assert_eq!(dispatcher.route(&touch(2, Phase::Started), Layer::Board),
+                   Some(Layer::Panel));
+        assert_eq!(dispatcher.route(&touch(1, Phase::Ended), Layer::Board), Some(Layer::Panel));
+        assert_eq!(dispatcher.route(&touch(2, Phase::Moved), Layer::Board), Some(Layer::Panel));
+        assert_eq!(dispatcher.route(&touch(2, Phase::Cancelled), Layer::Board),
+                   Some(Layer::Panel));
+        assert_eq!(dispatcher.route(&moved, Layer::Board), Some(Layer::Board));
+
+        // Keys aren't pointer events.
+        assert_eq!(dispatcher.route(&InputEvent::Char { ch: 'x' }, Layer::Board), None);
+    }
+}
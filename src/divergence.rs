@@ -0,0 +1,77 @@
+//! Writing down what we know when a participant's state diverges.
+//!
+//! Every turn carries a checksum of the state the scheduler reached by
+//! playing it. If a participant reaches a different state, something in the
+//! simulation isn't deterministic, and the game can't go on for them. Rather
+//! than just stopping, the participant writes a report to the current
+//! directory: the state before the turn that diverged, the state it reached
+//! instead, and the last few turns' actions. It tells the server, which writes
+//! down its own side: its latest checkpoint of the state, and every turn
+//! since. Comparing the two, or replaying the turns from each, should show
+//! where they part ways.
+
+use errors::*;
+use scheduler::{CollectedActions, Snapshot};
+use state::{Player, SerializableState};
+
+use serde::Serialize;
+use serde_json;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// How many of the latest turns a participant keeps to put in a report.
+pub const REPORT_TURNS: usize = 32;
+
+/// A participant's account of a divergence.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    /// The turn whose state didn't match.
+    pub turn: usize,
+
+    /// The checksum the scheduler sent, and the one we computed.
+    pub expected_checksum: u64,
+    pub actual_checksum: u64,
+
+    /// The state before the turn, which we agreed on.
+    pub before: SerializableState,
+
+    /// The state we reached by playing the turn.
+    pub after: SerializableState,
+
+    /// The latest turns played, oldest first, ending with the one that
+    /// diverged.
+    pub recent: Vec<&'a CollectedActions>,
+}
+
+/// The server's account of a divergence a participant told it about.
+#[derive(Serialize)]
+pub struct ServerReport {
+    /// The participant whose state diverged, and on which turn.
+    pub player: Player,
+    pub turn: usize,
+
+    /// The server's latest checkpoint of the state, and the turns since.
+    pub snapshot: Snapshot,
+}
+
+/// Return where a participant should write its report of a divergence on
+/// `turn`.
+pub fn report_path(turn: usize) -> PathBuf {
+    PathBuf::from(format!("rbattle-divergence-{}.json", turn))
+}
+
+/// Return where the server should write its side of `player`'s divergence on
+/// `turn`.
+pub fn server_report_path(player: Player, turn: usize) -> PathBuf {
+    PathBuf::from(format!("rbattle-divergence-{}-server-player-{}.json", turn, player.0))
+}
+
+/// Write `report` to `path` as pretty-printed JSON.
+pub fn write<T: Serialize>(report: &T, path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .chain_err(|| format!("creating divergence report {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), report)
+        .chain_err(|| format!("writing divergence report {}", path.display()))
+}
@@ -11,7 +11,8 @@
 //!
 //! - Window coordinates. The window's area is a rectangular matrix of pixels,
 //!   with (0,0) at the upper left, and the positive x and y axes pointing to
-//!   the right and down.
+//!   the right and down. These are physical pixels: on a high-DPI display,
+//!   there may be several to each logical pixel.
 //!
 //! - Normalized device coordinates (NDC). Normalized device coordinates cover
 //!   the window in a resolution-independent way. In NDC, (0,0) is the center of
@@ -29,19 +30,35 @@
 //!   between (0,0) and upper_right, where upper_right is what you get from
 //!   VisibleGraph::bounds().
 
+use chat::{self, Chat};
 use errors::*;
-use graph::Graph;
-use map::Map;
-use state::{State, MAX_GOOP, Occupied};
-use math::{compose, inverse, midpoint, scale_transform, translate_transform};
-use mouse::{Mouse, Display, OutflowState};
+use font;
+use graph::{Graph, Node};
+use map::{Map, Pattern};
+use menu::Menu;
+use state::{Event, Phase, Player, State, MAX_GOOP, Occupied};
+use theme::Theme;
+use math::{apply, compose, inverse, midpoint, scale_transform, translate_transform};
+use input::{ButtonState, Display, Input, OutflowState};
+use palette::Palette;
+use renderer::{GameOver, GridStyle, Renderer, Screenshot};
+use scheduler::Sample;
+use settings::{self, Panel};
+use timing::PerfStats;
+use ui::{self, Button};
 use visible_graph::{GraphPt, VisibleGraph};
+use xorshift::XorShift128Plus;
 
-use glium::{Blend, DrawParameters, Frame, IndexBuffer, Program, Surface, VertexBuffer};
+use glium::{Blend, Display as GliumDisplay, DrawParameters, Frame, IndexBuffer, Program,
+            Surface, VertexBuffer};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::RawImage2d;
 
-use std::cell::RefCell;
+use rand::Rng;
+
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
 use std::time::Duration;
 
 /// A `Drawer` knows how to draw a `State` on a Glium `Frame`.
@@ -51,8 +68,28 @@ use std::time::Duration;
 /// `Frame` value, representing one frame of video.
 ///
 /// The `Drawer` is the right place to hold Glium state that persists between
-/// frames, like vertex and index buffers for the map.
+/// frames, like vertex and index buffers for the map. It is the Glium
+/// implementation of the `Renderer` trait.
 pub struct Drawer {
+    /// The display we draw frames on.
+    display: GliumDisplay,
+
+    /// The color to clear each frame to.
+    background: [f32; 4],
+
+    /// Cached information for drawing the backdrop and the board's frame.
+    backdrop: BackdropDrawer,
+
+    /// The number of physical pixels per logical pixel on the display the
+    /// window is on. Line widths, point sizes, and the like are given in
+    /// logical pixels, and multiplied by this before drawing, so they look the
+    /// same on high-DPI displays as on others.
+    hidpi_factor: f32,
+
+    /// A further factor applied to the widths of the lines between nodes,
+    /// outflows, and the mouse highlight, which players may ask to enlarge.
+    ui_scale: f32,
+
     /// Cached information needed to drawing the map, excluding the map itself.
     /// This holds vertex and index buffers, shader programs, transformations,
     /// and the like.
@@ -64,30 +101,100 @@ pub struct Drawer {
     /// Cached information for drawing goop amounts.
     goop: GoopDrawer,
 
-    /// Cached information for drawing mouse interaction.
-    mouse: MouseDrawer,
+    /// Cached information for marking goop sources.
+    sources: SourceDrawer,
+
+    /// Cached information for drawing mouse and keyboard interaction.
+    input: InputDrawer,
+
+    /// Cached information for marking the phases of the game.
+    phase: PhaseDrawer,
+
+    /// Cached information for drawing the heads-up display.
+    hud: HudDrawer,
+
+    /// Particle effects marking captures and attacks.
+    effects: EffectsDrawer,
+
+    /// Cached information for drawing the legend.
+    legend: LegendDrawer,
+
+    /// Cached information for describing the node under the mouse.
+    tooltip: TooltipDrawer,
+
+    /// Cached information for drawing the game-over overlay.
+    overlay: OverlayDrawer,
+
+    /// Cached information for drawing the buttons along the top.
+    buttons: ButtonsDrawer,
+
+    /// Cached information for drawing the performance overlay.
+    perf: PerfDrawer,
+
+    /// Graphics state for the settings panel.
+    panel: PanelDrawer,
+
+    /// Graphics state for the chat messages, and the line being typed.
+    chat: ChatDrawer,
+
+    /// Cached information for shading outflows' hit regions, when asked to.
+    hit_regions: HitRegionsDrawer,
+    show_hit_regions: bool,
+
+    /// The colors in which to draw each player.
+    palette: Palette,
 }
 
 impl Drawer {
-    pub fn new(display: &Facade, map: &Map) -> Result<Drawer>
+    /// Construct a `Drawer` for `map`, drawing players in the colors given by
+    /// `palette`, and everything else as `theme` says. Animations that mark
+    /// events in the game are timed assuming turns take `turn_duration`.
+    pub fn new(display: &GliumDisplay,
+               map: &Map,
+               palette: Palette,
+               theme: &Theme,
+               turn_duration: Duration) -> Result<Drawer>
     {
-        let map_drawer = MapDrawer::new(display, map)?;
-        let outflows = OutflowsDrawer::new(display, map)?;
+        let backdrop = BackdropDrawer::new(display, map, theme)?;
+        let map_drawer = MapDrawer::new(display, map, theme)?;
+        let outflows = OutflowsDrawer::new(display, map, theme)?;
         let goop = GoopDrawer::new(display, map)?;
-        let mouse = MouseDrawer::new(display, map)?;
-
-        Ok(Drawer { map: map_drawer, outflows, goop, mouse })
+        let sources = SourceDrawer::new(display, map)?;
+        let input = InputDrawer::new(display, map, theme)?;
+        let phase = PhaseDrawer::new(display, map, turn_duration)?;
+        let hud = HudDrawer::new(display, map)?;
+        let effects = EffectsDrawer::new(display, map)?;
+        let legend = LegendDrawer::new(display, map)?;
+        let tooltip = TooltipDrawer::new(display, map)?;
+        let overlay = OverlayDrawer::new(display, map)?;
+        let buttons = ButtonsDrawer::new(display, map)?;
+        let perf = PerfDrawer::new(display, map)?;
+        let hit_regions = HitRegionsDrawer::new(display, map)?;
+        let panel = PanelDrawer::new(display, map)?;
+        let chat = ChatDrawer::new(display, map)?;
+
+        let hidpi_factor = display.gl_window().get_hidpi_factor() as f32;
+
+        Ok(Drawer { display: display.clone(), background: theme.background.to_gl(), hidpi_factor,
+                    ui_scale: 1.0,
+                    backdrop, map: map_drawer, outflows, goop, sources, input, phase, hud, effects,
+                    legend, tooltip, overlay, buttons, perf, panel, chat, hit_regions,
+                    show_hit_regions: false, palette })
     }
 
-    /// Draw `state` on `frame`
+    /// Draw `state` on `frame`. If the game is over, `game_over` says how it
+    /// ended, and we cover the board with an overlay announcing the result. If
+    /// `perf` is given, we show it in the upper right corner.
     ///
     /// Return the current transformation from window coordinates to game
     /// coordinates, for use by the controller.
-    pub fn draw(&self,
-                frame: &mut Frame,
-                time: Duration,
-                state: &State,
-                mouse: &Mouse) -> Result<[[f32; 3]; 3]>
+    fn draw(&self,
+            frame: &mut Frame,
+            time: Duration,
+            state: &State,
+            input: &Input,
+            game_over: Option<&GameOver>,
+            perf: Option<&PerfStats>) -> Result<[[f32; 3]; 3]>
     {
         let map = &*state.map;
 
@@ -97,23 +204,40 @@ impl Drawer {
         let device_aspect = width as f32 / height as f32;
 
         // Compute the transformation from game coordinates to normalized device
-        // coordinates. Depending on their relative aspect ratios, the game may
-        // be centered either vertically or horizontally within the window.
-        let game_to_device =
-            if device_aspect > map.game_aspect {
+        // coordinates. The heads-up display takes a strip along the bottom of
+        // the window, and the game gets the rest. Depending on their relative
+        // aspect ratios, the game may be centered either vertically or
+        // horizontally within that area.
+        let game_height = 1.0 - HUD_HEIGHT / 2.0;
+        let game_to_device = compose(
+            translate_transform(0.0, HUD_HEIGHT / 2.0),
+            if device_aspect > map.game_aspect * game_height {
                 // Window is wider than game. Game centered horizontally.
-                scale_transform(map.game_aspect / device_aspect, 1.0)
+                scale_transform(map.game_aspect * game_height / device_aspect, game_height)
             } else {
                 // Game is wider than window. Game centered vertically.
                 scale_transform(1.0, device_aspect / map.game_aspect)
-            };
+            });
+
+        // Then zoom and pan as the camera says.
+        let game_to_device = compose(input.camera().transform(), game_to_device);
 
         let graph_to_device = compose(game_to_device, map.graph_to_game);
 
-        self.map.draw(frame, &graph_to_device, &state.map)?;
-        self.goop.draw(frame, &graph_to_device, time, &state.nodes, &state.map)?;
-        self.outflows.draw(frame, &graph_to_device, &state.nodes, &state.map)?;
-        self.mouse.draw(frame, &graph_to_device, state, mouse)?;
+        let scale = self.hidpi_factor;
+        let line_scale = scale * self.ui_scale;
+        self.backdrop.draw(frame, &graph_to_device, scale)?;
+        self.map.draw(frame, &graph_to_device, line_scale, state, &self.palette)?;
+        self.goop.draw(frame, &graph_to_device, time, state, &self.palette)?;
+        self.sources.draw(frame, &graph_to_device, scale, time, state, &self.palette)?;
+        self.outflows.draw(frame, &graph_to_device, line_scale, time, state)?;
+        self.effects.draw(frame, &graph_to_device, scale, time, state, &self.palette)?;
+        if self.show_hit_regions {
+            self.hit_regions.draw(frame, &graph_to_device, state, input.hit_tolerance())?;
+        }
+        self.input.draw(frame, &graph_to_device, line_scale, state, input)?;
+        self.phase.draw(frame, &graph_to_device, scale, time, state)?;
+        self.hud.draw(frame, state, &self.palette)?;
 
         // Compute the transformation from window coordinates (pixels) to game
         // coordinates, for the mouse handling to use. In window coordinates:
@@ -128,6 +252,24 @@ impl Drawer {
         let window_to_device
             = compose(translate_transform(-1.0, 1.0),
                       scale_transform(2.0 / (width as f32), -2.0 / (height as f32)));
+        self.legend.draw(frame, &window_to_device, state, input.player(), &self.palette)?;
+        match (game_over, input.hovered_node()) {
+            (Some(over), _) =>
+                self.overlay.draw(frame, &window_to_device, state, over, &self.palette)?,
+            (None, Some(node)) =>
+                self.tooltip.draw(frame, &graph_to_device, &window_to_device, state, node,
+                                  &self.palette)?,
+            (None, None) => (),
+        }
+        self.buttons.draw(frame, &window_to_device, input)?;
+        self.chat.draw(frame, &window_to_device, state, input.chat(), &self.palette)?;
+        if let Some(perf) = perf {
+            let vertices = [("MAP", self.map.vertices.len()),
+                            ("OUTFLOWS", self.outflows.vertex_count()),
+                            ("GOOP", self.goop.squares.len())];
+            self.perf.draw(frame, &window_to_device, perf, &vertices)?;
+        }
+
         let device_to_game = inverse(game_to_device)
             .expect("graph_to_game transformation should be invertible");
 
@@ -137,42 +279,242 @@ impl Drawer {
     }
 }
 
+impl Renderer for Drawer {
+    fn render(&mut self,
+              time: Duration,
+              state: &State,
+              input: &Input,
+              panel: &Panel,
+              game_over: Option<&GameOver>,
+              perf: Option<&PerfStats>) -> Result<[[f32; 3]; 3]>
+    {
+        let mut frame = self.display.draw();
+        let [r, g, b, a] = self.background;
+        frame.clear_color(r, g, b, a);
+        let status = self.draw(&mut frame, time, state, input, game_over, perf)
+            .and_then(|window_to_game| {
+                self.panel.draw(&mut frame, panel)?;
+                Ok(window_to_game)
+            });
+        frame.finish()
+            .chain_err(|| "drawing finish failed")?;
+        status
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.map.invalidate();
+        self.goop.invalidate();
+    }
+
+    fn set_grid_style(&mut self, style: GridStyle) {
+        self.map.style = style;
+    }
+
+    fn set_hidpi_factor(&mut self, factor: f64) {
+        self.hidpi_factor = factor as f32;
+    }
+
+    fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale;
+    }
+
+    fn set_show_hit_regions(&mut self, show: bool) {
+        self.show_hit_regions = show;
+    }
+
+    fn screenshot(&self) -> Result<Screenshot> {
+        // Once a frame is finished, it's in the front buffer. OpenGL gives us
+        // its rows from the bottom up.
+        let image: RawImage2d<u8> = self.display.read_front_buffer();
+        let row_length = image.width as usize * 4;
+        let mut rgb = Vec::with_capacity(image.width as usize * image.height as usize * 3);
+        for row in image.data.chunks(row_length).rev() {
+            for pixel in row.chunks(4) {
+                rgb.extend_from_slice(&pixel[..3]);
+            }
+        }
+        Ok(Screenshot { width: image.width, height: image.height, rgb })
+    }
+}
+
+/// Graphics state for what lies behind the board.
+///
+/// The whole window is filled with a gentle vertical gradient, and the board
+/// is drawn over it in the background color, with a frame around it. When the
+/// window's shape doesn't match the board's, the gradient shows at the sides,
+/// so the extra space looks deliberate, rather than like a gap.
+struct BackdropDrawer {
+    /// Shader program for the gradient and the board. This is the HUD's, since
+    /// it takes a color per vertex.
+    fill_program: Program,
+
+    /// Two triangles covering the whole window, in normalized device
+    /// coordinates, colored to make the gradient.
+    gradient: VertexBuffer<HudVertex>,
+
+    /// Two triangles covering the board, in graph coordinates.
+    board: VertexBuffer<HudVertex>,
+
+    /// Shader program for the frame around the board.
+    frame_program: Program,
+
+    /// Quads for the four sides of the frame.
+    frame: VertexBuffer<LineVertex>,
+
+    /// The color and width, in pixels, of the frame.
+    frame_color: [f32; 4],
+    frame_width: f32,
+}
+
+impl BackdropDrawer {
+    fn new(display: &dyn Facade, map: &Map, theme: &Theme) -> Result<BackdropDrawer>
+    {
+        let fill_program = Program::from_source(display,
+                                                include_str!("hud.vert"),
+                                                include_str!("hud.frag"),
+                                                None)
+            .chain_err(|| "compiling backdrop shaders")?;
+
+        let frame_program = Program::from_source(display,
+                                                 include_str!("line.vert"),
+                                                 include_str!("map.frag"),
+                                                 None)
+            .chain_err(|| "compiling board frame shaders")?;
+
+        let mut gradient = vec![];
+        let (top, bottom) = (theme.backdrop_top.to_gl(), theme.backdrop_bottom.to_gl());
+        for &[x, y] in &[[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0],
+                         [1.0, 1.0], [-1.0, 1.0], [-1.0, -1.0]] {
+            gradient.push(HudVertex { point: [x, y], color: if y > 0.0 { top } else { bottom } });
+        }
+        let gradient = VertexBuffer::new(display, &gradient)
+            .chain_err(|| "building backdrop buffer")?;
+
+        let GraphPt([width, height]) = map.graph.bounds();
+        let mut board = vec![];
+        push_rectangle(&mut board, [0.0, 0.0], [width, height], theme.background.to_gl());
+        let board = VertexBuffer::new(display, &board)
+            .chain_err(|| "building board buffer")?;
+
+        let corners = [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height]];
+        let mut frame = vec![];
+        for i in 0..4 {
+            push_segment(&mut frame, corners[i], corners[(i + 1) % 4]);
+        }
+        let frame = VertexBuffer::new(display, &frame)
+            .chain_err(|| "building board frame buffer")?;
+
+        Ok(BackdropDrawer {
+            fill_program, gradient, board, frame_program, frame,
+            frame_color: theme.frame.to_gl(),
+            frame_width: theme.frame_width,
+        })
+    }
+
+    fn draw(&self, frame: &mut Frame, to_device: &[[f32; 3]; 3], scale: f32) -> Result<()> {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0f32]];
+        frame.draw(&self.gradient, NoIndices(PrimitiveType::TrianglesList), &self.fill_program,
+                   &uniform! {
+                       hud_to_device: identity,
+                   },
+                   &Default::default())
+            .chain_err(|| "drawing backdrop")?;
+
+        frame.draw(&self.board, NoIndices(PrimitiveType::TrianglesList), &self.fill_program,
+                   &uniform! {
+                       hud_to_device: *to_device,
+                   },
+                   &Default::default())
+            .chain_err(|| "drawing board")?;
+
+        let (width, height) = frame.get_dimensions();
+        frame.draw(&self.frame, NoIndices(PrimitiveType::TrianglesList), &self.frame_program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                       viewport: [width as f32, height as f32],
+                       line_width: self.frame_width * scale,
+                       line_color: self.frame_color,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing board frame")
+    }
+}
+
+/// How opaque the tint on occupied nodes is, when the grid is hidden.
+const TINT_ALPHA: f32 = 0.2;
+
 struct MapDrawer {
     /// Shader program for drawing the map.
     program: Program,
 
-    /// Vertices of the graph's boundary lines.
-    vertices: VertexBuffer<GraphVertex>,
+    /// Quads covering the graph's boundary lines.
+    vertices: VertexBuffer<LineVertex>,
 
-    /// Indices for the graph's boundary lines.
-    indices: IndexBuffer<u32>,
+    /// Shader program for drawing the dots at boundary corners.
+    dot_program: Program,
+
+    /// The corners of the graph's boundaries.
+    dots: VertexBuffer<GraphVertex>,
+
+    /// Shader program for tinting occupied nodes. This is the HUD's, since it
+    /// takes a color per vertex; we simply hand it a transformation from graph
+    /// coordinates, rather than from window coordinates.
+    tint_program: Program,
+
+    /// Triangles covering each occupied node's area, in its owner's color.
+    /// Each node's area is a fan of triangles from its center to each of its
+    /// boundary segments.
+    tints: RefCell<VertexBuffer<HudVertex>>,
+
+    /// The turn whose tints `tints` holds, and how many vertices they take,
+    /// or `None` if they need to be rebuilt regardless.
+    tinted: Cell<Option<(usize, usize)>>,
 
     /// Draw parameters for drawing the map.
-    draw_params: DrawParameters<'static>
+    draw_params: DrawParameters<'static>,
+
+    /// The color and width, in pixels, of the boundary lines.
+    color: [f32; 4],
+    width: f32,
+
+    /// How to draw the boundaries.
+    style: GridStyle,
 }
 
 impl MapDrawer {
-    fn new(display: &Facade, map: &Map) -> Result<MapDrawer>
+    fn new(display: &dyn Facade, map: &Map, theme: &Theme) -> Result<MapDrawer>
     {
         let graph = &map.graph;
 
         let program = Program::from_source(display,
-                                           include_str!("map.vert"),
+                                           include_str!("line.vert"),
                                            include_str!("map.frag"),
                                            None)
             .chain_err(|| "compiling map shaders")?;
 
-        // It's a little annoying that we have to do this map to convert GraphPt
-        // to GraphVertex, but I'd rather do this than a transmute.
-        let vertices: Vec<GraphVertex> = graph.endpoints().into_iter()
-            .map(|GraphPt(point)| GraphVertex { point })
-            .collect();
-        let vertices = VertexBuffer::new(display, &vertices)
-            .chain_err(|| "building buffer for graph vertices")?;
+        let dot_program = Program::from_source(display,
+                                               include_str!("map.vert"),
+                                               include_str!("mouse.frag"),
+                                               None)
+            .chain_err(|| "compiling grid dot shaders")?;
+
+        let tint_program = Program::from_source(display,
+                                                include_str!("hud.vert"),
+                                                include_str!("hud.frag"),
+                                                None)
+            .chain_err(|| "compiling node tint shaders")?;
 
-        let mut indices = Vec::new();
+        let endpoints = graph.endpoints();
+        let mut vertices = Vec::new();
+        let mut tint_vertices = 0;
         for node in 0..graph.nodes() {
-            for segment in graph.boundary(node) {
+            let boundary = graph.boundary(node);
+            tint_vertices += boundary.len() * 3;
+            for segment in boundary {
                 // A boundary line between two nodes will appear twice in the
                 // list. Cull out the duplicates by only retaining segments with
                 // no node on the other side, or where the node on the other
@@ -181,40 +523,145 @@ impl MapDrawer {
                     None => true,
                     Some(neighbor) => node < neighbor
                 } {
-                    indices.push(segment.line.start as u32);
-                    indices.push(segment.line.end as u32);
+                    let GraphPt(start) = endpoints[segment.line.start];
+                    let GraphPt(end) = endpoints[segment.line.end];
+                    push_segment(&mut vertices, start, end);
                 }
             }
         }
 
-        let indices = IndexBuffer::new(display, PrimitiveType::LinesList, &indices)
-            .chain_err(|| "building buffer for graph indices")?;
+        let vertices = VertexBuffer::new(display, &vertices)
+            .chain_err(|| "building buffer for graph boundaries")?;
+
+        let dots: Vec<_> = endpoints.iter()
+            .map(|&GraphPt(point)| GraphVertex { point })
+            .collect();
+        let dots = VertexBuffer::new(display, &dots)
+            .chain_err(|| "building buffer for grid dots")?;
+
+        let tints = VertexBuffer::empty_persistent(display, tint_vertices)
+            .chain_err(|| "allocating node tint vertex buffer")?;
 
         let draw_params = DrawParameters {
-            line_width: Some(2.0),
+            blend: Blend::alpha_blending(),
             .. Default::default()
         };
 
         Ok(MapDrawer {
-            program, vertices, indices, draw_params
+            program, vertices,
+            dot_program, dots,
+            tint_program,
+            tints: RefCell::new(tints),
+            tinted: Cell::new(None),
+            draw_params,
+            color: theme.grid.to_gl(),
+            width: theme.grid_width,
+            style: GridStyle::Lines,
         })
     }
 
-    /// Draw `map` on `frame`.
+    /// Rebuild the node tints on the next frame, even if the turn hasn't
+    /// changed; say, because the palette has.
+    fn invalidate(&self) {
+        self.tinted.set(None);
+    }
+
+    /// Draw the map `state` is played on, on `frame`, in the current style.
     ///
     /// The map `state` uses must be the same map that was passed to
     /// `MapDrawer::new` when this `MapDrawer` was created.
-    fn draw(&self, frame: &mut Frame, to_device: &[[f32; 3]; 3], _map: &Map) -> Result<()>
+    fn draw(&self,
+            frame: &mut Frame,
+            to_device: &[[f32; 3]; 3],
+            scale: f32,
+            state: &State,
+            palette: &Palette) -> Result<()>
     {
-        frame.draw(&self.vertices, &self.indices, &self.program,
-                   &uniform! {
-                       graph_to_device: *to_device
-                   },
-                   &self.draw_params)
-            .chain_err(|| "drawing map")?;
+        match self.style {
+            GridStyle::Lines => {
+                let (width, height) = frame.get_dimensions();
+                frame.draw(&self.vertices, NoIndices(PrimitiveType::TrianglesList),
+                           &self.program,
+                           &uniform! {
+                               graph_to_device: *to_device,
+                               viewport: [width as f32, height as f32],
+                               line_width: self.width * scale,
+                               line_color: self.color,
+                           },
+                           &self.draw_params)
+                    .chain_err(|| "drawing map")?;
+            }
+
+            GridStyle::Dots => {
+                frame.draw(&self.dots, NoIndices(PrimitiveType::Points), &self.dot_program,
+                           &uniform! {
+                               graph_to_device: *to_device,
+                               color: self.color,
+                           },
+                           &DrawParameters {
+                               point_size: Some(2.0 * self.width * scale),
+                               .. self.draw_params.clone()
+                           })
+                    .chain_err(|| "drawing grid dots")?;
+            }
+
+            GridStyle::Hidden => {
+                // Ownership only changes from one turn to the next, so most
+                // frames can reuse the tints already in the buffer.
+                let count = match self.tinted.get() {
+                    Some((turn, count)) if turn == state.turn => count,
+                    _ => {
+                        let count = self.write_tints(state, palette);
+                        self.tinted.set(Some((state.turn, count)));
+                        count
+                    }
+                };
+
+                if count > 0 {
+                    frame.draw(self.tints.borrow().slice(0..count).unwrap(),
+                               NoIndices(PrimitiveType::TrianglesList),
+                               &self.tint_program,
+                               &uniform! {
+                                   hud_to_device: *to_device,
+                               },
+                               &self.draw_params)
+                        .chain_err(|| "drawing node tints")?;
+                }
+            }
+        }
 
         Ok(())
     }
+
+    /// Write triangles tinting each occupied node in `state` to the start of
+    /// `self.tints`, and return how many vertices they take.
+    fn write_tints(&self, state: &State, palette: &Palette) -> usize {
+        let map = &*state.map;
+        let endpoints = map.graph.endpoints();
+
+        let mut tints = Vec::new();
+        for (node, occupied) in state.nodes.iter().enumerate() {
+            if let Some(ref occupied) = *occupied {
+                let (r, g, b) = palette.color(map, occupied.player);
+                let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, TINT_ALPHA];
+                let GraphPt(center) = map.graph.center(node);
+                for segment in map.graph.boundary(node) {
+                    let GraphPt(start) = endpoints[segment.line.start];
+                    let GraphPt(end) = endpoints[segment.line.end];
+                    for &point in &[center, start, end] {
+                        tints.push(HudVertex { point, color });
+                    }
+                }
+            }
+        }
+
+        if !tints.is_empty() {
+            self.tints.borrow_mut().slice_mut(0..tints.len())
+                .expect("more node boundary segments than graph claimed")
+                .write(&tints);
+        }
+        tints.len()
+    }
 }
 
 /// A vertex in Graph space.
@@ -223,89 +670,220 @@ struct GraphVertex { point: [f32; 2] }
 
 implement_vertex!(GraphVertex, point);
 
+/// A vertex of a quad covering a line segment, for `line.vert`.
+///
+/// Drawing lines as quads, rather than with `PrimitiveType::LinesList`, lets
+/// the shaders give them soft edges, and doesn't depend on the driver
+/// supporting wide lines. Every vertex carries both ends of the segment, in
+/// Graph space; `corner` says which corner of the quad it is. The shader
+/// widens the quad to the line width in pixels.
+#[derive(Copy, Clone, Debug)]
+struct LineVertex { start: [f32; 2], end: [f32; 2], corner: [f32; 2] }
+
+implement_vertex!(LineVertex, start, end, corner);
+
+/// The number of vertices `push_segment` pushes for each segment.
+const SEGMENT_VERTICES: usize = 6;
+
+/// Push onto the end of `vec` two triangles covering the line segment from
+/// `start` to `end`.
+fn push_segment(vec: &mut Vec<LineVertex>, start: [f32; 2], end: [f32; 2]) {
+    for &corner in &[[0.0, -1.0], [1.0, -1.0], [1.0, 1.0],
+                     [1.0, 1.0], [0.0, 1.0], [0.0, -1.0]] {
+        vec.push(LineVertex { start, end, corner });
+    }
+}
+
+/// The length of an outflow's arrowhead, and its width at the base, as
+/// fractions of the graph's node radius.
+const ARROW_LENGTH: f32 = 0.4;
+const ARROW_WIDTH: f32 = 0.5;
+
+/// The number of vertices `push_arrowhead` pushes for each arrowhead.
+const ARROW_VERTICES: usize = 3;
+
+/// Push onto the end of `vec` a triangle with its base centered on `base`,
+/// `width` wide, and its point at `tip`.
+fn push_arrowhead(vec: &mut Vec<GraphVertex>, base: [f32; 2], tip: [f32; 2], width: f32) {
+    let (dx, dy) = (tip[0] - base[0], tip[1] - base[1]);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (nx, ny) = (-dy / length * width / 2.0, dx / length * width / 2.0);
+    vec.push(GraphVertex { point: [base[0] + nx, base[1] + ny] });
+    vec.push(GraphVertex { point: [base[0] - nx, base[1] - ny] });
+    vec.push(GraphVertex { point: tip });
+}
+
+/// Cached information about drawing outflows.
+///
+/// Each outflow is a line from the center of the node goop leaves to the edge
+/// of the node it enters, ending in an arrowhead pointing at the node it
+/// enters. The line is drawn with dashes that march along it over time, so the
+/// direction of flow is obvious.
 struct OutflowsDrawer {
     /// Shader program for drawing the outflows.
     program: Program,
 
-    /// Vertices of the outflows' endpoints.
-    vertices: RefCell<VertexBuffer<GraphVertex>>,
+    /// Quads covering the outflows' lines.
+    vertices: RefCell<VertexBuffer<LineVertex>>,
+
+    /// Shader program for drawing the arrowheads.
+    arrow_program: Program,
+
+    /// Triangles for the outflows' arrowheads, parallel to `vertices`.
+    arrows: RefCell<VertexBuffer<GraphVertex>>,
 
     /// Draw parameters for outflows.
-    draw_params: DrawParameters<'static>
+    draw_params: DrawParameters<'static>,
+
+    /// The turn whose outflows `vertices` and `arrows` hold, and how many
+    /// outflows there are, or `None` if nothing has been written yet.
+    drawn: Cell<Option<(usize, usize)>>,
+
+    /// The colors of the lines and of the dashes along them, and the lines'
+    /// width in pixels.
+    color: [f32; 4],
+    dash_color: [f32; 4],
+    width: f32,
 }
 
 impl OutflowsDrawer {
-    fn new(display: &Facade, map: &Map) -> Result<OutflowsDrawer>
+    fn new(display: &dyn Facade, map: &Map, theme: &Theme) -> Result<OutflowsDrawer>
     {
         let graph = &map.graph;
 
         let program = Program::from_source(display,
-                                           include_str!("map.vert"),
+                                           include_str!("line.vert"),
                                            include_str!("outflow.frag"),
                                            None)
             .chain_err(|| "compiling outflow shaders")?;
 
+        let arrow_program = Program::from_source(display,
+                                                 include_str!("map.vert"),
+                                                 include_str!("mouse.frag"),
+                                                 None)
+            .chain_err(|| "compiling arrowhead shaders")?;
+
         let vertices = VertexBuffer::empty_persistent(display,
-                                                      2 * graph.edges())
+                                                      SEGMENT_VERTICES * graph.edges())
             .chain_err(|| "allocating outflow vertex buffer")?;
 
+        let arrows = VertexBuffer::empty_persistent(display, ARROW_VERTICES * graph.edges())
+            .chain_err(|| "allocating arrowhead vertex buffer")?;
+
         let draw_params = DrawParameters {
-            line_width: Some(5.0),
+            blend: Blend::alpha_blending(),
             .. Default::default()
         };
 
         Ok(OutflowsDrawer {
             program,
             vertices: RefCell::new(vertices),
-            draw_params
+            arrow_program,
+            arrows: RefCell::new(arrows),
+            draw_params,
+            drawn: Cell::new(None),
+            color: theme.outflow.to_gl(),
+            dash_color: theme.outflow_dash.to_gl(),
+            width: theme.outflow_width,
         })
     }
 
     fn draw(&self,
             frame: &mut Frame,
             to_device: &[[f32; 3]; 3],
-            nodes: &[Option<Occupied>],
-            map: &Map)
+            scale: f32,
+            time: Duration,
+            state: &State)
             -> Result<()>
     {
-        // Build vertex positions for all goop outflows.
-        let mut vertices = Vec::new();
-        for (node, state) in nodes.iter().enumerate() {
-            match state {
-                &Some(ref occupied) => {
-                    let GraphPt(start) = map.graph.center(node);
-                    for &outflow in &occupied.outflows {
-                        let GraphPt(end) = map.graph.center(outflow);
-                        let mid = midpoint(start, end);
-
-                        vertices.push(GraphVertex { point: start });
-                        vertices.push(GraphVertex { point: mid });
-                    }
-                },
-                _ => ()
+        // Outflows only change from one turn to the next, so most frames can
+        // reuse the vertices already in the buffers.
+        let count = match self.drawn.get() {
+            Some((turn, count)) if turn == state.turn => count,
+            _ => {
+                let count = self.write_vertices(state);
+                self.drawn.set(Some((state.turn, count)));
+                count
             }
-        }
+        };
 
         // Glium seems to have a bug with zero-length slices. Let's not argue
         // with it.
-        if vertices.len() > 0 {
-            // Write the indices to an appropriately sized slice of `self.indices`.
-            self.vertices.borrow_mut().slice_mut(0..vertices.len())
-                .expect("more outflow edges than graph claimed")
-                .write(&vertices);
+        if count > 0 {
+            let time_as_float =
+                time.as_secs() as f32 + time.subsec_nanos() as f32 / 1e9;
 
-            frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
-                       &NoIndices(PrimitiveType::LinesList),
+            let (width, height) = frame.get_dimensions();
+            frame.draw(self.vertices.borrow().slice(0..count * SEGMENT_VERTICES).unwrap(),
+                       NoIndices(PrimitiveType::TrianglesList),
                        &self.program,
                        &uniform! {
-                           graph_to_device: *to_device
+                           graph_to_device: *to_device,
+                           viewport: [width as f32, height as f32],
+                           line_width: self.width * scale,
+                           line_color: self.color,
+                           dash_color: self.dash_color,
+                           time: time_as_float
                        },
                        &self.draw_params)
                 .chain_err(|| "drawing outflows")?;
+
+            frame.draw(self.arrows.borrow().slice(0..count * ARROW_VERTICES).unwrap(),
+                       NoIndices(PrimitiveType::TrianglesList),
+                       &self.arrow_program,
+                       &uniform! {
+                           graph_to_device: *to_device,
+                           color: self.color,
+                       },
+                       &self.draw_params)
+                .chain_err(|| "drawing outflow arrowheads")?;
         }
 
         Ok(())
     }
+
+    /// Return the number of vertices drawn for outflows on the last frame.
+    fn vertex_count(&self) -> usize {
+        self.drawn.get().map_or(0, |(_, count)| count * (SEGMENT_VERTICES + ARROW_VERTICES))
+    }
+
+    /// Write quads and arrowheads for all the outflows in `state` to the start
+    /// of `self.vertices` and `self.arrows`, and return how many outflows
+    /// there are.
+    fn write_vertices(&self, state: &State) -> usize {
+        let (nodes, map) = (&state.nodes, &*state.map);
+        let radius = map.graph.radius();
+
+        // Build vertex positions for all goop outflows. Each line stops where
+        // its arrowhead begins, so the arrowhead's point is left sharp.
+        let mut vertices = Vec::new();
+        let mut arrows = Vec::new();
+        for (node, state) in nodes.iter().enumerate() {
+            if let Some(ref occupied) = *state {
+                let GraphPt(start) = map.graph.center(node);
+                for &outflow in &occupied.outflows {
+                    let GraphPt(end) = map.graph.center(outflow);
+                    let mid = midpoint(start, end);
+                    let (dx, dy) = (mid[0] - start[0], mid[1] - start[1]);
+                    let back = ARROW_LENGTH * radius / (dx * dx + dy * dy).sqrt();
+                    let base = [mid[0] - dx * back, mid[1] - dy * back];
+
+                    push_segment(&mut vertices, start, base);
+                    push_arrowhead(&mut arrows, base, mid, ARROW_WIDTH * radius);
+                }
+            }
+        }
+
+        if !vertices.is_empty() {
+            self.vertices.borrow_mut().slice_mut(0..vertices.len())
+                .expect("more outflow edges than graph claimed")
+                .write(&vertices);
+            self.arrows.borrow_mut().slice_mut(0..arrows.len())
+                .expect("more outflow edges than graph claimed")
+                .write(&arrows);
+        }
+        vertices.len() / SEGMENT_VERTICES
+    }
 }
 
 /// A point in UV space. A parameter passed to fragment shaders.
@@ -332,7 +910,9 @@ implement_vertex!(UVVertex, vertex_uv);
 ///
 /// Actually, because we need circles of different colors, our imaginary texture
 /// has 4096 circles on it. The circle index is a 12-bit value, which we break
-/// into four groups of four bits to get R, G, and B values.
+/// into four groups of four bits to get R, G, and B values. And since each
+/// player's goop also carries a `map::Pattern`, there is a row of such circles
+/// for each pattern, stacked along the y axis.
 ///
 /// Since we need empty space around each unit circle so that we can draw them
 /// as small circles, they are spaced at `sqrt(MAX_GOOP)` intervals.<
@@ -358,19 +938,24 @@ struct GoopDrawer {
 
     /// Draw parameters for goop squares.
     draw_params: DrawParameters<'static>,
+
+    /// The turn whose goop levels `textures` holds, or `None` if it needs to
+    /// be rebuilt regardless.
+    drawn: Cell<Option<usize>>,
 }
 
 
-/// Given an RGB triple, return the position in the texture of the center of the
-/// circle of radius one with that color.
-fn color_to_circle((r, g, b): (u8, u8, u8)) -> [f32; 2] {
+/// Given an RGB triple and a pattern, return the position in the texture of the
+/// center of the circle of radius one with that color and pattern.
+fn color_to_circle((r, g, b): (u8, u8, u8), pattern: Pattern) -> [f32; 2] {
     // Take the upper four bits of each component, and combine them into a
     // twelve-bit value.
     let (r, g, b) = ((r >> 4) as u32, (g >> 4) as u32, (b >> 4) as u32);
     let index = r << 8 | g << 4 | b;
 
-    // Space out the circles by MAX_GOOP, just to be safe.
-    [(index + 1) as f32 * (MAX_GOOP as f32), 0.0]
+    // Space out the circles by MAX_GOOP, just to be safe. The shader numbers
+    // the rows of patterns in the order `Pattern` lists them.
+    [(index + 1) as f32 * (MAX_GOOP as f32), pattern as u32 as f32 * (MAX_GOOP as f32)]
 }
 
 /// A type that can be constructed from a coordinate pair.
@@ -399,7 +984,7 @@ fn push_corners<T: TwoD>(vec: &mut Vec<T>, center: [f32; 2], radius: f32) {
 
 
 impl GoopDrawer {
-    fn new(display: &Facade, map: &Map) -> Result<GoopDrawer>
+    fn new(display: &dyn Facade, map: &Map) -> Result<GoopDrawer>
     {
         let program = Program::from_source(display,
                                            include_str!("goop.vert"),
@@ -409,8 +994,12 @@ impl GoopDrawer {
 
         let graph = &map.graph;
 
-        // Don't take up the node's full area.
-        let radius = graph.radius() * 0.8;
+        // The goop circles must be the same size on every node, so that
+        // players can compare them, and they must fit within every node's
+        // area, whatever its shape. Don't take up the full area, either.
+        let radius = (0..graph.nodes())
+            .map(|node| graph.inscribed_radius(node))
+            .fold(graph.radius(), f32::min) * 0.8;
 
         let mut squares = Vec::with_capacity(graph.nodes() * 4);
         for node in 0 .. graph.nodes() {
@@ -428,14 +1017,14 @@ impl GoopDrawer {
             let base = node * 4;
 
             // Upper-left triangle.
-            indices.push((base + 0) as u32);
+            indices.push(base as u32);
             indices.push((base + 1) as u32);
             indices.push((base + 2) as u32);
 
             // Lower-right triangle.
             indices.push((base + 2) as u32);
             indices.push((base + 3) as u32);
-            indices.push((base + 0) as u32);
+            indices.push(base as u32);
         }
         let indices = IndexBuffer::new(display,
                                        PrimitiveType::TrianglesList,
@@ -446,24 +1035,60 @@ impl GoopDrawer {
 
         Ok(GoopDrawer { program, squares,
                         textures: RefCell::new(textures),
-                        indices, draw_params })
+                        indices, draw_params,
+                        drawn: Cell::new(None) })
+    }
+
+    /// Rebuild the texture coordinates on the next frame, even if the turn
+    /// hasn't changed; say, because the palette has.
+    fn invalidate(&self) {
+        self.drawn.set(None);
     }
 
     fn draw(&self,
             frame: &mut Frame,
             to_device: &[[f32; 3]; 3],
             time: Duration,
-            nodes: &[Option<Occupied>],
-            map: &Map) -> Result<()>
+            state: &State,
+            palette: &Palette) -> Result<()>
     {
+        // Goop levels only change from one turn to the next, so most frames
+        // can reuse the texture coordinates already in the buffer.
+        if self.drawn.get() != Some(state.turn) {
+            self.write_textures(state, palette);
+            self.drawn.set(Some(state.turn));
+        }
+
+        let time_as_float =
+            time.as_secs() as f32 + time.subsec_nanos() as f32 / 1e9;
+
+        frame.draw((&self.squares, &*self.textures.borrow()),
+                   &self.indices,
+                   &self.program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                       circle_spacing: MAX_GOOP as f32,
+                       time: time_as_float
+                   },
+                   &self.draw_params)
+            .chain_err(|| "drawing goop")?;
+
+        Ok(())
+    }
+
+    /// Fill `self.textures` with texture coordinates showing the goop in
+    /// `state`.
+    fn write_textures(&self, state: &State, palette: &Palette) {
+        let (nodes, map) = (&state.nodes, &*state.map);
         assert_eq!(nodes.len(), map.graph.nodes());
 
         let mut textures = Vec::with_capacity(nodes.len() * 4);
         for state in nodes {
-            match state {
-                &Some(ref occupied) if occupied.goop > 0 => {
+            match *state {
+                Some(ref occupied) if occupied.goop > 0 => {
                     // Find the center of the circle of this player's color.
-                    let center = color_to_circle(map.player_colors[occupied.player.0]);
+                    let center = color_to_circle(palette.color(map, occupied.player),
+                                                 map.player_patterns[occupied.player.0]);
 
                     // Compute the radius of a circle whose area is MAX_GOOP
                     // if a unit circle has an area of `goop`.
@@ -482,44 +1107,55 @@ impl GoopDrawer {
         }
         assert_eq!(textures.len(), textures.capacity());
 
-        let time_as_float =
-            time.as_secs() as f32 + time.subsec_nanos() as f32 / 1e9;
-
         self.textures.borrow_mut().write(&textures);
-        frame.draw((&self.squares, &*self.textures.borrow()),
-                   &self.indices,
-                   &self.program,
-                   &uniform! {
-                       graph_to_device: *to_device,
-                       circle_spacing: MAX_GOOP as f32,
-                       time: time_as_float
-                   },
-                   &self.draw_params)
-            .chain_err(|| "drawing goop")?;
-
-        Ok(())
     }
 }
 
 /// Graphics state for drawing mouse interactions.
 ///
-/// Our mouse interactions are pretty simple. The `mouse::Display` enum
+/// Our mouse interactions are pretty simple. The `input::Display` enum
 /// specifies what state the interface is in, and it's up to this type to decide
 /// what that state looks like:
 ///
 /// - Hover(outflow): draw outflow in a light, transparent gray.
 ///
 /// - Active(outflow): Draw outflow in a solid yellow.
-struct MouseDrawer {
+///
+/// The theme can choose other colors for these. The keyboard selection, if the
+/// player has used it, is outlined in the hover color, and the start of a path
+/// being chosen in flow mode in the active color.
+///
+/// Outflows the player has asked to open, but which the scheduler hasn't
+/// opened yet, are drawn as dashed ghosts in the hover color. With hints on, the
+/// outflow the hint suggests opening is drawn in the theme's hint color.
+struct InputDrawer {
     /// Shader program for drawing outflows being clicked upon.
     program: Program,
 
-    /// Vertices of the outflow.
+    /// Vertices of the outflows: one for a click, or one for each edge swept
+    /// across in a drag.
     outflow: RefCell<VertexBuffer<GraphVertex>>,
+
+    /// Every node's outline, inset slightly, as pairs of vertices.
+    outlines: VertexBuffer<GraphVertex>,
+
+    /// The range of `outlines` holding each node's outline.
+    outline_ranges: Vec<Range<usize>>,
+
+    /// Vertices of the dashes of queued outflows' ghosts.
+    ghosts: RefCell<VertexBuffer<GraphVertex>>,
+
+    /// Vertices of the outflow the hint suggests.
+    hint: RefCell<VertexBuffer<GraphVertex>>,
+
+    /// The colors for hovered, active, and hinted outflows.
+    hover: [f32; 4],
+    active: [f32; 4],
+    hint_color: [f32; 4],
 }
 
-impl MouseDrawer {
-    fn new(display: &Facade, _map: &Map) -> Result<MouseDrawer>
+impl InputDrawer {
+    fn new(display: &dyn Facade, map: &Map, theme: &Theme) -> Result<InputDrawer>
     {
         let program = Program::from_source(display,
                                            include_str!("map.vert"),
@@ -527,65 +1163,1932 @@ impl MouseDrawer {
                                            None)
             .chain_err(|| "compiling mouse shaders")?;
 
-        let outflow = VertexBuffer::empty_persistent(display, 2)
+        // A drag can sweep across each edge at most once.
+        let outflow = VertexBuffer::empty_persistent(display, 2 * map.graph.edges().max(1))
             .chain_err(|| "allocating mouse vertex buffer")?;
 
-        Ok(MouseDrawer { program, outflow: RefCell::new(outflow) })
+        // Outline the selection just inside its boundary, so that it doesn't
+        // disappear into the grid lines.
+        let endpoints = map.graph.endpoints();
+        let mut outlines = vec![];
+        let mut outline_ranges = Vec::with_capacity(map.graph.nodes());
+        for node in 0..map.graph.nodes() {
+            let GraphPt(center) = map.graph.center(node);
+            let inset = |GraphPt(point): GraphPt| GraphVertex {
+                point: [center[0] + (point[0] - center[0]) * 0.85,
+                        center[1] + (point[1] - center[1]) * 0.85],
+            };
+            let start = outlines.len();
+            for segment in map.graph.boundary(node) {
+                outlines.push(inset(endpoints[segment.line.start]));
+                outlines.push(inset(endpoints[segment.line.end]));
+            }
+            outline_ranges.push(start..outlines.len());
+        }
+        let outlines = VertexBuffer::new(display, &outlines)
+            .chain_err(|| "building selection outline buffer")?;
+
+        // Each edge can have at most one ghost.
+        let ghosts = VertexBuffer::empty_persistent(display,
+                                                    2 * GHOST_DASHES * map.graph.edges().max(1))
+            .chain_err(|| "allocating queued outflow vertex buffer")?;
+
+        let hint = VertexBuffer::empty_persistent(display, 2)
+            .chain_err(|| "allocating hint vertex buffer")?;
+
+        Ok(InputDrawer {
+            program,
+            outflow: RefCell::new(outflow),
+            outlines,
+            outline_ranges,
+            ghosts: RefCell::new(ghosts),
+            hint: RefCell::new(hint),
+            hover: theme.hover.to_gl(),
+            active: theme.active.to_gl(),
+            hint_color: theme.hint.to_gl(),
+        })
     }
 
     fn draw(&self, frame: &mut Frame,
             to_device: &[[f32; 3]; 3],
+            scale: f32,
             state: &State,
-            mouse: &Mouse) -> Result<()>
+            input: &Input) -> Result<()>
     {
-        match mouse.display(state) {
-            Display::Nothing => Ok(()),
+        let outlines = [(input.selection(), self.hover), (input.flow_origin(), self.active)];
+        for &(node, color) in &outlines {
+            let node = match node {
+                Some(node) => node,
+                None => continue,
+            };
+            frame.draw(self.outlines.slice(self.outline_ranges[node].clone()).unwrap(),
+                       NoIndices(PrimitiveType::LinesList),
+                       &self.program,
+                       &uniform! {
+                           graph_to_device: *to_device,
+                           color: color,
+                       },
+                       &DrawParameters {
+                           line_width: Some(3.0 * scale),
+                           blend: Blend::alpha_blending(),
+                           .. Default::default()
+                       })
+                .chain_err(|| "drawing node outline")?;
+        }
 
-            Display::Outflow { nodes: (from, to), state: outflow_state } => {
-                // Prepare the vertices.
-                let graph = &state.map.graph;
-                let GraphPt(start) = graph.center(from);
-                let GraphPt(end) = graph.center(to);
-                let mid = midpoint(start, end);
-                let outflow = [GraphVertex { point: start },
-                               GraphVertex { point: mid }];
-                self.outflow.borrow_mut().write(&outflow);
-
-                match outflow_state {
-                    OutflowState::Hover => {
-                        frame.draw(&*self.outflow.borrow(),
-                                   &NoIndices(PrimitiveType::LinesList),
-                                   &self.program,
-                                   &uniform! {
-                                       graph_to_device: *to_device,
-                                       // transparent black
-                                       color: [0.0_f32, 0.0, 0.0, 0.5],
-                                   },
-                                   &DrawParameters {
-                                       line_width: Some(5.0),
-                                       blend: Blend::alpha_blending(),
-                                       .. Default::default()
-                                   })
-                            .chain_err(|| "drawing hover mouse outflow")
-                    }
+        self.draw_hint(frame, to_device, scale, state, input)?;
+        self.draw_ghosts(frame, to_device, scale, state, input)?;
 
-                    OutflowState::Active => {
-                        frame.draw(&*self.outflow.borrow(),
-                                   NoIndices(PrimitiveType::LinesList),
-                                   &self.program,
-                                   &uniform! {
-                                       graph_to_device: *to_device,
-                                       // yellow
-                                       color: [0.94_f32, 0.96, 0.0, 1.0],
-                                   },
-                                   &DrawParameters {
-                                       line_width: Some(5.0),
-                                       .. Default::default()
-                                   })
-                            .chain_err(|| "drawing active mouse outflow")
-                    }
-                }
+        let (edges, color) = match input.display(state) {
+            Display::Nothing => return Ok(()),
+            Display::Outflow { nodes, state: OutflowState::Hover } => (vec![nodes], self.hover),
+            Display::Outflow { nodes, state: OutflowState::Active } => (vec![nodes], self.active),
+            Display::Drag { edges } => (edges, self.active),
+        };
+
+        // Prepare the vertices.
+        let graph = &state.map.graph;
+        let mut outflows = Vec::with_capacity(2 * edges.len());
+        for (from, to) in edges {
+            let GraphPt(start) = graph.center(from);
+            let GraphPt(end) = graph.center(to);
+            outflows.push(GraphVertex { point: start });
+            outflows.push(GraphVertex { point: midpoint(start, end) });
+        }
+        self.outflow.borrow_mut().slice_mut(0..outflows.len())
+            .expect("more mouse outflows than edges in the graph")
+            .write(&outflows);
+
+        frame.draw(self.outflow.borrow().slice(0..outflows.len()).unwrap(),
+                   NoIndices(PrimitiveType::LinesList),
+                   &self.program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                       color: color,
+                   },
+                   &DrawParameters {
+                       line_width: Some(5.0 * scale),
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing mouse outflows")
+    }
+
+    /// Draw the outflow the hint suggests opening, if any.
+    fn draw_hint(&self, frame: &mut Frame,
+                 to_device: &[[f32; 3]; 3],
+                 scale: f32,
+                 state: &State,
+                 input: &Input) -> Result<()>
+    {
+        let (from, to) = match input.hint() {
+            Some(hint) => hint,
+            None => return Ok(()),
+        };
+        let graph = &state.map.graph;
+        let GraphPt(start) = graph.center(from);
+        let GraphPt(end) = graph.center(to);
+        self.hint.borrow_mut().write(&[GraphVertex { point: start },
+                                       GraphVertex { point: midpoint(start, end) }]);
+
+        frame.draw(&*self.hint.borrow(),
+                   NoIndices(PrimitiveType::LinesList),
+                   &self.program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                       color: self.hint_color,
+                   },
+                   &DrawParameters {
+                       line_width: Some(5.0 * scale),
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing hint")
+    }
+
+    /// Draw ghosts of the outflows the player has queued up to open.
+    fn draw_ghosts(&self, frame: &mut Frame,
+                   to_device: &[[f32; 3]; 3],
+                   scale: f32,
+                   state: &State,
+                   input: &Input) -> Result<()>
+    {
+        let graph = &state.map.graph;
+        let mut dashes = vec![];
+        for (from, to) in state.queued_outflows(input.queued()) {
+            let GraphPt(start) = graph.center(from);
+            let end = midpoint(start, graph.center(to).0);
+            let along = |fraction: f32| GraphVertex {
+                point: [start[0] + (end[0] - start[0]) * fraction,
+                        start[1] + (end[1] - start[1]) * fraction],
+            };
+            for dash in 0..GHOST_DASHES {
+                let dash = dash as f32 / GHOST_DASHES as f32;
+                dashes.push(along(dash));
+                dashes.push(along(dash + 0.5 / GHOST_DASHES as f32));
             }
         }
+        if dashes.is_empty() {
+            return Ok(());
+        }
+        self.ghosts.borrow_mut().slice_mut(0..dashes.len())
+            .expect("more queued outflows than edges in the graph")
+            .write(&dashes);
+
+        frame.draw(self.ghosts.borrow().slice(0..dashes.len()).unwrap(),
+                   NoIndices(PrimitiveType::LinesList),
+                   &self.program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                       color: self.hover,
+                   },
+                   &DrawParameters {
+                       line_width: Some(3.0 * scale),
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing queued outflows")
     }
 }
+
+/// How many dashes make up the ghost of a queued outflow.
+const GHOST_DASHES: usize = 4;
+
+/// How many times per graph unit, along each axis, the hit region overlay
+/// samples `edge_hit`.
+const HIT_SAMPLES: usize = 16;
+
+/// The colors in which the hit region overlay shades the regions for outflows
+/// heading north, south, east, and west.
+const HIT_COLORS: [[f32; 4]; 4] = [
+    [1.0, 0.2, 0.2, 0.35],
+    [0.2, 1.0, 0.2, 0.35],
+    [0.2, 0.4, 1.0, 0.35],
+    [1.0, 1.0, 0.2, 0.35],
+];
+
+/// Graphics state for the hit region overlay, a debugging aid that shades each
+/// point on the board by the direction of the outflow a click there would
+/// toggle, leaving ambiguous points clear. It samples `edge_hit` itself, so it
+/// shows exactly what the mouse handling will do at the current tolerance. It
+/// uses the HUD's shaders, in graph coordinates.
+struct HitRegionsDrawer {
+    /// The display we draw frames on.
+    display: GliumDisplay,
+
+    program: Program,
+
+    /// The overlay's rectangles, and the tolerance they were built for.
+    regions: RefCell<Option<(f32, VertexBuffer<HudVertex>)>>,
+}
+
+impl HitRegionsDrawer {
+    fn new(display: &GliumDisplay, _map: &Map) -> Result<HitRegionsDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling hit region shaders")?;
+
+        Ok(HitRegionsDrawer { display: display.clone(), program, regions: RefCell::new(None) })
+    }
+
+    /// Return the overlay's rectangles for `map` at `tolerance`, as triangles.
+    /// Runs of samples in the same row with the same shading are merged.
+    fn build(map: &Map, tolerance: f32) -> Vec<HudVertex> {
+        let graph = &map.graph;
+        let GraphPt(bounds) = graph.bounds();
+        let step = 1.0 / HIT_SAMPLES as f32;
+        let columns = (bounds[0] * HIT_SAMPLES as f32).ceil() as usize;
+        let rows = (bounds[1] * HIT_SAMPLES as f32).ceil() as usize;
+
+        // Return the index into HIT_COLORS for the sample at column `i`.
+        let shade = |i: usize, y: f32| {
+            let x = (i as f32 + 0.5) * step;
+            graph.edge_hit(&GraphPt([x, y]), tolerance).map(|(from, to)| {
+                let GraphPt(start) = graph.center(from);
+                let GraphPt(end) = graph.center(to);
+                let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+                match (dx.abs() > dy.abs(), dx > 0.0, dy > 0.0) {
+                    (false, _, true) => 0,
+                    (false, _, false) => 1,
+                    (true, true, _) => 2,
+                    (true, false, _) => 3,
+                }
+            })
+        };
+
+        let mut vertices = vec![];
+        for j in 0..rows {
+            let (y0, y1) = (j as f32 * step, (j + 1) as f32 * step);
+            let y = (y0 + y1) / 2.0;
+            let mut i = 0;
+            while i < columns {
+                let run = shade(i, y);
+                let start = i;
+                while i < columns && shade(i, y) == run {
+                    i += 1;
+                }
+                if let Some(index) = run {
+                    let color = HIT_COLORS[index];
+                    let (x0, x1) = (start as f32 * step, i as f32 * step);
+                    for &point in &[[x0, y0], [x1, y0], [x1, y1], [x0, y0], [x1, y1], [x0, y1]] {
+                        vertices.push(HudVertex { point, color });
+                    }
+                }
+            }
+        }
+        vertices
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            graph_to_device: &[[f32; 3]; 3],
+            state: &State,
+            tolerance: f32) -> Result<()>
+    {
+        let mut regions = self.regions.borrow_mut();
+        let stale = match *regions {
+            Some((built, _)) => built != tolerance,
+            None => true,
+        };
+        if stale {
+            let vertices = HitRegionsDrawer::build(&state.map, tolerance);
+            let buffer = VertexBuffer::new(&self.display, &vertices)
+                .chain_err(|| "building hit region buffer")?;
+            *regions = Some((tolerance, buffer));
+        }
+        let buffer = &regions.as_ref().expect("hit regions were just built").1;
+
+        frame.draw(buffer,
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *graph_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing hit regions")
+    }
+}
+
+/// How many line segments make up the ring around each source. Only every
+/// other segment is drawn, making the ring dashed, so that its turning shows.
+const SOURCE_RING_SEGMENTS: usize = 24;
+
+/// A vertex of a source's ring.
+#[derive(Copy, Clone, Debug)]
+struct SourceVertex { center: [f32; 2], offset: [f32; 2] }
+
+implement_vertex!(SourceVertex, center, offset);
+
+/// Graphics state for marking goop sources.
+///
+/// Sources are the most important nodes on the board, so we draw a dashed ring
+/// inside each one, which turns and pulses so that sources stand out even when
+/// they hold no goop. A source's ring is drawn in the color of the player whose
+/// seat it is, or in gray if nobody has taken that seat yet. The animation is
+/// all done by the shaders, from the `time` uniform.
+struct SourceDrawer {
+    /// Shader program for drawing the rings.
+    program: Program,
+
+    /// The rings' dashes: `SOURCE_RING_SEGMENTS / 2` pairs of vertices for
+    /// each source, in the order they appear in the map.
+    rings: VertexBuffer<SourceVertex>,
+}
+
+impl SourceDrawer {
+    fn new(display: &dyn Facade, map: &Map) -> Result<SourceDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("source.vert"),
+                                           include_str!("source.frag"),
+                                           None)
+            .chain_err(|| "compiling source shaders")?;
+
+        let mut rings = Vec::with_capacity(map.sources.len() * SOURCE_RING_SEGMENTS);
+        for &source in &map.sources {
+            let radius = map.graph.inscribed_radius(source) * 0.8;
+            let GraphPt(center) = map.graph.center(source);
+            let point = |i: usize| {
+                let angle = i as f32 * 2.0 * ::std::f32::consts::PI
+                    / SOURCE_RING_SEGMENTS as f32;
+                SourceVertex { center, offset: [radius * angle.cos(), radius * angle.sin()] }
+            };
+            for i in (0..SOURCE_RING_SEGMENTS).step_by(2) {
+                rings.push(point(i));
+                rings.push(point(i + 1));
+            }
+        }
+        let rings = VertexBuffer::new(display, &rings)
+            .chain_err(|| "building source ring buffer")?;
+
+        Ok(SourceDrawer { program, rings })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            to_device: &[[f32; 3]; 3],
+            scale: f32,
+            time: Duration,
+            state: &State,
+            palette: &Palette) -> Result<()>
+    {
+        let per_source = SOURCE_RING_SEGMENTS;
+        for seat in 0..state.map.sources.len() {
+            let color = if state.joined[seat] {
+                let (r, g, b) = palette.color(&state.map, Player(seat));
+                [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+            } else {
+                [0.5, 0.5, 0.5, 1.0]
+            };
+            let ring = self.rings.slice(seat * per_source .. (seat + 1) * per_source)
+                .expect("source ring out of range");
+            frame.draw(ring,
+                       NoIndices(PrimitiveType::LinesList),
+                       &self.program,
+                       &uniform! {
+                           graph_to_device: *to_device,
+                           time: time.as_secs_f32(),
+                           color: color,
+                       },
+                       &DrawParameters {
+                           line_width: Some(3.0 * scale),
+                           blend: Blend::alpha_blending(),
+                           .. Default::default()
+                       })
+                .chain_err(|| "drawing source marker")?;
+        }
+        Ok(())
+    }
+}
+
+/// Graphics state for announcing the phases of the game.
+///
+/// During warm-up, we draw a pulsing amber frame around the board, so nobody
+/// mistakes practice for the real thing. When the game proper begins, we flash
+/// the whole board, fading out over `START_FLASH_TURNS` turns.
+struct PhaseDrawer {
+    /// Shader program for drawing the frame and the flash.
+    program: Program,
+
+    /// The corners of the board, in counterclockwise order.
+    corners: VertexBuffer<GraphVertex>,
+
+    /// How many turns the flash announcing the start of the game lasts.
+    start_flash_turns: usize,
+}
+
+/// How long the flash announcing the start of the game lasts.
+const START_FLASH: Duration = Duration::from_millis(750);
+
+impl PhaseDrawer {
+    fn new(display: &dyn Facade, map: &Map, turn_duration: Duration) -> Result<PhaseDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("map.vert"),
+                                           include_str!("mouse.frag"),
+                                           None)
+            .chain_err(|| "compiling phase shaders")?;
+
+        let GraphPt(bounds) = map.graph.bounds();
+        let corners = [GraphVertex { point: [0.0, 0.0] },
+                       GraphVertex { point: [bounds[0], 0.0] },
+                       GraphVertex { point: bounds },
+                       GraphVertex { point: [0.0, bounds[1]] }];
+        let corners = VertexBuffer::new(display, &corners)
+            .chain_err(|| "building board corner buffer")?;
+
+        let start_flash_turns =
+            (START_FLASH.as_secs_f32() / turn_duration.as_secs_f32()).ceil() as usize;
+
+        Ok(PhaseDrawer { program, corners, start_flash_turns })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            to_device: &[[f32; 3]; 3],
+            scale: f32,
+            time: Duration,
+            state: &State) -> Result<()>
+    {
+        match state.phase() {
+            Phase::WarmUp { .. } => {
+                let time_as_float =
+                    time.as_secs() as f32 + time.subsec_nanos() as f32 / 1e9;
+                let alpha = 0.6 + 0.4 * (time_as_float * 4.0).sin();
+                frame.draw(&self.corners,
+                           NoIndices(PrimitiveType::LineLoop),
+                           &self.program,
+                           &uniform! {
+                               graph_to_device: *to_device,
+                               // amber
+                               color: [1.0_f32, 0.69, 0.0, alpha],
+                           },
+                           &DrawParameters {
+                               line_width: Some(8.0 * scale),
+                               blend: Blend::alpha_blending(),
+                               .. Default::default()
+                           })
+                    .chain_err(|| "drawing warm-up frame")
+            }
+
+            // Only games that had a warm-up need their start announced.
+            Phase::Play { turns } if state.params.warmup_turns > 0 &&
+                                     turns < self.start_flash_turns => {
+                let alpha = 0.5 * (1.0 - turns as f32 / self.start_flash_turns as f32);
+                frame.draw(&self.corners,
+                           NoIndices(PrimitiveType::TriangleFan),
+                           &self.program,
+                           &uniform! {
+                               graph_to_device: *to_device,
+                               // a go-ahead green
+                               color: [0.2_f32, 0.85, 0.3, alpha],
+                           },
+                           &DrawParameters {
+                               blend: Blend::alpha_blending(),
+                               .. Default::default()
+                           })
+                    .chain_err(|| "drawing game start flash")
+            }
+
+            Phase::Play { .. } => Ok(())
+        }
+    }
+}
+
+/// The height of the heads-up display, in normalized device coordinates.
+const HUD_HEIGHT: f32 = 0.12;
+
+/// A vertex of a shape in the heads-up display. HUD coordinates run from (0,0)
+/// at the lower left of the HUD strip to (1,1) at its upper right. The
+/// game-over overlay uses the same vertices, in window coordinates.
+#[derive(Copy, Clone, Debug)]
+struct HudVertex { point: [f32; 2], color: [f32; 4] }
+
+implement_vertex!(HudVertex, point, color);
+
+/// Graphics state for the heads-up display.
+///
+/// The HUD is a strip along the bottom of the window, divided into one column
+/// per seat in the game. Each column has two bars in the player's color: the
+/// upper one shows the fraction of the board the player holds, and the lower,
+/// paler one shows their share of all the goop on the board. The bars are
+/// drawn over gray tracks, so an empty bar is still visible.
+struct HudDrawer {
+    /// Shader program for drawing the HUD.
+    program: Program,
+
+    /// Vertices of the HUD's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+/// The color of the track behind each HUD bar.
+const HUD_TRACK_COLOR: [f32; 4] = [0.85, 0.85, 0.85, 1.0];
+
+impl HudDrawer {
+    fn new(display: &dyn Facade, map: &Map) -> Result<HudDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling HUD shaders")?;
+
+        // Two bars per player, each a track and a fill, each two triangles.
+        let vertices = VertexBuffer::empty_persistent(display,
+                                                      map.player_colors.len() * 2 * 2 * 6)
+            .chain_err(|| "allocating HUD vertex buffer")?;
+
+        Ok(HudDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame, state: &State, palette: &Palette) -> Result<()>
+    {
+        let nodes = state.node_counts();
+        let goop = state.goop_totals();
+        let all_nodes = state.map.graph.nodes().max(1) as f32;
+        let all_goop = goop.iter().sum::<usize>().max(1) as f32;
+
+        let columns = state.max_players();
+        let width = 1.0 / columns as f32;
+        let mut vertices = Vec::with_capacity(columns * 2 * 2 * 6);
+        for player in 0..columns {
+            let (r, g, b) = palette.color(&state.map, Player(player));
+            let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+            let pale = [color[0], color[1], color[2], 0.5];
+
+            let left = (player as f32 + 0.05) * width;
+            let right = (player as f32 + 0.95) * width;
+            let bars = [(0.55, 0.85, nodes[player] as f32 / all_nodes, color),
+                        (0.15, 0.45, goop[player] as f32 / all_goop, pale)];
+            for &(bottom, top, fraction, fill) in &bars {
+                push_rectangle(&mut vertices, [left, bottom], [right, top], HUD_TRACK_COLOR);
+                let end = left + (right - left) * fraction;
+                push_rectangle(&mut vertices, [left, bottom], [end, top], fill);
+            }
+        }
+
+        // Map the HUD strip onto the bottom of the window.
+        let hud_to_device = compose(translate_transform(-1.0, -1.0),
+                                    scale_transform(2.0, HUD_HEIGHT));
+
+        self.vertices.borrow_mut().write(&vertices);
+        frame.draw(&*self.vertices.borrow(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: hud_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing HUD")
+    }
+}
+
+/// Push onto the end of `vec` two triangles covering the axis-aligned
+/// rectangle from `lower_left` to `upper_right`, in `color`.
+fn push_rectangle(vec: &mut Vec<HudVertex>,
+                  lower_left: [f32; 2], upper_right: [f32; 2],
+                  color: [f32; 4]) {
+    let [x0, y0] = lower_left;
+    let [x1, y1] = upper_right;
+    for &point in &[[x0, y0], [x1, y0], [x1, y1], [x1, y1], [x0, y1], [x0, y0]] {
+        vec.push(HudVertex { point, color });
+    }
+}
+
+/// The most vertices the overlay can draw in one frame. Text takes two
+/// triangles per run of lit pixels, so this is generous.
+const OVERLAY_VERTICES: usize = 1 << 15;
+
+/// The color of the overlay's backdrop, which fades out the board behind it.
+const OVERLAY_BACKDROP: [f32; 4] = [1.0, 1.0, 1.0, 0.85];
+
+/// The color of the overlay's text, other than the headline.
+const OVERLAY_TEXT: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
+/// The background of the post-game graphs, and the color of their labels'
+/// and axes.
+const GRAPH_BACKGROUND: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+const GRAPH_AXES: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+
+/// Graphics state for the overlay announcing the end of the game.
+///
+/// When the game is over, we fade out the board and show the winner, how much
+/// of the board and goop each player was left with, graphs of how those
+/// changed over the game, and what the player can do next. The overlay is laid out in window coordinates, so that its text
+/// comes out in whole pixels. It uses the HUD's shaders.
+struct OverlayDrawer {
+    program: Program,
+
+    /// The overlay's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl OverlayDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<OverlayDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling overlay shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, OVERLAY_VERTICES)
+            .chain_err(|| "allocating overlay vertex buffer")?;
+
+        Ok(OverlayDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            window_to_device: &[[f32; 3]; 3],
+            state: &State,
+            over: &GameOver,
+            palette: &Palette) -> Result<()>
+    {
+        let (width, height) = frame.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let color_of = |player: Player| {
+            let (r, g, b) = palette.color(&state.map, player);
+            [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+        };
+
+        let headline = if over.winner == over.local {
+            "YOU WIN!".to_string()
+        } else {
+            format!("PLAYER {} WINS", over.winner.0)
+        };
+        let nodes = state.node_counts();
+        let goop = state.goop_totals();
+        let scores: Vec<(Player, String)> = (0..state.max_players())
+            .filter(|&player| over.stats.peak_nodes.get(player).cloned().unwrap_or(0) > 0)
+            .map(|player| {
+                let did = over.stats.players.get(player).cloned().unwrap_or_default();
+                (Player(player),
+                 format!("PLAYER {}: {} NODES, {} GOOP, {:.0} APM, {} TOGGLES, {} CAPTURES",
+                         player, nodes[player], goop[player],
+                         over.stats.actions_per_minute(Player(player)),
+                         did.toggles, did.captures))
+            })
+            .collect();
+        let footer = if over.can_rematch {
+            "PRESS ESC TO EXIT, OR R FOR A REMATCH"
+        } else {
+            "PRESS ESC TO EXIT. THE HOST MAY START A REMATCH."
+        };
+
+        // Choose the size of a font pixel so that the longest line fits
+        // comfortably, leaving room for the graphs if we have any, and
+        // everything lands on whole window pixels.
+        let samples = &over.stats.samples;
+        let graphed = samples.len() >= 2;
+        let widest = scores.iter().map(|(_, line)| font::width(line))
+            .chain(Some(font::width(footer)))
+            .max().unwrap_or(0) + 2 * font::ADVANCE;
+        let pixel = (width * 0.9 / widest as f32)
+            .min(height / if graphed { 200.0 } else { 120.0 })
+            .floor().max(1.0);
+        let line_height = (font::GLYPH_HEIGHT + 5) as f32 * pixel;
+
+        // Graph each player's nodes and goop over the game, side by side.
+        let graph_height = if graphed { (height * 0.3).floor() } else { 0.0 };
+        let graphs_height = if graph_height > 0.0 {
+            graph_height + line_height * 2.0
+        } else {
+            0.0
+        };
+
+        let mut vertices = vec![];
+        push_rectangle(&mut vertices, [0.0, 0.0], [width, height], OVERLAY_BACKDROP);
+
+        // The headline, at twice the size of everything else, then a row for
+        // each player, then the graphs, then the footer.
+        let total = line_height * (2.0 + 1.0 + scores.len() as f32 + 1.0) + graphs_height;
+        let mut top = ((height - total) / 2.0).floor();
+        let big = pixel * 2.0;
+        let left = ((width - font::width(&headline) as f32 * big) / 2.0).floor();
+        push_text(&mut vertices, &headline, [left, top], big, color_of(over.winner));
+        top += line_height * 3.0;
+
+        // Each row starts with a swatch of the player's color.
+        let swatch = font::ADVANCE as f32 * pixel;
+        for &(player, ref line) in &scores {
+            let row_width = swatch + font::width(line) as f32 * pixel;
+            let left = ((width - row_width) / 2.0).floor();
+            push_rectangle(&mut vertices,
+                           [left, top],
+                           [left + font::GLYPH_WIDTH as f32 * pixel,
+                            top + font::GLYPH_HEIGHT as f32 * pixel],
+                           color_of(player));
+            push_text(&mut vertices, line, [left + swatch, top], pixel, OVERLAY_TEXT);
+            top += line_height;
+        }
+
+        if graph_height > 0.0 {
+            top += line_height;
+            let graph_width = (width * 0.4).min(graph_height * 1.6).floor();
+            let gap = line_height * 2.0;
+            let mut left = ((width - 2.0 * graph_width - gap) / 2.0).floor();
+            let players: Vec<Player> = scores.iter().map(|&(player, _)| player).collect();
+            for &(label, goop) in &[("NODES", false), ("GOOP", true)] {
+                let value = |sample: &Sample, player: Player| {
+                    let values = if goop { &sample.goop } else { &sample.nodes };
+                    values.get(player.0).cloned().unwrap_or(0)
+                };
+                push_text(&mut vertices, label, [left, top], pixel, OVERLAY_TEXT);
+                let lines: Vec<([f32; 4], Vec<usize>)> = players.iter()
+                    .map(|&player| (color_of(player),
+                                    samples.iter().map(|sample| value(sample, player))
+                                    .collect()))
+                    .collect();
+                push_graph(&mut vertices, [left, top + line_height],
+                           [graph_width, graph_height], samples, &lines, pixel);
+                left += graph_width + gap;
+            }
+            top += graphs_height - line_height;
+        }
+
+        top += line_height;
+        let left = ((width - font::width(footer) as f32 * pixel) / 2.0).floor();
+        push_text(&mut vertices, footer, [left, top], pixel, OVERLAY_TEXT);
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more overlay vertices than OVERLAY_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing game-over overlay")
+    }
+}
+
+/// Push onto the end of `vec` a graph of `lines` over the turns of `samples`,
+/// with its upper left corner at `origin` and `size` pixels in extent. Each
+/// line has its color, and a value for each sample. Lines are drawn `pixel`
+/// wide, the same as a font pixel.
+fn push_graph(vec: &mut Vec<HudVertex>, origin: [f32; 2], size: [f32; 2],
+              samples: &[Sample], lines: &[([f32; 4], Vec<usize>)], pixel: f32) {
+    let [left, top] = origin;
+    let [width, height] = size;
+    push_rectangle(vec, [left, top], [left + width, top + height], GRAPH_BACKGROUND);
+
+    let first = samples.first().map_or(0, |sample| sample.turn);
+    let last = samples.last().map_or(0, |sample| sample.turn);
+    let turns = (last - first).max(1) as f32;
+    let highest = lines.iter()
+        .flat_map(|(_, values)| values.iter().cloned())
+        .max().unwrap_or(0).max(1) as f32;
+    let point = |turn: usize, value: usize| {
+        [left + (turn - first) as f32 / turns * width,
+         top + height - value as f32 / highest * height]
+    };
+
+    for (color, values) in lines {
+        let points: Vec<[f32; 2]> = samples.iter().zip(values)
+            .map(|(sample, &value)| point(sample.turn, value))
+            .collect();
+        for pair in points.windows(2) {
+            push_line(vec, pair[0], pair[1], pixel, *color);
+        }
+    }
+
+    // The axes go on top, so lines at zero don't hide them.
+    let bottom = top + height;
+    push_rectangle(vec, [left, bottom - pixel], [left + width, bottom], GRAPH_AXES);
+    push_rectangle(vec, [left, top], [left + pixel, bottom], GRAPH_AXES);
+}
+
+/// Push onto the end of `vec` two triangles covering a line `width` wide
+/// from `start` to `end`, in `color`.
+fn push_line(vec: &mut Vec<HudVertex>, start: [f32; 2], end: [f32; 2], width: f32,
+             color: [f32; 4]) {
+    let [dx, dy] = [end[0] - start[0], end[1] - start[1]];
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return;
+    }
+    let half = width / 2.0;
+    let [nx, ny] = [-dy / length * half, dx / length * half];
+    let corners = [[start[0] + nx, start[1] + ny], [start[0] - nx, start[1] - ny],
+                   [end[0] - nx, end[1] - ny], [end[0] + nx, end[1] + ny]];
+    for &index in &[0, 1, 2, 2, 3, 0] {
+        vec.push(HudVertex { point: corners[index], color });
+    }
+}
+
+/// The most vertices the legend can take.
+const LEGEND_VERTICES: usize = 1 << 13;
+
+/// The color of the legend's background, and of the band marking the local
+/// player's row.
+const LEGEND_BACKGROUND: [f32; 4] = [1.0, 1.0, 1.0, 0.75];
+const LEGEND_HIGHLIGHT: [f32; 4] = [1.0, 0.95, 0.5, 0.9];
+
+/// Graphics state for the legend in the upper left corner of the window.
+///
+/// The legend lists the players who have joined, from first place to last,
+/// each with a swatch of their color. The local player's row is marked, so
+/// they can find themselves at a glance. It's laid out in window coordinates,
+/// and uses the HUD's shaders.
+struct LegendDrawer {
+    program: Program,
+
+    /// The legend's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl LegendDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<LegendDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling legend shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, LEGEND_VERTICES)
+            .chain_err(|| "allocating legend vertex buffer")?;
+
+        Ok(LegendDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            window_to_device: &[[f32; 3]; 3],
+            state: &State,
+            local: Player,
+            palette: &Palette) -> Result<()>
+    {
+        let standings = state.standings();
+        if standings.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = standings.iter().enumerate()
+            .map(|(rank, &player)| {
+                if player == local {
+                    format!("{}. PLAYER {} (YOU)", rank + 1, player.0)
+                } else {
+                    format!("{}. PLAYER {}", rank + 1, player.0)
+                }
+            })
+            .collect();
+
+        let (_, height) = frame.get_dimensions();
+        let pixel = (height as f32 / 300.0).floor().max(2.0);
+        let line_height = (font::GLYPH_HEIGHT + 3) as f32 * pixel;
+        let padding = 3.0 * pixel;
+        let swatch = font::ADVANCE as f32 * pixel;
+        let text_width = lines.iter().map(|line| font::width(line)).max().unwrap_or(0);
+        let box_width = swatch + text_width as f32 * pixel + 2.0 * padding;
+        let box_height = lines.len() as f32 * line_height - 3.0 * pixel + 2.0 * padding;
+
+        let mut vertices = vec![];
+        push_rectangle(&mut vertices, [0.0, 0.0], [box_width, box_height], LEGEND_BACKGROUND);
+        for (i, (&player, line)) in standings.iter().zip(&lines).enumerate() {
+            let top = padding + i as f32 * line_height;
+            if player == local {
+                push_rectangle(&mut vertices,
+                               [0.0, top - pixel],
+                               [box_width, top + (font::GLYPH_HEIGHT + 1) as f32 * pixel],
+                               LEGEND_HIGHLIGHT);
+            }
+            let (r, g, b) = palette.color(&state.map, player);
+            push_rectangle(&mut vertices,
+                           [padding, top],
+                           [padding + font::GLYPH_WIDTH as f32 * pixel,
+                            top + font::GLYPH_HEIGHT as f32 * pixel],
+                           [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]);
+            push_text(&mut vertices, line, [padding + swatch, top], pixel, OVERLAY_TEXT);
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more legend vertices than LEGEND_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing legend")
+    }
+}
+
+/// The most vertices the chat can take: enough for a full history of
+/// full-length messages.
+const CHAT_VERTICES: usize = 1 << 16;
+
+/// The chat's background, and the color of the line being typed.
+const CHAT_BACKGROUND: [f32; 4] = [1.0, 1.0, 1.0, 0.75];
+const CHAT_TYPING: [f32; 4] = [1.0, 1.0, 0.9, 0.95];
+
+/// Graphics state for the chat, drawn in the lower left corner just above the
+/// HUD: the latest messages, oldest first, each marked with a swatch of its
+/// sender's color like the legend's, and below them the line being typed. It's
+/// drawn in window coordinates, and uses the HUD's shaders.
+struct ChatDrawer {
+    program: Program,
+
+    /// The chat's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl ChatDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<ChatDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling chat shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, CHAT_VERTICES)
+            .chain_err(|| "allocating chat vertex buffer")?;
+
+        Ok(ChatDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            window_to_device: &[[f32; 3]; 3],
+            state: &State,
+            chat: &Chat,
+            palette: &Palette) -> Result<()>
+    {
+        let messages = chat.recent(state.turn);
+        let typing = chat.line().map(|line| format!("SAY: {}_", line));
+        if messages.is_empty() && typing.is_none() {
+            return Ok(());
+        }
+
+        let (_, height) = frame.get_dimensions();
+        let pixel = ui::pixel_size(height as f32);
+        let line_height = (font::GLYPH_HEIGHT + 3) as f32 * pixel;
+        let padding = 3.0 * pixel;
+        let swatch = font::ADVANCE as f32 * pixel;
+        let box_width = padding * 2.0 + swatch +
+            (chat::MAX_MESSAGE + "PLAYER 0: ".len()) as f32 * font::ADVANCE as f32 * pixel;
+        let lines = messages.len() + typing.is_some() as usize;
+        let bottom = (height as f32 * (1.0 - HUD_HEIGHT / 2.0) - padding).floor();
+        let top = bottom - lines as f32 * line_height - 2.0 * padding + 3.0 * pixel;
+
+        let mut vertices = vec![];
+        push_rectangle(&mut vertices, [0.0, top], [box_width, bottom], CHAT_BACKGROUND);
+        for (i, message) in messages.iter().enumerate() {
+            let y = top + padding + i as f32 * line_height;
+            let (r, g, b) = palette.color(&state.map, message.player);
+            push_rectangle(&mut vertices,
+                           [padding, y],
+                           [padding + font::GLYPH_WIDTH as f32 * pixel,
+                            y + font::GLYPH_HEIGHT as f32 * pixel],
+                           [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]);
+            let line = format!("PLAYER {}: {}", message.player.0, message.text);
+            push_text(&mut vertices, &line, [padding + swatch, y], pixel, OVERLAY_TEXT);
+        }
+        if let Some(typing) = typing {
+            let y = top + padding + messages.len() as f32 * line_height;
+            push_rectangle(&mut vertices,
+                           [0.0, y - pixel],
+                           [box_width, y + (font::GLYPH_HEIGHT + 1) as f32 * pixel],
+                           CHAT_TYPING);
+            push_text(&mut vertices, &typing, [padding + swatch, y], pixel, OVERLAY_TEXT);
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more chat vertices than CHAT_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing chat")
+    }
+}
+
+/// The most vertices a tooltip can take.
+const TOOLTIP_VERTICES: usize = 1 << 13;
+
+/// The color of a tooltip's background, and of its border.
+const TOOLTIP_BACKGROUND: [f32; 4] = [1.0, 1.0, 0.9, 0.95];
+const TOOLTIP_BORDER: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+
+/// Graphics state for the tooltip describing the node under the mouse.
+///
+/// The tooltip sits just above and to the right of the node, and gives its
+/// owner, how much goop it holds, and which way its outflows point. Like the
+/// game-over overlay, it's laid out in window coordinates, and uses the HUD's
+/// shaders.
+struct TooltipDrawer {
+    program: Program,
+
+    /// The tooltip's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl TooltipDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<TooltipDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling tooltip shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, TOOLTIP_VERTICES)
+            .chain_err(|| "allocating tooltip vertex buffer")?;
+
+        Ok(TooltipDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            graph_to_device: &[[f32; 3]; 3],
+            window_to_device: &[[f32; 3]; 3],
+            state: &State,
+            node: Node,
+            palette: &Palette) -> Result<()>
+    {
+        let map = &*state.map;
+        let source = if map.sources.contains(&node) { ", SOURCE" } else { "" };
+        let (owner, color, goop, outflows) = match state.nodes[node] {
+            Some(ref occupied) => {
+                let (r, g, b) = palette.color(map, occupied.player);
+                (format!("PLAYER {}{}", occupied.player.0, source),
+                 [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0],
+                 occupied.goop,
+                 &occupied.outflows[..])
+            }
+            None => (format!("EMPTY{}", source), OVERLAY_TEXT, 0, &[][..]),
+        };
+        let outflows = if outflows.is_empty() {
+            "NONE".to_string()
+        } else {
+            outflows.iter()
+                .map(|&to| direction(map, node, to))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let lines = [(owner, color),
+                     (format!("GOOP: {} / {}", goop, MAX_GOOP), OVERLAY_TEXT),
+                     (format!("OUTFLOWS: {}", outflows), OVERLAY_TEXT)];
+
+        let (width, height) = frame.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let pixel = (height / 300.0).floor().max(2.0);
+        let line_height = (font::GLYPH_HEIGHT + 3) as f32 * pixel;
+        let padding = 3.0 * pixel;
+        let text_width = lines.iter().map(|(line, _)| font::width(line)).max().unwrap_or(0);
+        let box_width = text_width as f32 * pixel + 2.0 * padding;
+        let box_height = lines.len() as f32 * line_height - 3.0 * pixel + 2.0 * padding;
+
+        // Find the upper right corner of the node's area, in window
+        // coordinates, and put the tooltip there, as long as it fits.
+        let GraphPt([x, y]) = map.graph.center(node);
+        let radius = map.graph.radius();
+        let device = apply(*graph_to_device, [x + radius, y + radius]);
+        let corner = apply(inverse(*window_to_device)
+                           .expect("window_to_device should be invertible"),
+                           device);
+        let left = corner[0].min(width - box_width).max(0.0).floor();
+        let top = (corner[1] - box_height).max(0.0).floor();
+
+        let mut vertices = vec![];
+        push_rectangle(&mut vertices, [left - 1.0, top - 1.0],
+                       [left + box_width + 1.0, top + box_height + 1.0], TOOLTIP_BORDER);
+        push_rectangle(&mut vertices, [left, top],
+                       [left + box_width, top + box_height], TOOLTIP_BACKGROUND);
+        for (i, (line, color)) in lines.iter().enumerate() {
+            push_text(&mut vertices, line,
+                      [left + padding, top + padding + i as f32 * line_height],
+                      pixel, *color);
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more tooltip vertices than TOOLTIP_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing tooltip")
+    }
+}
+
+/// The most vertices the buttons can take.
+const BUTTON_VERTICES: usize = 1 << 13;
+
+/// The colors of a button's face, normally, with the mouse over it, and while
+/// it's being clicked; and of its border.
+const BUTTON_FACE: [f32; 4] = [0.92, 0.92, 0.92, 0.9];
+const BUTTON_HOVER: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BUTTON_ACTIVE: [f32; 4] = [1.0, 0.95, 0.5, 1.0];
+const BUTTON_BORDER: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+
+/// Graphics state for the buttons along the top of the window. The `Input`
+/// holds their layout, and knows which one the pointer is over. They're drawn
+/// in window coordinates, and use the HUD's shaders.
+struct ButtonsDrawer {
+    program: Program,
+
+    /// The buttons' rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl ButtonsDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<ButtonsDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling button shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, BUTTON_VERTICES)
+            .chain_err(|| "allocating button vertex buffer")?;
+
+        Ok(ButtonsDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            window_to_device: &[[f32; 3]; 3],
+            input: &Input) -> Result<()>
+    {
+        let buttons = input.buttons();
+        if buttons.is_empty() {
+            return Ok(());
+        }
+
+        let mut vertices = vec![];
+        for button in buttons {
+            push_button(&mut vertices, button, input.button_state(button.command));
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more button vertices than BUTTON_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing buttons")
+    }
+}
+
+/// Push onto the end of `vec` the rectangles needed to draw `button`, in
+/// window coordinates, looking as `state` says.
+fn push_button<C>(vec: &mut Vec<HudVertex>, button: &Button<C>, state: ButtonState) {
+    let face = match state {
+        ButtonState::Normal => BUTTON_FACE,
+        ButtonState::Hover => BUTTON_HOVER,
+        ButtonState::Active => BUTTON_ACTIVE,
+    };
+    let ([left, top], [right, bottom]) = (button.top_left, button.bottom_right);
+    push_rectangle(vec, [left - 1.0, top - 1.0], [right + 1.0, bottom + 1.0], BUTTON_BORDER);
+    push_rectangle(vec, [left, top], [right, bottom], face);
+    let padding = ui::padding(button.pixel);
+    push_text(vec, &button.label, [left + padding, top + padding], button.pixel, OVERLAY_TEXT);
+}
+
+/// The title shown above the start screen's menu.
+const MENU_TITLE: &str = "RBATTLE";
+
+/// Graphics state for the start screen, shown before there's any game to
+/// draw. It draws the backdrop gradient, the menu's message, and its buttons,
+/// in window coordinates, using the HUD's shaders.
+pub struct MenuDrawer {
+    /// The display we draw frames on.
+    display: GliumDisplay,
+
+    program: Program,
+
+    /// The menu's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+
+    /// The backdrop's colors at the top and bottom of the window.
+    backdrop: ([f32; 4], [f32; 4]),
+}
+
+impl MenuDrawer {
+    pub fn new(display: &GliumDisplay, theme: &Theme) -> Result<MenuDrawer> {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling menu shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, BUTTON_VERTICES)
+            .chain_err(|| "allocating menu vertex buffer")?;
+
+        Ok(MenuDrawer {
+            display: display.clone(),
+            program,
+            vertices: RefCell::new(vertices),
+            backdrop: (theme.backdrop_top.to_gl(), theme.backdrop_bottom.to_gl()),
+        })
+    }
+
+    /// Draw a frame showing `menu`.
+    pub fn render(&self, menu: &Menu) -> Result<()> {
+        let mut frame = self.display.draw();
+        let (width, height) = frame.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let window_to_device = compose(translate_transform(-1.0, 1.0),
+                                       scale_transform(2.0 / width, -2.0 / height));
+
+        let (top, bottom) = self.backdrop;
+        let mut vertices = vec![];
+        for &(point, color) in &[([0.0, 0.0], top), ([width, 0.0], top),
+                                 ([width, height], bottom), ([width, height], bottom),
+                                 ([0.0, height], bottom), ([0.0, 0.0], top)] {
+            vertices.push(HudVertex { point, color });
+        }
+
+        let buttons = menu.buttons();
+        if let Some(first) = buttons.first() {
+            let pixel = 2.0 * first.pixel;
+            let title_width = font::width(MENU_TITLE) as f32 * pixel;
+            let title_height = font::GLYPH_HEIGHT as f32 * pixel;
+            let origin = [((width - title_width) / 2.0).floor(),
+                          (first.top_left[1] - 2.0 * title_height).max(0.0)];
+            push_text(&mut vertices, MENU_TITLE, origin, pixel, OVERLAY_TEXT);
+
+            // Any message goes between the title and the buttons, at the
+            // size of the labels along the top of the game window.
+            if let Some(message) = menu.message() {
+                let pixel = ui::pixel_size(height);
+                let message_width = font::width(message) as f32 * pixel;
+                let origin = [((width - message_width) / 2.0).max(0.0).floor(),
+                              (first.top_left[1] - title_height / 2.0).max(0.0).floor()];
+                push_text(&mut vertices, message, origin, pixel, OVERLAY_TEXT);
+            }
+        }
+        for button in buttons {
+            push_button(&mut vertices, button, menu.button_state(button.command));
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more menu vertices than BUTTON_VERTICES")
+            .write(&vertices);
+
+        let status = frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                                NoIndices(PrimitiveType::TrianglesList),
+                                &self.program,
+                                &uniform! {
+                                    hud_to_device: window_to_device,
+                                },
+                                &DrawParameters {
+                                    blend: Blend::alpha_blending(),
+                                    .. Default::default()
+                                })
+            .chain_err(|| "drawing menu");
+        frame.finish()
+            .chain_err(|| "drawing finish failed")?;
+        status
+    }
+}
+
+/// The most vertices the settings panel can take.
+const PANEL_VERTICES: usize = 1 << 16;
+
+/// The color of the veil the settings panel draws over the game.
+const PANEL_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.7];
+
+/// Graphics state for the settings panel. The `Panel` holds its layout; this
+/// draws a veil over the whole window, a title, the panel's buttons, and when
+/// asked, the list of keyboard shortcuts, in window coordinates, using the
+/// HUD's shaders.
+struct PanelDrawer {
+    program: Program,
+
+    /// The panel's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl PanelDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<PanelDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling settings panel shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, PANEL_VERTICES)
+            .chain_err(|| "allocating settings panel vertex buffer")?;
+
+        Ok(PanelDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    fn draw(&self, frame: &mut Frame, panel: &Panel) -> Result<()> {
+        let buttons = panel.buttons();
+        let first = match buttons.first() {
+            Some(first) if panel.is_open() => first,
+            _ => return Ok(()),
+        };
+
+        let (width, height) = frame.get_dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let window_to_device = compose(translate_transform(-1.0, 1.0),
+                                       scale_transform(2.0 / width, -2.0 / height));
+
+        let mut vertices = vec![];
+        push_rectangle(&mut vertices, [0.0, 0.0], [width, height], PANEL_BACKGROUND);
+
+        // The keyboard shortcuts go in two columns above the buttons, at the
+        // size of the labels along the top of the window.
+        let mut top = first.top_left[1];
+        if panel.showing_keys() {
+            let pixel = ui::pixel_size(height);
+            let line_height = (font::GLYPH_HEIGHT + 3) as f32 * pixel;
+            let key_width = settings::KEYS.iter()
+                .map(|&(key, _)| font::width(key))
+                .max().unwrap_or(0) as f32 * pixel + 4.0 * font::ADVANCE as f32 * pixel;
+            let description_width = settings::KEYS.iter()
+                .map(|&(_, description)| font::width(description))
+                .max().unwrap_or(0) as f32 * pixel;
+            let left = ((width - key_width - description_width) / 2.0).max(0.0).floor();
+            top = (top - (settings::KEYS.len() + 1) as f32 * line_height).max(0.0).floor();
+            for (row, &(key, description)) in settings::KEYS.iter().enumerate() {
+                let y = top + row as f32 * line_height;
+                push_text(&mut vertices, key, [left, y], pixel, PERF_TEXT);
+                push_text(&mut vertices, description, [left + key_width, y], pixel, PERF_TEXT);
+            }
+        }
+
+        let title = if panel.showing_keys() { "KEYS" } else { "SETTINGS" };
+        let pixel = 2.0 * first.pixel;
+        let title_width = font::width(title) as f32 * pixel;
+        let title_height = font::GLYPH_HEIGHT as f32 * pixel;
+        let origin = [((width - title_width) / 2.0).floor(),
+                      (top - 2.0 * title_height).max(0.0)];
+        push_text(&mut vertices, title, origin, pixel, PERF_TEXT);
+
+        for button in buttons {
+            push_button(&mut vertices, button, panel.button_state(button.command));
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more settings panel vertices than PANEL_VERTICES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing settings panel")
+    }
+}
+
+/// The most vertices the performance overlay can take.
+const PERF_VERTICES: usize = 1 << 14;
+
+/// The colors of the performance overlay's background and text.
+const PERF_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const PERF_TEXT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Graphics state for the performance overlay in the upper right corner of the
+/// window: how long frames are taking, how fast the game is going, and how
+/// much there is to draw. It's laid out in window coordinates, and uses the
+/// HUD's shaders.
+struct PerfDrawer {
+    program: Program,
+
+    /// The overlay's rectangles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<HudVertex>>,
+}
+
+impl PerfDrawer {
+    fn new(display: &dyn Facade, _map: &Map) -> Result<PerfDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("hud.vert"),
+                                           include_str!("hud.frag"),
+                                           None)
+            .chain_err(|| "compiling performance overlay shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, PERF_VERTICES)
+            .chain_err(|| "allocating performance overlay vertex buffer")?;
+
+        Ok(PerfDrawer { program, vertices: RefCell::new(vertices) })
+    }
+
+    /// Draw `perf` on `frame`, along with the number of vertices in each of
+    /// the buffers listed in `vertices`.
+    fn draw(&self, frame: &mut Frame,
+            window_to_device: &[[f32; 3]; 3],
+            perf: &PerfStats,
+            vertices: &[(&str, usize)]) -> Result<()>
+    {
+        let millis = |duration: Duration| duration.as_secs_f32() * 1000.0;
+        let mut lines = vec![
+            format!("FRAME: {:.1} MS, MAX {:.1}", millis(perf.mean_frame), millis(perf.max_frame)),
+            format!("FPS: {:.0}", perf.frames_per_second),
+            format!("TURNS/S: {:.1}", perf.turns_per_second),
+            format!("BEHIND: {}", perf.turns_behind),
+            format!("VERTICES: {}", vertices.iter().map(|&(_, count)| count).sum::<usize>()),
+        ];
+        lines.extend(vertices.iter().map(|&(name, count)| format!("  {}: {}", name, count)));
+
+        let (width, height) = frame.get_dimensions();
+        let pixel = (height as f32 / 400.0).floor().max(1.0);
+        let line_height = (font::GLYPH_HEIGHT + 3) as f32 * pixel;
+        let padding = 3.0 * pixel;
+        let text_width = lines.iter().map(|line| font::width(line)).max().unwrap_or(0);
+        let box_width = text_width as f32 * pixel + 2.0 * padding;
+        let box_height = lines.len() as f32 * line_height - 3.0 * pixel + 2.0 * padding;
+        let left = width as f32 - box_width;
+
+        let mut rectangles = vec![];
+        push_rectangle(&mut rectangles, [left, 0.0], [width as f32, box_height], PERF_BACKGROUND);
+        for (i, line) in lines.iter().enumerate() {
+            push_text(&mut rectangles, line,
+                      [left + padding, padding + i as f32 * line_height],
+                      pixel, PERF_TEXT);
+        }
+
+        self.vertices.borrow_mut().slice_mut(0..rectangles.len())
+            .expect("more performance overlay vertices than PERF_VERTICES")
+            .write(&rectangles);
+
+        frame.draw(self.vertices.borrow().slice(0..rectangles.len()).unwrap(),
+                   NoIndices(PrimitiveType::TrianglesList),
+                   &self.program,
+                   &uniform! {
+                       hud_to_device: *window_to_device,
+                   },
+                   &DrawParameters {
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing performance overlay")
+    }
+}
+
+/// Return the compass direction from `from` to its neighbor `to` on `map`.
+fn direction(map: &Map, from: Node, to: Node) -> &'static str {
+    let GraphPt(from) = map.graph.center(from);
+    let GraphPt(to) = map.graph.center(to);
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    if dx.abs() > dy.abs() {
+        if dx > 0.0 { "EAST" } else { "WEST" }
+    } else if dy > 0.0 {
+        "NORTH"
+    } else {
+        "SOUTH"
+    }
+}
+
+/// Push onto the end of `vec` the rectangles needed to draw `text` in window
+/// coordinates, with its upper left corner at `origin`, using squares `pixel`
+/// wide for each of the font's pixels.
+fn push_text(vec: &mut Vec<HudVertex>, text: &str, origin: [f32; 2], pixel: f32,
+             color: [f32; 4]) {
+    for run in font::runs(text) {
+        let x = origin[0] + run.column as f32 * pixel;
+        let y = origin[1] + run.row as f32 * pixel;
+        push_rectangle(vec, [x, y], [x + run.length as f32 * pixel, y + pixel], color);
+    }
+}
+
+/// How long a particle lives, in seconds.
+const PARTICLE_LIFETIME: f32 = 0.6;
+
+/// The most particles we'll keep alive at once.
+const MAX_PARTICLES: usize = 2048;
+
+/// How many particles burst from a node when it changes hands.
+const CAPTURE_PARTICLES: usize = 24;
+
+/// How many sparks fly from a node under attack, every `ATTACK_SPARK_TURNS`.
+const ATTACK_PARTICLES: usize = 3;
+
+/// How often nodes under attack throw off sparks. An attack can go on for many
+/// turns, and sparks every turn would bury the board.
+const ATTACK_SPARK_TURNS: usize = 8;
+
+/// How long a contested node stays outlined, in seconds.
+const CONTEST_HIGHLIGHT: f32 = 1.5;
+
+/// The color of the outline around a contested node.
+const CONTEST_COLOR: [f32; 3] = [0.9, 0.1, 0.0];
+
+/// How long the flash on an outflow cleared by an attack lasts, in seconds.
+const CLEARED_FLASH: f32 = 0.5;
+
+/// The most cleared outflows we'll flash at once.
+const MAX_FLASHES: usize = 512;
+
+/// A vertex of a particle: its position in Graph space, and its color.
+#[derive(Copy, Clone, Debug)]
+struct ParticleVertex { point: [f32; 2], color: [f32; 4] }
+
+implement_vertex!(ParticleVertex, point, color);
+
+/// A single particle, flying outward from where something happened.
+struct Particle {
+    /// Where the particle started, in Graph space.
+    origin: [f32; 2],
+
+    /// How far the particle travels per second, in Graph space.
+    velocity: [f32; 2],
+
+    /// When the particle was emitted, in seconds.
+    born: f32,
+
+    color: [f32; 3],
+}
+
+/// Graphics state for the effects marking captures and attacks.
+///
+/// Most of what happens between one state and the next can be seen by
+/// comparing them, so we keep the nodes from the last state we drew and
+/// compare them with each new state. When a node changes hands, a burst of particles
+/// in the new owner's color flies out from it; when a node is being attacked,
+/// it throws off a few sparks every so often.
+///
+/// A node that changes hands, or whose outflows are stopped by an attack, is
+/// also contested: we outline it in red for `CONTEST_HIGHLIGHT` seconds, so
+/// that its owner notices the incursion. Outflows can't be seen once they're
+/// stopped, so the state reports those as events; we flash each stopped
+/// outflow in red for `CLEARED_FLASH` seconds, so the defender can see what
+/// they lost.
+struct EffectsDrawer {
+    /// Shader program for drawing particles.
+    program: Program,
+
+    /// The positions of live particles, rebuilt on each frame.
+    vertices: RefCell<VertexBuffer<ParticleVertex>>,
+
+    /// Live particles, oldest first.
+    particles: RefCell<Vec<Particle>>,
+
+    /// The turn number and nodes of the last state we drew.
+    previous: RefCell<Option<(usize, Vec<Option<Occupied>>)>>,
+
+    /// Shader program for drawing contested nodes' outlines.
+    outline_program: Program,
+
+    /// The outline of every node, as a pair of vertices for each segment of
+    /// its boundary.
+    outlines: VertexBuffer<GraphVertex>,
+
+    /// For each node, the range of `outlines` holding its outline.
+    outline_ranges: Vec<Range<usize>>,
+
+    /// For each node, when it was last contested, in seconds.
+    contested: RefCell<Vec<Option<f32>>>,
+
+    /// Shader program for flashing cleared outflows. This is the HUD's, since
+    /// it takes a color per vertex; we simply hand it a transformation from
+    /// graph coordinates, rather than from window coordinates.
+    flash_program: Program,
+
+    /// The lines of the cleared outflows being flashed, rebuilt on each frame.
+    flash_vertices: RefCell<VertexBuffer<HudVertex>>,
+
+    /// Outflows cleared by attacks, as the times they were cleared, in
+    /// seconds, and their source and destination nodes, oldest first.
+    flashes: RefCell<Vec<(f32, Node, Node)>>,
+
+    /// The source of particles' directions and speeds. This is purely
+    /// cosmetic, so it needn't match anything on other hosts.
+    rng: RefCell<XorShift128Plus>,
+}
+
+impl EffectsDrawer {
+    fn new(display: &dyn Facade, map: &Map) -> Result<EffectsDrawer>
+    {
+        let program = Program::from_source(display,
+                                           include_str!("particle.vert"),
+                                           include_str!("particle.frag"),
+                                           None)
+            .chain_err(|| "compiling particle shaders")?;
+
+        let vertices = VertexBuffer::empty_persistent(display, MAX_PARTICLES)
+            .chain_err(|| "allocating particle vertex buffer")?;
+
+        let outline_program = Program::from_source(display,
+                                                   include_str!("map.vert"),
+                                                   include_str!("mouse.frag"),
+                                                   None)
+            .chain_err(|| "compiling contested node shaders")?;
+
+        // Each outline follows the node's boundary, shrunk a bit toward its
+        // center, so that neighboring nodes' outlines don't overlap.
+        let endpoints = map.graph.endpoints();
+        let mut outlines = vec![];
+        let mut outline_ranges = Vec::with_capacity(map.graph.nodes());
+        for node in 0..map.graph.nodes() {
+            let GraphPt(center) = map.graph.center(node);
+            let inset = |GraphPt(point): GraphPt| GraphVertex {
+                point: [center[0] + (point[0] - center[0]) * 0.9,
+                        center[1] + (point[1] - center[1]) * 0.9],
+            };
+            let start = outlines.len();
+            for segment in map.graph.boundary(node) {
+                outlines.push(inset(endpoints[segment.line.start]));
+                outlines.push(inset(endpoints[segment.line.end]));
+            }
+            outline_ranges.push(start..outlines.len());
+        }
+        let outlines = VertexBuffer::new(display, &outlines)
+            .chain_err(|| "building node outline buffer")?;
+
+        let flash_program = Program::from_source(display,
+                                                 include_str!("hud.vert"),
+                                                 include_str!("hud.frag"),
+                                                 None)
+            .chain_err(|| "compiling cleared outflow shaders")?;
+
+        let flash_vertices = VertexBuffer::empty_persistent(display, MAX_FLASHES * 2)
+            .chain_err(|| "allocating cleared outflow vertex buffer")?;
+
+        Ok(EffectsDrawer {
+            program,
+            vertices: RefCell::new(vertices),
+            particles: RefCell::new(vec![]),
+            previous: RefCell::new(None),
+            outline_program,
+            outlines,
+            outline_ranges,
+            contested: RefCell::new(vec![None; map.graph.nodes()]),
+            flash_program,
+            flash_vertices: RefCell::new(flash_vertices),
+            flashes: RefCell::new(vec![]),
+            rng: RefCell::new(XorShift128Plus::new([0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210])),
+        })
+    }
+
+    fn draw(&self, frame: &mut Frame,
+            to_device: &[[f32; 3]; 3],
+            scale: f32,
+            time: Duration,
+            state: &State,
+            palette: &Palette) -> Result<()>
+    {
+        let now = time.as_secs_f32();
+        self.emit(now, state, palette);
+        self.draw_contested(frame, to_device, scale, now)?;
+        self.draw_flashes(frame, to_device, scale, now, state)?;
+
+        let mut particles = self.particles.borrow_mut();
+        particles.retain(|particle| now - particle.born < PARTICLE_LIFETIME);
+        if particles.is_empty() {
+            return Ok(());
+        }
+
+        let vertices: Vec<ParticleVertex> = particles.iter()
+            .map(|particle| {
+                let age = now - particle.born;
+                let [r, g, b] = particle.color;
+                ParticleVertex {
+                    point: [particle.origin[0] + particle.velocity[0] * age,
+                            particle.origin[1] + particle.velocity[1] * age],
+                    color: [r, g, b, 1.0 - age / PARTICLE_LIFETIME],
+                }
+            })
+            .collect();
+
+        self.vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more particles than MAX_PARTICLES")
+            .write(&vertices);
+
+        frame.draw(self.vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::Points),
+                   &self.program,
+                   &uniform! {
+                       graph_to_device: *to_device,
+                   },
+                   &DrawParameters {
+                       point_size: Some(6.0 * scale),
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing particles")
+    }
+
+    /// Outline each node contested within the last `CONTEST_HIGHLIGHT`
+    /// seconds, fading out as the contest recedes.
+    fn draw_contested(&self, frame: &mut Frame, to_device: &[[f32; 3]; 3], scale: f32,
+                      now: f32) -> Result<()>
+    {
+        let mut contested = self.contested.borrow_mut();
+        for (node, when) in contested.iter_mut().enumerate() {
+            let age = match *when {
+                Some(when) if now - when < CONTEST_HIGHLIGHT => now - when,
+                Some(_) => {
+                    *when = None;
+                    continue;
+                }
+                None => continue,
+            };
+            let [r, g, b] = CONTEST_COLOR;
+            let outline = self.outlines.slice(self.outline_ranges[node].clone())
+                .expect("node outline out of range");
+            frame.draw(outline,
+                       NoIndices(PrimitiveType::LinesList),
+                       &self.outline_program,
+                       &uniform! {
+                           graph_to_device: *to_device,
+                           color: [r, g, b, 1.0 - age / CONTEST_HIGHLIGHT],
+                       },
+                       &DrawParameters {
+                           line_width: Some(3.0 * scale),
+                           blend: Blend::alpha_blending(),
+                           .. Default::default()
+                       })
+                .chain_err(|| "drawing contested node")?;
+        }
+        Ok(())
+    }
+
+    /// Flash each outflow cleared within the last `CLEARED_FLASH` seconds,
+    /// fading out as time passes.
+    fn draw_flashes(&self, frame: &mut Frame, to_device: &[[f32; 3]; 3], scale: f32,
+                    now: f32, state: &State) -> Result<()>
+    {
+        let mut flashes = self.flashes.borrow_mut();
+        flashes.retain(|&(when, _, _)| now - when < CLEARED_FLASH);
+        if flashes.is_empty() {
+            return Ok(());
+        }
+
+        let graph = &state.map.graph;
+        let [r, g, b] = CONTEST_COLOR;
+        let mut vertices = Vec::with_capacity(flashes.len() * 2);
+        for &(when, from, to) in flashes.iter() {
+            let color = [r, g, b, 1.0 - (now - when) / CLEARED_FLASH];
+            let GraphPt(start) = graph.center(from);
+            let GraphPt(end) = graph.center(to);
+            vertices.push(HudVertex { point: start, color });
+            vertices.push(HudVertex { point: midpoint(start, end), color });
+        }
+
+        self.flash_vertices.borrow_mut().slice_mut(0..vertices.len())
+            .expect("more cleared outflows than MAX_FLASHES")
+            .write(&vertices);
+
+        frame.draw(self.flash_vertices.borrow().slice(0..vertices.len()).unwrap(),
+                   NoIndices(PrimitiveType::LinesList),
+                   &self.flash_program,
+                   &uniform! {
+                       hud_to_device: *to_device,
+                   },
+                   &DrawParameters {
+                       line_width: Some(5.0 * scale),
+                       blend: Blend::alpha_blending(),
+                       .. Default::default()
+                   })
+            .chain_err(|| "drawing cleared outflows")
+    }
+
+    /// Compare `state` with the last state we drew, and emit particles for
+    /// whatever has happened in between. Note which nodes were contested.
+    fn emit(&self, now: f32, state: &State, palette: &Palette) {
+        let mut previous = self.previous.borrow_mut();
+        let (previous_turn, before) = match previous.take() {
+            // If the state has gone backwards, say because we reconnected and
+            // got a fresh copy, there's nothing sensible to compare.
+            Some((turn, nodes)) if turn <= state.turn => (turn, nodes),
+            _ => {
+                *previous = Some((state.turn, state.nodes.clone()));
+                return;
+            }
+        };
+        if previous_turn == state.turn {
+            *previous = Some((previous_turn, before));
+            return;
+        }
+
+        let map = &*state.map;
+        let color_of = |player: Player| {
+            let (r, g, b) = palette.color(map, player);
+            [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+        };
+        let sparks_due = previous_turn / ATTACK_SPARK_TURNS != state.turn / ATTACK_SPARK_TURNS;
+        let mut contested = self.contested.borrow_mut();
+
+        // Flash the outflows cleared since the last state we drew.
+        let mut flashes = self.flashes.borrow_mut();
+        for event in state.events.iter().filter(|event| event.turn() > previous_turn) {
+            match *event {
+                Event::OutflowsCleared { node, ref outflows, .. } => {
+                    contested[node] = Some(now);
+                    flashes.extend(outflows.iter().map(|&to| (now, node, to)));
+                }
+                Event::Chat { .. } => (),
+            }
+        }
+        if flashes.len() > MAX_FLASHES {
+            let excess = flashes.len() - MAX_FLASHES;
+            flashes.drain(..excess);
+        }
+
+        for (node, (old, new)) in before.iter().zip(&state.nodes).enumerate() {
+            let old_owner = old.as_ref().map(|occupied| occupied.player);
+            let new_owner = new.as_ref().map(|occupied| occupied.player);
+            if changed_hands(old, new) {
+                contested[node] = Some(now);
+            }
+            match (old_owner, new_owner) {
+                // A node left empty by a fight gets a gray puff.
+                (Some(_), None) => self.burst(now, map, node, CAPTURE_PARTICLES,
+                                              [0.5, 0.5, 0.5]),
+                (Some(old), Some(new)) if old != new =>
+                    self.burst(now, map, node, CAPTURE_PARTICLES, color_of(new)),
+                (_, Some(owner)) if sparks_due => {
+                    if let Some(attacker) = attacker(state, node, owner) {
+                        self.burst(now, map, node, ATTACK_PARTICLES, color_of(attacker));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        *previous = Some((state.turn, state.nodes.clone()));
+    }
+
+    /// Emit `count` particles of `color` from the center of `node`.
+    fn burst(&self, now: f32, map: &Map, node: Node, count: usize, color: [f32; 3]) {
+        let GraphPt(origin) = map.graph.center(node);
+        let reach = map.graph.radius() * 2.0 / PARTICLE_LIFETIME;
+        let mut rng = self.rng.borrow_mut();
+        let mut particles = self.particles.borrow_mut();
+        for _ in 0..count {
+            let angle = rng.gen_range(0.0, 2.0 * ::std::f32::consts::PI);
+            let speed = rng.gen_range(0.3, 1.0) * reach;
+            particles.push(Particle {
+                origin,
+                velocity: [angle.cos() * speed, angle.sin() * speed],
+                born: now,
+                color,
+            });
+        }
+
+        // If there's too much going on, let the oldest particles go early.
+        if particles.len() > MAX_PARTICLES {
+            let excess = particles.len() - MAX_PARTICLES;
+            particles.drain(..excess);
+        }
+    }
+}
+
+/// Return true if a node that was `old` and is now `new` has lost its owner,
+/// or been taken by another player.
+fn changed_hands(old: &Option<Occupied>, new: &Option<Occupied>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => old.player != new.player,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+/// If some player other than `owner` has an outflow into `node`, return one
+/// such player.
+fn attacker(state: &State, node: Node, owner: Player) -> Option<Player> {
+    state.map.graph.neighbors(node).into_iter()
+        .filter_map(|neighbor| state.nodes[neighbor].as_ref())
+        .find(|occupied| occupied.player != owner && occupied.outflows.contains(&node))
+        .map(|occupied| occupied.player)
+}
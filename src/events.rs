@@ -0,0 +1,212 @@
+//! A machine-readable stream of game events for spectators and tools.
+//!
+//! When the server is started with `--events ADDR`, it listens on that address
+//! for connections from external tools like stream overlays or stat trackers.
+//! Each connection receives one line of JSON per turn, describing the actions
+//! taken and how many nodes each player holds afterwards. The stream is
+//! write-only: anything a tool sends is ignored.
+//!
+//! This is deliberately independent of the game protocol: tools don't have to
+//! simulate the game to follow it, and the game protocol can change without
+//! breaking them.
+
+use scheduler::{CollectedActions, Observer};
+use state::{Action, Phase, State};
+
+use serde_json;
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+/// How long we'll wait for a subscriber to accept an event before giving up on
+/// it. Slow readers shouldn't be able to hold up the stream for everyone else.
+const WRITE_TIMEOUT_MS: u64 = 250;
+
+/// The event published for each turn.
+#[derive(Debug, Serialize)]
+pub struct TurnEvent<'a> {
+    /// The turn that was just played.
+    pub turn: usize,
+
+    /// The actions applied to produce that turn.
+    pub actions: &'a [Action],
+
+    /// The number of nodes each player occupies, indexed by player number.
+    pub nodes: Vec<usize>,
+
+    /// True if the game is still in its warm-up phase. The first event with
+    /// this false marks the start of the game proper.
+    pub warming_up: bool,
+}
+
+/// A publisher of turn events to whoever has connected to the event port.
+pub struct EventStream {
+    /// Serialized event lines, waiting to be written by the publishing thread.
+    sender: mpsc::Sender<String>,
+}
+
+impl EventStream {
+    /// Start accepting event stream subscribers on `addr`.
+    pub fn listen(addr: SocketAddr) -> io::Result<EventStream> {
+        Ok(EventStream::serve(TcpListener::bind(addr)?))
+    }
+
+    /// Publish events to subscribers accepted from `listener`.
+    fn serve(listener: TcpListener) -> EventStream {
+        let subscribers = Arc::new(Mutex::new(Vec::<TcpStream>::new()));
+
+        // Accept subscribers on one thread...
+        let accepted = subscribers.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                if stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS)))
+                    .is_ok()
+                {
+                    accepted.lock().unwrap().push(stream);
+                }
+            }
+        });
+
+        // ... and write events to them on another, so that the scheduler never
+        // waits on the network.
+        let (sender, receiver) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in receiver {
+                let mut guard = subscribers.lock().unwrap();
+                guard.retain(|mut stream| stream.write_all(line.as_bytes()).is_ok());
+            }
+        });
+
+        EventStream { sender }
+    }
+}
+
+impl Observer for EventStream {
+    fn observe(&mut self, turn: &CollectedActions, state: &State) {
+        let event = TurnEvent {
+            turn: turn.turn,
+            actions: &turn.actions,
+            nodes: state.node_counts(),
+            warming_up: matches!(state.phase(), Phase::WarmUp { .. }),
+        };
+        let mut line = serde_json::to_string(&event)
+            .expect("failed to serialize turn event");
+        line.push('\n');
+
+        // If the publishing thread has died, there's nobody to tell.
+        let _ = self.sender.send(line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use scheduler::CollectedActions;
+    use state::{GameParameters, Player};
+
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader};
+
+    fn collected(turn: usize, actions: Vec<Action>) -> CollectedActions {
+        CollectedActions { turn, actions, state_checksum: 0, turn_duration: None }
+    }
+
+    /// Connect a subscriber to the stream at `addr`, publishing turn zero
+    /// until it arrives, so we know the stream has accepted it.
+    fn subscribe(events: &mut EventStream, addr: SocketAddr, state: &State)
+                 -> BufReader<TcpStream>
+    {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            events.observe(&collected(0, vec![]), state);
+            if reader.read_line(&mut line).is_ok_and(|read| read > 0) {
+                reader.get_ref().set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                return reader;
+            }
+        }
+    }
+
+    /// Return the next event from `reader` after turn zero, or `None` if the
+    /// stream has dropped us.
+    fn next_event(reader: &mut BufReader<TcpStream>) -> Option<Value> {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).ok()? == 0 || !line.ends_with('\n') {
+                return None;
+            }
+            let event: Value = serde_json::from_str(&line).unwrap();
+            if event["turn"] != 0 {
+                return Some(event);
+            }
+        }
+    }
+
+    #[test]
+    fn events() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut events = EventStream::serve(listener);
+        let mut state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters { warmup_turns: 2, ..GameParameters::default() });
+
+        let mut prompt = subscribe(&mut events, addr, &state);
+        let mut slow = subscribe(&mut events, addr, &state);
+
+        // A few turns: someone joins, and the warm-up ends.
+        let join = Action::Join { player: Player(0) };
+        for turn in 1..4 {
+            let actions = if turn == 1 { vec![join.clone()] } else { vec![] };
+            for action in &actions {
+                state.take_action(action);
+            }
+            state.advance();
+            events.observe(&collected(turn, actions), &state);
+        }
+        for reader in [&mut prompt, &mut slow] {
+            let first = next_event(reader).unwrap();
+            assert_eq!(first["turn"], 1);
+            assert_eq!(first["actions"][0]["Join"]["player"], 0);
+            assert_eq!(first["nodes"], serde_json::json!([1, 0]));
+            assert_eq!(first["warming_up"], true);
+            assert_eq!(next_event(reader).unwrap()["turn"], 2);
+            let third = next_event(reader).unwrap();
+            assert_eq!((third["turn"].clone(), third["warming_up"].clone()),
+                       (Value::from(3), Value::from(false)));
+        }
+
+        // Now `slow` stops reading, while `prompt` keeps up. Once `slow` falls
+        // far enough behind to hold up the stream, it's dropped, and `prompt`
+        // carries on undisturbed.
+        let chat = Action::Chat { player: Player(0), text: "x".repeat(64 * 1024) };
+        const TURNS: usize = 400;
+        let reading = thread::spawn(move || {
+            (0..TURNS).map(|_| next_event(&mut prompt).unwrap()["turn"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for turn in 4..4 + TURNS {
+            events.observe(&collected(turn, vec![chat.clone()]), &state);
+        }
+        let turns = reading.join().unwrap();
+        assert_eq!(turns, (4..4 + TURNS as u64).collect::<Vec<_>>());
+
+        let mut behind = 0;
+        while next_event(&mut slow).is_some() {
+            behind += 1;
+        }
+        assert!(behind < TURNS, "slow subscriber was never dropped");
+    }
+}
@@ -0,0 +1,96 @@
+//! Saving rendered frames as images, so games can be turned into videos.
+//!
+//! Given `--export-frames DIR`, the client writes every Nth frame it draws to
+//! `DIR` as a numbered image file, which a tool like `ffmpeg` can assemble into
+//! a video afterwards. The images are in binary PPM format, which is trivial
+//! to write, and which every image tool reads.
+
+use errors::*;
+use renderer::Screenshot;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Decides which rendered frames to save, and saves them.
+pub struct FrameExporter {
+    /// The directory to write images to.
+    dir: PathBuf,
+
+    /// Save one frame out of every this many.
+    every: usize,
+
+    /// The number of frames rendered so far.
+    rendered: usize,
+
+    /// The number of frames saved so far.
+    saved: usize,
+}
+
+impl FrameExporter {
+    /// Return an exporter that saves every `every`th frame into `dir`,
+    /// creating it if necessary.
+    pub fn new(dir: PathBuf, every: usize) -> Result<FrameExporter> {
+        fs::create_dir_all(&dir)
+            .chain_err(|| format!("creating frame export directory {}", dir.display()))?;
+        Ok(FrameExporter { dir, every: every.max(1), rendered: 0, saved: 0 })
+    }
+
+    /// Note that a frame has been rendered, and return true if it should be
+    /// saved.
+    pub fn due(&mut self) -> bool {
+        let due = self.rendered.is_multiple_of(self.every);
+        self.rendered += 1;
+        due
+    }
+
+    /// Save `shot` as the next frame in the sequence.
+    pub fn save(&mut self, shot: &Screenshot) -> Result<()> {
+        let path = self.dir.join(format!("frame-{:06}.ppm", self.saved));
+        let file = File::create(&path)
+            .chain_err(|| format!("creating {}", path.display()))?;
+        write_ppm(BufWriter::new(file), shot)
+            .chain_err(|| format!("writing {}", path.display()))?;
+        self.saved += 1;
+        Ok(())
+    }
+}
+
+/// Write `shot` to `out` as a binary PPM image.
+fn write_ppm<W: Write>(mut out: W, shot: &Screenshot) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", shot.width, shot.height)?;
+    out.write_all(&shot.rgb)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export() {
+        let shot = Screenshot { width: 2, height: 1, rgb: vec![255, 0, 0, 0, 0, 255] };
+        let mut ppm = vec![];
+        write_ppm(&mut ppm, &shot).unwrap();
+        assert_eq!(ppm, b"P6\n2 1\n255\n\xff\x00\x00\x00\x00\xff");
+
+        let dir = ::std::env::temp_dir()
+            .join(format!("rbattle-export-{}", ::std::process::id()));
+        let mut exporter = FrameExporter::new(dir.clone(), 3).unwrap();
+        for _ in 0..7 {
+            if exporter.due() {
+                exporter.save(&shot).unwrap();
+            }
+        }
+
+        // Frames 0, 3, and 6 were saved, numbered consecutively.
+        let mut names: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["frame-000000.ppm", "frame-000001.ppm", "frame-000002.ppm"]);
+        assert_eq!(fs::read(dir.join("frame-000002.ppm")).unwrap(), ppm);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
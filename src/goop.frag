@@ -12,6 +12,14 @@
 // values and taking them as RGB values, so the circle 0 is black, circle 0xf00
 // is red, circle 0xfff is white, and so on.
 //
+// The circles are repeated in rows above the x axis, again spaced apart by
+// `circle_spacing`, one row for each of the patterns in `map::Pattern`: row 0 is
+// solid, row 1 striped, row 2 dotted, and row 3 ringed. The patterns are drawn
+// in a paler shade of the circle's color, so that players can tell each other
+// apart by something other than hue. Each circle has a darker outline, so that
+// pale colors still stand out from the board, and neighboring circles of the
+// same color don't run together.
+//
 // To draw a large goop circle, we draw a zoomed-in view of one of the unit
 // circles. To draw smaller goop circles, we zoom out. To draw no circle at all,
 // we take pixels from a blank portion of the image, off to the left of the y
@@ -42,12 +50,14 @@ void main() {
   if (fragment_uv.x < -circle_spacing)
     discard;
 
-  // Which circle are we on?
+  // Which circle are we on, and in which row?
   int circle_index = int(fragment_uv.x / circle_spacing + 0.5);
+  int pattern = int(fragment_uv.y / circle_spacing + 0.5);
 
   // Find the position of fragment_uv relative to the circle's center.
   vec2 frag_circle = fragment_uv;
   frag_circle.x -= circle_index * circle_spacing;
+  frag_circle.y -= pattern * circle_spacing;
 
   // Find the angle of fragment_uv relative to the circle's center.
   float angle = atan(frag_circle.y, frag_circle.x);
@@ -56,7 +66,8 @@ void main() {
   float radius = 1 + 0.1 * sin(time * 2) * sin(angle * 6);
 
   // Pixels outside the circle we leave alone.
-  if (length(frag_circle) > radius)
+  float from_center = length(frag_circle);
+  if (from_center > radius)
     discard;
 
   // The circle index is between 0 and 4095. Treat it as a twelve-bit number,
@@ -66,4 +77,29 @@ void main() {
   float blue = (circle_index >> 4) & 0xf;
   float green = circle_index & 0xf;
   color = vec4(red, blue, green, 15) / 15;
+
+  // Decide whether this pixel falls on the pattern's marks.
+  bool marked;
+  if (pattern == 1) {
+    // Diagonal stripes.
+    marked = fract((frag_circle.x + frag_circle.y) * 2.5) < 0.4;
+  } else if (pattern == 2) {
+    // A grid of dots.
+    marked = length(fract(frag_circle * 2.5) - 0.5) < 0.22;
+  } else if (pattern == 3) {
+    // Concentric rings.
+    marked = fract(length(frag_circle) * 3.0) < 0.4;
+  } else {
+    marked = false;
+  }
+
+  if (marked)
+    color.rgb = mix(color.rgb, vec3(1.0), 0.55);
+
+  // Draw the outline about two pixels wide, whatever the circle's size, but
+  // don't let it swallow small circles entirely. `fwidth` tells us how much
+  // `from_center` changes from one pixel to the next.
+  float outline = min(2.0 * fwidth(from_center), 0.3 * radius);
+  if (from_center > radius - outline)
+    color.rgb = mix(color.rgb, vec3(0.0), 0.6);
 }
@@ -0,0 +1,88 @@
+//! Suggesting moves to new players.
+//!
+//! With hints on, the board highlights an outflow worth opening: the move a
+//! medium bot would make in the player's place, reinforcing a node under
+//! pressure or pushing toward the nearest source. The F2 key turns hints on
+//! and off; `--hints` starts with them on.
+//!
+//! The suggestion is worked out again every so often rather than every frame,
+//! so it holds still long enough to follow, and right away once the player
+//! has taken it.
+
+use bot::{Difficulty, Heuristic};
+use graph::Node;
+use state::{Action, Player, State};
+
+/// How many turns a hint stands before we look for a better one.
+const HINT_TURNS: usize = 60;
+
+/// The local player's current hint, and when it was chosen.
+pub struct Hints {
+    player: Player,
+    advisor: Heuristic,
+
+    /// The turn we last chose a hint on, and the outflow we chose, if any.
+    chosen: Option<(usize, Option<(Node, Node)>)>,
+}
+
+impl Hints {
+    pub fn new(player: Player) -> Hints {
+        Hints { player, advisor: Heuristic::new(player, Difficulty::Medium), chosen: None }
+    }
+
+    /// Return the outflow we suggest opening in `state`, if we have a
+    /// suggestion.
+    pub fn update(&mut self, state: &State) -> Option<(Node, Node)> {
+        if let Some((turn, hint)) = self.chosen {
+            let stale = turn > state.turn || state.turn - turn >= HINT_TURNS;
+            if !stale && !hint.is_some_and(|outflow| taken(state, outflow)) {
+                return hint;
+            }
+        }
+
+        let hint = self.advisor.advice(state, self.player)
+            .and_then(|actions| actions.into_iter().next())
+            .and_then(|action| match action {
+                Action::ToggleOutflow { from, to, .. } => Some((from, to)),
+                _ => None,
+            });
+        self.chosen = Some((state.turn, hint));
+        hint
+    }
+}
+
+/// Return true if `outflow` is already open.
+fn taken(state: &State, (from, to): (Node, Node)) -> bool {
+    state.nodes[from].as_ref().is_some_and(|occupied| occupied.outflows.contains(&to))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::GameParameters;
+
+    #[test]
+    fn hints() {
+        let mut state = State::new(MapParameters {
+            size: (5, 5),
+            sources: vec![0, 24],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        state.advance();
+
+        // With nothing but a source, the hint is to push out of it, and it
+        // holds from one frame to the next.
+        let mut hints = Hints::new(Player(0));
+        let (from, to) = hints.update(&state).unwrap();
+        assert_eq!(from, 0);
+        assert_eq!(hints.update(&state), Some((from, to)));
+
+        // Once the player takes it, we find the next step.
+        state.take_action(&Action::ToggleOutflow { player: Player(0), from, to });
+        state.advance();
+        assert_ne!(hints.update(&state), Some((from, to)));
+    }
+}
@@ -0,0 +1,11 @@
+#version 150
+
+in vec4 vertex_color;
+
+// This is automatically assigned to be the color and transparency of the pixel
+// we're responsible for.
+out vec4 out_color;
+
+void main() {
+  out_color = vertex_color;
+}
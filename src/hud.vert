@@ -0,0 +1,19 @@
+#version 150
+
+// The transformation from HUD coordinates to normalized device coordinates,
+// as a homogeneous transform.
+uniform mat3 hud_to_device;
+
+// The vertex location in HUD coordinates.
+in vec2 point;
+
+// The color of the shape this vertex belongs to.
+in vec4 color;
+
+out vec4 vertex_color;
+
+void main() {
+  vec3 device = hud_to_device * vec3(point, 1.0);
+  gl_Position = vec4(device.xy, 0.0, 1.0);
+  vertex_color = color;
+}
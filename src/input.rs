@@ -0,0 +1,1203 @@
+//! Handling user interaction with the game.
+//!
+//! This module handles input events like mouse clicks and keyboard input, and
+//! turns them into UI effects like hover highlights, and then game moves like
+//! outflow toggles, or commands from the buttons along the top of the window.
+//!
+//! Holding the button down and sweeping from node to node toggles the outflow
+//! across each boundary crossed, in the direction of travel, so a long chain
+//! of outflows takes a single stroke. Double-clicking a node we own opens all
+//! its outflows at once.
+//!
+//! To send goop somewhere distant, the player can turn on flow mode, click one
+//! of their nodes, and then a node of someone else's: we find a shortest path
+//! between them, and open the outflows along it that we can. This is purely a
+//! convenience; the server only ever sees ordinary toggles.
+//!
+//! The keyboard works through a selection: a cursor on one node, which the
+//! arrow keys move from node to node. Other keys toggle the selected node's
+//! outflow in a given direction. Which keys do what is up to the main loop;
+//! this module only deals in `Direction`s. The selection can also clear or
+//! open all the selected node's outflows, and pick the ends of a path in flow
+//! mode, so nothing on the board needs the mouse. Tab moves a highlight along
+//! the buttons at the top of the window, for the same reason.
+//!
+//! On big boards, the player can snap the camera to their source, or to the
+//! most recent attack along their frontline, to see what's going on there.
+//!
+//! On a touchscreen, tapping acts like clicking, and sliding one finger like
+//! dragging with the button held. Two fingers pan and zoom the camera instead.
+//! With a mouse, the scroll wheel zooms, and dragging with the middle button
+//! held pans. On a trackpad, pinching zooms, and sliding two fingers pans.
+
+use camera::Camera;
+use chat::Chat;
+use graph::{self, Graph, Node};
+use map::Map;
+use math::{apply, inverse, midpoint, Matrix};
+use state::{Action, Event, Player, State};
+use ui::{self, Button, Command};
+use visible_graph::{EDGE_TOLERANCE, GraphPt, VisibleGraph};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The game's state for handling mouse and keyboard activity.
+#[derive(Debug, Clone)]
+pub struct Input {
+    /// The player we represent.
+    player: Player,
+
+    /// The map we're controlling.
+    map: Arc<Map>,
+
+    /// Where we last saw the mouse. Rather than representing this as a point on
+    /// the plane, we keep it in dathe form relevant to our purposes, broken
+    /// down by which clickable element it's over.
+    position: Affordance,
+
+    /// The node the mouse is over, if any. Nodes aren't clickable, but we show
+    /// details about the node under the mouse.
+    node: Option<Node>,
+
+    /// If the mouse is clicked, this is where the button went down.
+    click: Option<Affordance>,
+
+    /// While the button is held, the edges the mouse has swept across, from
+    /// the node it left to the node it entered, in the order crossed. Each
+    /// edge appears at most once.
+    drag: Vec<(Node, Node)>,
+
+    /// While the button is held, the node the mouse was last over.
+    drag_node: Option<Node>,
+
+    /// The buttons in the window, as last laid out.
+    buttons: Vec<Button>,
+
+    /// How much to widen the ambiguous zones between outflows' hit areas.
+    ui_scale: f32,
+
+    /// How wide the ambiguous zones between outflows' hit areas are, in graph
+    /// units, at a UI scale of 1 with the camera zoomed all the way out.
+    hit_tolerance: f32,
+
+    /// The node the keyboard selection is on, once the player has used it.
+    selection: Option<Node>,
+
+    /// The button the keyboard has highlighted, if any.
+    button_focus: Option<Command>,
+
+    /// The fingers on the touchscreen, by id, and where each was last seen,
+    /// in window coordinates.
+    touches: Vec<(u64, [f32; 2])>,
+
+    /// How the board is zoomed and panned.
+    camera: Camera,
+
+    /// Where we last saw the mouse, in window coordinates.
+    window: [f32; 2],
+
+    /// How the board last appeared in the window, if it has been drawn yet.
+    window_to_graph: Option<Matrix>,
+
+    /// The node of ours, or bordering ours, most recently attacked.
+    contested: Option<Node>,
+
+    /// True while the middle button is held, panning the camera.
+    panning: bool,
+
+    /// When and on which node the last click without a drag was released, if
+    /// a second one there would make a double-click.
+    last_release: Option<(Instant, Node)>,
+
+    /// Where we are in choosing a path for goop to flow along.
+    flow: Flow,
+
+    /// The actions we've requested that haven't taken effect yet.
+    queued: Vec<Action>,
+
+    /// The outflow we suggest opening, if hints are on and we have one.
+    hint: Option<(Node, Node)>,
+
+    /// The chat message being typed, and the latest ones received.
+    chat: Chat,
+}
+
+/// The steps of choosing a path for goop to flow along.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Flow {
+    /// Clicks act on outflows as usual.
+    Off,
+
+    /// The next click on one of our nodes chooses where the path starts.
+    Origin,
+
+    /// The path starts at the given node. The next click on a node of
+    /// someone else's finishes it; a click on another of ours starts it there
+    /// instead.
+    Target(Node),
+}
+
+/// The longest time between two clicks' releases that makes a double-click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// How much one line's worth of scrolling zooms the camera.
+const SCROLL_ZOOM: f32 = 1.2;
+
+/// How far, at least, the camera zooms in when it snaps to a node.
+const FOCUS_ZOOM: f32 = 3.0;
+
+/// A direction on the board, for moving the selection or choosing an outflow
+/// from the keyboard.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    /// Return a unit vector pointing this way, in graph space.
+    fn vector(self) -> [f32; 2] {
+        match self {
+            Direction::North => [0.0, 1.0],
+            Direction::South => [0.0, -1.0],
+            Direction::East => [1.0, 0.0],
+            Direction::West => [-1.0, 0.0],
+        }
+    }
+}
+
+/// A thing on the map the user can interact with. Think of this as a mouse
+/// position, but put in the terms we actually care about.
+///
+/// Whenever I see an enum like this I want to make it into an `Option`. But if
+/// there were more clickable things on the map, this would be the natural place
+/// to list them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Affordance {
+    /// The mouse is not over any interesting element on the map.
+    Nothing,
+
+    /// The mouse is over an outflow edge from the first node to the second.
+    Outflow((Node, Node)),
+
+    /// The mouse is over the button for the given command.
+    Button(Command),
+}
+
+/// What a click, once released, asks for.
+#[derive(Debug, Clone)]
+pub enum Click {
+    /// An action to carry out on the state.
+    Action(Action),
+
+    /// Actions to carry out together, from sweeping across several edges.
+    Drag(Vec<Action>),
+
+    /// Actions to carry out together, opening outflows along a path chosen
+    /// in flow mode.
+    Path(Vec<Action>),
+
+    /// A command from one of the window's buttons.
+    Button(Command),
+}
+
+/// How to draw a button.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ButtonState {
+    Normal,
+
+    /// The mouse is over the button.
+    Hover,
+
+    /// The button is being clicked upon, but not yet released.
+    Active,
+}
+
+impl Input {
+    pub fn new(player: Player, map: Arc<Map>) -> Input {
+        Input { player, map, position: Affordance::Nothing, node: None, click: None,
+                drag: vec![], drag_node: None, buttons: vec![], ui_scale: 1.0,
+                hit_tolerance: EDGE_TOLERANCE,
+                selection: None, button_focus: None, touches: vec![], camera: Camera::default(),
+                window: [0.0, 0.0], window_to_graph: None, contested: None, panning: false, last_release: None, flow: Flow::Off,
+                queued: vec![], hint: None, chat: Chat::default() }
+    }
+
+    /// Report that the mouse moved to `pos` in graph space coordinates, which
+    /// is `window` in window coordinates.
+    pub fn move_to(&mut self, pos: GraphPt, window: [f32; 2]) {
+        if self.panning {
+            self.camera.pan([window[0] - self.window[0], window[1] - self.window[1]]);
+        }
+        self.window = window;
+
+        // Buttons sit on top of the board.
+        if let Some(button) = self.buttons.iter().find(|button| button.contains(window)) {
+            self.position = Affordance::Button(button.command);
+            self.node = None;
+            return;
+        }
+
+        self.position = match self.map.graph.edge_hit(&pos, self.hit_tolerance()) {
+            Some(pos) => Affordance::Outflow(pos),
+            None => Affordance::Nothing
+        };
+        self.node = self.map.graph.node_hit(&pos);
+
+        // If the button is held, note any boundary we've just crossed.
+        match (self.click, self.drag_node, self.node) {
+            (Some(Affordance::Button(_)), _, _) | (None, _, _) => (),
+            (Some(_), Some(last), Some(node)) if last != node => {
+                let edge = (last, node);
+                if self.map.graph.neighbors(last).contains(&node) && !self.drag.contains(&edge) {
+                    self.drag.push(edge);
+                }
+                self.drag_node = Some(node);
+            }
+            (Some(_), None, node) => self.drag_node = node,
+            _ => (),
+        }
+    }
+
+    /// Scale the ambiguous zones between outflows' hit areas by `scale` from
+    /// now on, to match the line widths the renderer is drawing.
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.ui_scale = scale;
+    }
+
+    /// Make the ambiguous zones between outflows' hit areas `tolerance` graph
+    /// units wide from now on, before scaling for the UI scale and zoom.
+    pub fn set_hit_tolerance(&mut self, tolerance: f32) {
+        self.hit_tolerance = tolerance;
+    }
+
+    /// Return how close, in graph units, the mouse may come to a boundary
+    /// before we can't tell which outflow it's over. This keeps the ambiguous
+    /// zones the same width on screen however far the camera has zoomed in.
+    pub fn hit_tolerance(&self) -> f32 {
+        self.hit_tolerance * self.ui_scale / self.camera.zoom()
+    }
+
+    /// Use `buttons` as the window's buttons from now on. They take effect
+    /// the next time the mouse moves.
+    pub fn set_buttons(&mut self, buttons: Vec<Button>) {
+        self.buttons = buttons;
+    }
+
+    /// The window is now `width` by `height` pixels.
+    pub fn set_window_size(&mut self, width: f32, height: f32) {
+        self.camera.set_size(width, height);
+    }
+
+    /// The actions we've requested that haven't taken effect yet are now
+    /// `queued`, so the renderer can show them.
+    pub fn set_queued(&mut self, queued: Vec<Action>) {
+        self.queued = queued;
+    }
+
+    /// Return the actions we've requested that haven't taken effect yet.
+    pub fn queued(&self) -> &[Action] {
+        &self.queued
+    }
+
+    /// Suggest opening the outflow `hint`, or nothing, from now on. See the
+    /// `hint` module.
+    pub fn set_hint(&mut self, hint: Option<(Node, Node)>) {
+        self.hint = hint;
+    }
+
+    /// Return the outflow we're suggesting the player open, if any.
+    pub fn hint(&self) -> Option<(Node, Node)> {
+        self.hint
+    }
+
+    /// Return how the board is zoomed and panned.
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn chat(&self) -> &Chat {
+        &self.chat
+    }
+
+    pub fn chat_mut(&mut self) -> &mut Chat {
+        &mut self.chat
+    }
+
+    /// Return the window's buttons.
+    pub fn buttons(&self) -> &[Button] {
+        &self.buttons
+    }
+
+    /// Return how to draw the button for `command`.
+    pub fn button_state(&self, command: Command) -> ButtonState {
+        let over = self.position == Affordance::Button(command);
+        match self.click {
+            Some(Affordance::Button(clicked)) if clicked == command && over =>
+                ButtonState::Active,
+            // While some other click is held, buttons don't light up.
+            Some(_) => ButtonState::Normal,
+            None if over || self.button_focus == Some(command) => ButtonState::Hover,
+            None => ButtonState::Normal,
+        }
+    }
+
+    /// Return the player we represent.
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    /// Return the node the mouse is over, if any.
+    pub fn hovered_node(&self) -> Option<Node> {
+        self.node
+    }
+
+    /// The main mouse button was clicked at the last reported position.
+    pub fn click(&mut self) {
+        self.click = Some(self.position);
+        self.drag.clear();
+        self.drag_node = self.node;
+    }
+
+    /// The main mouse button was released at `now`. This may return an
+    /// action to carry out on the state, the actions for the edges swept over,
+    /// or a button's command. If this completes a double-click on a node we
+    /// own in `state`, return an action opening all its outflows instead.
+    pub fn release(&mut self, state: &State, now: Instant) -> Option<Click> {
+        // In flow mode, clicks on the board choose the path's ends.
+        match (self.flow, self.click) {
+            (Flow::Off, _) | (_, Some(Affordance::Button(_))) => (),
+            (_, click) => {
+                self.click = None;
+                self.drag.clear();
+                self.drag_node = None;
+                return match (click, self.node) {
+                    (Some(_), Some(node)) => self.choose_flow(node, state),
+                    _ => None,
+                };
+            }
+        }
+
+        let plain = self.drag.is_empty() && match self.click {
+            Some(Affordance::Button(_)) | None => false,
+            Some(_) => true,
+        };
+        let click = self.finish_click();
+
+        let node = match (plain, self.node) {
+            (true, Some(node)) => node,
+            _ => {
+                self.last_release = None;
+                return click;
+            }
+        };
+        match self.last_release.take() {
+            Some((then, last)) if last == node && now - then <= DOUBLE_CLICK_TIME => {
+                match state.nodes[node] {
+                    Some(ref occupied) if occupied.player == self.player =>
+                        Some(Click::Action(Action::OpenOutflows { player: self.player, node })),
+                    _ => click,
+                }
+            }
+            _ => {
+                self.last_release = Some((now, node));
+                click
+            }
+        }
+    }
+
+    /// Turn flow mode on, or off if it's already on.
+    pub fn toggle_flow(&mut self) {
+        self.flow = match self.flow {
+            Flow::Off => Flow::Origin,
+            _ => Flow::Off,
+        };
+    }
+
+    /// Return true if flow mode is on.
+    pub fn flow_active(&self) -> bool {
+        self.flow != Flow::Off
+    }
+
+    /// Return the node the path being chosen in flow mode starts from, if the
+    /// player has chosen one.
+    pub fn flow_origin(&self) -> Option<Node> {
+        match self.flow {
+            Flow::Target(origin) => Some(origin),
+            _ => None,
+        }
+    }
+
+    /// In flow mode, choose `node` as an end of the path, and once we have
+    /// both, leave flow mode and return the toggles opening our outflows
+    /// along it.
+    fn choose_flow(&mut self, node: Node, state: &State) -> Option<Click> {
+        let player = self.player;
+        let ours = |node: Node| match state.nodes[node] {
+            Some(ref occupied) => occupied.player == player,
+            None => false,
+        };
+        match self.flow {
+            Flow::Off => None,
+            _ if ours(node) => {
+                self.flow = Flow::Target(node);
+                None
+            }
+            Flow::Origin => None,
+            Flow::Target(origin) => {
+                self.flow = Flow::Off;
+                let path = graph::shortest_path(&self.map.graph, origin, node)?;
+                let actions = path.windows(2)
+                    .filter(|step| match state.nodes[step[0]] {
+                        Some(ref occupied) =>
+                            occupied.player == player && !occupied.outflows.contains(&step[1]),
+                        None => false,
+                    })
+                    .map(|step| Action::ToggleOutflow { player, from: step[0], to: step[1] })
+                    .collect();
+                Some(Click::Path(actions))
+            }
+        }
+    }
+
+    /// Finish a click or drag, returning what it does.
+    fn finish_click(&mut self) -> Option<Click> {
+        self.drag_node = None;
+        if !self.drag.is_empty() {
+            self.click = None;
+            let player = self.player;
+            let actions = self.drag.drain(..)
+                .map(|(from, to)| Action::ToggleOutflow { player, from, to })
+                .collect();
+            return Some(Click::Drag(actions));
+        }
+
+        match self.click.take() {
+            // If we get a release with no click, ignore.
+            None => None,
+
+            Some(affordance) => {
+                // If we released on something different from what we clicked
+                // on, that's a drag-off, so we do nothing.
+                if affordance != self.position {
+                    return None;
+                }
+
+                match affordance {
+                    Affordance::Nothing => None,
+                    Affordance::Outflow((from, to)) =>
+                        Some(Click::Action(Action::ToggleOutflow {
+                            player: self.player,
+                            from, to
+                        })),
+                    Affordance::Button(command) => Some(Click::Button(command)),
+                }
+            }
+        }
+    }
+
+    /// The board now appears in the window as `window_to_graph` says, so
+    /// check what the mouse is over again. When the camera moves, the board
+    /// moves under the mouse without the mouse moving at all.
+    pub fn refresh(&mut self, window_to_graph: Matrix) {
+        self.window_to_graph = Some(window_to_graph);
+        let window = self.window;
+        self.move_to(GraphPt(apply(window_to_graph, window)), window);
+    }
+
+    /// The middle mouse button went down, if `pressed`, or came up. While
+    /// it's held, moving the mouse pans the camera.
+    pub fn middle_button(&mut self, pressed: bool) {
+        self.panning = pressed;
+    }
+
+    /// The scroll wheel turned by `lines`, positive away from the player.
+    /// Zoom in or out, keeping the point under the mouse where it is.
+    pub fn scroll(&mut self, lines: f32) {
+        self.camera.zoom_at(self.window, SCROLL_ZOOM.powf(lines));
+    }
+
+    /// The player slid two fingers across a trackpad by `delta` pixels. Move
+    /// the board along with them.
+    pub fn swipe(&mut self, delta: [f32; 2]) {
+        self.camera.pan(delta);
+    }
+
+    /// Look through `state`'s recent events for attacks on nodes we hold or
+    /// border, and remember the latest as where the action is.
+    pub fn note_attacks(&mut self, state: &State) {
+        let graph = &self.map.graph;
+        let ours = |node: Node| {
+            state.nodes[node].as_ref().is_some_and(|occupied| occupied.player == self.player)
+        };
+        let latest = state.events.iter().rev().find_map(|event| match *event {
+            Event::OutflowsCleared { node, .. } => {
+                if ours(node) || graph.neighbors(node).iter().any(|&neighbor| ours(neighbor)) {
+                    Some(node)
+                } else {
+                    None
+                }
+            }
+            Event::Chat { .. } => None,
+        });
+        if latest.is_some() {
+            self.contested = latest;
+        }
+    }
+
+    /// Snap the camera to `node`, zooming in if need be. This does nothing
+    /// until the board has been drawn, since until then we don't know where
+    /// it is.
+    fn focus(&mut self, node: Node) {
+        let graph_to_window = match self.window_to_graph.and_then(inverse) {
+            Some(matrix) => matrix,
+            None => return,
+        };
+        let GraphPt(center) = self.map.graph.center(node);
+        self.camera.focus(apply(graph_to_window, center), FOCUS_ZOOM);
+    }
+
+    /// Snap the camera to our source.
+    pub fn focus_source(&mut self) {
+        let source = self.map.sources[self.player.0];
+        self.focus(source);
+    }
+
+    /// Snap the camera to the most recent attack along our frontline, if
+    /// there has been one.
+    pub fn focus_contested(&mut self) {
+        if let Some(node) = self.contested {
+            self.focus(node);
+        }
+    }
+
+    /// Finger `id` touched the screen at `pos` in graph space coordinates,
+    /// which is `window` in window coordinates. The first finger down acts
+    /// like the mouse button; a second turns the touch into a gesture that
+    /// moves the camera, and abandons the click.
+    pub fn touch_start(&mut self, id: u64, pos: GraphPt, window: [f32; 2]) {
+        self.touches.retain(|&(other, _)| other != id);
+        self.touches.push((id, window));
+        if self.touches.len() == 1 {
+            self.move_to(pos, window);
+            self.click();
+        } else {
+            self.click = None;
+            self.drag.clear();
+            self.drag_node = None;
+        }
+    }
+
+    /// Finger `id` moved to `pos` in graph space coordinates, which is
+    /// `window` in window coordinates.
+    pub fn touch_move(&mut self, id: u64, pos: GraphPt, window: [f32; 2]) {
+        let index = match self.touches.iter().position(|&(other, _)| other == id) {
+            Some(index) => index,
+            None => return,
+        };
+        match self.touches.len() {
+            1 => self.move_to(pos, window),
+            2 => {
+                let (before_center, before_spread) = gesture(&self.touches);
+                self.touches[index].1 = window;
+                let (center, spread) = gesture(&self.touches);
+                if before_spread > 0.0 && spread > 0.0 {
+                    self.camera.zoom_at(center, spread / before_spread);
+                }
+                self.camera.pan([center[0] - before_center[0], center[1] - before_center[1]]);
+            }
+            _ => (),
+        }
+        self.touches[index].1 = window;
+    }
+
+    /// Finger `id` left the screen at `now`. If it was the only one, this
+    /// may return whatever lifting the mouse button would; a double tap is a
+    /// double-click.
+    pub fn touch_end(&mut self, id: u64, state: &State, now: Instant) -> Option<Click> {
+        let before = self.touches.len();
+        self.touches.retain(|&(other, _)| other != id);
+        if before == 1 && self.touches.is_empty() {
+            self.release(state, now)
+        } else {
+            None
+        }
+    }
+
+    /// The system has taken finger `id` away from us, as when the window
+    /// loses focus. Forget it without acting on it.
+    pub fn touch_cancel(&mut self, id: u64) {
+        self.touches.retain(|&(other, _)| other != id);
+        if self.touches.is_empty() {
+            self.click = None;
+            self.drag.clear();
+            self.drag_node = None;
+        }
+    }
+
+    /// The secondary mouse button was clicked at the last reported position.
+    /// If that's within a node we own in `state` that has outflows, return an
+    /// action closing them all.
+    pub fn right_click(&self, state: &State) -> Option<Action> {
+        self.clear_outflows(self.node?, state)
+    }
+
+    /// If `node` is ours in `state` and has outflows, return an action closing
+    /// them all.
+    fn clear_outflows(&self, node: Node, state: &State) -> Option<Action> {
+        match state.nodes[node] {
+            Some(ref occupied) if occupied.player == self.player && !occupied.outflows.is_empty() =>
+                Some(Action::ClearOutflows { player: self.player, node }),
+            _ => None,
+        }
+    }
+
+    /// Return the node the keyboard selection is on, if the player has used
+    /// it.
+    pub fn selection(&self) -> Option<Node> {
+        self.selection
+    }
+
+    /// Return the selected node, starting the selection at our source if the
+    /// player hasn't used it yet.
+    fn selected(&mut self) -> Node {
+        let map = &self.map;
+        let player = self.player;
+        *self.selection.get_or_insert_with(|| map.sources.get(player.0).cloned().unwrap_or(0))
+    }
+
+    /// Return the neighbor of `node` lying in `direction` from it, if any.
+    /// The neighbor must lie within 45 degrees of that direction; of several,
+    /// we take the one most nearly in line.
+    fn neighbor_toward(&self, node: Node, direction: Direction) -> Option<Node> {
+        let graph = &self.map.graph;
+        let GraphPt(center) = graph.center(node);
+        let [dx, dy] = direction.vector();
+        graph.neighbors(node).into_iter()
+            .filter_map(|neighbor| {
+                let GraphPt(other) = graph.center(neighbor);
+                let offset = [other[0] - center[0], other[1] - center[1]];
+                let length = offset[0].hypot(offset[1]);
+                let cosine = (offset[0] * dx + offset[1] * dy) / length;
+                if cosine > ::std::f32::consts::FRAC_1_SQRT_2 {
+                    Some((cosine, neighbor))
+                } else {
+                    None
+                }
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, neighbor)| neighbor)
+    }
+
+    /// Move the keyboard selection to the neighbor in `direction`, if there
+    /// is one. The first move just shows the selection, on our source.
+    pub fn move_selection(&mut self, direction: Direction) {
+        match self.selection {
+            None => { self.selected(); }
+            Some(node) => {
+                if let Some(neighbor) = self.neighbor_toward(node, direction) {
+                    self.selection = Some(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Return an action toggling the selected node's outflow in `direction`,
+    /// if it has a neighbor that way.
+    pub fn toggle_toward(&mut self, direction: Direction) -> Option<Action> {
+        let from = self.selected();
+        let to = self.neighbor_toward(from, direction)?;
+        Some(Action::ToggleOutflow { player: self.player, from, to })
+    }
+
+    /// Return an action closing all the selected node's outflows, if it's
+    /// ours in `state` and has any.
+    pub fn clear_selected(&mut self, state: &State) -> Option<Action> {
+        let node = self.selected();
+        self.clear_outflows(node, state)
+    }
+
+    /// Return an action opening all the selected node's outflows, if it's
+    /// ours in `state`, as a double-click would.
+    pub fn open_selected(&mut self, state: &State) -> Option<Action> {
+        let node = self.selected();
+        match state.nodes[node] {
+            Some(ref occupied) if occupied.player == self.player =>
+                Some(Action::OpenOutflows { player: self.player, node }),
+            _ => None,
+        }
+    }
+
+    /// In flow mode, choose the selected node as an end of the path, as a
+    /// click on it would.
+    pub fn choose_selected(&mut self, state: &State) -> Option<Click> {
+        let node = self.selected();
+        self.choose_flow(node, state)
+    }
+
+    /// Move the keyboard's highlight to the next button along the top of the
+    /// window, or the previous one if `backward`.
+    pub fn cycle_buttons(&mut self, backward: bool) {
+        self.button_focus = ui::next_focus(&self.buttons, self.button_focus, backward);
+    }
+
+    /// Return the button the keyboard has highlighted, if any.
+    pub fn focused_button(&self) -> Option<Command> {
+        self.button_focus
+    }
+
+    /// Take the keyboard's highlight off the buttons.
+    pub fn unfocus_buttons(&mut self) {
+        self.button_focus = None;
+    }
+
+    /// Given `state`, choose what the mouse pointer should look like.
+    pub fn cursor(&self, state: &State) -> Cursor {
+        if self.flow != Flow::Off {
+            return Cursor::Crosshair;
+        }
+        match self.position {
+            Affordance::Nothing => Cursor::Default,
+            Affordance::Button(_) => Cursor::Hand,
+            Affordance::Outflow((from, _)) => match state.nodes[from] {
+                Some(ref occupied) if occupied.player == self.player => Cursor::Hand,
+                Some(_) => Cursor::Forbidden,
+                None => Cursor::Default,
+            },
+        }
+    }
+
+    /// Given `state`, choose how to display the interactive parts of the game
+    /// grid.
+    pub fn display(&self, _state: &State) -> Display {
+        if self.click.is_some() && !self.drag.is_empty() {
+            return Display::Drag { edges: self.drag.clone() };
+        }
+
+        match (self.click, self.position) {
+            // We're over something we're not clicking on.
+            (None, Affordance::Outflow(pos)) =>
+                Display::Outflow { nodes: pos, state: OutflowState::Hover },
+
+            (Some(Affordance::Outflow(cpos)), Affordance::Outflow(mpos)) => {
+                if cpos == mpos {
+                    // We're clicking on something that we're still over.
+                    Display::Outflow { nodes: cpos, state: OutflowState::Active }
+                } else {
+                    // We clicked on one thing, but moved elsewhere. This is
+                    // arguably a distinct state, but treat it like a hover
+                    // that's stuck on the click position.
+                    Display::Outflow { nodes: cpos, state: OutflowState::Hover }
+                }
+            }
+
+            // Otherwise, no action.
+            _ => Display::Nothing
+        }
+    }
+}
+
+/// What the mouse pointer should look like, to hint at what clicking would do.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Cursor {
+    /// Clicking here does nothing in particular.
+    Default,
+
+    /// Clicking here does something: toggles one of our outflows, or presses
+    /// a button.
+    Hand,
+
+    /// This is someone else's outflow, which we can't toggle.
+    Forbidden,
+
+    /// We're choosing the ends of a path in flow mode.
+    Crosshair,
+}
+
+/// How to display the current mouse state. This is always computed as a
+/// function of some pair of `State` and `Mouse` values.
+pub enum Display {
+    Nothing,
+
+    /// We're going to highlight an outflow.
+    Outflow { nodes: (Node, Node), state: OutflowState },
+
+    /// We're sweeping across edges, and will toggle all of these on release.
+    Drag { edges: Vec<(Node, Node)> },
+}
+
+/// How to highlight an outflow.
+pub enum OutflowState {
+    /// Draw the outflow as something one could click on. (The mouse is
+    /// hovering over it, or was clicked on it but moved off without being
+    /// released.)
+    Hover,
+
+    /// Draw the outflow as being clicked upon, but not yet released.
+    /// (The mouse was clicked on it, and is still over it.)
+    Active
+}
+
+/// Return the point midway between the first two `touches`, and the distance
+/// between them.
+fn gesture(touches: &[(u64, [f32; 2])]) -> ([f32; 2], f32) {
+    let (a, b) = (touches[0].1, touches[1].1);
+    (midpoint(a, b), (b[0] - a[0]).hypot(b[1] - a[1]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use math::scale_transform;
+    use state::Occupied;
+
+    fn params() -> MapParameters {
+        MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }
+    }
+
+    fn mouse() -> Input {
+        Input::new(Player(0), Arc::new(Map::new(params())))
+    }
+
+    fn state() -> State {
+        State::new(params(), Default::default())
+    }
+
+    fn edges(click: Option<Click>) -> Vec<(Node, Node)> {
+        match click {
+            Some(Click::Drag(actions)) => actions.into_iter()
+                .map(|action| match action {
+                    Action::ToggleOutflow { from, to, .. } => (from, to),
+                    otherwise => panic!("unexpected action in drag: {:?}", otherwise),
+                })
+                .collect(),
+            _ => panic!("release should have produced a drag"),
+        }
+    }
+
+    #[test]
+    fn click_one_edge() {
+        let mut mouse = mouse();
+
+        // Node 0's east quadrant, toward node 1.
+        mouse.move_to(GraphPt([0.9, 0.5]), [0.0, 0.0]);
+        mouse.click();
+        match mouse.release(&state(), Instant::now()) {
+            Some(Click::Action(Action::ToggleOutflow { from: 0, to: 1, .. })) => (),
+            otherwise => panic!("unexpected click: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn drag_chain() {
+        let mut mouse = mouse();
+
+        // Sweep from the center of node 0 east to node 2, then south to
+        // node 5, and back to node 2: each boundary crossed toggles once.
+        let path = [[0.5, 0.5], [1.0, 0.5], [1.5, 0.5], [2.5, 0.5],
+                    [2.5, 1.5], [2.5, 0.5], [2.5, 1.5]];
+        mouse.move_to(GraphPt(path[0]), [0.0, 0.0]);
+        mouse.click();
+        for &point in &path[1..] {
+            mouse.move_to(GraphPt(point), [0.0, 0.0]);
+        }
+        match mouse.display(&State::new(params(), Default::default())) {
+            Display::Drag { ref edges } => assert_eq!(edges.len(), 4),
+            _ => panic!("drag should be displayed"),
+        }
+        assert_eq!(edges(mouse.release(&state(), Instant::now())), vec![(0, 1), (1, 2), (2, 5), (5, 2)]);
+
+        // The next drag starts afresh.
+        mouse.click();
+        mouse.move_to(GraphPt([2.5, 0.5]), [0.0, 0.0]);
+        assert_eq!(edges(mouse.release(&state(), Instant::now())), vec![(5, 2)]);
+    }
+
+    #[test]
+    fn right_click() {
+        let mut mouse = mouse();
+        let mut state = State::new(params(), Default::default());
+        state.take_action(&Action::Join { player: Player(0) });
+
+        // Our source, with no outflows yet: nothing to clear.
+        mouse.move_to(GraphPt([0.5, 0.5]), [0.0, 0.0]);
+        assert!(mouse.right_click(&state).is_none());
+
+        state.take_action(&Action::ToggleOutflow { player: Player(0), from: 0, to: 1 });
+        match mouse.right_click(&state) {
+            Some(Action::ClearOutflows { player: Player(0), node: 0 }) => (),
+            otherwise => panic!("unexpected right click: {:?}", otherwise),
+        }
+
+        // Someone else's node, or an empty one, can't be cleared.
+        mouse.move_to(GraphPt([1.5, 0.5]), [0.0, 0.0]);
+        assert!(mouse.right_click(&state).is_none());
+    }
+
+    #[test]
+    fn keyboard() {
+        // Player 1's source is node 8, in the northeast corner.
+        let mut input = Input::new(Player(1), Arc::new(Map::new(params())));
+        assert_eq!(input.selection(), None);
+
+        // The first move only shows the selection.
+        input.move_selection(Direction::South);
+        assert_eq!(input.selection(), Some(8));
+        input.move_selection(Direction::South);
+        assert_eq!(input.selection(), Some(5));
+        input.move_selection(Direction::West);
+        assert_eq!(input.selection(), Some(4));
+
+        // Moves off the edge of the board go nowhere.
+        input.move_selection(Direction::South);
+        input.move_selection(Direction::South);
+        assert_eq!(input.selection(), Some(1));
+
+        match input.toggle_toward(Direction::East) {
+            Some(Action::ToggleOutflow { player: Player(1), from: 1, to: 2 }) => (),
+            otherwise => panic!("unexpected toggle: {:?}", otherwise),
+        }
+        assert!(input.toggle_toward(Direction::South).is_none());
+    }
+
+    #[test]
+    fn keyboard_only() {
+        let mut input = mouse();
+        let mut state = state();
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![], goop: 1 });
+
+        // The selection starts on our source, which we can open up and clear.
+        assert!(input.clear_selected(&state).is_none());
+        assert!(matches!(input.open_selected(&state),
+                         Some(Action::OpenOutflows { player: Player(0), node: 0 })));
+        state.nodes[0].as_mut().unwrap().outflows = vec![1];
+        assert!(matches!(input.clear_selected(&state),
+                         Some(Action::ClearOutflows { player: Player(0), node: 0 })));
+
+        // Squares that aren't ours can't be opened.
+        input.move_selection(Direction::North);
+        input.move_selection(Direction::North);
+        assert_eq!(input.selection(), Some(6));
+        assert!(input.open_selected(&state).is_none());
+
+        // Flow mode takes its ends from the selection.
+        input.toggle_flow();
+        assert!(input.flow_active());
+        assert!(input.choose_selected(&state).is_none());
+        assert_eq!(input.flow_origin(), None);
+        input.move_selection(Direction::South);
+        input.move_selection(Direction::South);
+        input.move_selection(Direction::East);
+        assert!(input.choose_selected(&state).is_none());
+        assert_eq!(input.flow_origin(), Some(1));
+        input.move_selection(Direction::East);
+        input.move_selection(Direction::North);
+        input.move_selection(Direction::North);
+        assert_eq!(input.selection(), Some(8));
+        assert!(matches!(input.choose_selected(&state), Some(Click::Path(_))));
+        assert!(!input.flow_active());
+
+        // Tab moves a highlight along the buttons, wrapping around.
+        input.set_buttons(ui::layout(800.0, 600.0));
+        let commands: Vec<Command> = input.buttons().iter().map(|button| button.command).collect();
+        assert_eq!(input.focused_button(), None);
+        input.cycle_buttons(true);
+        assert_eq!(input.focused_button(), commands.last().cloned());
+        assert_eq!(input.button_state(commands[commands.len() - 1]), ButtonState::Hover);
+        input.cycle_buttons(false);
+        assert_eq!(input.focused_button(), Some(commands[0]));
+        input.unfocus_buttons();
+        assert_eq!(input.button_state(commands[0]), ButtonState::Normal);
+    }
+
+    #[test]
+    fn touch() {
+        let mut input = mouse();
+        input.set_window_size(200.0, 200.0);
+
+        // A tap toggles the outflow it lands on.
+        input.touch_start(7, GraphPt([1.5, 1.9]), [0.0, 0.0]);
+        match input.touch_end(7, &state(), Instant::now()) {
+            Some(Click::Action(Action::ToggleOutflow { from: 4, to: 7, .. })) => (),
+            otherwise => panic!("unexpected tap: {:?}", otherwise),
+        }
+
+        // Spreading two fingers zooms in, and doesn't click.
+        input.touch_start(1, GraphPt([1.5, 1.9]), [90.0, 100.0]);
+        input.touch_start(2, GraphPt([1.5, 1.9]), [110.0, 100.0]);
+        input.touch_move(2, GraphPt([1.5, 1.9]), [130.0, 100.0]);
+        assert_eq!(input.camera().zoom(), 2.0);
+        assert!(input.touch_end(2, &state(), Instant::now()).is_none());
+        assert!(input.touch_end(1, &state(), Instant::now()).is_none());
+    }
+
+    #[test]
+    fn camera() {
+        let mut input = mouse();
+        input.set_window_size(200.0, 200.0);
+
+        // Scrolling zooms in about the mouse.
+        input.move_to(GraphPt([0.0, 0.0]), [50.0, 50.0]);
+        input.scroll(2.0);
+        assert!((input.camera().zoom() - SCROLL_ZOOM * SCROLL_ZOOM).abs() < 1e-6);
+        assert_eq!(apply(input.camera().transform(), [-0.5, 0.5]), [-0.5, 0.5]);
+
+        // Dragging with the middle button pans; moving without it doesn't.
+        input.middle_button(true);
+        input.move_to(GraphPt([0.0, 0.0]), [60.0, 50.0]);
+        input.middle_button(false);
+        input.move_to(GraphPt([0.0, 0.0]), [100.0, 100.0]);
+        assert_eq!(apply(input.camera().transform(), [-0.5, 0.5]), [-0.4, 0.5]);
+
+        // So does sliding two fingers on a trackpad.
+        input.swipe([-10.0, 0.0]);
+        assert_eq!(apply(input.camera().transform(), [-0.5, 0.5]), [-0.5, 0.5]);
+    }
+
+    #[test]
+    fn focus() {
+        let mut input = mouse();
+        let mut state = state();
+        state.take_action(&Action::Join { player: Player(0) });
+        input.set_window_size(200.0, 200.0);
+
+        // Until the board has been drawn, there's nowhere to go.
+        input.focus_source();
+        assert_eq!(input.camera().zoom(), 1.0);
+
+        // Draw the board in the window's upper left, each square 66⅔ pixels
+        // across, and focus on our source in the corner.
+        input.refresh(scale_transform(0.015, 0.015));
+        input.focus_source();
+        assert_eq!(input.camera().zoom(), FOCUS_ZOOM);
+        let GraphPt(source) = input.map.graph.center(input.map.sources[0]);
+        let device = [source[0] / 1.5 - 1.0, 1.0 - source[1] / 1.5];
+        let centered = apply(input.camera().transform(), device);
+        assert!(centered[0].abs() < 1e-5 && centered[1].abs() < 1e-5);
+
+        // Attacks far from our territory aren't our concern.
+        let far = input.map.sources[1];
+        state.events.push(Event::OutflowsCleared { turn: 0, node: far, outflows: vec![] });
+        input.note_attacks(&state);
+        assert_eq!(input.contested, None);
+
+        // Attacks on our nodes are.
+        let source = input.map.sources[0];
+        state.events.push(Event::OutflowsCleared { turn: 0, node: source, outflows: vec![] });
+        input.note_attacks(&state);
+        assert_eq!(input.contested, Some(source));
+
+        // And we remember them after they've left the event log.
+        state.events.clear();
+        input.note_attacks(&state);
+        assert_eq!(input.contested, Some(source));
+    }
+
+    #[test]
+    fn double_click() {
+        let mut mouse = mouse();
+        let mut state = state();
+        state.take_action(&Action::Join { player: Player(0) });
+        let start = Instant::now();
+
+        // Two quick clicks in our source open all its outflows.
+        mouse.move_to(GraphPt([0.5, 0.9]), [0.0, 0.0]);
+        mouse.click();
+        assert!(matches!(mouse.release(&state, start),
+                         Some(Click::Action(Action::ToggleOutflow { from: 0, to: 3, .. }))));
+        mouse.click();
+        assert!(matches!(mouse.release(&state, start + Duration::from_millis(100)),
+                         Some(Click::Action(Action::OpenOutflows { player: Player(0), node: 0 }))));
+
+        // A third click starts over, as do clicks too far apart.
+        mouse.click();
+        assert!(matches!(mouse.release(&state, start + Duration::from_millis(200)),
+                         Some(Click::Action(Action::ToggleOutflow { .. }))));
+        mouse.click();
+        assert!(matches!(mouse.release(&state, start + Duration::from_millis(1000)),
+                         Some(Click::Action(Action::ToggleOutflow { .. }))));
+
+        // Double-clicking a node we don't own just clicks.
+        mouse.move_to(GraphPt([1.5, 0.9]), [0.0, 0.0]);
+        for &millis in &[1100, 1200] {
+            mouse.click();
+            assert!(matches!(mouse.release(&state, start + Duration::from_millis(millis)),
+                             Some(Click::Action(Action::ToggleOutflow { from: 1, to: 4, .. }))));
+        }
+    }
+
+    #[test]
+    fn flow() {
+        let mut mouse = mouse();
+        let mut state = state();
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        // Node 1's outflows already head toward player 1's source.
+        state.nodes[1] = Some(Occupied { player: Player(0), outflows: vec![2, 4], goop: 1 });
+        let click_at = |mouse: &mut Input, point: [f32; 2]| {
+            mouse.move_to(GraphPt(point), [0.0, 0.0]);
+            mouse.click();
+            mouse.release(&state, Instant::now())
+        };
+
+        // Clicking someone else's node first does nothing; ours starts the
+        // path.
+        mouse.toggle_flow();
+        assert!(click_at(&mut mouse, [2.5, 2.5]).is_none());
+        assert_eq!(mouse.flow_origin(), None);
+        assert!(click_at(&mut mouse, [0.5, 0.5]).is_none());
+        assert_eq!(mouse.flow_origin(), Some(0));
+        assert!(click_at(&mut mouse, [1.5, 0.5]).is_none());
+        assert_eq!(mouse.flow_origin(), Some(1));
+
+        // Finishing the path opens only our outflows that aren't open yet.
+        match click_at(&mut mouse, [2.5, 2.5]) {
+            Some(Click::Path(actions)) => assert!(actions.is_empty()),
+            otherwise => panic!("unexpected click: {:?}", otherwise),
+        }
+        assert_eq!(mouse.flow_origin(), None);
+
+        // Flow mode is over, so clicks toggle outflows again.
+        assert!(matches!(click_at(&mut mouse, [0.9, 0.5]),
+                         Some(Click::Action(Action::ToggleOutflow { from: 0, to: 1, .. }))));
+
+        mouse.toggle_flow();
+        click_at(&mut mouse, [0.5, 0.5]);
+        match click_at(&mut mouse, [2.5, 2.5]) {
+            Some(Click::Path(actions)) => {
+                assert_eq!(actions.len(), 1);
+                assert!(matches!(actions[0], Action::ToggleOutflow { from: 0, .. }));
+            }
+            otherwise => panic!("unexpected click: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn cursor() {
+        let mut mouse = mouse();
+        let mut state = state();
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+
+        // Our source, someone else's, and an empty node.
+        mouse.move_to(GraphPt([0.9, 0.5]), [0.0, 0.0]);
+        assert_eq!(mouse.cursor(&state), Cursor::Hand);
+        mouse.move_to(GraphPt([2.1, 2.5]), [0.0, 0.0]);
+        assert_eq!(mouse.cursor(&state), Cursor::Forbidden);
+        mouse.move_to(GraphPt([1.9, 1.5]), [0.0, 0.0]);
+        assert_eq!(mouse.cursor(&state), Cursor::Default);
+
+        mouse.toggle_flow();
+        assert_eq!(mouse.cursor(&state), Cursor::Crosshair);
+    }
+}
@@ -1,4 +1,4 @@
-/// A Tokio protocol that sends Rust types serialized as JSON back and forth.
+//! A Tokio protocol that sends Rust types serialized as JSON back and forth.
 
 use bytes::BytesMut;
 use serde::de::DeserializeOwned;
@@ -8,8 +8,100 @@ use tokio_core::net::TcpStream;
 use tokio_codec::{Decoder, Encoder, Framed};
 use tokio_proto::pipeline::ServerProto;
 
-use std::io::{Error, ErrorKind};
+use std::error;
+use std::fmt;
+use std::io::{BufRead, Error, ErrorKind, Read};
 use std::marker::PhantomData;
+use std::str;
+
+/// The longest message, in bytes, that we're willing to decode, including the
+/// terminating newline. The largest legitimate message is the `Welcome`
+/// response carrying the whole game state, which is a few tens of kilobytes
+/// for the maps we use; this leaves plenty of room for bigger ones, while
+/// keeping a misbehaving peer from making us buffer without bound.
+pub const MAX_MESSAGE_BYTES: usize = 4 << 20;
+
+/// The ways decoding a message can fail.
+///
+/// The Tokio machinery requires codec errors to be `io::Error`s, so these are
+/// always wrapped in one, with a kind of `ErrorKind::InvalidData`. Use
+/// `DecodeError::from_io` to get them back out.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// A message exceeded `MAX_MESSAGE_BYTES` without a terminating newline.
+    TooLong,
+
+    /// A message was not valid UTF-8.
+    NotUtf8(str::Utf8Error),
+
+    /// A message was UTF-8, but not JSON for the type we expected.
+    BadJson(serde_json::Error),
+}
+
+impl DecodeError {
+    /// If `error` wraps a `DecodeError`, return a reference to it.
+    pub fn from_io(error: &Error) -> Option<&DecodeError> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<DecodeError>())
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::TooLong =>
+                write!(f, "message longer than {} bytes", MAX_MESSAGE_BYTES),
+            DecodeError::NotUtf8(ref e) => write!(f, "message is not UTF-8: {}", e),
+            DecodeError::BadJson(ref e) => write!(f, "malformed message: {}", e),
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DecodeError::TooLong => None,
+            DecodeError::NotUtf8(ref e) => Some(e),
+            DecodeError::BadJson(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Error {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+/// Decode `line`, a single message including its terminating newline, as a
+/// value of type `T`.
+pub fn decode_line<T: DeserializeOwned>(line: &[u8]) -> Result<T, DecodeError> {
+    if line.len() > MAX_MESSAGE_BYTES {
+        return Err(DecodeError::TooLong);
+    }
+    let text = str::from_utf8(line).map_err(DecodeError::NotUtf8)?;
+    serde_json::from_str(text).map_err(DecodeError::BadJson)
+}
+
+/// Read a single message from `reader`, for code using blocking I/O rather than
+/// Tokio. Return `Ok(None)` at end of file. Malformed messages produce an
+/// `io::Error` wrapping a `DecodeError`, as for `JsonCodec`.
+pub fn read_message<T, R>(reader: &mut R) -> Result<Option<T>, Error>
+    where T: DeserializeOwned,
+          R: BufRead
+{
+    let mut line = Vec::new();
+    reader.take(MAX_MESSAGE_BYTES as u64).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        if line.len() >= MAX_MESSAGE_BYTES {
+            return Err(DecodeError::TooLong.into());
+        }
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed mid-message"));
+    }
+    Ok(Some(decode_line(&line)?))
+}
 
 /// A codec that encodes values of type `Out` as JSON, and decodes values of
 /// type `In` from JSON.
@@ -22,7 +114,7 @@ pub struct JsonCodec<In, Out> {
 }
 
 impl<In, Out> Default for JsonCodec<In, Out> {
-    fn default() -> Self { JsonCodec { marker: PhantomData::default() } }
+    fn default() -> Self { JsonCodec { marker: PhantomData } }
 }
 
 impl<In, Out> Decoder for JsonCodec<In, Out>
@@ -33,14 +125,12 @@ impl<In, Out> Decoder for JsonCodec<In, Out>
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<In>, Error> {
         if let Some(i) = src.iter().position(|b| *b == b'\n') {
+            // Consume the line before decoding it, so that a malformed message
+            // doesn't stay in the buffer to be tripped over again.
             let line = src.split_to(i + 1);
-            ::std::str::from_utf8(&line)
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))
-                .and_then(|s| {
-                    serde_json::from_str(s)
-                        .map_err(|e| Error::new(ErrorKind::Other, e))
-                })
-                .map(Some)
+            Ok(Some(decode_line(&line)?))
+        } else if src.len() >= MAX_MESSAGE_BYTES {
+            Err(DecodeError::TooLong.into())
         } else {
             Ok(None)
         }
@@ -54,7 +144,7 @@ impl<In, Out> Encoder for JsonCodec<In, Out>
     type Error = Error;
     fn encode(&mut self, item: Out, dst: &mut BytesMut) -> Result<(), Error> {
         let mut json = serde_json::to_string(&item)
-            .map_err(|e| Error::new(ErrorKind::Other, e))?;
+            .map_err(Error::other)?;
         json.push('\n');
         dst.extend(json.as_bytes());
         Ok(())
@@ -69,7 +159,7 @@ pub struct JsonProto<In, Out> {
 
 impl<In, Out> JsonProto<In, Out> {
     pub fn new() -> Self {
-        JsonProto { marker: PhantomData::default() }
+        JsonProto { marker: PhantomData }
     }
 }
 
@@ -86,3 +176,71 @@ impl<In, Out> ServerProto<TcpStream> for JsonProto<In, Out>
         Ok(JsonCodec::default().framed(io))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<Option<Vec<u32>>, Error> {
+        let mut codec = JsonCodec::<Vec<u32>, ()>::default();
+        let mut buf = BytesMut::from(bytes);
+        codec.decode(&mut buf)
+    }
+
+    /// Decode `bytes`, expecting it to fail, and return the `DecodeError`.
+    fn decode_error(bytes: &[u8]) -> Error {
+        let err = decode(bytes).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(DecodeError::from_io(&err).is_some(),
+                "codec error doesn't carry a DecodeError: {:?}", err);
+        err
+    }
+
+    #[test]
+    fn well_formed() {
+        assert_eq!(decode(b"[1,2,3]\n").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(decode(b"[1,2,").unwrap(), None);
+    }
+
+    #[test]
+    fn malformed() {
+        let err = decode_error(b"[1,2,\n");
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::BadJson(_))));
+        let err = decode_error(b"{\"a\": 1}\n");
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::BadJson(_))));
+        let err = decode_error(b"\xff\xfe[1]\n");
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::NotUtf8(_))));
+    }
+
+    #[test]
+    fn malformed_line_is_consumed() {
+        let mut codec = JsonCodec::<Vec<u32>, ()>::default();
+        let mut buf = BytesMut::from(&b"garbage\n[7]\n"[..]);
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(vec![7]));
+    }
+
+    #[test]
+    fn too_long() {
+        let mut long = vec![b'['; MAX_MESSAGE_BYTES];
+        let err = decode_error(&long);
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::TooLong)));
+
+        // Even with a newline, it's still too long.
+        long.push(b'\n');
+        let err = decode_error(&long);
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::TooLong)));
+    }
+
+    #[test]
+    fn blocking_reader() {
+        let mut input = &b"[1]\n\xff\n[2]\n[3"[..];
+        assert_eq!(read_message::<Vec<u32>, _>(&mut input).unwrap(), Some(vec![1]));
+        let err = read_message::<Vec<u32>, _>(&mut input).unwrap_err();
+        assert!(matches!(DecodeError::from_io(&err), Some(DecodeError::NotUtf8(_))));
+        assert_eq!(read_message::<Vec<u32>, _>(&mut input).unwrap(), Some(vec![2]));
+        let err = read_message::<Vec<u32>, _>(&mut input).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(read_message::<Vec<u32>, _>(&mut input).unwrap(), None);
+    }
+}
@@ -0,0 +1,53 @@
+#version 150
+
+// Lines are drawn as quads, rather than with GL_LINES, so that they can be any
+// width and have soft edges on every driver. Each quad is two triangles, and
+// every vertex carries both ends of its segment; `corner` says which corner of
+// the quad this vertex is.
+
+// The transformation from graph coordinates to normalized device coordinates,
+// as a homogeneous transform.
+uniform mat3 graph_to_device;
+
+// The size of the window, in pixels.
+uniform vec2 viewport;
+
+// The width of the line, in pixels.
+uniform float line_width;
+
+// The ends of the segment, in graph coordinates.
+in vec2 start;
+in vec2 end;
+
+// Which corner of the quad this is: x is 0 at the start and 1 at the end, and
+// y is -1 on the right side of the line and 1 on its left.
+in vec2 corner;
+
+// How far along the segment this vertex is, from 0 at the start to 1 at the
+// end.
+out float fragment_along;
+
+// How far this vertex is from the center line of the segment, in pixels.
+out float across;
+
+void main() {
+  vec2 half_viewport = viewport / 2.0;
+  vec2 device_start = (graph_to_device * vec3(start, 1.0)).xy;
+  vec2 device_end = (graph_to_device * vec3(end, 1.0)).xy;
+
+  // Work out the line's direction in pixels, since device coordinates may
+  // not be square.
+  vec2 direction = normalize((device_end - device_start) * half_viewport);
+  vec2 normal = vec2(-direction.y, direction.x);
+
+  // Leave an extra pixel on each side for the edge to fade out in, and extend
+  // the ends by half the width, so that lines meeting at a corner join up.
+  float half_width = line_width / 2.0 + 1.0;
+  vec2 offset = normal * corner.y * half_width
+              + direction * (corner.x * 2.0 - 1.0) * line_width / 2.0;
+
+  vec2 device = mix(device_start, device_end, corner.x) + offset / half_viewport;
+  gl_Position = vec4(device, 0.0, 1.0);
+  fragment_along = corner.x;
+  across = corner.y * half_width;
+}
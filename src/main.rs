@@ -1,12 +1,9 @@
-// `error_chain!` can recurse deeply
-#![recursion_limit = "1024"]
-
-#[macro_use] extern crate error_chain;
 #[macro_use] extern crate glium;
 #[macro_use] extern crate serde_derive;
 extern crate bytes;
 extern crate futures;
 extern crate rand;
+extern crate rbattle_core;
 extern crate serde;
 extern crate serde_json;
 extern crate tokio_codec;
@@ -15,40 +12,74 @@ extern crate tokio_io;
 extern crate tokio_proto;
 extern crate tokio_service;
 
-#[cfg(test)]
-#[macro_use]
-mod test_utils;
-
+mod addr;
+mod camera;
+mod chat;
+mod dispatch;
+mod divergence;
+// glium's `implement_vertex!` expands to a call to the deprecated
+// `mem::uninitialized`.
+#[allow(deprecated)]
 mod drawer;
-mod errors;
-mod graph;
+mod events;
+mod export;
+mod font;
+mod hint;
+mod input;
 mod jsonproto;
-mod map;
-mod math;
-mod mouse;
+mod mapcache;
+mod menu;
+mod palette;
 mod protocol;
-mod scheduler;
-mod square;
-mod state;
-mod visible_graph;
-mod xorshift;
+mod recording;
+mod relay;
+mod renderer;
+mod settings;
+mod summary;
+mod theme;
+mod ui;
+
+// The simulation lives in the `rbattle_core` library. Bringing its modules in
+// here lets ours name them just as they name each other.
+use rbattle_core::{anonymize, bot, errors, graph, history, map, math, messages, replay};
+use rbattle_core::{save, scheduler, simulate, state, storage, timestep, timing, visible_graph};
+use rbattle_core::{wasm, xorshift};
 
-use drawer::Drawer;
+use bot::{Difficulty, Registry};
+use dispatch::{Dispatcher, Layer};
+use drawer::{Drawer, MenuDrawer};
+use export::FrameExporter;
+use hint::Hints;
+use input::{Click, Cursor, Direction, Input};
 use map::MapParameters;
 use math::{apply, compose};
-use mouse::Mouse;
-use protocol::Participant;
-use visible_graph::GraphPt;
+use menu::{Menu, Outcome};
+use palette::{Palette, parse_color};
+use protocol::{ClientStage, Participant, PendingClient, Rendezvous, ServerOptions};
+use recording::{Button, InputEvent, Key, Phase, Playback, Recorder};
+use renderer::{GameOver, GridStyle, Renderer};
+use replay::Replay;
+use save::{Autosave, SavedGame};
+use settings::{Panel, Settings};
+use simulate::TrainingWriter;
+use state::{Action, GameParameters, Handicap, Player, SerializableState, State};
+use storage::Format;
+use theme::Theme;
+use timestep::FrameLimiter;
+use timing::{FrameTimes, PerfMeter};
+use ui::Command;
+use visible_graph::{EDGE_TOLERANCE, GraphPt};
 
-use glium::{Display, Surface};
+use glium::Display;
 use glium::glutin::{ContextBuilder, ElementState, Event, EventsLoop, KeyboardInput,
-                    ModifiersState, MouseButton, VirtualKeyCode, WindowBuilder,
-                    WindowEvent};
-use glium::glutin::dpi::PhysicalPosition;
+                    MouseButton, MouseCursor, VirtualKeyCode, WindowBuilder, WindowEvent};
+use glium::glutin::dpi::{LogicalSize, PhysicalPosition};
 
 use std::io::Write;
-use std::net::SocketAddr;
-use std::time::Instant;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // This only gives access within this module. Make this `pub use errors::*;`
 // instead if the types must be accessible from other modules (e.g., within
@@ -77,49 +108,917 @@ fn main() {
     }
 }
 
+const USAGE: &str = "\
+Usage: rbattle [OPTIONS]
+       rbattle server (ADDR | --relay RELAY_ADDR) [OPTIONS]
+       rbattle client ADDR [OPTIONS]
+       rbattle bot ADDR [--ai KIND] [--wasm-bot NAME=FILE]
+       rbattle replay FILE [OPTIONS]
+       rbattle sandbox FILE [OPTIONS]
+       rbattle relay ADDR
+       rbattle anonymize FILE [--binary] [--compress]
+       rbattle simulate [FILE] --bots LEVEL,... [--games N] [--max-turns TURNS]
+                        [--training-data OUT]
+
+With no mode, rbattle opens a start screen offering to host a game, join one,
+or change settings. Hosting from there listens on all interfaces. The replay
+mode shows a game recorded with --record-game again. The sandbox mode starts
+a game of one from a state dumped with F9, for chasing bugs. The anonymize
+mode copies the replay FILE, leaving out the chat and shuffling the seats, so
+it can be shared without saying who played: NAME.replay is copied to
+NAME.anonymous.replay.
+
+The bot mode joins the game at ADDR like the client mode, but with no window:
+a computer-controlled player of the given --ai KIND takes the seat, and plays
+until the server goes away. KIND is easy, medium, hard, or a name given to
+--wasm-bot; the default is medium. This lets people practice online against
+bots run on machines with no display.
+
+The simulate mode plays games between computer-controlled players, one for
+each --bots LEVEL, with no window and no network, as fast as it can, and
+prints how often each won and how long games ran. The games are on the usual
+board, or from the position dumped in FILE with F9, on its map. Each game's
+players choose their moves differently, but running the same simulation
+again plays the same games. --warmup, --handicap, --wasm-bot, and --turn-ms
+apply as for servers; the last only to the game lengths reported.
+
+Addresses may be written HOST:PORT, HOST, or PORT. HOST may be a hostname, an
+IPv4 address, or an IPv6 address in brackets, like [::]. The port defaults to
+12345. A bare PORT means all interfaces when listening, or this machine when
+connecting.
+
+Server options:
+    --events ADDR     Stream turn events as JSON to connections on ADDR.
+    --warmup TURNS    Play TURNS turns of warm-up before the game begins.
+    --handicap PLAYER=N
+                      Handicap player number PLAYER: sources they hold
+                      produce goop on only one of every N turns they
+                      otherwise would; N must be at least 1. Give this
+                      once for each player to handicap.
+    --turn-ms MS      Play a turn every MS milliseconds. The default is 16.
+    --adaptive        Slow the game down when players can't keep up, and
+                      speed it back up when they can.
+    --pause-on-drop SECS
+                      If a player loses their connection, pause the game for
+                      up to SECS seconds to let them reconnect.
+    --bots N          Fill N seats with computer-controlled players.
+    --bots LEVEL,...  Fill a seat with a computer-controlled player for each
+                      LEVEL, which is easy, medium, or hard. Plain N means
+                      N medium players. Easier ones react more slowly, look
+                      less far ahead, and make one move at a time.
+    --fill-seats LEVEL
+                      When the game proper begins, seat a computer-controlled
+                      player of the given LEVEL in every seat no one has
+                      taken. With --warmup, that's when warm-up ends;
+                      without it, that's right away, before anyone but the
+                      host can join.
+    --wasm-bot NAME=FILE
+                      Let --bots name NAME, for a player run by the
+                      WebAssembly module FILE. Such bots are sandboxed, and
+                      limited in how much they can compute each turn; see
+                      core/src/wasm.rs for what the module must provide.
+    --log-timing SECS Every SECS seconds, print statistics about how well
+                      the server is keeping to its schedule.
+    --save FILE       Save the game to FILE when the host presses Ctrl+S.
+                      The default is rbattle-save.json.
+    --autosave TURNS  Save the game every TURNS turns as well, next to the
+                      --save file, with the turn number added to the name.
+    --autosave-keep N Keep only the latest N autosaves. The default is 3.
+    --resume FILE     Pick up the game saved in FILE where it left off.
+                      Clients still running reconnect to their own seats;
+                      anyone joining afresh takes the lowest empty one.
+    --puzzle FILE     Start a new game from the position dumped in FILE
+                      with F9, on its map. Each player who joins takes
+                      their nodes in it, and rematches start from it again.
+
+Recording options, for servers and clients:
+    --record-game FILE
+                      Write the game to FILE as it's played, to watch again
+                      later with the replay mode.
+    --stats-log FILE  When a game is won, append a summary of it to FILE:
+                      the players, the map, who won, and how many nodes each
+                      player held on each turn. If FILE ends in .csv, write
+                      just the counts, as CSV; otherwise, write JSON.
+
+File options, for servers and clients:
+    --binary          Write saved games and replays as MessagePack, rather
+                      than JSON.
+    --compress        Compress saved games and replays with zstd. Files are
+                      read back however they were written.
+
+Simulation options:
+    --games N         Play N games. The default is 100.
+    --max-turns TURNS Give up on a game no one has won after TURNS turns.
+                      The default is 100000.
+    --training-data OUT
+                      Write, for each turn on which a bot acts, the board,
+                      how each player stands, the actions taken, and who
+                      went on to win, to OUT, for training bots elsewhere.
+                      --binary and --compress apply. See
+                      core/src/simulate.rs for the details.
+
+Replay options:
+    --from-turn TURN  Start watching at turn TURN.
+
+Debugging options:
+    --dump-state FILE Dump the state to FILE when F9 is pressed. The default
+                      is rbattle-state.json.
+
+While watching a replay, P pauses and resumes it, the [ and ] keys step ten
+seconds back and forward, Home and End jump to the start and the end, and the
+digit keys jump that many tenths of the way through.
+
+During the game, the scroll wheel zooms in and out, and dragging with the
+middle mouse button held pans. On a trackpad, pinch to zoom, and slide two
+fingers to pan. The arrow keys move a cursor from square to
+square, and Shift+arrow, or W, A, S, or D, toggles the outflow from the
+cursor's square in that direction; E opens all the cursor's square's
+outflows, and X closes them. The F key starts choosing a path for goop
+to flow along: click one of your squares, then someone else's, or move the
+cursor to each and press Enter, and the outflows along the shortest path
+between them open. The H key zooms in on your
+source, and the space bar on the latest attack along your frontline. Ctrl+Z
+undoes your last outflow toggle. Tab and Shift+Tab move a highlight along
+the buttons at the top of the window, and Enter presses the highlighted one.
+Otherwise, Enter starts a chat message to the other players, and sends it;
+Escape abandons it. On the start screen and the settings panel, the arrow
+keys or Tab choose an item, and Enter picks it.
+
+While hosting, the - and = keys slow the game down and speed it up, Ctrl+S
+saves the game, and once the game is over, the R key starts a rematch.
+
+The F1 key, or the SETTINGS button, opens a panel for changing the grid style
+and UI scale, and lists these keys. The F2 key turns hints on and off: with
+hints on, the board highlights an outflow worth opening next.
+
+Display options:
+    --settings FILE   Load and save settings in FILE, rather than in
+                      ~/.config/rbattle/settings.json. Changing the grid
+                      style or UI scale during the game saves them there,
+                      along with any --grid or --ui-scale flags given.
+    --theme FILE      Load colors and line widths from the JSON file FILE.
+    --color PLAYER=RRGGBB
+                      Draw player number PLAYER in the given color.
+    --enemy-color RRGGBB
+                      Draw all other players in the given color. The C key
+                      toggles this on and off; it defaults to red.
+    --grid STYLE      Draw the boundaries between squares as lines, as dots
+                      at their corners, or not at all, tinting squares in
+                      their owner's color instead. STYLE is one of lines,
+                      dots, or hidden. The G key switches between these.
+    --ui-scale FACTOR Draw the lines between squares, outflows, and the mouse
+                      highlight FACTOR times as thick, like 1.5, and make
+                      the mouse less likely to pick the wrong outflow near
+                      a boundary.
+    --hit-tolerance T How close, in squares, the mouse may come to a square's
+                      edge or diagonals before we can't tell which outflow
+                      it's over. The default is 0.05. The F4 key shades the
+                      region where clicking toggles each outflow.
+    --size WIDTHxHEIGHT
+                      Open a window of the given size, like 1280x720.
+    --fullscreen      Start in fullscreen. Alt+Enter switches between
+                      fullscreen and a window.
+    --hints           Start with hints on, highlighting an outflow worth
+                      opening next. The F2 key turns them on and off.
+    --fps N           Draw at most N frames per second. With 0, the
+                      default, there is no limit beyond vsync. This doesn't
+                      affect the pace of the game.
+    --no-vsync        Don't wait for the display to refresh before drawing
+                      the next frame.
+    --log-frames SECS Every SECS seconds, print how long frames are taking
+                      to draw. The F3 key shows the same, and more, in the
+                      corner of the window.
+    --export-frames DIR
+                      Save the frames drawn to DIR as numbered PPM images,
+                      for making videos.
+    --export-every N  With --export-frames, save only every Nth frame.
+    --record-input FILE
+                      Save the mouse, keyboard, and touch input the game
+                      window receives to FILE, with timestamps.
+    --replay-input FILE
+                      Play back input saved with --record-input, at the
+                      times it was recorded. Use a window of the same size.
+";
+
 fn usage() -> ! {
-    writeln!(std::io::stderr(), "Usage: rbattle (client|server) ADDR")
+    write!(std::io::stderr(), "{}", USAGE)
         .expect("error writing to stderr");
     std::process::exit(1);
 }
 
-fn run() -> Result<()> {
-    let mut args = std::env::args().skip(1);
-    let mode = args.next().unwrap_or_else(|| usage());
-    let socket_addr: SocketAddr = args.next()
-        .unwrap_or_else(|| usage())
+/// Parse an address to listen on. A bare port number means every interface.
+fn parse_listen_addr(arg: Option<String>) -> Result<SocketAddr> {
+    let arg = arg.unwrap_or_else(|| usage());
+    addr::resolve(&arg, IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+        .chain_err(|| format!("couldn't resolve address '{}'", arg))
+}
+
+/// Parse an address to connect to. A bare port number means this machine.
+fn parse_connect_addr(arg: Option<String>) -> Result<SocketAddr> {
+    let arg = arg.unwrap_or_else(|| usage());
+    addr::resolve(&arg, IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .chain_err(|| format!("couldn't resolve address '{}'", arg))
+}
+
+fn parse_number<T: std::str::FromStr>(arg: Option<String>) -> T {
+    arg.unwrap_or_else(|| usage())
         .parse()
-        .expect("couldn't parse address");
-
-    let mut participant =
-        if mode == "server" {
-            Participant::new_server(socket_addr, MapParameters {
-                size: (15, 15),
-                sources: vec![32, 42, 182, 192],
-                player_colors: vec![(0x9f, 0x20, 0xb1), (0xe0, 0x6f, 0x3a),
-                                    (0x20, 0xb1, 0x21), (0x20, 0x67, 0xb1)]
-            })
-        } else if mode == "client" {
-            Participant::new_client(socket_addr)?
-        } else {
-            usage()
-        };
+        .unwrap_or_else(|_| usage())
+}
 
-    let map = participant.snapshot().map.clone();
+/// Parse the argument to `--bots`: either a number of bots of the default
+/// kind, no more than `seats`, or a comma-separated list of kinds of bot, one
+/// per bot. Whoever seats them checks that the kinds exist.
+fn parse_bots(arg: &str, seats: usize) -> Vec<String> {
+    if let Ok(count) = arg.parse() {
+        if count > seats {
+            usage();
+        }
+        return vec![bot::DEFAULT_KIND.to_string(); count];
+    }
+    arg.split(',').map(str::to_string).collect()
+}
+
+/// Join the game at `addr` with no window, and let a bot of the kind called
+/// `kind` in `registry` play our seat, until we lose touch with the server.
+fn play_bot(addr: SocketAddr, registry: &Registry, kind: &str) -> Result<()> {
+    if !registry.contains(kind) {
+        return Err(format!("no such kind of bot: {}", kind).into());
+    }
+
+    let mut client = PendingClient::start(addr);
+    let mut participant = loop {
+        match client.poll() {
+            Some(joined) => break joined.chain_err(|| "couldn't join game")?,
+            None => thread::sleep(BOT_POLL_INTERVAL),
+        }
+    };
+    let player = participant.get_player();
+    let bot = registry.create(kind, player, 0)
+        .ok_or_else(|| format!("no such kind of bot: {}", kind))?;
+    participant.set_bot(bot);
+    println!("Joined the game at {} as player {}, playing {}.", addr, player.0, kind);
+
+    // The bot acts on the connection's thread; we just report how games go.
+    let mut announced = false;
+    while !participant.disconnected() && participant.diverged().is_none() {
+        match participant.result() {
+            Some((winner, ref stats)) if !announced => {
+                println!("Player {} won after {} turns.", winner.0, stats.turns);
+                announced = true;
+            }
+            None => announced = false,
+            _ => (),
+        }
+        thread::sleep(BOT_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// How often the bot mode checks on its connection.
+const BOT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The color the `C` key draws enemies in, if `--enemy-color` doesn't say.
+const DEFAULT_ENEMY_COLOR: (u8, u8, u8) = (0xe0, 0x10, 0x10);
+
+/// How many samples per pixel to ask for when antialiasing.
+const MSAA_SAMPLES: u16 = 4;
+
+/// Parse an argument of the form `WIDTHxHEIGHT`.
+fn parse_size(arg: Option<String>) -> LogicalSize {
+    let arg = arg.unwrap_or_else(|| usage());
+    let mut parts = arg.splitn(2, 'x');
+    let width = parts.next().and_then(|w| w.parse().ok());
+    let height = parts.next().and_then(|h| h.parse().ok());
+    match (width, height) {
+        (Some(width), Some(height)) => LogicalSize::new(width, height),
+        _ => usage(),
+    }
+}
+
+/// Parse an argument of the form `PLAYER=RRGGBB`.
+fn parse_player_color(arg: Option<String>) -> (usize, (u8, u8, u8)) {
+    let arg = arg.unwrap_or_else(|| usage());
+    let mut parts = arg.splitn(2, '=');
+    let player = parts.next().and_then(|p| p.parse().ok());
+    let color = parts.next().and_then(parse_color);
+    match (player, color) {
+        (Some(player), Some(color)) => (player, color),
+        _ => usage(),
+    }
+}
+
+/// Parse an argument of the form `PLAYER=N`, giving player number `PLAYER` a
+/// handicap of producing goop one turn in `N`.
+fn parse_handicap(arg: Option<String>) -> (usize, Handicap) {
+    let arg = arg.unwrap_or_else(|| usage());
+    let (player, every) = arg.split_once('=').unwrap_or_else(|| usage());
+    match (player.parse(), every.parse()) {
+        (Ok(player), Ok(goop_every)) if goop_every > 0 => (player, Handicap { goop_every }),
+        _ => usage(),
+    }
+}
+
+/// How many turns the bracket keys step back or forward in a replay: ten
+/// seconds, at the default pace.
+const REPLAY_STEP_TURNS: usize = 600;
+
+/// Return the direction `key` moves the keyboard selection, or toggles the
+/// selected node's outflow toward, and whether it toggles. The arrow keys
+/// move, or toggle with Shift; W, A, S, and D always toggle.
+fn key_direction(key: Key, shift: bool) -> Option<(Direction, bool)> {
+    match key {
+        Key::Up => Some((Direction::North, shift)),
+        Key::Down => Some((Direction::South, shift)),
+        Key::Left => Some((Direction::West, shift)),
+        Key::Right => Some((Direction::East, shift)),
+        Key::W => Some((Direction::North, true)),
+        Key::S => Some((Direction::South, true)),
+        Key::A => Some((Direction::West, true)),
+        Key::D => Some((Direction::East, true)),
+        _ => None,
+    }
+}
+
+/// The board we play on, unless a puzzle says otherwise.
+fn standard_map() -> MapParameters {
+    MapParameters {
+        size: (15, 15),
+        sources: vec![32, 42, 182, 192],
+        player_colors: vec![(0x9f, 0x20, 0xb1), (0xe0, 0x6f, 0x3a),
+                            (0x20, 0xb1, 0x21), (0x20, 0x67, 0xb1)]
+    }
+}
+
+/// Start hosting a game, meeting the other players as `rendezvous` says.
+fn host(rendezvous: Rendezvous, params: GameParameters, options: ServerOptions)
+        -> Result<Participant>
+{
+    Ok(Participant::new_server(rendezvous, standard_map(), params, options)?)
+}
+
+/// Return the number of seats in games we host: those on the standard board,
+/// or on `puzzle`'s.
+fn seats(puzzle: Option<&SerializableState>) -> usize {
+    match puzzle {
+        Some(position) => State::puzzle(position.clone(), GameParameters::default()).max_players(),
+        None => standard_map().player_colors.len(),
+    }
+}
+
+/// Play `games` games between bots at `options.bots`, on the standard board or
+/// from `options.puzzle`, and print how they went. If `training_path` is
+/// given, write training data there in `format`.
+fn simulate(params: GameParameters, options: &ServerOptions,
+            games: usize, max_turns: usize, training_path: Option<PathBuf>, format: Format)
+            -> Result<()>
+{
+    let new_game = || match options.puzzle {
+        Some(ref position) => State::puzzle(position.clone(), params.clone()),
+        None => State::new(standard_map(), params.clone()),
+    };
+    let seats = new_game().max_players();
+    if options.bots.len() < 2 || options.bots.len() > seats {
+        return Err(format!("a simulation needs between 2 and {} bots, one per --bots level",
+                           seats).into());
+    }
+
+    let mut training = match training_path {
+        Some(ref path) => Some(TrainingWriter::create(path, format, &new_game().map,
+                                                      &options.bots)?),
+        None => None,
+    };
+    let tally = simulate::simulate(new_game, &options.registry, &options.bots, games,
+                                   max_turns, training.as_mut())?;
+    if let Some(ref mut training) = training {
+        training.finish()?;
+    }
+    for (player, kind) in options.bots.iter().enumerate() {
+        println!("player {} ({}): {} wins, {:.1}%", player, kind,
+                 tally.wins[player], tally.win_rate(Player(player)) * 100.0);
+    }
+    if tally.unfinished() > 0 {
+        println!("unfinished after {} turns: {}", max_turns, tally.unfinished());
+    }
+    if let Some(turns) = tally.average_turns() {
+        let seconds = (turns * options.turn_duration.as_secs_f64()).round() as u64;
+        println!("average game length: {:.0} turns ({}:{:02} at {} ms per turn)",
+                 turns, seconds / 60, seconds % 60, options.turn_duration.as_millis());
+    }
+    Ok(())
+}
+
+/// How the start screen ended.
+enum Start {
+    /// The player chose to host a game.
+    Host,
+
+    /// We've joined a game, and can start playing.
+    Joined(Participant),
+
+    /// The player chose to leave.
+    Quit,
+}
+
+/// Return the message to show while `client` joins the game at `addr`.
+fn joining_message(client: &PendingClient, addr: &str) -> String {
+    match client.stage() {
+        ClientStage::Connecting => format!("CONNECTING TO {}...", addr),
+        ClientStage::Waiting => "CONNECTED. WAITING FOR A SEAT IN THE GAME...".to_string(),
+    }
+}
+
+/// Show the start screen in `display` until the player chooses what to do,
+/// or we've joined a game, and return how it ended. If `pending` is given,
+/// we're already joining a game at the command line's request; if that fails,
+/// return the error, as the command line always has, rather than going back
+/// to the menu.
+fn run_menu(display: &Display, events_loop: &mut EventsLoop, menu_drawer: &MenuDrawer,
+            menu: &mut Menu, mut pending: Option<PendingClient>) -> Result<Start>
+{
+    let from_command_line = pending.is_some();
+    let mut hidpi_factor = display.gl_window().get_hidpi_factor();
+    loop {
+        if let Some(mut client) = pending.take() {
+            match client.poll() {
+                None => {
+                    menu.connecting(joining_message(&client, &menu.address));
+                    pending = Some(client);
+                }
+                Some(Ok(participant)) => return Ok(Start::Joined(participant)),
+                Some(Err(error)) if from_command_line => {
+                    return Err(error).chain_err(|| "couldn't join game");
+                }
+                Some(Err(error)) => menu.failed(format!("COULDN'T JOIN: {}", error)),
+            }
+        }
+
+        let (width, height) = display.get_framebuffer_dimensions();
+        menu.set_size(width as f32, height as f32);
+        menu_drawer.render(menu)?;
+
+        let mut outcome = None;
+        events_loop.poll_events(|event| {
+            if let Event::WindowEvent { event, .. } = event {
+                match event {
+                    WindowEvent::CloseRequested => outcome = Some(Outcome::Quit),
+                    WindowEvent::HiDpiFactorChanged(factor) => hidpi_factor = factor,
+                    WindowEvent::CursorMoved { position, .. } => {
+                        let PhysicalPosition { x, y } = position.to_physical(hidpi_factor);
+                        menu.move_to([x as f32, y as f32]);
+                    }
+                    WindowEvent::MouseInput {
+                        button: MouseButton::Left,
+                        state,
+                        ..
+                    } => match state {
+                        ElementState::Pressed => menu.click(),
+                        ElementState::Released => outcome = menu.release(),
+                    },
+                    WindowEvent::ReceivedCharacter(ch) => menu.type_char(ch),
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            modifiers,
+                            ..
+                        },
+                        ..
+                    } => match key {
+                        VirtualKeyCode::Return => outcome = menu.enter(),
+                        VirtualKeyCode::Escape => outcome = menu.back(),
+                        VirtualKeyCode::Up => menu.move_focus(true),
+                        VirtualKeyCode::Down => menu.move_focus(false),
+                        VirtualKeyCode::Tab => menu.move_focus(modifiers.shift),
+                        _ => (),
+                    },
+                    _ => (),
+                }
+            }
+        });
+
+        match outcome {
+            Some(Outcome::Host) => return Ok(Start::Host),
+            Some(Outcome::Quit) => return Ok(Start::Quit),
+            Some(Outcome::Join(text)) => {
+                match addr::resolve(&text, IpAddr::V4(Ipv4Addr::LOCALHOST)) {
+                    Ok(addr) => pending = Some(PendingClient::start(addr)),
+                    Err(error) => menu.failed(format!("COULDN'T FIND {}: {}", text, error)),
+                }
+            }
+            // Dropping the pending client abandons the connection.
+            Some(Outcome::Cancel) => pending = None,
+            None => (),
+        }
+    }
+}
+
+/// Carry out the pointer `event` on the settings panel, changing `settings` if
+/// it says to. Return true if `settings` changed. The panel takes clicks and
+/// taps, and ignores the scroll wheel and other buttons.
+fn panel_pointer(panel: &mut Panel, event: &InputEvent, settings: &mut Settings) -> bool {
+    match *event {
+        InputEvent::CursorMoved { x, y } |
+        InputEvent::Touch { phase: Phase::Moved, x, y, .. } => panel.move_to([x, y]),
+        InputEvent::Button { button: Button::Left, pressed: true } => panel.click(),
+        InputEvent::Touch { phase: Phase::Started, x, y, .. } => {
+            panel.move_to([x, y]);
+            panel.click();
+        }
+        InputEvent::Button { button: Button::Left, pressed: false } |
+        InputEvent::Touch { phase: Phase::Ended, .. } => return panel.release(settings),
+        _ => (),
+    }
+    false
+}
+
+/// Show the game as `settings` say from now on, and save them to `path`, if
+/// we have one.
+fn change_settings(settings: &Settings, path: Option<&Path>,
+                   renderer: &mut dyn Renderer, input: &mut Input) {
+    renderer.set_grid_style(settings.grid_style);
+    renderer.set_ui_scale(settings.ui_scale);
+    input.set_ui_scale(settings.ui_scale);
+    save_settings(settings, path);
+}
+
+/// Save `settings` to `path`, if we have one. This isn't worth interrupting
+/// the game over, so if it fails, just complain.
+fn save_settings(settings: &Settings, path: Option<&Path>) {
+    if let Some(path) = path {
+        if let Err(error) = settings.save(path) {
+            eprintln!("rbattle: couldn't save settings: {}", error);
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    // With no mode, we show the start screen, and let the player choose there.
+    let mut args = std::env::args().skip(1).peekable();
+    let mode = match args.peek() {
+        Some(arg) if !arg.starts_with("--") => args.next(),
+        _ => None,
+    };
+
+    // Sort the remaining arguments into flags and a positional address.
+    let mut addr = None;
+    let mut relay = None;
+    let mut params = GameParameters::default();
+    let mut handicaps = vec![];
+    let mut bots = None;
+    let mut options = ServerOptions::default();
+    let mut colors = vec![];
+    let mut enemy_color = None;
+    let mut fps = 0;
+    let mut vsync = true;
+    let mut frame_log = None;
+    let mut size = None;
+    let mut fullscreen = false;
+    let mut show_hints = false;
+    let mut export_dir = None;
+    let mut export_every = 1;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut theme_path = None;
+    let mut grid_style = None;
+    let mut ui_scale = None;
+    let mut settings_path = Settings::default_path();
+    let mut hit_tolerance = EDGE_TOLERANCE;
+    let mut save_path = PathBuf::from(save::DEFAULT_PATH);
+    let mut autosave_every = None;
+    let mut autosave_keep = save::DEFAULT_AUTOSAVES;
+    let mut game_path = None;
+    let mut stats_path = None;
+    let mut from_turn = None;
+    let mut dump_path = PathBuf::from(save::DEFAULT_DUMP_PATH);
+    let mut format = Format::default();
+    let mut games = 100;
+    let mut max_turns = 100_000;
+    let mut training_path = None;
+    let mut ai = bot::DEFAULT_KIND.to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--relay" => relay = Some(parse_connect_addr(args.next())?),
+            "--events" => options.events = Some(parse_listen_addr(args.next())?),
+            "--warmup" => params.warmup_turns = parse_number(args.next()),
+            "--handicap" => handicaps.push(parse_handicap(args.next())),
+            "--turn-ms" => {
+                options.turn_duration = Duration::from_millis(parse_number(args.next()));
+            }
+            "--adaptive" => options.adaptive = true,
+            "--bots" => bots = Some(args.next().unwrap_or_else(|| usage())),
+            "--fill-seats" => options.fill_seats = Some(args.next().unwrap_or_else(|| usage())),
+            "--ai" => ai = args.next().unwrap_or_else(|| usage()),
+            "--wasm-bot" => {
+                let arg = args.next().unwrap_or_else(|| usage());
+                let (name, path) = arg.split_once('=').unwrap_or_else(|| usage());
+                let module = wasm::WasmModule::load(Path::new(path))?;
+                options.registry.register(name, move |_, _| module.bot());
+            }
+            "--pause-on-drop" => {
+                options.pause_on_drop = Some(Duration::from_secs(parse_number(args.next())));
+            }
+            "--save" => save_path = PathBuf::from(args.next().unwrap_or_else(|| usage())),
+            "--autosave" => autosave_every = Some(parse_number(args.next())),
+            "--autosave-keep" => autosave_keep = parse_number(args.next()),
+            "--puzzle" => {
+                let path = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+                options.puzzle = Some(save::load_state(&path)?);
+            }
+            "--resume" => {
+                let path = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+                options.resume = Some(SavedGame::load(&path)?);
+            }
+            "--log-timing" => {
+                options.timing_log = Some(Duration::from_secs(parse_number(args.next())));
+            }
+            "--color" => colors.push(parse_player_color(args.next())),
+            "--enemy-color" => {
+                enemy_color = Some(parse_color(&args.next().unwrap_or_else(|| usage()))
+                                   .unwrap_or_else(|| usage()));
+            }
+            "--fps" => fps = parse_number(args.next()),
+            "--no-vsync" => vsync = false,
+            "--log-frames" => frame_log = Some(Duration::from_secs(parse_number(args.next()))),
+            "--size" => size = Some(parse_size(args.next())),
+            "--fullscreen" => fullscreen = true,
+            "--hints" => show_hints = true,
+            "--theme" => {
+                theme_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--export-frames" => {
+                export_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--export-every" => export_every = parse_number(args.next()),
+            "--record-input" => {
+                record_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--record-game" => {
+                game_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--binary" => format.binary = true,
+            "--compress" => format.compressed = true,
+            "--stats-log" => {
+                stats_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--games" => games = parse_number(args.next()),
+            "--max-turns" => max_turns = parse_number(args.next()),
+            "--training-data" => {
+                training_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--from-turn" => from_turn = Some(parse_number(args.next())),
+            "--dump-state" => {
+                dump_path = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+            }
+            "--replay-input" => {
+                replay_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            "--ui-scale" => {
+                ui_scale = match parse_number::<f32>(args.next()) {
+                    scale if scale.is_finite() && scale > 0.0 => Some(scale),
+                    _ => usage(),
+                };
+            }
+            "--hit-tolerance" => {
+                hit_tolerance = match parse_number::<f32>(args.next()) {
+                    tolerance if (0.0..0.5).contains(&tolerance) => tolerance,
+                    _ => usage(),
+                };
+            }
+            "--grid" => {
+                grid_style = Some(GridStyle::parse(&args.next().unwrap_or_else(|| usage()))
+                                  .unwrap_or_else(|| usage()));
+            }
+            "--settings" => {
+                settings_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage())));
+            }
+            _ if arg.starts_with("--") || addr.is_some() => usage(),
+            _ => addr = Some(arg),
+        }
+    }
+
+    // Simulations take the puzzle to play as their positional argument.
+    if mode.as_deref() == Some("simulate") {
+        if let Some(ref path) = addr {
+            options.puzzle = Some(save::load_state(Path::new(path))?);
+        }
+    }
+
+    // Now that we know the board, make sure every player handicapped, and
+    // every bot, has a seat on it.
+    let seats = seats(options.puzzle.as_ref());
+    if let Some(arg) = bots {
+        options.bots = parse_bots(&arg, seats);
+    }
+    for (player, handicap) in handicaps {
+        if player >= seats {
+            return Err(format!("can't handicap player {}: the game has only {} seats",
+                               player, seats).into());
+        }
+        if params.handicaps.len() <= player {
+            params.handicaps.resize(player + 1, Handicap::default());
+        }
+        params.handicaps[player] = handicap;
+    }
+
+    match mode.as_deref() {
+        Some("relay") => {
+            return relay::serve(parse_listen_addr(addr)?)
+                .chain_err(|| "relay failed");
+        }
+        Some("anonymize") => {
+            let path = PathBuf::from(addr.unwrap_or_else(|| usage()));
+            let output = anonymize::output_path(&path);
+            anonymize::anonymize(&Replay::load(&path)?, &mut rand::thread_rng())
+                .chain_err(|| format!("anonymizing replay {}", path.display()))?
+                .save(&output, format)?;
+            println!("Wrote {}", output.display());
+            return Ok(());
+        }
+        Some("bot") => {
+            return play_bot(parse_connect_addr(addr)?, &options.registry, &ai);
+        }
+        Some("simulate") => {
+            return simulate(params, &options, games, max_turns, training_path, format);
+        }
+        None | Some("server") | Some("client") | Some("replay") | Some("sandbox") => (),
+        Some(_) => usage(),
+    }
+
+    if let Some(every) = autosave_every {
+        options.autosave = Some(Autosave::start(save_path.clone(), format, every,
+                                                autosave_keep));
+    }
+
+    // Start from the saved settings, overridden by any flags.
+    let mut settings = match settings_path {
+        Some(ref path) => Settings::load(path)?,
+        None => Settings::default(),
+    };
+    settings.grid_style = grid_style.unwrap_or(settings.grid_style);
+    settings.ui_scale = ui_scale.unwrap_or(settings.ui_scale);
 
     let mut events_loop = EventsLoop::new();
-    let window = WindowBuilder::new()
+    let mut window = WindowBuilder::new()
         .with_title("rbattle".to_string());
-    let context = ContextBuilder::new();
-    let display = Display::new(window, context, &events_loop)
-        .chain_err(|| "unable to open window")?;
+    if let Some(size) = size {
+        window = window.with_dimensions(size);
+    }
+    if fullscreen {
+        window = window.with_fullscreen(Some(events_loop.get_primary_monitor()));
+    }
+    // How often we draw is entirely up to the vsync setting and the frame
+    // limiter. The game's pace is set by the scheduler's timer.
+    //
+    // Ask for multisampling, to smooth the edges of shapes, but not every
+    // driver supports it, so settle for a plain context if we must.
+    let context = |samples| ContextBuilder::new().with_vsync(vsync).with_multisampling(samples);
+    let display = match Display::new(window.clone(), context(MSAA_SAMPLES), &events_loop) {
+        Ok(display) => display,
+        Err(_) => Display::new(window, context(0), &events_loop)
+            .chain_err(|| "unable to open window")?,
+    };
+
+    let theme = match theme_path {
+        Some(path) => Theme::load(&path)?,
+        None => Theme::default(),
+    };
+
+    let mut participant = match mode.as_deref() {
+        Some("server") => {
+            let rendezvous = match relay {
+                Some(relay_addr) => Rendezvous::Relay(relay_addr),
+                None => Rendezvous::Listen(parse_listen_addr(addr)?),
+            };
+            host(rendezvous, params, options)?
+        }
+        Some("sandbox") => {
+            // Listen only on this machine, on whatever port is free: this
+            // game is for one.
+            let path = PathBuf::from(addr.unwrap_or_else(|| usage()));
+            let state = save::load_state(&path)?;
+            options.resume = Some(SavedGame::sandbox(state, options.turn_duration));
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+            host(Rendezvous::Listen(addr), params, options)?
+        }
+        Some("replay") => {
+            let path = PathBuf::from(addr.unwrap_or_else(|| usage()));
+            let mut participant = Participant::new_replay(Replay::load(&path)?);
+            if let Some(turn) = from_turn {
+                participant.seek(turn);
+            }
+            participant
+        }
+        _ => {
+            // The command line's client mode goes straight to the connecting
+            // screen.
+            let pending = if mode.is_some() {
+                Some(PendingClient::start(parse_connect_addr(addr.clone())?))
+            } else {
+                None
+            };
+            let menu_drawer = MenuDrawer::new(&display, &theme)
+                .chain_err(|| "failed to construct drawer for menu")?;
+            // The menu only offers the built-in bots, so any other kinds
+            // asked for on the command line start out as the default.
+            let levels: Vec<Difficulty> = options.bots.iter()
+                .map(|kind| kind.parse().unwrap_or_default())
+                .collect();
+            let mut menu = Menu::new(addr.unwrap_or_else(|| "localhost".to_string()),
+                                     &levels, settings.grid_style, settings.ui_scale);
+            let start = run_menu(&display, &mut events_loop, &menu_drawer, &mut menu, pending)?;
+            let chosen = Settings { grid_style: menu.grid_style, ui_scale: menu.ui_scale };
+            if chosen != settings {
+                settings = chosen;
+                save_settings(&settings, settings_path.as_deref());
+            }
+            match start {
+                Start::Host => {
+                    options.bots = menu.bots.iter().map(Difficulty::to_string).collect();
+                    let rendezvous = match relay {
+                        Some(relay_addr) => Rendezvous::Relay(relay_addr),
+                        None => Rendezvous::Listen(SocketAddr::new(
+                            IpAddr::V4(Ipv4Addr::UNSPECIFIED), addr::DEFAULT_PORT)),
+                    };
+                    host(rendezvous, params, options)?
+                }
+                Start::Joined(participant) => participant,
+                Start::Quit => return Ok(()),
+            }
+        }
+    };
+
+    if let Some(path) = game_path {
+        participant.record_game(&path, format)?;
+    }
+    if let Some(path) = stats_path {
+        participant.log_stats(path);
+    }
+
+    let map = participant.snapshot().map.clone();
+
+    // Build two palettes: one that draws enemies in the enemy color, and one
+    // that doesn't. The `C` key switches between them. Colors given on the
+    // command line override the theme's.
+    let mut palette = Palette::new(participant.get_player(), map.player_colors.len());
+    for (player, color) in theme.players.iter().enumerate() {
+        // Themes may well have colors for more players than this game seats.
+        palette.set_color(Player(player), color.rgb);
+    }
+    for (player, color) in colors {
+        if !palette.set_color(Player(player), color) {
+            return Err(format!("can't color player {}: the game has only {} seats",
+                               player, map.player_colors.len()).into());
+        }
+    }
+    let mut contrast_palette = palette.clone();
+    contrast_palette.set_enemy_color(enemy_color.unwrap_or(DEFAULT_ENEMY_COLOR));
+    let mut high_contrast = enemy_color.is_some();
 
-    let drawer = Drawer::new(&display, &map)
-        .chain_err(|| "failed to construct Drawer for map")?;
+    // The number of physical pixels per logical pixel on the display the window
+    // is on. This changes when the window is dragged to a different display;
+    // we hear about that from `HiDpiFactorChanged` events.
+    let mut hidpi_factor = display.gl_window().get_hidpi_factor();
 
-    let mut mouse = Mouse::new(participant.get_player(), map.clone());
+    let mut renderer: Box<dyn Renderer> =
+        Box::new(Drawer::new(&display, &map,
+                             if high_contrast { contrast_palette.clone() }
+                             else { palette.clone() },
+                             &theme,
+                             participant.turn_duration())
+                 .chain_err(|| "failed to construct Drawer for map")?);
+    renderer.set_grid_style(settings.grid_style);
+    renderer.set_ui_scale(settings.ui_scale);
 
+    let mut input = Input::new(participant.get_player(), map.clone());
+    input.set_ui_scale(settings.ui_scale);
+    let mut panel = Panel::new();
+    let mut dispatcher = Dispatcher::default();
+    input.set_hit_tolerance(hit_tolerance);
+    let mut hints = if show_hints { Some(Hints::new(participant.get_player())) } else { None };
+
+    let mut title = "rbattle".to_string();
+    let mut cursor = Cursor::Default;
+    let mut announced = false;
     let start = Instant::now();
+    let mut limiter = FrameLimiter::new(fps, start);
+    let mut frame_times = FrameTimes::new();
+    let mut frame_log_due = frame_log.map(|period| start + period);
+    let mut perf_meter = PerfMeter::new();
+    let mut show_perf = false;
+    let mut show_hit_regions = false;
+    let mut exporter = match export_dir {
+        Some(dir) => Some(FrameExporter::new(dir, export_every)?),
+        None => None,
+    };
+    let mut recorder = match record_path {
+        Some(path) => Some(Recorder::create(&path)?),
+        None => None,
+    };
+    let mut playback = match replay_path {
+        Some(path) => Some(Playback::open(&path)?),
+        None => None,
+    };
     loop {
         // Record when this frame started.
         let time = start.elapsed();
@@ -127,21 +1026,103 @@ fn run() -> Result<()> {
         // Take a snapshot of the current state and operate on that.
         let state = participant.snapshot();
 
-        // It seems like glium always makes a frame take a full 16ms, regardless
-        // of how much work we ask it to do, but I don't see anything in the
-        // documentation about this. We're leaning on that for now to keep
-        // timing consistent, but we'll need to add something to control timing
-        // explicitly to avoid depending on this behavior.
-        let mut frame = display.draw();
-        frame.clear_color(1.0, 1.0, 1.0, 1.0);
-        let status = drawer.draw(&mut frame, time, &state, &mouse);
-        frame.finish()
-            .chain_err(|| "drawing finish failed")?;
-
-        let window_to_game = status?;
+        // Keep the window title up to date with how the game is going.
+        let result = participant.result();
+        let new_title = if let Some(turn) = participant.diverged() {
+            format!("rbattle: out of sync with the server on turn {}; see {}",
+                    turn, divergence::report_path(turn).display())
+        } else {
+            match (participant.replay_turns(), &result, participant.pause()) {
+                (Some((_, last)), _, _) => {
+                    let paused = if participant.replay_paused() { " (paused)" } else { "" };
+                    format!("rbattle: replay, turn {} of {}{}", state.turn, last, paused)
+                }
+                (None, &Some((winner, _)), _) if winner == participant.get_player() =>
+                    "rbattle: You win!".to_string(),
+                (None, &Some(_), _) => "rbattle: You lose.".to_string(),
+                (None, &None, Some((player, remaining))) =>
+                    format!("rbattle: waiting for player {} to reconnect ({}s)",
+                            player.0, remaining.as_secs()),
+                (None, &None, None) => match participant.turns_behind() {
+                    0 => "rbattle".to_string(),
+                    behind => format!("rbattle: catching up ({} turns behind)", behind),
+                },
+            }
+        };
+        if new_title != title {
+            display.gl_window().set_title(&new_title);
+            title = new_title;
+        }
+
+        match result {
+            Some((winner, ref stats)) if !announced => {
+                println!("Player {} won after {} turns.", winner.0, stats.turns);
+                for (player, peak) in stats.peak_nodes.iter().enumerate() {
+                    let did = stats.players.get(player).cloned().unwrap_or_default();
+                    println!("  player {}: held at most {} nodes; {:.0} actions per minute, \
+                              {} toggles, {} captures",
+                             player, peak, stats.actions_per_minute(Player(player)),
+                             did.toggles, did.captures);
+                }
+                announced = true;
+            }
+            // A rematch has started; announce its result when it comes.
+            None => announced = false,
+            _ => (),
+        }
+
+        let game_over = result.as_ref().map(|&(winner, ref stats)| GameOver {
+            winner,
+            local: participant.get_player(),
+            stats,
+            can_rematch: participant.is_host(),
+        });
+
+        let perf = if show_perf {
+            Some(perf_meter.stats(participant.turns_behind()))
+        } else {
+            None
+        };
+
+        let (width, height) = display.get_framebuffer_dimensions();
+        input.set_buttons(ui::layout(width as f32, height as f32));
+        input.set_window_size(width as f32, height as f32);
+        input.set_queued(participant.queued_actions());
+        input.set_hint(match hints {
+            Some(ref mut hints) if game_over.is_none() && participant.replay_turns().is_none() =>
+                hints.update(&state),
+            _ => None,
+        });
+        input.note_attacks(&state);
+        input.chat_mut().note_messages(&state);
+
+        panel.layout(width as f32, height as f32, &settings);
+
+        let window_to_game = renderer.render(time, &state, &input, &panel, game_over.as_ref(),
+                                             perf.as_ref())?;
+        if let Some(ref mut exporter) = exporter {
+            if exporter.due() {
+                exporter.save(&renderer.screenshot()?)?;
+            }
+        }
         let window_to_graph = compose(map.game_to_graph, window_to_game);
 
+        // If the camera moved, the board has moved under the mouse.
+        input.refresh(window_to_graph);
+
+        let new_cursor = if panel.is_open() { Cursor::Default } else { input.cursor(&state) };
+        if new_cursor != cursor {
+            display.gl_window().set_cursor(match new_cursor {
+                Cursor::Default => MouseCursor::Default,
+                Cursor::Hand => MouseCursor::Hand,
+                Cursor::Forbidden => MouseCursor::NotAllowed,
+                Cursor::Crosshair => MouseCursor::Crosshair,
+            });
+            cursor = new_cursor;
+        }
+
         let mut done = None;
+        let mut events = vec![];
         events_loop.poll_events(|event| {
             if let Event::WindowEvent { event, .. } = event {
                 match event {
@@ -149,61 +1130,310 @@ fn run() -> Result<()> {
                         done = Some(Ok(()));
                     }
 
-                    WindowEvent::CursorMoved { position, .. } => {
-                        let hidpi_factor = display.gl_window().get_hidpi_factor();
-                        let PhysicalPosition { x, y } = position.to_physical(hidpi_factor);
-                        let graph_pos = apply(window_to_graph, [x as f32, y as f32]);
-                        mouse.move_to(GraphPt(graph_pos));
+                    WindowEvent::HiDpiFactorChanged(factor) => {
+                        hidpi_factor = factor;
+                        renderer.set_hidpi_factor(factor);
                     }
 
-                    WindowEvent::MouseInput {
-                        button: MouseButton::Left,
-                        state: ElementState::Pressed,
-                        ..
-                    } => {
-                        mouse.click();
+                    event => events.extend(InputEvent::from_window_event(&event, hidpi_factor)),
+                }
+            }
+        });
+        if let Some(ref mut playback) = playback {
+            events.extend(playback.due(time));
+        }
+
+        for event in events {
+            if let Some(ref mut recorder) = recorder {
+                recorder.record(time, &event)?;
+            }
+
+            // Lifting the mouse button and lifting a finger both finish
+            // clicks, which we carry out once the event is handled.
+            let mut click = None;
+
+            // Pointer events for the settings panel go no further.
+            let top = if panel.is_open() { Layer::Panel } else { Layer::Board };
+            if dispatcher.route(&event, top) == Some(Layer::Panel) {
+                if panel_pointer(&mut panel, &event, &mut settings) {
+                    change_settings(&settings, settings_path.as_deref(),
+                                    &mut *renderer, &mut input);
+                }
+                continue;
+            }
+
+            let typing = input.chat().typing();
+            match event {
+                // While the player is typing a chat message, the keyboard is
+                // for that alone.
+                InputEvent::Char { ch } => input.chat_mut().type_char(ch),
+                InputEvent::Key { key: Key::Return, alt: false, .. } if typing => {
+                    if let Some(action) = input.chat_mut().send(participant.get_player()) {
+                        participant.request_action(action);
                     }
+                }
+                InputEvent::Key { key: Key::Escape, .. } if typing => input.chat_mut().cancel(),
+                InputEvent::Key { .. } if typing => (),
 
-                    WindowEvent::MouseInput {
-                        button: MouseButton::Left,
-                        state: ElementState::Released,
-                        ..
-                    } => {
-                        if let Some(action) = mouse.release() {
-                            participant.request_action(action);
+                // While the settings panel is open, the arrow keys and Tab
+                // move along its items, and Return chooses one.
+                InputEvent::Key { key: Key::Up, .. } if panel.is_open() => panel.move_focus(true),
+                InputEvent::Key { key: Key::Down, .. } if panel.is_open() => {
+                    panel.move_focus(false);
+                }
+                InputEvent::Key { key: Key::Tab, shift, .. } if panel.is_open() => {
+                    panel.move_focus(shift);
+                }
+                InputEvent::Key { key: Key::Return, alt: false, .. } if panel.is_open() => {
+                    if panel.enter(&mut settings) {
+                        change_settings(&settings, settings_path.as_deref(),
+                                        &mut *renderer, &mut input);
+                    }
+                }
+
+                // Otherwise, Tab moves along the buttons at the top of the
+                // window, and Return presses the highlighted one. Failing
+                // that, Return picks the selected node in flow mode, or starts
+                // a chat message.
+                InputEvent::Key { key: Key::Tab, shift, .. } => input.cycle_buttons(shift),
+                InputEvent::Key { key: Key::Return, alt: false, .. } => {
+                    if let Some(command) = input.focused_button() {
+                        click = Some(Click::Button(command));
+                    } else if input.flow_active() {
+                        click = input.choose_selected(&state);
+                    } else {
+                        input.chat_mut().open();
+                    }
+                }
+
+                InputEvent::CursorMoved { x, y } => {
+                    let graph_pos = apply(window_to_graph, [x, y]);
+                    input.move_to(GraphPt(graph_pos), [x, y]);
+                }
+
+                InputEvent::Button { button: Button::Left, pressed: true } => {
+                    input.click();
+                }
+
+                InputEvent::Button { button: Button::Right, pressed: true } => {
+                    if let Some(action) = input.right_click(&state) {
+                        participant.request_action(action);
+                    }
+                }
+
+                InputEvent::Button { button: Button::Left, pressed: false } => {
+                    click = input.release(&state, Instant::now());
+                }
+
+                InputEvent::Button { button: Button::Middle, pressed } => {
+                    input.middle_button(pressed);
+                }
+
+                InputEvent::Button { button: Button::Right, pressed: false } => (),
+
+                InputEvent::Scroll { lines } => {
+                    input.scroll(lines);
+                }
+
+                InputEvent::Swipe { x, y } => {
+                    input.swipe([x, y]);
+                }
+
+                InputEvent::Touch { id, phase, x, y } => {
+                    let window = [x, y];
+                    let graph_pos = GraphPt(apply(window_to_graph, window));
+                    match phase {
+                        Phase::Started => input.touch_start(id, graph_pos, window),
+                        Phase::Moved => input.touch_move(id, graph_pos, window),
+                        Phase::Ended => click = input.touch_end(id, &state, Instant::now()),
+                        Phase::Cancelled => input.touch_cancel(id),
+                    }
+                }
+
+                InputEvent::Key { key: Key::C, .. } => {
+                    high_contrast = !high_contrast;
+                    renderer.set_palette(if high_contrast { contrast_palette.clone() }
+                                         else { palette.clone() });
+                }
+
+                InputEvent::Key { key: Key::F, .. } => {
+                    input.toggle_flow();
+                }
+
+                InputEvent::Key { key: Key::F1, .. } => panel.toggle(),
+
+                InputEvent::Key { key: Key::F2, .. } => {
+                    hints = match hints {
+                        Some(_) => None,
+                        None => Some(Hints::new(participant.get_player())),
+                    };
+                }
+
+                InputEvent::Key { key: Key::F3, .. } => {
+                    show_perf = !show_perf;
+                }
+
+                InputEvent::Key { key: Key::F9, .. } => {
+                    match save::dump_state(&state, &dump_path) {
+                        Ok(()) => eprintln!("rbattle: dumped state to {}", dump_path.display()),
+                        Err(error) => eprintln!("rbattle: couldn't dump state: {}", error),
+                    }
+                }
+
+                InputEvent::Key { key: Key::F4, .. } => {
+                    show_hit_regions = !show_hit_regions;
+                    renderer.set_show_hit_regions(show_hit_regions);
+                }
+
+                InputEvent::Key { key: Key::G, .. } => {
+                    settings.grid_style = settings.grid_style.next();
+                    change_settings(&settings, settings_path.as_deref(),
+                                    &mut *renderer, &mut input);
+                }
+
+                InputEvent::Key { key: Key::S, ctrl: true, .. } => {
+                    if let Some(saved) = participant.save() {
+                        match saved.save(&save_path, format) {
+                            Ok(()) => eprintln!("rbattle: saved game to {}", save_path.display()),
+                            Err(error) => eprintln!("rbattle: couldn't save game: {}", error),
                         }
                     }
+                }
 
-                    WindowEvent::KeyboardInput {
-                        input: KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                        ..
-                    } => {
-                        std::process::exit(0);
+                // While watching a replay, P pauses it, the brackets step
+                // back and forward, and Home, End, and the digits jump to the
+                // start, the end, or that many tenths of the way through.
+                InputEvent::Key { key: Key::P, .. } => participant.toggle_replay_pause(),
+                InputEvent::Key { key: Key::LBracket, .. } => {
+                    participant.seek(state.turn.saturating_sub(REPLAY_STEP_TURNS));
+                }
+                InputEvent::Key { key: Key::RBracket, .. } => {
+                    participant.seek(state.turn + REPLAY_STEP_TURNS);
+                }
+                InputEvent::Key { key: Key::Home, .. } => participant.seek(0),
+                InputEvent::Key { key: Key::End, .. } => participant.seek(usize::MAX),
+                InputEvent::Key { key: Key::Digit(digit), .. } => {
+                    if let Some((first, last)) = participant.replay_turns() {
+                        participant.seek(first + (last - first) * digit as usize / 10);
                     }
+                }
 
-                    WindowEvent::KeyboardInput {
-                        input: KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::W),
-                            modifiers: ModifiersState { ctrl: true, .. },
-                            ..
-                        },
-                        ..
-                    } => {
-                        std::process::exit(0);
+                InputEvent::Key { key: Key::Z, ctrl: true, .. } => {
+                    participant.undo_toggle();
+                }
+
+                InputEvent::Key { key: Key::X, .. } => {
+                    if let Some(action) = input.clear_selected(&state) {
+                        participant.request_action(action);
+                    }
+                }
+
+                InputEvent::Key { key: Key::E, .. } => {
+                    if let Some(action) = input.open_selected(&state) {
+                        participant.request_action(action);
+                    }
+                }
+
+                InputEvent::Key { key: Key::H, .. } => {
+                    input.focus_source();
+                }
+
+                InputEvent::Key { key: Key::Space, .. } => {
+                    input.focus_contested();
+                }
+
+                InputEvent::Key { key: Key::Minus, .. } => {
+                    let slower = participant.turn_duration() * 5 / 4;
+                    participant.set_turn_duration(slower);
+                }
+
+                InputEvent::Key { key: Key::Equals, .. } => {
+                    let faster = participant.turn_duration() * 4 / 5;
+                    participant.set_turn_duration(faster);
+                }
+
+                InputEvent::Key { key: Key::R, .. } => {
+                    participant.rematch();
+                }
+
+                InputEvent::Key { key: Key::Return, alt: true, .. } => {
+                    // Changing the window in place keeps the GL context, and
+                    // all the Drawer's buffers and shaders with it.
+                    fullscreen = !fullscreen;
+                    let window = display.gl_window();
+                    let monitor = window.get_current_monitor();
+                    window.set_fullscreen(if fullscreen { Some(monitor) } else { None });
+                }
+
+                // Escape backs out of whatever the keyboard is in the middle
+                // of before it quits.
+                InputEvent::Key { key: Key::Escape, .. } if panel.is_open() => panel.toggle(),
+                InputEvent::Key { key: Key::Escape, .. } if input.focused_button().is_some() => {
+                    input.unfocus_buttons();
+                }
+                InputEvent::Key { key: Key::Escape, .. } if input.flow_active() => {
+                    input.toggle_flow();
+                }
+
+                InputEvent::Key { key: Key::Escape, .. } |
+                InputEvent::Key { key: Key::W, ctrl: true, .. } => {
+                    std::process::exit(0);
+                }
+
+                InputEvent::Key { key, shift, ctrl: false, alt: false } => {
+                    match key_direction(key, shift) {
+                        Some((direction, false)) => input.move_selection(direction),
+                        Some((direction, true)) => {
+                            if let Some(action) = input.toggle_toward(direction) {
+                                participant.request_action(action);
+                            }
+                        }
+                        None => (),
                     }
+                }
+
+                InputEvent::Key { .. } => (),
+            }
 
-                    _ => ()
+            match click {
+                Some(Click::Action(action)) => participant.request_action(action),
+                Some(Click::Drag(actions)) | Some(Click::Path(actions)) => {
+                    for action in actions {
+                        participant.request_action(action);
+                    }
+                }
+                Some(Click::Button(Command::Surrender)) => {
+                    let player = participant.get_player();
+                    participant.request_action(Action::Surrender { player });
                 }
+                Some(Click::Button(Command::ToggleGrid)) => {
+                    settings.grid_style = settings.grid_style.next();
+                    change_settings(&settings, settings_path.as_deref(),
+                                    &mut *renderer, &mut input);
+                }
+                Some(Click::Button(Command::Settings)) => panel.toggle(),
+                Some(Click::Button(Command::Quit)) => done = Some(Ok(())),
+                None => (),
             }
-        });
+        }
 
         if let Some(result) = done {
             return result;
         }
+
+        let now = Instant::now();
+        frame_times.record(now - start - time);
+        perf_meter.record(now, now - start - time, state.turn);
+        if let (Some(due), Some(period)) = (frame_log_due, frame_log) {
+            if now >= due {
+                eprintln!("frame timing, {}", frame_times.take_summary());
+                frame_log_due = Some(due + period);
+            }
+        }
+
+        // Wait for the next frame, if we're limiting the frame rate.
+        // Animations are driven by `time`, so they look the same at any frame
+        // rate.
+        std::thread::sleep(limiter.delay(now));
     }
 }
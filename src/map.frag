@@ -1,10 +1,21 @@
 #version 150
 
+// How far this pixel is from the center line of the boundary, in pixels.
+in float across;
+
+// The width of the line, in pixels.
+uniform float line_width;
+
+// The color of the line.
+uniform vec4 line_color;
+
 // This is automatically assigned to be the color and transparency of the pixel
 // we're responsible for.
 out vec4 color;
 
 void main() {
-  // A nice orange.
-  color = vec4(0.0, 0.349, 1.0, 1.0);
+  // Fade out over the last pixel at the edge of the line.
+  float coverage = clamp(line_width / 2.0 + 0.5 - abs(across), 0.0, 1.0);
+
+  color = vec4(line_color.rgb, line_color.a * coverage);
 }
@@ -0,0 +1,92 @@
+//! The maps we've played on before, kept so that we needn't fetch them again.
+//!
+//! A map is named by its hash (see `Map::hash`), so a server welcoming a client
+//! sends just that, and the client only asks for the map itself if it hasn't
+//! seen it. We keep each map we're sent in `~/.cache/rbattle/maps`, in a file
+//! named for its hash holding its canonical serialization, so any map file
+//! can be checked against its name. A file that doesn't match its name is
+//! ignored, and replaced the next time we're sent that map.
+
+use errors::*;
+use map::{Map, MapHash};
+
+use serde_json;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A directory of map files, each named by its map's hash.
+pub struct MapCache {
+    dir: PathBuf,
+}
+
+impl MapCache {
+    /// Return the cache in `dir`.
+    pub fn new(dir: PathBuf) -> MapCache {
+        MapCache { dir }
+    }
+
+    /// Return where maps are cached by default, if we can tell.
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(cache.join("rbattle").join("maps"))
+    }
+
+    /// Return the path of the file holding the map named `hash`.
+    pub fn path(&self, hash: MapHash) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    /// Return the map named `hash`, if we have it.
+    pub fn get(&self, hash: MapHash) -> Option<Map> {
+        let mut text = vec![];
+        File::open(self.path(hash)).ok()?.read_to_end(&mut text).ok()?;
+        let map: Map = serde_json::from_slice(&text).ok()?;
+        if map.hash() != hash || map.check("cached map").is_err() {
+            return None;
+        }
+        Some(map)
+    }
+
+    /// Keep `map`, creating the cache's directory if need be.
+    pub fn put(&self, map: &Map) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .chain_err(|| format!("creating map cache directory {}", self.dir.display()))?;
+        let path = self.path(map.hash());
+        fs::write(&path, map.canonical_json())
+            .chain_err(|| format!("writing map file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+
+    #[test]
+    fn cache() {
+        let dir = env::temp_dir().join(format!("rbattle-maps-{}", ::std::process::id()));
+        let cache = MapCache::new(dir.clone());
+        let map = Map::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        });
+        let hash = map.hash();
+        assert!(cache.get(hash).is_none());
+
+        cache.put(&map).unwrap();
+        assert_eq!(cache.get(hash).unwrap().hash(), hash);
+        assert!(cache.path(hash).ends_with(format!("{}.json", hash)));
+
+        // A file that isn't the map it's named for is passed over.
+        fs::write(cache.path(MapHash(hash.0 ^ 1)), map.canonical_json()).unwrap();
+        assert!(cache.get(MapHash(hash.0 ^ 1)).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,436 @@
+//! The start screen.
+//!
+//! Run with no arguments, rbattle opens a window offering to host a game, join
+//! one, or change a few settings, instead of printing its usage. This module
+//! holds the menu's state and handles its input; `drawer::MenuDrawer` draws
+//! it, and the main loop starts whichever game the player chooses, just as it
+//! would from the command line.
+//!
+//! Joining a game takes a moment, so the menu also has a screen to show while
+//! we connect, with a button to give up. The command line's `client` mode
+//! uses that screen too.
+
+use bot::Difficulty;
+use input::ButtonState;
+use renderer::GridStyle;
+use settings::next_ui_scale;
+use ui::{self, Button};
+
+/// The most characters the address field will hold.
+const MAX_ADDRESS: usize = 64;
+
+/// The most computer-controlled players a hosted game can have. The map has
+/// four seats, and the host takes one.
+const MAX_BOTS: usize = 3;
+
+/// Something on the menu that can be clicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    Host,
+    Join,
+    Settings,
+    Quit,
+
+    /// The address to join, which takes typing whenever the join screen is up.
+    Address,
+    Connect,
+
+    Bots,
+    BotLevel,
+    Grid,
+    UiScale,
+
+    /// Stop trying to join a game.
+    Cancel,
+
+    /// Return to the main screen.
+    Back,
+}
+
+/// Which set of items the menu is showing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Screen {
+    Main,
+    Join,
+    Settings,
+    Connecting,
+}
+
+/// What the player chose to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Host a game on this machine.
+    Host,
+
+    /// Join the game at the given address, written as on the command line.
+    Join(String),
+
+    /// Stop trying to join the game we're connecting to.
+    Cancel,
+
+    /// Leave without playing.
+    Quit,
+}
+
+pub struct Menu {
+    screen: Screen,
+
+    /// The address typed into the join screen.
+    pub address: String,
+
+    /// The computer-controlled players to host a game with, and how well
+    /// each plays.
+    pub bots: Vec<Difficulty>,
+
+    /// How well computer-controlled players added from the menu play.
+    bot_difficulty: Difficulty,
+
+    /// How to draw the grid, once the game starts.
+    pub grid_style: GridStyle,
+
+    /// How much to thicken lines and widen hit zones, once the game starts.
+    pub ui_scale: f32,
+
+    /// A line of text to show above the buttons, like how joining is going,
+    /// or why it failed.
+    message: Option<String>,
+
+    /// The window's size, in pixels.
+    size: [f32; 2],
+
+    /// The current screen's items, laid out in the window.
+    buttons: Vec<Button<Item>>,
+
+    /// The item under the mouse, if any.
+    hover: Option<Item>,
+
+    /// The item the mouse button went down on, if it's still down.
+    pressed: Option<Item>,
+}
+
+impl Menu {
+    pub fn new(address: String, bots: &[Difficulty], grid_style: GridStyle, ui_scale: f32)
+               -> Menu
+    {
+        Menu {
+            screen: Screen::Main,
+            address,
+            bots: bots.to_vec(),
+            bot_difficulty: bots.first().cloned().unwrap_or_default(),
+            grid_style, ui_scale,
+            message: None,
+            size: [0.0, 0.0],
+            buttons: vec![],
+            hover: None,
+            pressed: None,
+        }
+    }
+
+    /// Return the items on the current screen, with their labels.
+    fn items(&self) -> Vec<(Item, String)> {
+        match self.screen {
+            Screen::Main => vec![
+                (Item::Host, "HOST GAME".to_string()),
+                (Item::Join, "JOIN GAME".to_string()),
+                (Item::Settings, "SETTINGS".to_string()),
+                (Item::Quit, "QUIT".to_string()),
+            ],
+            Screen::Join => vec![
+                (Item::Address, format!("ADDRESS: {}_", self.address)),
+                (Item::Connect, "CONNECT".to_string()),
+                (Item::Back, "BACK".to_string()),
+            ],
+            Screen::Settings => vec![
+                (Item::Bots, format!("COMPUTER PLAYERS: {}", self.bots.len())),
+                (Item::BotLevel, format!("COMPUTER LEVEL: {}", self.bot_level()).to_uppercase()),
+                (Item::Grid, format!("GRID: {:?}", self.grid_style).to_uppercase()),
+                (Item::UiScale, format!("UI SCALE: {}X", self.ui_scale)),
+                (Item::Back, "BACK".to_string()),
+            ],
+            Screen::Connecting => vec![
+                (Item::Cancel, "CANCEL".to_string()),
+            ],
+        }
+    }
+
+    /// Lay out the current screen's items again, after the window's size or
+    /// the items themselves have changed.
+    fn relayout(&mut self) {
+        self.buttons = ui::column(&self.items(), self.size[0], self.size[1]);
+    }
+
+    /// The window is now `width` by `height` pixels.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        if self.size != [width, height] {
+            self.size = [width, height];
+            self.relayout();
+        }
+    }
+
+    /// Return the current screen's items, laid out in the window.
+    pub fn buttons(&self) -> &[Button<Item>] {
+        &self.buttons
+    }
+
+    /// Return the line of text to show above the buttons, if any.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Show the connecting screen, with `message` saying how it's going.
+    pub fn connecting(&mut self, message: String) {
+        self.message = Some(message);
+        if self.screen != Screen::Connecting {
+            self.screen = Screen::Connecting;
+            self.relayout();
+        }
+    }
+
+    /// Joining a game has failed: return to the join screen, showing
+    /// `message`, so the player can correct the address or try again.
+    pub fn failed(&mut self, message: String) {
+        self.message = Some(message);
+        self.screen = Screen::Join;
+        self.relayout();
+    }
+
+    /// Return how to draw `item`.
+    pub fn button_state(&self, item: Item) -> ButtonState {
+        if self.hover != Some(item) {
+            ButtonState::Normal
+        } else if self.pressed == Some(item) {
+            ButtonState::Active
+        } else {
+            ButtonState::Hover
+        }
+    }
+
+    /// The mouse has moved to `point`, in window coordinates.
+    pub fn move_to(&mut self, point: [f32; 2]) {
+        self.hover = self.buttons.iter()
+            .find(|button| button.contains(point))
+            .map(|button| button.command);
+    }
+
+    /// The mouse button has gone down.
+    pub fn click(&mut self) {
+        self.pressed = self.hover;
+    }
+
+    /// The mouse button has come up. If it went down and came up on the same
+    /// item, choose it.
+    pub fn release(&mut self) -> Option<Outcome> {
+        match self.pressed.take() {
+            Some(item) if self.hover == Some(item) => self.choose(item),
+            _ => None,
+        }
+    }
+
+    /// The player has typed `ch`. Only the join screen takes typing.
+    pub fn type_char(&mut self, ch: char) {
+        if self.screen != Screen::Join {
+            return;
+        }
+        match ch {
+            '\u{8}' => { self.address.pop(); }
+            _ if ch.is_control() || ch.is_whitespace() => return,
+            _ if self.address.chars().count() >= MAX_ADDRESS => return,
+            _ => self.address.push(ch),
+        }
+        self.relayout();
+    }
+
+    /// The player has pressed Tab or an arrow key: move the highlight to the
+    /// next item, or the previous one if `backward`, so the menu works
+    /// without a mouse.
+    pub fn move_focus(&mut self, backward: bool) {
+        self.hover = ui::next_focus(&self.buttons, self.hover, backward);
+    }
+
+    /// The player has pressed Return: choose the highlighted item, or
+    /// connect, if we're on the join screen and the highlight is on the
+    /// address or nowhere.
+    pub fn enter(&mut self) -> Option<Outcome> {
+        match (self.hover, self.screen) {
+            (Some(item), _) if item != Item::Address => self.choose(item),
+            (_, Screen::Join) => self.choose(Item::Connect),
+            _ => None,
+        }
+    }
+
+    /// The player has pressed Escape: stop connecting, go back to the main
+    /// screen, or leave if we're already there.
+    pub fn back(&mut self) -> Option<Outcome> {
+        match self.screen {
+            Screen::Main => self.choose(Item::Quit),
+            Screen::Connecting => self.choose(Item::Cancel),
+            _ => self.choose(Item::Back),
+        }
+    }
+
+    fn choose(&mut self, item: Item) -> Option<Outcome> {
+        self.message = None;
+        let screen = self.screen;
+        let outcome = self.carry_out(item);
+        // The highlight belongs to the screen it was on.
+        if self.screen != screen {
+            self.hover = None;
+        }
+        outcome
+    }
+
+    /// Return how well the computer-controlled players play, for the menu to
+    /// show: the level new ones get, unless the command line gave some
+    /// others.
+    fn bot_level(&self) -> String {
+        if self.bots.iter().all(|&level| level == self.bot_difficulty) {
+            self.bot_difficulty.to_string()
+        } else {
+            "mixed".to_string()
+        }
+    }
+
+    fn carry_out(&mut self, item: Item) -> Option<Outcome> {
+        match item {
+            Item::Host => return Some(Outcome::Host),
+            Item::Quit => return Some(Outcome::Quit),
+            Item::Connect if !self.address.is_empty() => {
+                return Some(Outcome::Join(self.address.clone()));
+            }
+            Item::Join => self.screen = Screen::Join,
+            Item::Settings => self.screen = Screen::Settings,
+            Item::Back => self.screen = Screen::Main,
+            Item::Bots => {
+                if self.bots.len() < MAX_BOTS {
+                    self.bots.push(self.bot_difficulty);
+                } else {
+                    self.bots.clear();
+                }
+            }
+            Item::BotLevel => {
+                self.bot_difficulty = self.bot_difficulty.next();
+                self.bots = vec![self.bot_difficulty; self.bots.len()];
+            }
+            Item::Grid => self.grid_style = self.grid_style.next(),
+            Item::UiScale => self.ui_scale = next_ui_scale(self.ui_scale),
+            Item::Cancel => {
+                self.screen = Screen::Join;
+                self.relayout();
+                return Some(Outcome::Cancel);
+            }
+            Item::Address | Item::Connect => (),
+        }
+        self.relayout();
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use settings::UI_SCALES;
+
+    /// Click the button for `item`, which must be on the current screen.
+    fn click(menu: &mut Menu, item: Item) -> Option<Outcome> {
+        let point = menu.buttons().iter()
+            .find(|button| button.command == item)
+            .map(|button| button.top_left)
+            .expect("item should be on screen");
+        menu.move_to(point);
+        menu.click();
+        assert_eq!(menu.button_state(item), ButtonState::Active);
+        menu.release()
+    }
+
+    #[test]
+    fn join() {
+        let mut menu = Menu::new("localhost".to_string(), &[], GridStyle::Lines, 1.0);
+        menu.set_size(800.0, 600.0);
+
+        // Typing does nothing until we're on the join screen.
+        menu.type_char('x');
+        assert_eq!(click(&mut menu, Item::Join), None);
+        assert_eq!(menu.enter(), Some(Outcome::Join("localhost".to_string())));
+
+        for _ in 0.."localhost".len() {
+            menu.type_char('\u{8}');
+        }
+        assert_eq!(click(&mut menu, Item::Connect), None);
+        for ch in "10.0.0.2:999\r".chars() {
+            menu.type_char(ch);
+        }
+        assert!(menu.buttons()[0].label.contains("10.0.0.2:999_"));
+        assert_eq!(click(&mut menu, Item::Connect), Some(Outcome::Join("10.0.0.2:999".to_string())));
+
+        // While we connect, the only button cancels, and takes us back to
+        // the join screen.
+        menu.connecting("CONNECTING".to_string());
+        assert_eq!(menu.message(), Some("CONNECTING"));
+        assert_eq!(menu.buttons().len(), 1);
+        assert_eq!(click(&mut menu, Item::Cancel), Some(Outcome::Cancel));
+        assert_eq!(menu.message(), None);
+        menu.failed("NO SUCH HOST".to_string());
+        assert_eq!(menu.message(), Some("NO SUCH HOST"));
+        assert_eq!(menu.buttons()[1].command, Item::Connect);
+
+        // Escape backs out to the main screen, and then leaves.
+        assert_eq!(menu.back(), None);
+        assert_eq!(menu.back(), Some(Outcome::Quit));
+    }
+
+    #[test]
+    fn settings() {
+        let mut menu = Menu::new(String::new(), &[], GridStyle::Lines, 1.0);
+        menu.set_size(800.0, 600.0);
+        click(&mut menu, Item::Settings);
+        for _ in 0..MAX_BOTS + 2 {
+            click(&mut menu, Item::Bots);
+        }
+        assert_eq!(menu.bots, vec![Difficulty::Medium]);
+        click(&mut menu, Item::BotLevel);
+        click(&mut menu, Item::Bots);
+        assert_eq!(menu.bots, vec![Difficulty::Hard; 2]);
+        assert_eq!(menu.buttons()[1].label, "COMPUTER LEVEL: HARD");
+        click(&mut menu, Item::Grid);
+        assert_eq!(menu.grid_style, GridStyle::Dots);
+        assert_eq!(menu.buttons()[2].label, "GRID: DOTS");
+
+        // The UI scale steps up through the choices, then wraps around.
+        click(&mut menu, Item::UiScale);
+        assert_eq!(menu.buttons()[3].label, "UI SCALE: 1.5X");
+        for _ in 1..UI_SCALES.len() {
+            click(&mut menu, Item::UiScale);
+        }
+        assert_eq!(menu.ui_scale, 1.0);
+
+        // Bots from the command line keep their levels until changed.
+        let mut mixed = Menu::new(String::new(), &[Difficulty::Easy, Difficulty::Hard],
+                                  GridStyle::Lines, 1.0);
+        mixed.set_size(800.0, 600.0);
+        click(&mut mixed, Item::Settings);
+        assert_eq!(mixed.buttons()[1].label, "COMPUTER LEVEL: MIXED");
+        click(&mut mixed, Item::BotLevel);
+        assert_eq!(mixed.bots, vec![Difficulty::Medium; 2]);
+
+        click(&mut menu, Item::Back);
+        assert_eq!(click(&mut menu, Item::Host), Some(Outcome::Host));
+
+        // The keyboard works too.
+        menu.move_focus(true);
+        assert_eq!(menu.button_state(Item::Quit), ButtonState::Hover);
+        menu.move_focus(false);
+        menu.move_focus(false);
+        assert_eq!(menu.enter(), None);
+        assert_eq!(menu.buttons()[0].command, Item::Address);
+        menu.move_focus(true);
+        assert_eq!(menu.button_state(Item::Back), ButtonState::Hover);
+        assert_eq!(menu.enter(), None);
+        assert_eq!(menu.button_state(Item::Host), ButtonState::Normal);
+
+        // Releasing off the item that was pressed does nothing.
+        menu.move_to(menu.buttons()[0].top_left);
+        menu.click();
+        menu.move_to([0.0, 0.0]);
+        assert_eq!(menu.release(), None);
+    }
+}
@@ -1,10 +1,33 @@
 #version 150
 
+// How far along the outflow this pixel is, from 0 at the node the goop leaves
+// to 1 at the far end of the line.
+in float fragment_along;
+
+// How far this pixel is from the center line of the outflow, in pixels.
+in float across;
+
+// The time in seconds, to animate the dashes.
+uniform float time;
+
+// The width of the line, in pixels.
+uniform float line_width;
+
+// The colors of the line, and of the dashes along it.
+uniform vec4 line_color;
+uniform vec4 dash_color;
+
 // This is automatically assigned to be the color and transparency of the pixel
 // we're responsible for.
 out vec4 color;
 
 void main() {
-  // A basic black.
-  color = vec4(0.0, 0.0, 0.0, 1.0);
+  // Fade out over the last pixel at the edge of the line.
+  float coverage = clamp(line_width / 2.0 + 0.5 - abs(across), 0.0, 1.0);
+
+  // Dashes march away from the source node, so that the direction of flow is
+  // clear at a glance.
+  float dash = fract(fragment_along * 3.0 - time * 1.5);
+  vec4 base = dash < 0.35 ? dash_color : line_color;
+  color = vec4(base.rgb, base.a * coverage);
 }
@@ -0,0 +1,112 @@
+//! Local choices about how to color players.
+//!
+//! Each player's color is part of the `Map`, and so is the same for everyone in
+//! the game. But some players can't tell certain colors apart, or simply find
+//! the game easier to follow if, say, the enemy is always red. A `Palette`
+//! layers this host's preferences over the map's colors. It only affects
+//! drawing; the shared state never sees it.
+
+use map::Map;
+use state::Player;
+
+/// This host's color preferences, layered over the map's player colors.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    /// The player on this host.
+    local: Player,
+
+    /// Colors to use for specific players, by player number, overriding both
+    /// `enemy` and the map. This has an entry for every seat in the game.
+    overrides: Vec<Option<(u8, u8, u8)>>,
+
+    /// If set, the color to use for every player other than `local` that has
+    /// no specific override.
+    enemy: Option<(u8, u8, u8)>,
+}
+
+impl Palette {
+    /// Return a palette for `local`, in a game with `seats` seats, that uses
+    /// the map's colors unchanged.
+    pub fn new(local: Player, seats: usize) -> Palette {
+        Palette { local, overrides: vec![None; seats], enemy: None }
+    }
+
+    /// Draw `player` in `color`. Return false if the game has no such player.
+    pub fn set_color(&mut self, player: Player, color: (u8, u8, u8)) -> bool {
+        match self.overrides.get_mut(player.0) {
+            Some(slot) => {
+                *slot = Some(color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draw all other players in `color`, unless they have their own override.
+    pub fn set_enemy_color(&mut self, color: (u8, u8, u8)) {
+        self.enemy = Some(color);
+    }
+
+    /// Return the color in which to draw `player` on `map`.
+    pub fn color(&self, map: &Map, player: Player) -> (u8, u8, u8) {
+        if let Some(&Some(color)) = self.overrides.get(player.0) {
+            return color;
+        }
+        match self.enemy {
+            Some(color) if player != self.local => color,
+            _ => map.player_colors[player.0],
+        }
+    }
+}
+
+/// Parse a color written as six hex digits, `RRGGBB`, optionally preceded by
+/// a `#`.
+pub fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let text = text.trim_start_matches('#');
+    if text.len() != 6 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&text[i .. i + 2], 16).ok();
+    Some((component(0)?, component(2)?, component(4)?))
+}
+
+#[cfg(test)]
+mod test {
+    use map::{Map, MapParameters};
+    use state::Player;
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_color("ff8000"), Some((0xff, 0x80, 0x00)));
+        assert_eq!(parse_color("#0A0b0C"), Some((0x0a, 0x0b, 0x0c)));
+        assert_eq!(parse_color("fff"), None);
+        assert_eq!(parse_color("gg0000"), None);
+        assert_eq!(parse_color("+f0000"), None);
+    }
+
+    #[test]
+    fn layering() {
+        let map = Map::new(MapParameters {
+            size: (1, 3),
+            sources: vec![0, 1, 2],
+            player_colors: vec![(1, 1, 1), (2, 2, 2), (3, 3, 3)],
+        });
+
+        let mut palette = Palette::new(Player(0), 3);
+        assert_eq!(palette.color(&map, Player(1)), (2, 2, 2));
+
+        palette.set_enemy_color((0xff, 0, 0));
+        assert_eq!(palette.color(&map, Player(0)), (1, 1, 1));
+        assert_eq!(palette.color(&map, Player(1)), (0xff, 0, 0));
+        assert_eq!(palette.color(&map, Player(2)), (0xff, 0, 0));
+
+        assert!(palette.set_color(Player(2), (0, 0xff, 0)));
+        assert!(palette.set_color(Player(0), (0, 0, 0xff)));
+        assert!(!palette.set_color(Player(3), (0, 0, 0)));
+        assert!(!palette.set_color(Player(usize::MAX), (0, 0, 0)));
+        assert_eq!(palette.color(&map, Player(0)), (0, 0, 0xff));
+        assert_eq!(palette.color(&map, Player(1)), (0xff, 0, 0));
+        assert_eq!(palette.color(&map, Player(2)), (0, 0xff, 0));
+    }
+}
@@ -0,0 +1,15 @@
+#version 150
+
+in vec4 particle_color;
+
+// This is automatically assigned to be the color and transparency of the pixel
+// we're responsible for.
+out vec4 out_color;
+
+void main() {
+  // Points are drawn as squares; trim them to circles.
+  if (length(gl_PointCoord - vec2(0.5)) > 0.5)
+    discard;
+
+  out_color = particle_color;
+}
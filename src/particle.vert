@@ -0,0 +1,19 @@
+#version 150
+
+// The transformation from graph coordinates to normalized device coordinates,
+// as a homogeneous transform.
+uniform mat3 graph_to_device;
+
+// The particle's location in graph coordinates.
+in vec2 point;
+
+// The particle's color and transparency.
+in vec4 color;
+
+out vec4 particle_color;
+
+void main() {
+  vec3 device = graph_to_device * vec3(point, 1.0);
+  gl_Position = vec4(device.xy, 0.0, 1.0);
+  particle_color = color;
+}
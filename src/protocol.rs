@@ -7,14 +7,18 @@
 //! Every effect the player can have on the game is represented as a
 //! `state::Action` value. Given an initial state, and a record of which actions
 //! were applied by which players on which turns, you can exactly recreate the
-//! progression of the game. Since rbattle is such a simple game, there's only
-//! one kind of action: toggling an outflow.
+//! progression of the game. Since rbattle is such a simple game, players can
+//! only take one kind of action: toggling an outflow. The scheduler adds a
+//! `Join` action of its own whenever a player arrives, so that everyone sees
+//! the newcomer's source occupied on the same turn.
 //!
 //! In that light, the protocol focuses on gathering user actions, and
 //! distributing them out in a way that helps apply them consistently. The
-//! protocol has no means for communicating the game state. The only record of
-//! the game state transmitted at all is a hash value to detect divergence,
-//! which causes the game to end.
+//! protocol communicates the game state only when a host joins: the server
+//! keeps a copy of the state from every so often, and sends that along with
+//! the turns played since, which the newcomer replays to catch up. Otherwise,
+//! the only record of the game state transmitted at all is a hash value to
+//! detect divergence, which causes the game to end.
 //!
 //! For simplicity, we designate one host as the server; the protocol doesn't
 //! provide for any resilience if the server goes down. All other hosts have TCP
@@ -27,65 +31,95 @@
 //! list for every turn, even if it's empty.
 //!
 //! The server is responsible for coordinating timing. For a given turn duration
-//! T, the server broadcasts the list of gathered actions as soon as they are
-//! available, but no sooner then T after the last broadcast.
+//! T, the server plays a turn every T, broadcasting whatever actions have
+//! arrived since the last one. Actions that arrive late simply go into a later
+//! turn.
 //!
 //! Clients should apply received action lists as soon as they are received,
-//! advance their state, and send any collected actions immediately.
+//! advance their state, and send any collected actions immediately. A client
+//! that has fallen behind gets all the turns it missed from the server's
+//! history as soon as it asks, and applies them in one go to catch up.
 
-use map::MapParameters;
-use jsonproto::JsonProto;
-use scheduler::{CollectedActions, Notifier, PlayerActions, Scheduler};
-use state::{Action, Player, SerializableState, State};
+use bot::{Bot, Registry};
+use divergence::{self, REPORT_TURNS, Report, ServerReport};
+use events::EventStream;
+use history::{self, History};
+use map::{Map, MapHash, MapParameters};
+use mapcache::MapCache;
+use messages::{Request, Response};
+use jsonproto::{JsonProto, read_message};
+use relay::RelayListener;
+use replay::{Replay, ReplayWriter, Viewer};
+use save::{Autosave, SavedGame};
+use scheduler::{CollectedActions, DEFAULT_TURN_DURATION, GameStats, Notifier, PlayerActions};
+use scheduler::{self, CatchUp, RejoinToken, Scheduler, Snapshot, start_timer};
+use state::{Action, GameParameters, Player, SerializableState, State};
+use storage::{Format, Output};
+use summary::StatsLog;
 
-use futures::{Future};
-use futures::future::ok;
-use futures::sync::oneshot;
+use futures::{Future, Stream};
+use futures::future::{err, ok};
+use futures::sync::{mpsc as future_mpsc, oneshot};
 use serde_json;
-use tokio_proto::TcpServer;
+use tokio_core::net::TcpStream as CoreTcpStream;
+use tokio_core::reactor::Core;
+use tokio_proto::{BindServer, TcpServer};
 use tokio_service::Service;
 
-use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
-use std::mem::replace;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::mem::{replace, take};
 use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, mpsc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
+/// The server's end of a single client connection.
 struct SchedulerService {
-    scheduler: Arc<Mutex<Scheduler>>
-}
+    scheduler: Arc<Mutex<Scheduler>>,
 
-/// Requests the server receives from clients.
-#[derive(Debug, Serialize, Deserialize)]
-enum Request {
-    Join,
-    Actions(PlayerActions),
+    /// The player this connection is playing for, once they've joined.
+    player: Cell<Option<Player>>,
 }
 
-/// The server's responses to those requests.
-#[derive(Debug, Serialize, Deserialize)]
-enum Response {
-    Welcome { player: Player, state: SerializableState },
-    GameFull,
-    Turn(CollectedActions)
+impl SchedulerService {
+    fn new(scheduler: Arc<Mutex<Scheduler>>) -> SchedulerService {
+        SchedulerService { scheduler, player: Cell::new(None) }
+    }
 }
 
-/// This impl allows `Scheduler` to resolve promises returned by
-/// SchedulerService::call.
-impl Notifier for oneshot::Sender<Response> {
-    fn notify(self: Box<Self>, turn: CollectedActions) {
-        self.send(Response::Turn(turn))
-            .expect("oneshot notifier receiver died");
+/// Tokio drops the service when its connection closes, so this is where we
+/// learn that a player has gone away.
+impl Drop for SchedulerService {
+    fn drop(&mut self) {
+        if let Some(player) = self.player.get() {
+            self.scheduler.lock().unwrap().player_dropped(player);
+        }
     }
 }
 
-/// This impl allows `Scheduler` to send the actions collected for a turn to the
-/// local game.
-impl Notifier for mpsc::Sender<CollectedActions> {
+/// A promise returned by SchedulerService::call, which `Scheduler` resolves
+/// once the turn it's waiting for has been played. If the connection has
+/// closed in the mean time, there's no one to tell, so we ignore errors.
+struct Reply(oneshot::Sender<Response>);
+
+impl Notifier for Reply {
     fn notify(self: Box<Self>, turn: CollectedActions) {
-        self.send(turn)
-            .expect("mpsc notifier receiver died");
+        let _ = self.0.send(Response::Turn(turn));
+    }
+
+    fn catch_up(self: Box<Self>, turns: Vec<CollectedActions>) {
+        let _ = self.0.send(Response::Turns(turns));
+    }
+
+    fn game_over(self: Box<Self>, winner: Player, stats: GameStats) {
+        let _ = self.0.send(Response::Result { winner, stats });
+    }
+
+    fn paused(self: Box<Self>, player: Player, timeout: Duration) {
+        let _ = self.0.send(Response::Paused { player, timeout });
     }
 }
 
@@ -93,33 +127,140 @@ impl Service for SchedulerService {
     type Request = Request;
     type Response = Response;
     type Error = Error;
-    type Future = Box<Future<Item=Response, Error=Error>>;
+    type Future = Box<dyn Future<Item=Response, Error=Error>>;
 
     fn call(&self, req: Request) -> Self::Future {
         match req {
             Request::Join => {
                 let mut guard = self.scheduler.lock().unwrap();
                 match guard.player_join() {
-                    Some((player, state)) =>
-                        Box::new(ok(Response::Welcome { player, state })),
+                    Some((player, snapshot)) => {
+                        self.player.set(Some(player));
+                        let token = guard.rejoin_token(player).expect("seated players have tokens");
+                        Box::new(ok(welcome(player, token, snapshot, guard.turn_duration())))
+                    }
                     None =>
                         Box::new(ok(Response::GameFull))
                 }
             },
+            Request::Rejoin(player, token, turn, reference) => {
+                let mut guard = self.scheduler.lock().unwrap();
+                if !guard.player_rejoin(player, token) {
+                    return Box::new(ok(Response::GameFull));
+                }
+                self.player.set(Some(player));
+                let turn_duration = guard.turn_duration();
+                let response = match guard.catch_up(turn, reference) {
+                    Some(CatchUp::Turns(turns)) => Response::Rejoined { turns, turn_duration },
+                    Some(CatchUp::Delta(snapshot)) =>
+                        Response::Resynced { snapshot: Box::new(snapshot), turn_duration },
+                    None => welcome(player, token, guard.snapshot(), turn_duration),
+                };
+                Box::new(ok(response))
+            }
+            Request::Spectate => {
+                let guard = self.scheduler.lock().unwrap();
+                let snapshot = Box::new(guard.snapshot());
+                let turn_duration = guard.turn_duration();
+                Box::new(ok(Response::Snapshot { snapshot, turn_duration }))
+            }
+            Request::CatchUp(reference) => {
+                let guard = self.scheduler.lock().unwrap();
+                let turn_duration = guard.turn_duration();
+                let response = match guard.delta_snapshot(reference) {
+                    Some(snapshot) => Response::Delta { snapshot: Box::new(snapshot), turn_duration },
+                    None => Response::Snapshot { snapshot: Box::new(guard.snapshot()), turn_duration },
+                };
+                Box::new(ok(response))
+            }
             Request::Actions(actions) => {
+                // A connection may only act for the player it joined as. If it
+                // sends anything else, drop it.
+                if self.player.get() != Some(actions.player) {
+                    return Box::new(err(Error::new(ErrorKind::InvalidData,
+                                                   "actions for another player")));
+                }
                 let (sender, receiver) = oneshot::channel();
                 let mut guard = self.scheduler.lock().unwrap();
-                guard.submit_actions(actions, Box::new(sender));
+                if let Err(e) = guard.submit_actions(actions, Box::new(Reply(sender))) {
+                    return Box::new(err(Error::new(ErrorKind::InvalidData, e.to_string())));
+                }
 
                 // Turn oneshot errors into io::Error, as this service requires.
-                let receiver = receiver.map_err(|e| Error::new(ErrorKind::Other, e));
+                let receiver = receiver.map_err(Error::other);
 
                 Box::new(receiver)
             }
+            Request::Watch(turn) => {
+                let (sender, receiver) = oneshot::channel();
+                let mut guard = self.scheduler.lock().unwrap();
+                guard.watch(turn, Box::new(Reply(sender)));
+                Box::new(receiver.map_err(Error::other))
+            }
+            Request::AwaitRematch(turn) => {
+                let (sender, receiver) = oneshot::channel();
+                let mut guard = self.scheduler.lock().unwrap();
+                guard.await_rematch(turn, Box::new(Reply(sender)));
+                Box::new(receiver.map_err(Error::other))
+            }
+            Request::TurnRange(start, end) => {
+                let guard = self.scheduler.lock().unwrap();
+                let response = match guard.turn_range(start..end) {
+                    Ok(turns) => Response::Turns(turns),
+                    Err(available) =>
+                        Response::TurnRangeUnavailable(available.start, available.end),
+                };
+                Box::new(ok(response))
+            }
+            Request::Diverged(player, turn) => {
+                // As with actions, a connection may only speak for the player
+                // it joined as.
+                if self.player.get() != Some(player) {
+                    return Box::new(err(Error::new(ErrorKind::InvalidData,
+                                                   "divergence for another player")));
+                }
+                let mut guard = self.scheduler.lock().unwrap();
+                if guard.note_divergence(player, turn) {
+                    report_divergence(&guard, player, turn);
+                }
+                Box::new(ok(Response::Noted))
+            }
+            Request::Map(hash) => {
+                let guard = self.scheduler.lock().unwrap();
+                let map = guard.map();
+                let response = if map.hash() == hash {
+                    Response::Map(Box::new(map.clone()))
+                } else {
+                    Response::UnknownMap(hash)
+                };
+                Box::new(ok(response))
+            }
         }
     }
 }
 
+/// Return the `Welcome` for `player`, whose seat's rejoin token is `token`,
+/// and who is to start from `snapshot`. The snapshot goes without its map,
+/// which we send by name.
+fn welcome(player: Player, token: RejoinToken, mut snapshot: Snapshot, turn_duration: Duration)
+           -> Response {
+    let fingerprint = snapshot.state.fingerprint();
+    let map = snapshot.state.detach_map().expect("scheduler snapshots have maps").hash();
+    Response::Welcome { player, token, snapshot: Box::new(snapshot), turn_duration, fingerprint,
+                        map }
+}
+
+/// Write down the server's side of `player`'s state diverging on `turn`.
+fn report_divergence(scheduler: &Scheduler, player: Player, turn: usize) {
+    let report = ServerReport { player, turn, snapshot: scheduler.snapshot() };
+    let path = divergence::server_report_path(player, turn);
+    match divergence::write(&report, &path) {
+        Ok(()) => eprintln!("rbattle: player {} diverged on turn {}; wrote {}",
+                            player.0, turn, path.display()),
+        Err(error) => eprintln!("rbattle: couldn't report divergence: {}", error),
+    }
+}
+
 /// Information shared between the main thread and helper threads.
 struct Shared {
     /// The player this state represents. Assigned by the server.
@@ -129,36 +270,644 @@ struct Shared {
     state: State,
 
     /// The queue of actions to be sent to the scheduler on the next turn.
-    pending: Vec<Action>
+    pending: Vec<Action>,
+
+    /// Actions we've sent the scheduler, but haven't yet seen come back in a
+    /// turn.
+    submitted: Vec<Action>,
+
+    /// The outflow toggle we requested most recently, if it hasn't been
+    /// undone.
+    last_toggle: Option<Action>,
+
+    /// The time between turns, as last announced by the server.
+    turn_duration: Duration,
+
+    /// Once the game is over, the winner and the game's statistics.
+    result: Option<(Player, GameStats)>,
+
+    /// If the game is paused waiting for a player to reconnect, that player,
+    /// and when the server will give up on them.
+    pause: Option<(Player, Instant)>,
+
+    /// How many turns behind the server we were when we last heard from it.
+    behind: usize,
+
+    /// If we're recording the game, where to write each turn we apply.
+    recording: Option<ReplayWriter<Output>>,
+
+    /// If we're logging games' statistics, the log.
+    stats_log: Option<StatsLog>,
+
+    /// The latest turns we've applied, oldest first, to report if our state
+    /// diverges. This holds at most `REPORT_TURNS` turns.
+    recent: VecDeque<CollectedActions>,
+
+    /// If our state has diverged from the scheduler's, the turn on which it
+    /// did. We apply no more turns after that.
+    diverged: Option<usize>,
+
+    /// The states we were in before each of the latest turns we applied.
+    history: History,
+
+    /// Our state as of the latest checkpoint we've passed through, or the one
+    /// we started from. If we lose our connection and miss a lot of turns,
+    /// the server can bring this up to date with just what has changed.
+    reference: State,
+
+    /// If a bot is playing our seat, the bot. It acts on each turn as we
+    /// apply it, queueing its actions as a person's would be.
+    bot: Option<Box<dyn Bot>>,
+
+    /// True once we've stopped talking to the server for good.
+    disconnected: bool,
 }
 
 impl Shared {
-    fn apply_collected_actions(&mut self,
-                               collected_actions: CollectedActions)
-                               -> PlayerActions
-    {
-        assert_eq!(self.state.turn + 1, collected_actions.turn);
+    fn new(player: Player, state: State, turn_duration: Duration) -> Shared {
+        Shared {
+            player,
+            pending: vec![],
+            submitted: vec![],
+            last_toggle: None,
+            turn_duration,
+            result: None,
+            pause: None,
+            behind: 0,
+            recording: None,
+            stats_log: None,
+            recent: VecDeque::new(),
+            diverged: None,
+            history: History::new(history::DEFAULT_STATES),
+            reference: state.clone(),
+            state,
+            bot: None,
+            disconnected: false,
+        }
+    }
+
+    /// Jump to `state`, which we didn't reach by applying a turn.
+    fn set_state(&mut self, state: State) {
+        let previous = replace(&mut self.state, state);
+        if previous.turn + 1 == self.state.turn {
+            // Replays play turn by turn, too.
+            self.history.push(previous);
+        } else {
+            self.history.clear();
+        }
+    }
+
+    fn apply_collected_actions(&mut self, collected_actions: &CollectedActions) {
+        if self.diverged.is_some() {
+            return;
+        }
+
+        // If we were paused, this turn means we've resumed. If the game was
+        // over, it means a rematch has begun.
+        self.pause = None;
+        self.result = None;
+
+        if let Some(turn_duration) = collected_actions.turn_duration {
+            self.turn_duration = turn_duration;
+        }
+
+        self.history.push(self.state.clone());
+        let matched = collected_actions.try_apply_to(&mut self.state);
+        if self.recent.len() >= REPORT_TURNS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(collected_actions.clone());
+        if !matched {
+            self.report_divergence(collected_actions.state_checksum);
+            return;
+        }
+
+        if let Some(ref mut recording) = self.recording {
+            if let Err(error) = recording.record(collected_actions) {
+                eprintln!("rbattle: game recording stopped: {}", error);
+                self.recording = None;
+            }
+        }
 
-        for action in collected_actions.actions {
-            self.state.take_action(&action);
+        if let Some(ref mut stats_log) = self.stats_log {
+            stats_log.record(&self.state);
         }
-        self.state.advance();
 
-        // We should have applied the same actions to the same state,
-        // and gotten the same checksum.
-        assert_eq!(self.state.checksum(),
-                   collected_actions.state_checksum,
-                   "Game state checksums have diverged!");
+        if scheduler::is_checkpoint(self.state.turn) {
+            self.reference = self.state.clone();
+        }
+    }
+
+    /// Note that `winner` has won the game, and log it if we're keeping a
+    /// log.
+    fn game_over(&mut self, winner: Player, stats: GameStats) {
+        if let Some(ref mut stats_log) = self.stats_log {
+            if let Err(error) = stats_log.finish(self.player, &self.state, winner, &stats) {
+                eprintln!("rbattle: couldn't log game: {}", error);
+            }
+        }
+        self.result = Some((winner, stats));
+    }
 
-        // Now that we've applied the actions from the prior turn, return
+    /// Our state has diverged from the scheduler's on the turn we just
+    /// applied: we should have reached a state with `expected_checksum`.
+    /// Write down what we know, and stop.
+    fn report_divergence(&mut self, expected_checksum: u64) {
+        let turn = self.state.turn;
+        let before = self.history.latest().expect("no state before the turn just applied");
+        let report = Report {
+            turn,
+            expected_checksum,
+            actual_checksum: self.state.checksum(),
+            before: before.serializable(),
+            after: self.state.serializable(),
+            recent: self.recent.iter().collect(),
+        };
+        let path = divergence::report_path(turn);
+        match divergence::write(&report, &path) {
+            Ok(()) => eprintln!("rbattle: game state diverged from the server's on turn {}; \
+                                 wrote {}", turn, path.display()),
+            Err(error) => eprintln!("rbattle: game state diverged from the server's on turn {}, \
+                                     and couldn't write a report: {}", turn, error),
+        }
+        self.diverged = Some(turn);
+    }
+
+    /// Apply the turns the server sent us in response to our last submission,
+    /// all at once, and return our actions for the next turn. If there's more
+    /// than one, we had fallen behind; since we hold the lock throughout, the
+    /// main thread never draws the turns in between.
+    fn catch_up(&mut self, turns: &[CollectedActions]) -> PlayerActions {
+        for turn in turns {
+            self.apply_collected_actions(turn);
+        }
+        if !turns.is_empty() {
+            // Our last submission was part of these turns.
+            self.submitted.clear();
+
+            // If a bot is playing for us, let it act on where the game stands
+            // now. Acting on each turn we skipped over would only queue up
+            // moves that no longer make sense.
+            if let (Some(bot), None) = (self.bot.as_mut(), self.diverged) {
+                self.pending.extend(bot.act(&self.state, self.player));
+            }
+        }
+        self.behind = turns.len().saturating_sub(1);
+
+        // Now that we've applied the actions from the prior turns, return
         // whatever actions have been queued up in the mean time as our next
         // turn.
+        self.next_actions()
+    }
+
+    /// Note that the game has paused for up to `timeout` to let `player`
+    /// reconnect, and return the actions to submit while we wait.
+    fn pause(&mut self, player: Player, timeout: Duration) -> PlayerActions {
+        self.pause = Some((player, Instant::now() + timeout));
+        self.next_actions()
+    }
+
+    /// Return the actions queued up for the current turn.
+    fn next_actions(&mut self) -> PlayerActions {
+        self.submitted.extend(self.pending.iter().cloned());
         PlayerActions {
             player: self.player,
             turn: self.state.turn,
-            actions: replace(&mut self.pending, vec![])
+            actions: take(&mut self.pending)
+        }
+    }
+}
+
+/// How a server should make itself available to clients.
+pub enum Rendezvous {
+    /// Listen for connections on the given address.
+    Listen(SocketAddr),
+
+    /// Register with the relay at the given address, and accept connections
+    /// forwarded from there. See the `relay` module for details.
+    Relay(SocketAddr),
+}
+
+/// Optional features of a hosted game.
+pub struct ServerOptions {
+    /// If set, publish a stream of turn events on this address. See the
+    /// `events` module.
+    pub events: Option<SocketAddr>,
+
+    /// The time between turns. Clients are told this
+    /// when they join.
+    pub turn_duration: Duration,
+
+    /// If true, slow the game down when players can't keep up, and speed it
+    /// back up when they can, but never faster than `turn_duration`.
+    pub adaptive: bool,
+
+    /// If set, print a summary of the scheduler's timing to stderr this often.
+    pub timing_log: Option<Duration>,
+
+    /// If set, pause the game for up to this long when a player loses their
+    /// connection, to give them a chance to reconnect.
+    pub pause_on_drop: Option<Duration>,
+
+    /// The seats to fill with computer-controlled players, and the kind of
+    /// bot to put in each, by the name `registry` gives it.
+    pub bots: Vec<String>,
+
+    /// The kinds of bot this server can seat, here or in a resumed game.
+    pub registry: Registry,
+
+    /// If set, the kind of bot to seat in every seat still empty when the
+    /// game proper begins. See `Scheduler::set_fill_seats`.
+    pub fill_seats: Option<String>,
+
+    /// If set, pick up this saved game where it left off, rather than
+    /// starting a new one. The map and turn duration come from the save.
+    pub resume: Option<SavedGame>,
+
+    /// If set, start a new game on this position's map, from this position,
+    /// rather than from the usual one. See `GameParameters::start`.
+    pub puzzle: Option<SerializableState>,
+
+    /// If set, save the game every so often, as this says.
+    pub autosave: Option<Autosave>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> ServerOptions {
+        ServerOptions {
+            events: None,
+            turn_duration: DEFAULT_TURN_DURATION,
+            adaptive: false,
+            timing_log: None,
+            pause_on_drop: None,
+            bots: vec![],
+            registry: Registry::default(),
+            fill_seats: None,
+            resume: None,
+            puzzle: None,
+            autosave: None,
+        }
+    }
+}
+
+/// Serve the game to clients arriving via the relay at `relay_addr`.
+///
+/// The relay hands us ordinary blocking `TcpStream`s, so we accept them on a
+/// thread of their own, and pass them to a Tokio event loop on this thread to
+/// be served just like connections to a listening socket.
+fn serve_via_relay(relay_addr: SocketAddr, scheduler: Arc<Mutex<Scheduler>>) {
+    let mut listener = RelayListener::connect(relay_addr)
+        .expect("failed to register with relay");
+
+    let (sender, receiver) = future_mpsc::unbounded();
+    thread::spawn(move || {
+        loop {
+            // A client that goes away before we reach it shouldn't keep anyone
+            // else out. Only once the relay itself is gone is there nothing
+            // more to accept.
+            let stream = match listener.accept() {
+                Ok(stream) => stream,
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    eprintln!("rbattle: lost connection to relay");
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("rbattle: couldn't accept connection through relay: {}", e);
+                    continue;
+                }
+            };
+            if sender.unbounded_send(stream).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut core = Core::new().expect("failed to create event loop");
+    let handle = core.handle();
+    let proto = JsonProto::<Request, Response>::new();
+    let connections = receiver.for_each(|stream| {
+        let io = CoreTcpStream::from_stream(stream, &handle)
+            .expect("failed to register relayed connection");
+        proto.bind_server(&handle, io, SchedulerService::new(scheduler.clone()));
+        Ok(())
+    });
+    core.run(connections).expect("relay connection stream failed");
+}
+
+/// How many times a client tries to reconnect after losing its connection.
+const RECONNECT_ATTEMPTS: usize = 30;
+
+/// How long a client waits between attempts to reconnect.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Open a connection to the server at `addr`.
+fn connect(addr: SocketAddr) -> Result<TcpStream, Error> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// What the server tells a client that has just taken a seat.
+struct Welcome {
+    player: Player,
+    token: RejoinToken,
+    snapshot: Box<Snapshot>,
+    turn_duration: Duration,
+}
+
+/// Ask the server on `stream` for a seat in the game, and return its welcome.
+fn join(stream: &TcpStream) -> Result<Welcome, Error> {
+    match handshake(stream, &Request::Join)? {
+        Response::Welcome { player, token, mut snapshot, turn_duration, fingerprint, map } => {
+            attach_map(stream, &mut snapshot, map, None)?;
+
+            // A server playing by other rules, or on a board we don't read
+            // the way it meant, would have us fail its checksums on the first
+            // turn; better to say so now.
+            snapshot.state.version().check("the server's game")
+                .and_then(|()| snapshot.state.check_fingerprint(fingerprint, "the server's game"))
+                .map_err(|error| Error::other(error.to_string()))?;
+            Ok(Welcome { player, token, snapshot, turn_duration })
+        }
+        otherwise => Err(Error::other(format!("Received unexpected {:?} on Join", otherwise))),
+    }
+}
+
+/// Put back the map the server left out of `snapshot`, which it named `hash`.
+/// If `current`, the map we're playing on already, isn't it, look in our cache
+/// of maps; failing that, ask the server on `stream` for it, and keep it.
+fn attach_map(stream: &TcpStream, snapshot: &mut Snapshot, hash: MapHash,
+              current: Option<&Map>) -> Result<(), Error> {
+    if let Some(map) = current.filter(|map| map.hash() == hash) {
+        snapshot.state.attach_map(map.clone());
+        return Ok(());
+    }
+
+    let cache = MapCache::default_dir().map(MapCache::new);
+    if let Some(map) = cache.as_ref().and_then(|cache| cache.get(hash)) {
+        snapshot.state.attach_map(map);
+        return Ok(());
+    }
+
+    let map = match handshake(stream, &Request::Map(hash))? {
+        Response::Map(map) if map.hash() == hash => *map,
+        Response::Map(_) => {
+            return Err(Error::other(format!("Server sent the wrong map for {}", hash)));
+        }
+        otherwise => {
+            return Err(Error::other(format!("Server couldn't send map {}: {:?}",
+                                            hash, otherwise)));
+        }
+    };
+    map.check(&format!("the server's map {}", hash))
+        .map_err(|error| Error::other(error.to_string()))?;
+    if let Some(cache) = cache {
+        if let Err(error) = cache.put(&map) {
+            eprintln!("rbattle: couldn't keep map {}: {}", hash, error);
         }
     }
+    snapshot.state.attach_map(map);
+    Ok(())
+}
+
+/// How far along a `PendingClient` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientStage {
+    /// Opening a connection to the server.
+    Connecting,
+
+    /// Connected, and waiting for the server to give us a seat. Behind a
+    /// relay, this lasts until the host arrives.
+    Waiting,
+}
+
+/// What a `PendingClient`'s thread reports back.
+enum ClientProgress {
+    Connected,
+    Joined(Result<(TcpStream, Welcome), Error>),
+}
+
+/// A client joining a game in the background.
+///
+/// Connecting to the server and waiting for a seat can take a while, so
+/// rather than block the caller, this does both on a thread of its own. The
+/// caller polls it from its event loop, and can give up at any point by
+/// dropping it: if the server seats us afterwards, the connection simply
+/// closes, and the seat is freed for someone else.
+pub struct PendingClient {
+    addr: SocketAddr,
+    stage: ClientStage,
+    progress: mpsc::Receiver<ClientProgress>,
+}
+
+impl PendingClient {
+    /// Start joining the game at `addr`.
+    pub fn start(addr: SocketAddr) -> PendingClient {
+        let (sender, progress) = mpsc::channel();
+        thread::spawn(move || {
+            let joined = connect(addr).and_then(|stream| {
+                // If the caller has given up, don't bother asking for a seat.
+                sender.send(ClientProgress::Connected)
+                    .map_err(|_| Error::other("join cancelled"))?;
+                let welcome = join(&stream)?;
+                Ok((stream, welcome))
+            });
+            let _ = sender.send(ClientProgress::Joined(joined));
+        });
+        PendingClient { addr, stage: ClientStage::Connecting, progress }
+    }
+
+    /// Return how far along we are.
+    pub fn stage(&self) -> ClientStage {
+        self.stage
+    }
+
+    /// Check on our progress. If we've been seated, return the `Participant`
+    /// for playing the game; if we've failed, return the error. Otherwise,
+    /// return `None`.
+    pub fn poll(&mut self) -> Option<Result<Participant, Error>> {
+        loop {
+            match self.progress.try_recv() {
+                Ok(ClientProgress::Connected) => self.stage = ClientStage::Waiting,
+                Ok(ClientProgress::Joined(joined)) => {
+                    return Some(joined.map(|(stream, welcome)| {
+                        Participant::start_client(self.addr, stream, welcome)
+                    }));
+                }
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Some(Err(Error::other("connection thread exited")));
+                }
+            }
+        }
+    }
+}
+
+/// Send `request` to the server on `writer`.
+fn send(writer: &mut BufWriter<&TcpStream>, request: &Request) -> Result<(), Error> {
+    writeln!(writer, "{}", serde_json::to_string(request)?)?;
+    writer.flush()
+}
+
+/// Ask for a seat in the game with `request`, which should be `Join` or
+/// `Rejoin`, and return the server's `Welcome`, `Rejoined` or `Resynced`
+/// response. This
+/// also asks for a map with `Request::Map`, returning its `Map` or
+/// `UnknownMap` response.
+fn handshake(stream: &TcpStream, request: &Request) -> Result<Response, Error> {
+    send(&mut BufWriter::new(stream), request)?;
+    let response = read_message(&mut BufReader::new(stream))?
+        .ok_or_else(|| Error::other(format!("Server closed connection during {:?}",
+                                            request)))?;
+    match response {
+        Response::GameFull => {
+            Err(Error::other("Connection rejected, game full."))
+        }
+        welcome @ Response::Welcome { .. } | welcome @ Response::Rejoined { .. } |
+        welcome @ Response::Resynced { .. } => Ok(welcome),
+        map @ Response::Map(_) | map @ Response::UnknownMap(_) => Ok(map),
+        otherwise => {
+            Err(Error::other(format!("Received unexpected {:?} on {:?}",
+                                     otherwise, request)))
+        }
+    }
+}
+
+/// Play the game over `stream`, and any rematches, until the connection fails.
+fn play(stream: &TcpStream, shared: &Mutex<Shared>) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream);
+    let mut writer = BufWriter::new(stream);
+
+    // Get the ball rolling by submitting our actions for the current turn.
+    let actions = shared.lock().unwrap().next_actions();
+    send(&mut writer, &Request::Actions(actions))?;
+
+    loop {
+        let response = read_message(&mut reader)?
+            .ok_or_else(|| Error::other("Server closed connection."))?;
+
+        let mut guard = shared.lock().unwrap();
+        let request = match response {
+            Response::Turn(collected_actions) =>
+                Request::Actions(guard.catch_up(&[collected_actions])),
+            Response::Turns(turns) => Request::Actions(guard.catch_up(&turns)),
+            Response::Paused { player, timeout } =>
+                Request::Actions(guard.pause(player, timeout)),
+            Response::Result { winner, stats } => {
+                // Stay connected, in case the host wants a rematch.
+                guard.game_over(winner, stats);
+                Request::AwaitRematch(guard.state.turn)
+            }
+            otherwise => {
+                return Err(Error::other(format!("Unexpected response from server: {:?}",
+                                                otherwise)));
+            }
+        };
+
+        // If our state has diverged, we can't play on. Tell the server, so
+        // it can write down its side of things, and stop.
+        if let Some(turn) = guard.diverged {
+            let player = guard.player;
+            drop(guard);
+            send(&mut writer, &Request::Diverged(player, turn))?;
+            read_message::<Response, _>(&mut reader)?;
+            return Ok(());
+        }
+
+        // Drop the guard on the shared data first, to avoid having to
+        // think about lock ordering.
+        drop(guard);
+
+        // Submit any requested next actions for the next turn.
+        send(&mut writer, &request)?;
+    }
+}
+
+/// Show the state `viewer` has reached in `shared`, for the main thread to
+/// draw.
+fn show(viewer: &Viewer, shared: &Mutex<Shared>) {
+    let mut guard = shared.lock().unwrap();
+    guard.set_state(viewer.state().clone());
+    guard.turn_duration = viewer.turn_duration();
+}
+
+/// Try to take our seat in the game at `addr` again, giving `token`, after
+/// losing our connection. On success, bring `shared` up to date and return the
+/// new connection. Any actions the player queued up while we were disconnected
+/// are submitted once play resumes.
+fn rejoin(addr: SocketAddr, token: RejoinToken, shared: &Mutex<Shared>) -> Option<TcpStream> {
+    let player = shared.lock().unwrap().player;
+    for _ in 0..RECONNECT_ATTEMPTS {
+        thread::sleep(RECONNECT_INTERVAL);
+        let stream = match connect(addr) {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let (turn, reference, current) = {
+            let guard = shared.lock().unwrap();
+            (guard.state.turn, guard.reference.turn, guard.state.map.clone())
+        };
+        let request = Request::Rejoin(player, token, turn, reference);
+        let mut response = match handshake(&stream, &request) {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Unable to rejoin: {}", e);
+                continue;
+            }
+        };
+
+        if let Response::Welcome { ref mut snapshot, map, .. } = response {
+            if let Err(error) = attach_map(&stream, snapshot, map, Some(&current)) {
+                eprintln!("Unable to rejoin: {}", error);
+                continue;
+            }
+        }
+
+        let mut guard = shared.lock().unwrap();
+        match response {
+            Response::Rejoined { turns, turn_duration } => {
+                for turn in &turns {
+                    guard.apply_collected_actions(turn);
+                }
+                guard.turn_duration = turn_duration;
+            }
+            Response::Welcome { snapshot, turn_duration, fingerprint, .. } => {
+                if let Err(error) = snapshot.state.check_fingerprint(fingerprint,
+                                                                     "the server's game") {
+                    eprintln!("Unable to rejoin: {}", error);
+                    return None;
+                }
+                guard.reference = State::from_serializable(snapshot.state.clone());
+                guard.set_state(snapshot.into_state());
+                guard.turn_duration = turn_duration;
+                if guard.recording.take().is_some() {
+                    eprintln!("rbattle: game recording stopped: missed turns while disconnected");
+                }
+            }
+            Response::Resynced { snapshot, turn_duration } => {
+                let checkpoint = match snapshot.checkpoint(&guard.reference) {
+                    Ok(checkpoint) => checkpoint,
+                    Err(error) => {
+                        eprintln!("Unable to rejoin: {}", error);
+                        return None;
+                    }
+                };
+                if guard.recording.take().is_some() {
+                    eprintln!("rbattle: game recording stopped: missed turns while disconnected");
+                }
+                guard.reference = checkpoint.clone();
+                guard.set_state(checkpoint);
+                for turn in &snapshot.turns {
+                    guard.apply_collected_actions(turn);
+                }
+                guard.turn_duration = turn_duration;
+            }
+            _ => unreachable!("handshake returned unexpected response"),
+        }
+        guard.pause = None;
+        return Some(stream);
+    }
+    None
 }
 
 pub struct Participant {
@@ -168,26 +917,66 @@ pub struct Participant {
     /// Information shared between the main thread, the server thread, and the
     /// scheduler thread.
     shared: Arc<Mutex<Shared>>,
+
+    /// If we're hosting the game, the scheduler coordinating it.
+    scheduler: Option<Arc<Mutex<Scheduler>>>,
+
+    /// If we're watching a recorded game rather than playing, what's
+    /// playing it back.
+    viewer: Option<Arc<Mutex<Viewer>>>,
 }
 
 impl Participant {
-    pub fn new_server(addr: SocketAddr, params: MapParameters) -> Participant {
-        assert!(params.player_colors.len() >= 1);
+    pub fn new_server(rendezvous: Rendezvous,
+                      map_params: MapParameters,
+                      params: GameParameters,
+                      options: ServerOptions)
+                      -> Result<Participant, Error>
+    {
+        assert!(!map_params.player_colors.is_empty());
 
         // Create a scheduler to coordinate turns amongst the players,
         // and add ourselves as the first player.
-        let mut scheduler = Scheduler::new(State::new(params));
-        let (player, current_state) = scheduler.player_join().unwrap();
+        let state = match options.puzzle {
+            Some(position) => State::puzzle(position, params),
+            None => State::new(map_params, params),
+        };
+        let registry = Arc::new(options.registry);
+        let mut scheduler = match options.resume {
+            Some(saved) => Scheduler::resume(saved, &registry),
+            None => Scheduler::new(state, options.turn_duration),
+        };
+        scheduler.set_adaptive(options.adaptive);
+        scheduler.set_pause_on_drop(options.pause_on_drop);
+        if let Some(autosave) = options.autosave {
+            scheduler.set_autosave(autosave);
+        }
+        let (player, snapshot) = scheduler.player_join().unwrap();
+        for kind in options.bots.iter().chain(&options.fill_seats) {
+            if !registry.contains(kind) {
+                return Err(Error::other(format!("no such kind of bot: {}", kind)));
+            }
+        }
+        for kind in &options.bots {
+            if scheduler.add_bot(&registry, kind).is_none() {
+                return Err(Error::other("not enough seats for that many bots"));
+            }
+        }
+        if let Some(ref kind) = options.fill_seats {
+            scheduler.set_fill_seats(registry.clone(), kind);
+        }
+
+        if let Some(addr) = options.events {
+            scheduler.add_observer(Box::new(EventStream::listen(addr)?));
+        }
 
         let scheduler = Arc::new(Mutex::new(scheduler));
 
-        let shared = Arc::new(Mutex::new(Shared {
-            player,
-            state: State::from_serializable(current_state),
-            pending: vec![]
-        }));
+        let turn_duration = scheduler.lock().unwrap().turn_duration();
+        let shared = Arc::new(Mutex::new(Shared::new(player, snapshot.into_state(),
+                                                     turn_duration)));
 
-        let (sender, receiver): (mpsc::Sender<CollectedActions>, _) = mpsc::channel();
+        let (sender, receiver): (mpsc::Sender<Response>, _) = mpsc::channel();
 
         // Create a thread to apply actions received from the scheduler.
         // These variables get moved into the closure.
@@ -195,17 +984,37 @@ impl Participant {
         let scheduler_handle = scheduler.clone();
         let sender_handle = sender.clone();
         thread::spawn(move || {
-            for collected_actions in receiver {
+            for response in receiver {
                 let mut guard = shared_handle.lock().unwrap();
-                let next_actions = guard.apply_collected_actions(collected_actions);
+                let next_actions = match response {
+                    Response::Turn(collected_actions) => guard.catch_up(&[collected_actions]),
+                    Response::Turns(turns) => guard.catch_up(&turns),
+                    Response::Paused { player, timeout } => guard.pause(player, timeout),
+                    Response::Result { winner, stats } => {
+                        guard.game_over(winner, stats);
+                        let turn = guard.state.turn;
+                        drop(guard);
+                        let mut guard = scheduler_handle.lock().unwrap();
+                        guard.await_rematch(turn, Box::new(sender_handle.clone()));
+                        continue;
+                    }
+                    otherwise => panic!("Unexpected notification: {:?}", otherwise),
+                };
+                let diverged = guard.diverged;
 
                 // Drop the guard on the shared data first, to avoid having to
                 // think about lock ordering.
                 drop(guard);
 
+                if let Some(turn) = diverged {
+                    report_divergence(&scheduler_handle.lock().unwrap(), player, turn);
+                    return;
+                }
+
                 // Submit any requested next actions for the next turn.
                 let mut guard = scheduler_handle.lock().unwrap();
-                guard.submit_actions(next_actions, Box::new(sender_handle.clone()));
+                guard.submit_actions(next_actions, Box::new(sender_handle.clone()))
+                    .expect("host submitted invalid actions");
             }
         });
 
@@ -213,10 +1022,17 @@ impl Participant {
         // This variable gets moved into the closure.
         let scheduler_handle = scheduler.clone();
         thread::spawn(move || {
-            let server = TcpServer::new(JsonProto::<Request, Response>::new(), addr);
-            server.serve(move || {
-                Ok(SchedulerService { scheduler: scheduler_handle.clone() })
-            });
+            match rendezvous {
+                Rendezvous::Listen(addr) => {
+                    let server = TcpServer::new(JsonProto::<Request, Response>::new(), addr);
+                    server.serve(move || {
+                        Ok(SchedulerService::new(scheduler_handle.clone()))
+                    });
+                }
+                Rendezvous::Relay(relay_addr) => {
+                    serve_via_relay(relay_addr, scheduler_handle);
+                }
+            }
         });
 
         // Get the ball rolling by submitting an empty first move.
@@ -224,110 +1040,106 @@ impl Participant {
             let mut guard = scheduler.lock().unwrap();
             let actions = PlayerActions {
                 player,
-                turn: 0,
+                turn: shared.lock().unwrap().state.turn,
                 actions: vec![]
             };
-            guard.submit_actions(actions, Box::new(sender));
+            guard.submit_actions(actions, Box::new(sender))
+                .expect("host submitted invalid actions");
         }
 
-        Participant { player, shared }
-    }
-
-    pub fn new_client(addr: SocketAddr) -> Result<Participant, Error> {
-        let stream = TcpStream::connect(addr)?;
-        stream.set_nodelay(true)?;
+        start_timer(scheduler.clone());
 
-        let (sender, receiver) = mpsc::channel();
-
-        fn setup(reader: &mut BufReader<&TcpStream>, writer: &mut BufWriter<&TcpStream>)
-                 -> Result<Shared, Error>
-        {
-            writeln!(writer, "{}", serde_json::to_string(&Request::Join)?)?;
-            writer.flush()?;
-            let mut response = String::new();
-            reader.read_line(&mut response)?;
-            let response = serde_json::from_str(&response)?;
-            let (player, state) = match response {
-                Response::GameFull => {
-                    return Err(Error::new(ErrorKind::Other,
-                                          "Connection rejected, game full."));
-                }
-                Response::Welcome { player, state } => (player, state),
-                Response::Turn(_) => {
-                    return Err(Error::new(ErrorKind::Other,
-                                          "Received unexpected Response::Turn on Join"));
+        if let Some(period) = options.timing_log {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || {
+                loop {
+                    thread::sleep(period);
+                    let summary = scheduler.lock().unwrap().timing().summary();
+                    eprintln!("scheduler timing, {}", summary);
                 }
-            };
-
-            let shared = Shared {
-                player,
-                state: State::from_serializable(state),
-                pending: vec![]
-            };
+            });
+        }
 
-            // Get the ball rolling by submitting an empty first move.
-            let actions = PlayerActions {
-                player,
-                turn: shared.state.turn,
-                actions: vec![]
-            };
-            writeln!(writer, "{}",
-                     serde_json::to_string(&Request::Actions(actions))?)?;
-            writer.flush()?;
+        Ok(Participant { player, shared, scheduler: Some(scheduler), viewer: None })
+    }
 
-            Ok(shared)
-        }
+    /// Start playing as a client of the server at `addr`, given the `stream`
+    /// connected to it and the `Welcome` it sent us.
+    fn start_client(addr: SocketAddr, stream: TcpStream, welcome: Welcome) -> Participant {
+        let Welcome { player, token, snapshot, turn_duration } = welcome;
+        let reference = State::from_serializable(snapshot.state.clone());
+        let mut shared = Shared::new(player, snapshot.into_state(), turn_duration);
+        shared.reference = reference;
+        let shared = Arc::new(Mutex::new(shared));
 
         // Spawn a thread to read collected actions, apply them to our state,
-        // and submit any accumulated actions requested.
+        // and submit any accumulated actions requested. If we lose our
+        // connection, try to get it back.
+        let shared_handle = shared.clone();
         thread::spawn(move || {
-            let stream = stream; // take ownership
-            let mut reader = BufReader::new(&stream);
-            let mut writer = BufWriter::new(&stream);
-
-            let shared = match setup(&mut reader, &mut writer) {
-                Err(e) => {
-                    sender.send(Err(e)).unwrap();
-                    return;
+            let mut stream = stream;
+            loop {
+                match play(&stream, &shared_handle) {
+                    Ok(()) => break,
+                    // Once the game is over, the host may well have quit.
+                    Err(_) if shared_handle.lock().unwrap().result.is_some() => break,
+                    Err(e) => eprintln!("Lost connection to server: {}", e),
                 }
-                Ok(shared) => shared
-            };
-
-            let player = shared.player;
-            let shared = Arc::new(Mutex::new(shared));
-            sender.send(Ok((player, shared.clone()))).unwrap();
-            drop(sender);
-
-            for line in reader.lines() {
-                let line = line.expect("error reading response from server");
-                let response: Response = serde_json::from_str(&line)
-                    .expect("error parsing response from server");
-                let collected_actions = match response {
-                    Response::Turn(collected_actions) => collected_actions,
-                    otherwise => {
-                        panic!("Unexpected response from server: {:?}", otherwise);
+                match rejoin(addr, token, &shared_handle) {
+                    Some(new_stream) => stream = new_stream,
+                    None => {
+                        eprintln!("Unable to reconnect to server.");
+                        break;
                     }
-                };
+                }
+            }
+            shared_handle.lock().unwrap().disconnected = true;
+        });
 
-                let mut guard = shared.lock().unwrap();
-                let next_actions = guard.apply_collected_actions(collected_actions);
+        Participant { player, shared, scheduler: None, viewer: None }
+    }
 
-                // Drop the guard on the shared data first, to avoid having to
-                // think about lock ordering.
-                drop(guard);
+    /// Show the recorded game `replay`, playing its turns at the pace they
+    /// were played originally. We watch from `Player(0)`'s seat, but any
+    /// actions requested are ignored.
+    pub fn new_replay(replay: Replay) -> Participant {
+        let player = Player(0);
+        let viewer = Viewer::new(replay);
+        let shared = Arc::new(Mutex::new(Shared::new(player, viewer.state().clone(),
+                                                     viewer.turn_duration())));
+        let viewer = Arc::new(Mutex::new(viewer));
 
-                // Submit any requested next actions for the next turn.
-                let actions = serde_json::to_string(&Request::Actions(next_actions))
-                    .expect("failed to jsonify next actions");
-                writeln!(writer, "{}", actions)
-                    .expect("Sending next actions to server");
-                writer.flush().unwrap();
+        let shared_handle = shared.clone();
+        let viewer_handle = viewer.clone();
+        thread::spawn(move || {
+            loop {
+                let turn_duration = viewer_handle.lock().unwrap().turn_duration();
+                thread::sleep(turn_duration);
+                let mut viewer = viewer_handle.lock().unwrap();
+                if viewer.play() {
+                    show(&viewer, &shared_handle);
+                }
             }
         });
 
-        let (player, shared) = receiver.recv().unwrap()?;
+        Participant { player, shared, scheduler: None, viewer: Some(viewer) }
+    }
 
-        Ok(Participant { player, shared })
+    /// Start recording the game to a replay file at `path`, from the current
+    /// turn on. See the `replay` module for details.
+    pub fn record_game(&mut self, path: &Path, format: Format) -> ::errors::Result<()> {
+        let mut guard = self.shared.lock().unwrap();
+        let recording = ReplayWriter::create(path, format, &guard.state,
+                                             guard.turn_duration)?;
+        guard.recording = Some(recording);
+        Ok(())
+    }
+
+    /// Log each game's statistics to `path` when it's won, from now on. See
+    /// the `summary` module for details.
+    pub fn log_stats(&mut self, path: PathBuf) {
+        let mut guard = self.shared.lock().unwrap();
+        guard.stats_log = Some(StatsLog::new(path));
     }
 
     /// Return a snapshot of the current state.
@@ -336,12 +1148,605 @@ impl Participant {
         guard.state.clone()
     }
 
+    /// If the game is over, return the winner and the game's statistics.
+    pub fn result(&self) -> Option<(Player, GameStats)> {
+        let guard = self.shared.lock().unwrap();
+        guard.result.clone()
+    }
+
+    /// If the game is paused waiting for a player to reconnect, return that
+    /// player, and how much longer the server will wait for them.
+    pub fn pause(&self) -> Option<(Player, Duration)> {
+        let guard = self.shared.lock().unwrap();
+        guard.pause.map(|(player, deadline)| {
+            (player, deadline.saturating_duration_since(Instant::now()))
+        })
+    }
+
+    /// Return true if this participant is hosting the game.
+    pub fn is_host(&self) -> bool {
+        self.scheduler.is_some()
+    }
+
+    /// Once the game is over, start another with the same players. Only the
+    /// host can start a rematch; on other participants, this has no effect.
+    pub fn rematch(&mut self) {
+        if let Some(ref scheduler) = self.scheduler {
+            scheduler.lock().unwrap().rematch();
+        }
+    }
+
+    /// If our state has diverged from the server's, return the turn on which
+    /// it did. The game is over for us; see the `divergence` module.
+    pub fn diverged(&self) -> Option<usize> {
+        let guard = self.shared.lock().unwrap();
+        guard.diverged
+    }
+
+    /// If we're watching a replay, return its first and last turns.
+    pub fn replay_turns(&self) -> Option<(usize, usize)> {
+        self.viewer.as_ref().map(|viewer| viewer.lock().unwrap().turns())
+    }
+
+    /// Return true if we're watching a replay, and it's paused.
+    pub fn replay_paused(&self) -> bool {
+        self.viewer.as_ref().is_some_and(|viewer| viewer.lock().unwrap().is_paused())
+    }
+
+    /// Pause the replay we're watching, or resume it if it's paused.
+    pub fn toggle_replay_pause(&mut self) {
+        if let Some(ref viewer) = self.viewer {
+            viewer.lock().unwrap().toggle_pause();
+        }
+    }
+
+    /// Jump to `turn` of the replay we're watching, or as close as it goes.
+    /// If we're not watching a replay, this has no effect.
+    pub fn seek(&mut self, turn: usize) {
+        if let Some(ref viewer) = self.viewer {
+            let mut viewer = viewer.lock().unwrap();
+            viewer.seek(turn);
+            show(&viewer, &self.shared);
+        }
+    }
+
+    /// Return the game as it stands, to save to disk. Only the host can save
+    /// the game; on other participants, this returns `None`.
+    pub fn save(&self) -> Option<SavedGame> {
+        self.scheduler.as_ref().map(|scheduler| scheduler.lock().unwrap().save())
+    }
+
+    /// Return how many turns behind the server we were as of its last
+    /// response. The turns we missed have already been applied, but a large
+    /// number suggests we're struggling to keep up.
+    pub fn turns_behind(&self) -> usize {
+        let guard = self.shared.lock().unwrap();
+        guard.behind
+    }
+
+    /// Return the time between turns in this game.
+    pub fn turn_duration(&self) -> Duration {
+        let guard = self.shared.lock().unwrap();
+        guard.turn_duration
+    }
+
+    /// Play a turn every `turn_duration` from now on. Only the host can change
+    /// the pace of the game; on other participants, this has no effect.
+    pub fn set_turn_duration(&mut self, turn_duration: Duration) {
+        if let Some(ref scheduler) = self.scheduler {
+            scheduler.lock().unwrap().set_turn_duration(turn_duration);
+        }
+    }
+
+    /// Let `bot` play our seat from now on. Each time we apply a turn, it
+    /// sees the new state, and its actions are submitted as if requested.
+    pub fn set_bot(&mut self, bot: Box<dyn Bot>) {
+        let mut guard = self.shared.lock().unwrap();
+        guard.bot = Some(bot);
+    }
+
+    /// Return true if we're a client that has stopped talking to the server
+    /// for good: the game is over and the host has quit, or we lost our
+    /// connection and couldn't get it back.
+    pub fn disconnected(&self) -> bool {
+        let guard = self.shared.lock().unwrap();
+        guard.disconnected
+    }
+
     /// Return the player number of this SynchronizedState.
     pub fn get_player(&self) -> Player { self.player }
 
+    /// Return the actions we've requested that haven't yet taken effect, in
+    /// the order requested.
+    pub fn queued_actions(&self) -> Vec<Action> {
+        let guard = self.shared.lock().unwrap();
+        guard.submitted.iter().chain(&guard.pending).cloned().collect()
+    }
+
     /// Submit `action` to be performed as soon as possible.
     pub fn request_action(&mut self, action: Action) {
+        if self.viewer.is_some() {
+            return;
+        }
         let mut guard = self.shared.lock().unwrap();
+        if let Action::ToggleOutflow { .. } = action {
+            guard.last_toggle = Some(action.clone());
+        }
         guard.pending.push(action);
     }
+
+    /// Undo the most recent outflow toggle we requested. If we haven't sent
+    /// it to the server yet, just drop it; otherwise, toggle the same outflow
+    /// again. Only one step of undo is available: a second undo does nothing
+    /// until we request another toggle.
+    pub fn undo_toggle(&mut self) {
+        let mut guard = self.shared.lock().unwrap();
+        let toggle = match guard.last_toggle.take() {
+            Some(toggle) => toggle,
+            None => return,
+        };
+
+        // The pending queue is sent all at once, so if the last toggle is
+        // still there, it's the last toggle in the queue.
+        let pending = guard.pending.iter()
+            .rposition(|action| matches!(action, Action::ToggleOutflow { .. }));
+        match pending {
+            Some(index) => { guard.pending.remove(index); }
+            None => guard.pending.push(toggle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::{BufRead, Read};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Start a server on some free local port, and return its address.
+    fn start_server() -> (Participant, SocketAddr) {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let server = Participant::new_server(Rendezvous::Listen(addr), MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default(), ServerOptions::default()).unwrap();
+
+        // Wait for the server thread to start listening.
+        for _ in 0..100 {
+            if TcpStream::connect(addr).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        (server, addr)
+    }
+
+    /// Send `garbage` to the server at `addr`, and check that it drops the
+    /// connection.
+    fn send_garbage(addr: SocketAddr, garbage: &[u8]) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(garbage).unwrap();
+        let mut buf = vec![];
+        match stream.read_to_end(&mut buf) {
+            Ok(_) => assert!(buf.is_empty(), "server replied to garbage"),
+            Err(e) => assert_ne!(e.kind(), ::std::io::ErrorKind::WouldBlock,
+                                 "server didn't close connection"),
+        }
+    }
+
+    /// Check that the server at `addr` is still playing turns.
+    fn assert_plays_turns(addr: SocketAddr) {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        writeln!(&stream, "\"Spectate\"").unwrap();
+        reader.read_line(&mut line).unwrap();
+        let turn = match serde_json::from_str(&line).unwrap() {
+            Response::Snapshot { snapshot, .. } => snapshot.into_state().turn,
+            otherwise => panic!("unexpected response to Spectate: {:?}", otherwise),
+        };
+
+        line.clear();
+        writeln!(&stream, "{}", serde_json::to_string(&Request::Watch(turn)).unwrap()).unwrap();
+        reader.read_line(&mut line).unwrap();
+        let next = match serde_json::from_str(&line).unwrap() {
+            Response::Turn(collected) => collected.turn,
+            Response::Turns(turns) => turns.last().expect("caught up with no turns").turn,
+            otherwise => panic!("unexpected response to Watch: {:?}", otherwise),
+        };
+        assert!(next > turn, "server stopped playing turns");
+    }
+
+    #[test]
+    fn corrupt_frames() {
+        let (_server, addr) = start_server();
+
+        send_garbage(addr, b"\"Jion\"\n");
+        send_garbage(addr, b"\xff\xff\xff\n");
+        send_garbage(addr, b"{\"Actions\": 17}\n");
+        assert_plays_turns(addr);
+
+        // Messages that parse but make no sense get the same treatment.
+        let actions = |player, turn, actions| {
+            let request = Request::Actions(PlayerActions { player: Player(player), turn,
+                                                           actions });
+            format!("{}\n", serde_json::to_string(&request).unwrap())
+        };
+        send_garbage(addr, actions(0, 0, vec![]).as_bytes());
+        let diverged = serde_json::to_string(&Request::Diverged(Player(0), 0)).unwrap();
+        send_garbage(addr, format!("{}\n", diverged).as_bytes());
+        assert_plays_turns(addr);
+
+        // The server is still happy to serve well-behaved clients.
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        writeln!(&stream, "\"Join\"").unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let token = match serde_json::from_str(&line).unwrap() {
+            Response::Welcome { player: Player(1), token, .. } => token,
+            otherwise => panic!("unexpected response to Join: {:?}", otherwise),
+        };
+
+        // Send `request` on `stream`, and check that the server drops it
+        // without a word, as it does once it has read anything it oughtn't.
+        let dropped = |stream: TcpStream, request: &[u8]| {
+            (&stream).write_all(request).unwrap();
+            let mut buf = vec![];
+            match (&stream).read_to_end(&mut buf) {
+                Ok(_) => {}
+                Err(e) => assert_ne!(e.kind(), ::std::io::ErrorKind::WouldBlock,
+                                     "server didn't close connection"),
+            }
+        };
+
+        // Take player 1's seat back, once the server has noticed it's free.
+        // Only the client holding the seat's token can.
+        let rejoin = |token| {
+            for _ in 0..100 {
+                let stream = TcpStream::connect(addr).unwrap();
+                stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                let request = Request::Rejoin(Player(1), token, 0, 0);
+                writeln!(&stream, "{}", serde_json::to_string(&request).unwrap()).unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                match serde_json::from_str(&line).unwrap() {
+                    Response::GameFull => thread::sleep(Duration::from_millis(10)),
+                    _ => return Some(stream),
+                }
+            }
+            None
+        };
+
+        // Once seated, a client that submits actions for a turn that hasn't
+        // happened yet, or for someone else's seat, is dropped.
+        dropped(stream, actions(1, 1_000_000, vec![]).as_bytes());
+        assert!(rejoin(RejoinToken(token.0.wrapping_add(1))).is_none());
+        let stream = rejoin(token).expect("couldn't rejoin");
+        dropped(stream, actions(0, 0, vec![]).as_bytes());
+        assert_plays_turns(addr);
+
+        // So is one that submits well-formed actions naming nodes off the
+        // board, actions only the scheduler may take, or actions on another
+        // player's behalf. None of them reach the game.
+        for action in [Action::ToggleOutflow { player: Player(1), from: 8, to: 1000 },
+                       Action::OpenOutflows { player: Player(1), node: 9 },
+                       Action::Join { player: Player(1) },
+                       Action::Restart,
+                       Action::Surrender { player: Player(0) }] {
+            let stream = rejoin(token).expect("couldn't rejoin");
+            dropped(stream, actions(1, 0, vec![action]).as_bytes());
+            assert_plays_turns(addr);
+        }
+
+        // A client that sends garbage while one of its requests is still
+        // waiting goes away without its reply; the server carries on without
+        // it. A `Watch` for a turn far off is sure to be waiting.
+        let stream = rejoin(token).expect("couldn't rejoin");
+        let mut request = serde_json::to_string(&Request::Watch(usize::MAX)).unwrap().into_bytes();
+        request.extend_from_slice(b"\n\xff\xff\xff\n");
+        dropped(stream, &request);
+        assert_plays_turns(addr);
+
+        // Nothing the clients said disturbed the scheduler.
+        let stream = TcpStream::connect(addr).unwrap();
+        writeln!(&stream, "\"Spectate\"").unwrap();
+        line.clear();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert!(matches!(response, Response::Snapshot { .. }));
+    }
+
+    #[test]
+    fn maps_by_hash() {
+        let (_server, addr) = start_server();
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut writer = BufWriter::new(&stream);
+        send(&mut writer, &Request::Join).unwrap();
+        let (mut snapshot, fingerprint, hash) = match read_message(&mut reader).unwrap().unwrap() {
+            Response::Welcome { snapshot, fingerprint, map, .. } => (snapshot, fingerprint, map),
+            otherwise => panic!("expected Welcome, got {:?}", otherwise),
+        };
+        assert!(snapshot.state.map().is_none());
+
+        // A client without the map can ask for it by name.
+        send(&mut writer, &Request::Map(hash)).unwrap();
+        let map = match read_message(&mut reader).unwrap().unwrap() {
+            Response::Map(map) => *map,
+            otherwise => panic!("expected Map, got {:?}", otherwise),
+        };
+        assert_eq!(map.hash(), hash);
+        snapshot.state.attach_map(map);
+        assert!(snapshot.state.check_fingerprint(fingerprint, "the server's game").is_ok());
+
+        send(&mut writer, &Request::Map(MapHash(hash.0 ^ 1))).unwrap();
+        assert!(matches!(read_message(&mut reader).unwrap().unwrap(),
+                         Response::UnknownMap(MapHash(other)) if other == hash.0 ^ 1));
+    }
+
+    #[test]
+    fn spectate() {
+        let (_server, addr) = start_server();
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut writer = BufWriter::new(&stream);
+        send(&mut writer, &Request::Spectate).unwrap();
+        let mut state = match read_message(&mut reader).unwrap().unwrap() {
+            Response::Snapshot { snapshot, .. } => snapshot.into_state(),
+            otherwise => panic!("unexpected response to Spectate: {:?}", otherwise),
+        };
+
+        // Follow the game for a few turns, without joining.
+        for _ in 0..3 {
+            send(&mut writer, &Request::Watch(state.turn)).unwrap();
+            match read_message(&mut reader).unwrap().unwrap() {
+                Response::Turn(turn) => turn.apply_to(&mut state),
+                otherwise => panic!("unexpected response to Watch: {:?}", otherwise),
+            }
+        }
+        assert!(state.joined[0]);
+        assert!(!state.joined[1]);
+    }
+
+    #[test]
+    fn catch_up() {
+        let (_server, addr) = start_server();
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut writer = BufWriter::new(&stream);
+        send(&mut writer, &Request::Spectate).unwrap();
+        let snapshot = match read_message(&mut reader).unwrap().unwrap() {
+            Response::Snapshot { snapshot, .. } => snapshot,
+            otherwise => panic!("unexpected response to Spectate: {:?}", otherwise),
+        };
+        let reference = State::from_serializable(snapshot.state.clone());
+        let current = snapshot.into_state();
+
+        // A spectator holding a checkpoint gets just what changed since.
+        send(&mut writer, &Request::CatchUp(reference.turn)).unwrap();
+        let mut state = match read_message(&mut reader).unwrap().unwrap() {
+            Response::Delta { snapshot, .. } => {
+                let mut state = snapshot.checkpoint(&reference).unwrap();
+                for turn in &snapshot.turns {
+                    turn.apply_to(&mut state);
+                }
+                state
+            }
+            otherwise => panic!("unexpected response to CatchUp: {:?}", otherwise),
+        };
+        assert!(state.turn >= current.turn);
+        send(&mut writer, &Request::Watch(state.turn)).unwrap();
+        match read_message(&mut reader).unwrap().unwrap() {
+            Response::Turn(turn) => turn.apply_to(&mut state),
+            Response::Turns(turns) => turns.iter().for_each(|turn| turn.apply_to(&mut state)),
+            otherwise => panic!("unexpected response to Watch: {:?}", otherwise),
+        }
+
+        // One holding a state the server never checkpointed gets everything.
+        send(&mut writer, &Request::CatchUp(reference.turn + 1)).unwrap();
+        assert!(matches!(read_message(&mut reader).unwrap().unwrap(),
+                         Response::Snapshot { .. }));
+    }
+
+    #[test]
+    fn pending_client() {
+        let (_server, addr) = start_server();
+
+        // Joining happens in the background; poll until we're seated.
+        let mut client = PendingClient::start(addr);
+        let mut joined = None;
+        for _ in 0..500 {
+            joined = client.poll();
+            if joined.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let participant = joined.expect("client never joined").unwrap();
+        assert_eq!(participant.get_player(), Player(1));
+
+        // A server that isn't there is reported as an error.
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut client = PendingClient::start(closed);
+        let mut failed = None;
+        for _ in 0..500 {
+            failed = client.poll();
+            if failed.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(failed.expect("connection never failed").is_err());
+    }
+
+    /// A bot that opens the outflow from player 1's source once.
+    struct Opener {
+        opened: bool,
+    }
+
+    impl Bot for Opener {
+        fn act(&mut self, _state: &State, player: Player) -> Vec<Action> {
+            if self.opened {
+                return vec![];
+            }
+            self.opened = true;
+            vec![Action::ToggleOutflow { player, from: 8, to: 7 }]
+        }
+    }
+
+    #[test]
+    fn bot_client() {
+        let (server, addr) = start_server();
+
+        let mut client = PendingClient::start(addr);
+        let mut joined = None;
+        for _ in 0..500 {
+            joined = client.poll();
+            if joined.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut participant = joined.expect("client never joined").unwrap();
+        participant.set_bot(Box::new(Opener { opened: false }));
+
+        // The bot's move reaches the server like anyone's.
+        let flows = || server.snapshot().nodes[8].as_ref()
+            .is_some_and(|occupied| occupied.outflows == vec![7]);
+        for _ in 0..500 {
+            if flows() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(flows());
+        assert!(!participant.disconnected());
+    }
+
+    /// A bot that moves every time it's asked.
+    struct Eager;
+
+    impl Bot for Eager {
+        fn act(&mut self, state: &State, player: Player) -> Vec<Action> {
+            vec![Action::ToggleOutflow { player, from: state.turn % 9, to: 4 }]
+        }
+    }
+
+    #[test]
+    fn bot_catch_up() {
+        let mut scheduler = Scheduler::new(State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default()), DEFAULT_TURN_DURATION);
+        let (player, snapshot) = scheduler.player_join().unwrap();
+        let mut shared = Shared::new(player, snapshot.into_state(), DEFAULT_TURN_DURATION);
+        shared.bot = Some(Box::new(Eager));
+        for _ in 0..4 {
+            scheduler.play_turn().send();
+        }
+
+        // One turn at a time, the bot acts on each.
+        let next = shared.catch_up(&scheduler.turn_range(1..2).unwrap());
+        assert_eq!(next.actions.len(), 1);
+
+        // After falling behind, it acts once, on the latest state.
+        let next = shared.catch_up(&scheduler.turn_range(2..5).unwrap());
+        assert!(matches!(next.actions[..], [Action::ToggleOutflow { from: 4, .. }]));
+    }
+
+    #[test]
+    fn queued_actions() {
+        let (mut server, _) = start_server();
+        let player = server.get_player();
+        server.request_action(Action::ToggleOutflow { player, from: 0, to: 1 });
+        assert!(matches!(server.queued_actions()[..],
+                         [Action::ToggleOutflow { from: 0, to: 1, .. }]));
+
+        // Once the action's turn comes around, it's no longer queued.
+        for _ in 0..500 {
+            let state = server.snapshot();
+            if state.nodes[0].as_ref().is_some_and(|node| node.outflows == vec![1]) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(server.queued_actions().is_empty());
+    }
+
+    #[test]
+    fn undo_toggle() {
+        let (mut server, _) = start_server();
+        let player = server.get_player();
+        let has_outflow = |server: &Participant| {
+            server.snapshot().nodes[0].as_ref().is_some_and(|node| node.outflows == vec![1])
+        };
+        let wait_for = |server: &Participant, outflow: bool| {
+            for _ in 0..500 {
+                if has_outflow(server) == outflow && server.queued_actions().is_empty() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("outflow never settled");
+        };
+
+        // Undoing a toggle right away cancels it, whether or not it has been
+        // sent yet.
+        server.request_action(Action::ToggleOutflow { player, from: 0, to: 1 });
+        server.undo_toggle();
+        wait_for(&server, false);
+
+        // Undoing a toggle that has taken effect toggles it back.
+        server.request_action(Action::ToggleOutflow { player, from: 0, to: 1 });
+        wait_for(&server, true);
+        server.undo_toggle();
+        wait_for(&server, false);
+
+        // There's only one step of undo.
+        server.undo_toggle();
+        assert!(server.queued_actions().is_empty());
+    }
+
+    #[test]
+    fn divergence() {
+        let state = State::new(MapParameters {
+            size: (3, 3),
+            sources: vec![0, 8],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff)],
+        }, GameParameters::default());
+        let mut shared = Shared::new(Player(0), state, Duration::from_millis(16));
+
+        // A turn whose checksum doesn't match what we get stops the game for
+        // us, and leaves a report behind.
+        let turn = CollectedActions { turn: 1, actions: vec![Action::Join { player: Player(0) }],
+                                      state_checksum: 0, turn_duration: None };
+        shared.apply_collected_actions(&turn);
+        assert_eq!(shared.diverged, Some(1));
+        let path = divergence::report_path(1);
+        let report: serde_json::Value =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(report["turn"], 1);
+        assert_eq!(report["recent"].as_array().unwrap().len(), 1);
+        assert_eq!(report["before"]["turn"], 0);
+        assert_eq!(shared.history.latest().unwrap().turn, 0);
+
+        // Later turns are ignored.
+        let next = CollectedActions { turn: 2, actions: vec![], state_checksum: 0,
+                                      turn_duration: None };
+        shared.apply_collected_actions(&next);
+        assert_eq!(shared.state.turn, 1);
+    }
 }
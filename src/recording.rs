@@ -0,0 +1,320 @@
+//! Recording and replaying the player's input.
+//!
+//! Given `--record-input FILE`, the client writes each mouse, keyboard, and
+//! touch event the game window acts on to `FILE`, one JSON object per line,
+//! stamped with the time since the window opened. Given `--replay-input FILE`,
+//! it feeds those events back in at the same times, as if the player were
+//! making them again. This is handy for reproducing bugs in the interface, and
+//! for scripting interaction tests.
+//!
+//! The main loop translates the window system's events into `InputEvent`s
+//! before acting on them, so recorded and live events take exactly the same
+//! path. Positions are in physical pixels, so a replay should use a window the
+//! same size as the recording did.
+
+use errors::*;
+
+use glium::glutin::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, Touch,
+                    TouchPhase, VirtualKeyCode, WindowEvent};
+use glium::glutin::dpi::PhysicalPosition;
+use serde_json;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// How many pixels of a trackpad pinch count as one line of a scroll wheel.
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+/// Something the player did that the game responds to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    /// The mouse moved to the given point, in window coordinates.
+    CursorMoved { x: f32, y: f32 },
+
+    /// A mouse button went down, or came up.
+    Button { button: Button, pressed: bool },
+
+    /// The scroll wheel turned by this many lines, positive away from the
+    /// player, or the player pinched a trackpad by the equivalent amount,
+    /// positive spreading their fingers.
+    Scroll { lines: f32 },
+
+    /// The player slid two fingers across a trackpad, by the given number of
+    /// pixels.
+    Swipe { x: f32, y: f32 },
+
+    /// A finger touched, moved across, or left the screen at the given point,
+    /// in window coordinates.
+    Touch { id: u64, phase: Phase, x: f32, y: f32 },
+
+    /// A key was pressed, with the given modifiers held.
+    Key { key: Key, shift: bool, ctrl: bool, alt: bool },
+
+    /// The keyboard produced a character, for typing text. This follows the
+    /// `Key` event for the same keystroke, if any.
+    Char { ch: char },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+/// The keys the game responds to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Key {
+    A, C, D, E, F, G, H, P, R, S, W, X, Z,
+
+    /// A digit key along the top of the keyboard.
+    Digit(u8),
+
+    F1,
+    F2,
+    F3,
+    F4,
+    F9,
+    Minus,
+    Equals,
+    Return,
+    Space,
+    Tab,
+    Escape,
+    LBracket,
+    RBracket,
+    Home,
+    End,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Key {
+    fn from_keycode(code: VirtualKeyCode) -> Option<Key> {
+        Some(match code {
+            VirtualKeyCode::A => Key::A,
+            VirtualKeyCode::C => Key::C,
+            VirtualKeyCode::D => Key::D,
+            VirtualKeyCode::E => Key::E,
+            VirtualKeyCode::F => Key::F,
+            VirtualKeyCode::G => Key::G,
+            VirtualKeyCode::H => Key::H,
+            VirtualKeyCode::P => Key::P,
+            VirtualKeyCode::R => Key::R,
+            VirtualKeyCode::S => Key::S,
+            VirtualKeyCode::W => Key::W,
+            VirtualKeyCode::X => Key::X,
+            VirtualKeyCode::Z => Key::Z,
+            VirtualKeyCode::Key0 => Key::Digit(0),
+            VirtualKeyCode::Key1 => Key::Digit(1),
+            VirtualKeyCode::Key2 => Key::Digit(2),
+            VirtualKeyCode::Key3 => Key::Digit(3),
+            VirtualKeyCode::Key4 => Key::Digit(4),
+            VirtualKeyCode::Key5 => Key::Digit(5),
+            VirtualKeyCode::Key6 => Key::Digit(6),
+            VirtualKeyCode::Key7 => Key::Digit(7),
+            VirtualKeyCode::Key8 => Key::Digit(8),
+            VirtualKeyCode::Key9 => Key::Digit(9),
+            VirtualKeyCode::F1 => Key::F1,
+            VirtualKeyCode::F2 => Key::F2,
+            VirtualKeyCode::F3 => Key::F3,
+            VirtualKeyCode::F4 => Key::F4,
+            VirtualKeyCode::F9 => Key::F9,
+            VirtualKeyCode::Minus => Key::Minus,
+            VirtualKeyCode::Equals => Key::Equals,
+            VirtualKeyCode::Return => Key::Return,
+            VirtualKeyCode::Space => Key::Space,
+            VirtualKeyCode::Tab => Key::Tab,
+            VirtualKeyCode::Escape => Key::Escape,
+            VirtualKeyCode::LBracket => Key::LBracket,
+            VirtualKeyCode::RBracket => Key::RBracket,
+            VirtualKeyCode::Home => Key::Home,
+            VirtualKeyCode::End => Key::End,
+            VirtualKeyCode::Up => Key::Up,
+            VirtualKeyCode::Down => Key::Down,
+            VirtualKeyCode::Left => Key::Left,
+            VirtualKeyCode::Right => Key::Right,
+            _ => return None,
+        })
+    }
+}
+
+impl InputEvent {
+    /// Translate `event`, from a window with `hidpi_factor` physical pixels
+    /// per logical pixel. Return `None` if it isn't input the game uses.
+    pub fn from_window_event(event: &WindowEvent, hidpi_factor: f64) -> Option<InputEvent> {
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let PhysicalPosition { x, y } = position.to_physical(hidpi_factor);
+                Some(InputEvent::CursorMoved { x: x as f32, y: y as f32 })
+            }
+            WindowEvent::MouseInput { button, state, .. } => {
+                let button = match button {
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Right,
+                    MouseButton::Other(_) => return None,
+                };
+                Some(InputEvent::Button { button, pressed: state == ElementState::Pressed })
+            }
+            // winit doesn't report trackpad gestures as such, but trackpads
+            // scroll by pixels rather than lines, and most drivers report a
+            // pinch as scrolling with Ctrl held.
+            WindowEvent::MouseWheel { delta, modifiers, .. } => Some(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => InputEvent::Scroll { lines },
+                MouseScrollDelta::PixelDelta(pixels) if modifiers.ctrl =>
+                    InputEvent::Scroll { lines: pixels.y as f32 / PIXELS_PER_SCROLL_LINE },
+                MouseScrollDelta::PixelDelta(pixels) => {
+                    let PhysicalPosition { x, y } = pixels.to_physical(hidpi_factor);
+                    InputEvent::Swipe { x: x as f32, y: y as f32 }
+                }
+            }),
+            WindowEvent::Touch(Touch { phase, location, id, .. }) => {
+                let PhysicalPosition { x, y } = location.to_physical(hidpi_factor);
+                let phase = match phase {
+                    TouchPhase::Started => Phase::Started,
+                    TouchPhase::Moved => Phase::Moved,
+                    TouchPhase::Ended => Phase::Ended,
+                    TouchPhase::Cancelled => Phase::Cancelled,
+                };
+                Some(InputEvent::Touch { id, phase, x: x as f32, y: y as f32 })
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(code),
+                    modifiers,
+                    ..
+                },
+                ..
+            } => {
+                let key = Key::from_keycode(code)?;
+                Some(InputEvent::Key { key, shift: modifiers.shift, ctrl: modifiers.ctrl,
+                                       alt: modifiers.alt })
+            }
+            WindowEvent::ReceivedCharacter(ch) => Some(InputEvent::Char { ch }),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a recording.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// When the event happened, in milliseconds since the window opened.
+    ms: u64,
+    event: InputEvent,
+}
+
+/// Writes input events to a recording as they happen.
+pub struct Recorder<W: Write> {
+    out: W,
+}
+
+impl Recorder<LineWriter<File>> {
+    /// Start a new recording in the file at `path`. Each event is written out
+    /// as soon as it's recorded, so nothing is lost if the game exits
+    /// abruptly.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .chain_err(|| format!("creating input recording {}", path.display()))?;
+        Ok(Recorder::new(LineWriter::new(file)))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    fn new(out: W) -> Recorder<W> {
+        Recorder { out }
+    }
+
+    /// Record that `event` happened at `time` since the window opened.
+    pub fn record(&mut self, time: Duration, event: &InputEvent) -> Result<()> {
+        let entry = Entry { ms: time.as_millis() as u64, event: *event };
+        let line = serde_json::to_string(&entry)
+            .chain_err(|| "serializing input event")?;
+        writeln!(self.out, "{}", line)
+            .chain_err(|| "writing input recording")
+    }
+}
+
+/// Plays back a recording, handing out events as their times come.
+pub struct Playback {
+    events: VecDeque<(Duration, InputEvent)>,
+}
+
+impl Playback {
+    /// Load the recording in the file at `path`.
+    pub fn open(path: &Path) -> Result<Playback> {
+        let file = File::open(path)
+            .chain_err(|| format!("opening input recording {}", path.display()))?;
+        Playback::read(BufReader::new(file))
+            .chain_err(|| format!("reading input recording {}", path.display()))
+    }
+
+    fn read<R: BufRead>(input: R) -> Result<Playback> {
+        let mut events = VecDeque::new();
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)
+                .chain_err(|| format!("line {}: malformed input event", number + 1))?;
+            events.push_back((Duration::from_millis(entry.ms), entry.event));
+        }
+        Ok(Playback { events })
+    }
+
+    /// Return the events due by `time` since the window opened, in the order
+    /// they were recorded.
+    pub fn due(&mut self, time: Duration) -> Vec<InputEvent> {
+        let count = self.events.iter().take_while(|&&(when, _)| when <= time).count();
+        self.events.drain(..count).map(|(_, event)| event).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let events = [
+            (10, InputEvent::CursorMoved { x: 12.5, y: 40.0 }),
+            (10, InputEvent::Button { button: Button::Left, pressed: true }),
+            (250, InputEvent::Key { key: Key::Up, shift: true, ctrl: false, alt: false }),
+            (250, InputEvent::Char { ch: 'é' }),
+            (900, InputEvent::Touch { id: 3, phase: Phase::Ended, x: 1.0, y: 2.0 }),
+        ];
+        let mut buffer = vec![];
+        {
+            let mut recorder = Recorder::new(&mut buffer);
+            for &(ms, ref event) in &events {
+                recorder.record(Duration::from_millis(ms), event).unwrap();
+            }
+        }
+        assert_eq!(String::from_utf8_lossy(&buffer).lines().count(), events.len());
+
+        let mut playback = Playback::read(&buffer[..]).unwrap();
+        assert!(playback.due(Duration::from_millis(5)).is_empty());
+        assert_eq!(playback.due(Duration::from_millis(300)),
+                   events[..4].iter().map(|&(_, event)| event).collect::<Vec<_>>());
+        assert_eq!(playback.due(Duration::from_secs(10)), vec![events[4].1]);
+        assert!(playback.due(Duration::from_secs(20)).is_empty());
+
+        assert!(Playback::read(&b"{\"ms\": 1}\n"[..]).is_err());
+    }
+}
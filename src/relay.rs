@@ -0,0 +1,303 @@
+//! Relaying game traffic for hosts that can't accept incoming connections.
+//!
+//! Normally the rbattle server listens on a TCP port and clients connect to it
+//! directly. That doesn't work when the server is behind a NAT that won't
+//! forward the port. In relay mode, a third machine that everyone *can* reach
+//! runs `rbattle relay ADDR`, and both the server and the clients make only
+//! outbound connections to it:
+//!
+//! - The server opens a "control" connection to the relay and announces itself
+//!   by sending `RelayHello::RelayHost`.
+//!
+//! - Clients connect to the relay exactly as they would connect to a server.
+//!   When the relay sees a connection whose first line isn't a `RelayHello`, it
+//!   assumes it is a game client, sets it aside under a fresh id, and sends
+//!   that id to the server over the control connection as a `RelayNotice`.
+//!   Ids are drawn from the operating system's random number generator, so
+//!   only the host, which hears them over its control connection, knows them.
+//!
+//! - The server responds by opening a new "data" connection to the relay and
+//!   sending `RelayHello::RelayData(id)`. The relay then replays the client's
+//!   first line to the server and copies bytes in both directions until either
+//!   side closes. The relay only accepts a data connection if it names a
+//!   waiting client and comes from the same address as the host's control
+//!   connection.
+//!
+//! The relay never looks inside the game protocol beyond the first line, so it
+//! doesn't need to change when the protocol does. It won't read a first line
+//! longer than the protocol allows any message to be, though.
+
+use jsonproto::MAX_MESSAGE_BYTES;
+
+use rand::Rng;
+use rand::rngs::OsRng;
+use serde_json;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The first line a host sends on each connection it makes to the relay.
+#[derive(Debug, Serialize, Deserialize)]
+enum RelayHello {
+    /// This is the host's control connection.
+    RelayHost,
+
+    /// This is a data connection the host opened for the client with the given
+    /// id.
+    RelayData(u64),
+}
+
+/// Messages the relay sends the host on its control connection.
+#[derive(Debug, Serialize, Deserialize)]
+enum RelayNotice {
+    /// A client has connected to the relay, and is waiting for the host to open
+    /// a data connection for it.
+    Connect(u64),
+}
+
+/// The relay's record of who is connected.
+#[derive(Default)]
+struct Relay {
+    /// The host's control connection, if a host has announced itself.
+    host: Option<TcpStream>,
+
+    /// Clients waiting for the host to open their data connection, along with
+    /// whatever bytes we've already read from them.
+    waiting: HashMap<u64, (TcpStream, Vec<u8>)>,
+}
+
+/// Run a relay listening on `addr`. This only returns if listening fails.
+pub fn serve(addr: SocketAddr) -> io::Result<()> {
+    let relay = Arc::new(Mutex::new(Relay::default()));
+    serve_listener(TcpListener::bind(addr)?, relay)
+}
+
+/// Relay connections accepted from `listener`, recording them in `relay`.
+fn serve_listener(listener: TcpListener, relay: Arc<Mutex<Relay>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let relay = relay.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&relay, stream) {
+                eprintln!("relay: dropping connection: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Read the first line from `stream`, and decide what to do with it.
+fn handle_connection(relay: &Mutex<Relay>, stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+
+    // Take the first line, plus anything the reader happened to buffer beyond
+    // it; we'll need to pass all of it along. Don't let a connection that
+    // never sends a newline have us hold onto everything it sends.
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut first = String::new();
+    (&mut reader).take(MAX_MESSAGE_BYTES as u64).read_line(&mut first)?;
+    if !first.ends_with('\n') {
+        return Err(Error::other("first line too long, or cut short"));
+    }
+    let mut prefix = first.clone().into_bytes();
+    prefix.extend_from_slice(reader.buffer());
+
+    match serde_json::from_str::<RelayHello>(&first) {
+        Ok(RelayHello::RelayHost) => {
+            let mut guard = relay.lock().unwrap();
+            if guard.host.is_some() {
+                return Err(Error::other("a host is already registered"));
+            }
+            guard.host = Some(stream);
+
+            // Everyone waiting was waiting for some earlier host.
+            guard.waiting.clear();
+            drop(guard);
+
+            // Watch the control connection, so we notice when the host leaves.
+            // The host never sends anything after its hello.
+            let mut sink = io::sink();
+            let _ = io::copy(&mut reader, &mut sink);
+            relay.lock().unwrap().host = None;
+            Ok(())
+        }
+
+        Ok(RelayHello::RelayData(id)) => {
+            let mut guard = relay.lock().unwrap();
+            let host_ip = match guard.host {
+                Some(ref host) => host.peer_addr()?.ip(),
+                None => return Err(Error::other("data connection, but no host registered")),
+            };
+            if stream.peer_addr()?.ip() != host_ip {
+                return Err(Error::other("data connection doesn't come from the host"));
+            }
+            let client = guard.waiting.remove(&id);
+            drop(guard);
+            let (client, client_prefix) = client
+                .ok_or_else(|| Error::other(format!("no client waiting with id {}", id)))?;
+
+            // Deliver what each side has said so far, and then splice the two
+            // connections together.
+            (&stream).write_all(&client_prefix)?;
+            (&client).write_all(&prefix[first.len()..])?;
+            splice(client, stream);
+            Ok(())
+        }
+
+        Err(_) => {
+            // This must be a game client.
+            let mut rng = OsRng::new().map_err(Error::other)?;
+            let mut guard = relay.lock().unwrap();
+            let mut id = rng.gen();
+            while guard.waiting.contains_key(&id) {
+                id = rng.gen();
+            }
+
+            let notice = serde_json::to_string(&RelayNotice::Connect(id))?;
+            match guard.host {
+                None => {
+                    return Err(Error::other("client connected, but no host registered"));
+                }
+                Some(ref mut host) => writeln!(host, "{}", notice)?,
+            }
+            guard.waiting.insert(id, (stream, prefix));
+            Ok(())
+        }
+    }
+}
+
+/// Copy bytes between `a` and `b` in both directions until one side closes.
+fn splice(a: TcpStream, b: TcpStream) {
+    fn pump(mut from: TcpStream, mut to: TcpStream) {
+        let _ = io::copy(&mut from, &mut to);
+        let _ = to.shutdown(Shutdown::Both);
+        let _ = from.shutdown(Shutdown::Both);
+    }
+
+    let (a2, b2) = match (a.try_clone(), b.try_clone()) {
+        (Ok(a2), Ok(b2)) => (a2, b2),
+        _ => return,
+    };
+    thread::spawn(move || pump(a2, b2));
+    pump(b, a);
+}
+
+/// A host's registration with a relay. This is the relay-mode analogue of a
+/// `TcpListener`: each client that connects to the relay turns up here as a
+/// fresh `TcpStream`.
+pub struct RelayListener {
+    relay: SocketAddr,
+    control: BufReader<TcpStream>,
+}
+
+impl RelayListener {
+    /// Register with the relay at `relay` as the game host.
+    pub fn connect(relay: SocketAddr) -> io::Result<RelayListener> {
+        let control = TcpStream::connect(relay)?;
+        send_hello(&control, &RelayHello::RelayHost)?;
+        Ok(RelayListener { relay, control: BufReader::new(control) })
+    }
+
+    /// Wait for a client to connect to the relay, and return a stream that
+    /// speaks to it.
+    pub fn accept(&mut self) -> io::Result<TcpStream> {
+        let mut line = String::new();
+        if self.control.read_line(&mut line)? == 0 {
+            return Err(Error::new(io::ErrorKind::UnexpectedEof,
+                                  "relay closed the control connection"));
+        }
+
+        let RelayNotice::Connect(id) = serde_json::from_str(&line)?;
+        let data = TcpStream::connect(self.relay)?;
+        data.set_nodelay(true)?;
+        send_hello(&data, &RelayHello::RelayData(id))?;
+        Ok(data)
+    }
+}
+
+/// Send `hello` as the first line on `stream`.
+fn send_hello(stream: &TcpStream, hello: &RelayHello) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream);
+    writeln!(writer, "{}", serde_json::to_string(hello)?)?;
+    writer.flush()
+}
+
+#[test]
+fn relay_round_trip() {
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    let relay = Arc::new(Mutex::new(Relay::default()));
+    let relay_handle = relay.clone();
+    thread::spawn(move || serve_listener(listener, relay_handle));
+
+    // Register as the host, and wait for the relay to notice.
+    let mut host = RelayListener::connect(relay_addr).unwrap();
+    while relay.lock().unwrap().host.is_none() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let client = TcpStream::connect(relay_addr).unwrap();
+    writeln!(&client, "\"Join\"").unwrap();
+    let data = host.accept().unwrap();
+
+    // The host sees the client's first line, and can reply.
+    let mut line = String::new();
+    BufReader::new(&data).read_line(&mut line).unwrap();
+    assert_eq!(line, "\"Join\"\n");
+    writeln!(&data, "\"GameFull\"").unwrap();
+
+    line.clear();
+    BufReader::new(&client).read_line(&mut line).unwrap();
+    assert_eq!(line, "\"GameFull\"\n");
+}
+
+#[test]
+fn relay_rejects_unknown_ids() {
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    let relay = Arc::new(Mutex::new(Relay::default()));
+    let relay_handle = relay.clone();
+    thread::spawn(move || serve_listener(listener, relay_handle));
+
+    let mut host = RelayListener::connect(relay_addr).unwrap();
+    while relay.lock().unwrap().host.is_none() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let client = TcpStream::connect(relay_addr).unwrap();
+    writeln!(&client, "\"Join\"").unwrap();
+    let data = host.accept().unwrap();
+
+    // A third party guessing an id gets nothing: the relay hangs up on it,
+    // and the waiting clients are left alone.
+    let intruder = TcpStream::connect(relay_addr).unwrap();
+    send_hello(&intruder, &RelayHello::RelayData(0)).unwrap();
+    let mut line = String::new();
+    assert_eq!(BufReader::new(&intruder).read_line(&mut line).unwrap(), 0);
+
+    writeln!(&data, "\"GameFull\"").unwrap();
+    BufReader::new(&client).read_line(&mut line).unwrap();
+    assert_eq!(line, "\"GameFull\"\n");
+}
+
+#[test]
+fn relay_rejects_endless_lines() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let relay_addr = listener.local_addr().unwrap();
+    thread::spawn(move || serve_listener(listener, Arc::new(Mutex::new(Relay::default()))));
+
+    // A first line with no end in sight gets hung up on, rather than read
+    // forever.
+    let mut client = TcpStream::connect(relay_addr).unwrap();
+    client.write_all(&vec![b'['; MAX_MESSAGE_BYTES]).unwrap();
+    let mut line = String::new();
+    assert_eq!(BufReader::new(&client).read_line(&mut line).unwrap(), 0);
+}
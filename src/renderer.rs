@@ -0,0 +1,140 @@
+//! The interface between the game and whatever draws it.
+//!
+//! The main loop doesn't care how the game gets onto the screen: it hands a
+//! `Renderer` the state to show once per frame, and gets back what it needs to
+//! interpret the mouse. `drawer::Drawer` renders with Glium; another backend,
+//! or one that draws nothing at all for testing, need only implement this
+//! trait to take its place.
+
+use errors::*;
+use input::Input;
+use palette::Palette;
+use scheduler::GameStats;
+use settings::Panel;
+use state::{Player, State};
+use timing::PerfStats;
+
+use std::time::Duration;
+
+/// Something that can draw frames of the game.
+pub trait Renderer {
+    /// Draw a frame showing `state`, as of `time` since the game window
+    /// opened, reflecting the mouse and keyboard interaction in `input`, with
+    /// `panel` on top if it's open. If the game is over, `game_over` says how
+    /// it ended. If `perf` is given, show its
+    /// figures, along with the renderer's own, in a corner of the window.
+    ///
+    /// Return the current transformation from window coordinates to game
+    /// coordinates, for use by the controller.
+    fn render(&mut self,
+              time: Duration,
+              state: &State,
+              input: &Input,
+              panel: &Panel,
+              game_over: Option<&GameOver>,
+              perf: Option<&PerfStats>) -> Result<[[f32; 3]; 3]>;
+
+    /// Draw players in the colors given by `palette` from now on.
+    fn set_palette(&mut self, palette: Palette);
+
+    /// Draw the boundaries between nodes in the given style from now on.
+    fn set_grid_style(&mut self, style: GridStyle);
+
+    /// The window has moved to a display with `factor` physical pixels per
+    /// logical pixel. Sizes given in pixels, like line widths, should be
+    /// scaled to match from now on.
+    fn set_hidpi_factor(&mut self, factor: f64);
+
+    /// Draw the lines between nodes, outflows, and the mouse highlight
+    /// `scale` times their usual width from now on, for players who find
+    /// thin lines hard to see.
+    fn set_ui_scale(&mut self, scale: f32);
+
+    /// If `show` is true, shade the regions of the board where clicking
+    /// toggles each outflow from now on, to help tune the hit tolerance.
+    fn set_show_hit_regions(&mut self, show: bool);
+
+    /// Return the pixels of the frame most recently rendered.
+    fn screenshot(&self) -> Result<Screenshot>;
+}
+
+/// The pixels of a rendered frame.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+
+    /// The pixels' red, green, and blue components, one byte each, running
+    /// left to right along each row, and from the top row to the bottom.
+    pub rgb: Vec<u8>,
+}
+
+/// What a renderer needs to know about a finished game, beyond its final
+/// state.
+pub struct GameOver<'a> {
+    /// The player who won.
+    pub winner: Player,
+
+    /// The player on this host.
+    pub local: Player,
+
+    /// Statistics about the game just finished.
+    pub stats: &'a GameStats,
+
+    /// True if this host can start a rematch.
+    pub can_rematch: bool,
+}
+
+/// How to draw the boundaries between nodes. On large boards, the full grid
+/// can be visually noisy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridStyle {
+    /// Draw every boundary line.
+    Lines,
+
+    /// Draw only a dot at each corner where boundaries meet.
+    Dots,
+
+    /// Draw no boundaries, but tint each occupied node faintly in its owner's
+    /// color, so ownership is still clear.
+    Hidden,
+}
+
+impl GridStyle {
+    /// Parse a style name, as given to `--grid`.
+    pub fn parse(text: &str) -> Option<GridStyle> {
+        match text {
+            "lines" => Some(GridStyle::Lines),
+            "dots" => Some(GridStyle::Dots),
+            "hidden" => Some(GridStyle::Hidden),
+            _ => None,
+        }
+    }
+
+    /// Return the style after this one, for cycling through them with a key.
+    pub fn next(self) -> GridStyle {
+        match self {
+            GridStyle::Lines => GridStyle::Dots,
+            GridStyle::Dots => GridStyle::Hidden,
+            GridStyle::Hidden => GridStyle::Lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_style() {
+        assert_eq!(GridStyle::parse("dots"), Some(GridStyle::Dots));
+        assert_eq!(GridStyle::parse("grid"), None);
+
+        let mut style = GridStyle::Lines;
+        for _ in 0..3 {
+            style = style.next();
+            assert_eq!(GridStyle::parse(&format!("{:?}", style).to_lowercase()), Some(style));
+        }
+        assert_eq!(style, GridStyle::Lines);
+    }
+}
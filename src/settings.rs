@@ -0,0 +1,359 @@
+//! The player's settings, and the in-game panel for changing them.
+//!
+//! A few choices about how the game looks are worth keeping from one game to
+//! the next, so we save them as JSON in `~/.config/rbattle/settings.json`, or
+//! wherever `--settings` says. Changing a setting, whether from the start
+//! screen, with a hotkey, or from the panel, saves them all. Flags on the
+//! command line override the saved settings, and are saved along with them if
+//! the player changes something.
+//!
+//! The panel is a column of buttons over the board, laid out with the same
+//! `ui` functions as the start screen. Like `Menu`, it only tracks which
+//! button the mouse is over and which one was chosen; the main loop changes
+//! the settings and passes them along, and `drawer` draws it. It can also
+//! list the game's keyboard shortcuts, since there's no other way to discover
+//! them from inside the game.
+
+use errors::*;
+use input::ButtonState;
+use renderer::GridStyle;
+use ui::{self, Button};
+
+use serde_json;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The UI scales the settings screens offer, in the order they cycle through
+/// them.
+pub const UI_SCALES: [f32; 4] = [1.0, 1.5, 2.0, 3.0];
+
+/// The keyboard shortcuts the panel lists, and what they do.
+pub const KEYS: [(&str, &str); 24] = [
+    ("ARROWS", "MOVE THE CURSOR"),
+    ("SHIFT+ARROW", "TOGGLE AN OUTFLOW"),
+    ("W A S D", "TOGGLE AN OUTFLOW"),
+    ("E", "OPEN ALL OUTFLOWS"),
+    ("X", "CLOSE ALL OUTFLOWS"),
+    ("F", "CHOOSE A PATH TO FLOW ALONG"),
+    ("CTRL+Z", "UNDO THE LAST TOGGLE"),
+    ("H", "ZOOM IN ON YOUR SOURCE"),
+    ("SPACE", "JUMP TO THE LATEST ATTACK"),
+    ("C", "HIGH CONTRAST COLORS"),
+    ("G", "NEXT GRID STYLE"),
+    ("- =", "SLOW DOWN, SPEED UP (HOST)"),
+    ("CTRL+S", "SAVE THE GAME (HOST)"),
+    ("R", "REMATCH (HOST)"),
+    ("TAB", "HIGHLIGHT THE NEXT BUTTON"),
+    ("ENTER", "PRESS BUTTON, PICK SQUARE, OR CHAT"),
+    ("P [ ] 0-9", "PAUSE, STEP, JUMP (REPLAY)"),
+    ("F1", "SETTINGS"),
+    ("F2", "HINTS"),
+    ("F3", "PERFORMANCE"),
+    ("F4", "SHOW HIT REGIONS"),
+    ("F9", "DUMP THE STATE, FOR DEBUGGING"),
+    ("ALT+ENTER", "FULLSCREEN"),
+    ("ESCAPE", "QUIT"),
+];
+
+/// The settings we save between games.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// How to draw the boundaries between squares.
+    pub grid_style: GridStyle,
+
+    /// How much to thicken lines and widen hit zones.
+    pub ui_scale: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings { grid_style: GridStyle::Lines, ui_scale: 1.0 }
+    }
+}
+
+impl Settings {
+    /// Return where settings are saved by default, if we can tell.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("rbattle").join("settings.json"))
+    }
+
+    /// Read settings from the JSON file at `path`. If there's no such file,
+    /// return the defaults.
+    pub fn load(path: &Path) -> Result<Settings> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                return Ok(Settings::default());
+            }
+            Err(error) => {
+                return Err(error).chain_err(|| format!("opening settings file {}",
+                                                       path.display()));
+            }
+        };
+        serde_json::from_reader(file)
+            .chain_err(|| format!("reading settings file {}", path.display()))
+    }
+
+    /// Write these settings to the JSON file at `path`, creating its
+    /// directory if need be.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .chain_err(|| format!("creating settings directory {}", dir.display()))?;
+        }
+        let file = File::create(path)
+            .chain_err(|| format!("creating settings file {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)
+            .chain_err(|| format!("writing settings file {}", path.display()))
+    }
+}
+
+/// Return the UI scale after `scale`, for cycling through `UI_SCALES`.
+pub fn next_ui_scale(scale: f32) -> f32 {
+    UI_SCALES.iter().cloned()
+        .find(|&next| next > scale)
+        .unwrap_or(UI_SCALES[0])
+}
+
+/// Something on the panel that can be clicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    Grid,
+    UiScale,
+
+    /// Show the keyboard shortcuts.
+    Keys,
+
+    /// Return from the keyboard shortcuts to the settings.
+    Back,
+
+    /// Put the panel away.
+    Close,
+}
+
+/// The in-game settings panel.
+pub struct Panel {
+    open: bool,
+
+    /// True if the panel is listing keyboard shortcuts, rather than settings.
+    showing_keys: bool,
+
+    /// The panel's items, laid out in the window.
+    buttons: Vec<Button<Item>>,
+
+    /// The item under the mouse, if any.
+    hover: Option<Item>,
+
+    /// The item the mouse button went down on, if it's still down.
+    pressed: Option<Item>,
+}
+
+impl Panel {
+    pub fn new() -> Panel {
+        Panel { open: false, showing_keys: false, buttons: vec![], hover: None, pressed: None }
+    }
+
+    /// Return true if the panel is showing.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Show the panel if it's hidden, or hide it if it's showing.
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.showing_keys = false;
+        self.hover = None;
+        self.pressed = None;
+    }
+
+    /// Return true if the panel is listing keyboard shortcuts.
+    pub fn showing_keys(&self) -> bool {
+        self.showing_keys
+    }
+
+    /// Lay out the panel's items for a window `width` by `height` pixels,
+    /// labeled with the current `settings`.
+    pub fn layout(&mut self, width: f32, height: f32, settings: &Settings) {
+        let items = if self.showing_keys {
+            vec![(Item::Back, "BACK".to_string())]
+        } else {
+            vec![
+                (Item::Grid, format!("GRID: {:?}", settings.grid_style).to_uppercase()),
+                (Item::UiScale, format!("UI SCALE: {}X", settings.ui_scale)),
+                (Item::Keys, "KEYS".to_string()),
+                (Item::Close, "CLOSE".to_string()),
+            ]
+        };
+        self.buttons = ui::column(&items, width, height);
+    }
+
+    /// Return the panel's items, laid out in the window.
+    pub fn buttons(&self) -> &[Button<Item>] {
+        &self.buttons
+    }
+
+    /// Return how to draw `item`.
+    pub fn button_state(&self, item: Item) -> ButtonState {
+        if self.hover != Some(item) {
+            ButtonState::Normal
+        } else if self.pressed == Some(item) {
+            ButtonState::Active
+        } else {
+            ButtonState::Hover
+        }
+    }
+
+    /// The mouse has moved to `point`, in window coordinates.
+    pub fn move_to(&mut self, point: [f32; 2]) {
+        self.hover = self.buttons.iter()
+            .find(|button| button.contains(point))
+            .map(|button| button.command);
+    }
+
+    /// The mouse button has gone down.
+    pub fn click(&mut self) {
+        self.pressed = self.hover;
+    }
+
+    /// The mouse button has come up. If it went down and came up on the same
+    /// item, carry it out, changing `settings` as it says. Return true if
+    /// `settings` changed.
+    pub fn release(&mut self, settings: &mut Settings) -> bool {
+        match self.pressed.take() {
+            Some(item) if self.hover == Some(item) => self.choose(item, settings),
+            _ => false,
+        }
+    }
+
+    /// The player has pressed Tab or an arrow key: move the highlight to the
+    /// next item, or the previous one if `backward`.
+    pub fn move_focus(&mut self, backward: bool) {
+        self.hover = ui::next_focus(&self.buttons, self.hover, backward);
+    }
+
+    /// The player has pressed Return: carry out the highlighted item, as
+    /// `release` would.
+    pub fn enter(&mut self, settings: &mut Settings) -> bool {
+        match self.hover {
+            Some(item) => self.choose(item, settings),
+            None => false,
+        }
+    }
+
+    fn choose(&mut self, item: Item, settings: &mut Settings) -> bool {
+        match item {
+            Item::Grid => settings.grid_style = settings.grid_style.next(),
+            Item::UiScale => settings.ui_scale = next_ui_scale(settings.ui_scale),
+            Item::Keys => {
+                self.showing_keys = true;
+                self.hover = None;
+                return false;
+            }
+            Item::Back => {
+                self.showing_keys = false;
+                self.hover = None;
+                return false;
+            }
+            Item::Close => {
+                self.toggle();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Click the button for `item`, which must be on the panel.
+    fn click(panel: &mut Panel, settings: &mut Settings, item: Item) -> bool {
+        panel.layout(800.0, 600.0, settings);
+        let point = panel.buttons().iter()
+            .find(|button| button.command == item)
+            .map(|button| button.top_left)
+            .expect("item should be on the panel");
+        panel.move_to(point);
+        panel.click();
+        assert_eq!(panel.button_state(item), ButtonState::Active);
+        panel.release(settings)
+    }
+
+    #[test]
+    fn panel() {
+        let mut panel = Panel::new();
+        let mut settings = Settings::default();
+        panel.toggle();
+        assert!(panel.is_open());
+
+        assert!(click(&mut panel, &mut settings, Item::Grid));
+        assert_eq!(settings.grid_style, GridStyle::Dots);
+        assert!(click(&mut panel, &mut settings, Item::UiScale));
+        assert_eq!(settings.ui_scale, 1.5);
+        panel.layout(800.0, 600.0, &settings);
+        assert_eq!(panel.buttons()[0].label, "GRID: DOTS");
+        assert_eq!(panel.buttons()[1].label, "UI SCALE: 1.5X");
+
+        // The keyboard shortcuts replace the settings until we go back.
+        assert!(!click(&mut panel, &mut settings, Item::Keys));
+        assert!(panel.showing_keys());
+        assert!(!click(&mut panel, &mut settings, Item::Back));
+        assert!(!panel.showing_keys());
+
+        // The keyboard works too.
+        panel.layout(800.0, 600.0, &settings);
+        panel.move_focus(false);
+        assert_eq!(panel.button_state(Item::Grid), ButtonState::Hover);
+        assert!(panel.enter(&mut settings));
+        assert_eq!(settings.grid_style, GridStyle::Hidden);
+        panel.move_focus(true);
+        assert!(!panel.enter(&mut settings));
+        assert!(!panel.is_open());
+        panel.toggle();
+        assert!(!panel.enter(&mut settings));
+
+        assert!(!click(&mut panel, &mut settings, Item::Close));
+        assert!(!panel.is_open());
+    }
+
+    #[test]
+    fn ui_scales() {
+        let mut scale = UI_SCALES[0];
+        for &expected in UI_SCALES[1..].iter().chain(&UI_SCALES[..1]) {
+            scale = next_ui_scale(scale);
+            assert_eq!(scale, expected);
+        }
+        // Scales from the command line join the cycle where they fit.
+        assert_eq!(next_ui_scale(1.25), 1.5);
+        assert_eq!(next_ui_scale(5.0), 1.0);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = env::temp_dir().join(format!("rbattle-settings-{}", ::std::process::id()));
+        let path = dir.join("nested").join("settings.json");
+        assert_eq!(Settings::load(&path).unwrap(), Settings::default());
+
+        let settings = Settings { grid_style: GridStyle::Hidden, ui_scale: 2.0 };
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path).unwrap(), settings);
+
+        // Fields left out keep their defaults.
+        fs::write(&path, "{\"ui_scale\": 3.0}").unwrap();
+        assert_eq!(Settings::load(&path).unwrap(),
+                   Settings { grid_style: GridStyle::Lines, ui_scale: 3.0 });
+
+        fs::write(&path, "{\"ui_scale\": \"big\"}").unwrap();
+        assert!(Settings::load(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+#version 150
+
+// The time in seconds, to animate the ring.
+uniform float time;
+
+// The color of the ring at its brightest.
+uniform vec4 color;
+
+// This is automatically assigned to be the color and transparency of the pixel
+// we're responsible for.
+out vec4 out_color;
+
+void main() {
+  // Fade in and out in step with the ring's breathing.
+  out_color = vec4(color.rgb, color.a * (0.65 + 0.35 * sin(time * 3.0)));
+}
@@ -0,0 +1,25 @@
+#version 150
+
+// The transformation from graph coordinates to normalized device coordinates,
+// as a homogeneous transform.
+uniform mat3 graph_to_device;
+
+// The time in seconds, to animate the ring.
+uniform float time;
+
+// The center of the source this vertex's ring marks, in graph coordinates.
+in vec2 center;
+
+// Where this vertex sits on the ring, relative to `center`, when the ring is
+// at rest.
+in vec2 offset;
+
+void main() {
+  // The ring turns slowly, and breathes in and out.
+  float angle = time * 0.5;
+  mat2 rotation = mat2(cos(angle), sin(angle), -sin(angle), cos(angle));
+  float pulse = 1.0 + 0.08 * sin(time * 3.0);
+
+  vec3 device = graph_to_device * vec3(center + rotation * offset * pulse, 1.0);
+  gl_Position = vec4(device.xy, 0.0, 1.0);
+}
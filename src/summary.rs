@@ -0,0 +1,264 @@
+//! Writing a summary of each finished game, for analysis elsewhere.
+//!
+//! Given `--stats-log FILE`, a participant, whether hosting or not, keeps
+//! count of how many nodes each player holds on every turn of the game
+//! proper, and when the game is won, appends a summary of it to FILE: who
+//! played, on what map, how long it went, who won, and those counts. Each
+//! participant counts from its own copy of the state, so any of them can keep
+//! the log.
+//!
+//! If FILE ends in `.csv`, we append just the counts, as rows of
+//! `ended,turn,player,nodes,winner`, writing that header first if the file is
+//! new; `ended` tells games apart. Otherwise, we append each summary as a
+//! line of JSON.
+//!
+//! A client that reconnects and gets a fresh copy of the state, rather than
+//! the turns it missed, has no counts for those turns; its summary skips
+//! them.
+
+use errors::*;
+use graph::Node;
+use scheduler::{GameStats, PlayerStats};
+use state::{Phase, Player, State};
+
+use serde_json;
+
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::mem::take;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A finished game, as written to the log.
+#[derive(Serialize)]
+pub struct Summary {
+    /// When the game ended, in seconds since the Unix epoch.
+    pub ended: u64,
+
+    /// The player whose participant wrote this summary.
+    pub written_by: Player,
+
+    /// The board's dimensions, and where each player's source is, indexed
+    /// by player number.
+    pub size: (usize, usize),
+    pub sources: Vec<Node>,
+
+    /// Each seat's color, and whether anyone took it, indexed by player
+    /// number.
+    pub colors: Vec<(u8, u8, u8)>,
+    pub joined: Vec<bool>,
+
+    /// The number of turns the game proper lasted, not counting warm-up.
+    pub turns: usize,
+
+    /// The winner.
+    pub winner: Player,
+
+    /// The most nodes each player held at once, indexed by player number.
+    pub peak_nodes: Vec<usize>,
+
+    /// What each player did, and how many actions they took per minute,
+    /// indexed by player number.
+    pub players: Vec<PlayerStats>,
+    pub actions_per_minute: Vec<f64>,
+
+    /// How many nodes each player held after each turn of the game proper,
+    /// oldest first.
+    pub ownership: Vec<Ownership>,
+}
+
+/// How many nodes each player held as of some turn of the game proper.
+#[derive(Serialize)]
+pub struct Ownership {
+    /// The turn of the game proper, counting from zero at its start.
+    pub turn: usize,
+
+    /// The number of nodes each player held, indexed by player number.
+    pub nodes: Vec<usize>,
+}
+
+/// Keeps count of the game as it goes, and writes its summary when it's won.
+pub struct StatsLog {
+    path: PathBuf,
+
+    /// The counts for the game in progress.
+    ownership: Vec<Ownership>,
+
+    /// True if we've written the summary of the last game won, and have seen
+    /// no turn of another since.
+    written: bool,
+}
+
+impl StatsLog {
+    /// Start keeping a log of games in `path`.
+    pub fn new(path: PathBuf) -> StatsLog {
+        StatsLog { path, ownership: vec![], written: false }
+    }
+
+    /// Note the counts as of `state`, which has just played a turn.
+    pub fn record(&mut self, state: &State) {
+        if self.written {
+            // A rematch has begun.
+            self.ownership.clear();
+            self.written = false;
+        }
+        match state.phase() {
+            Phase::Play { turns } => {
+                self.ownership.push(Ownership { turn: turns, nodes: state.node_counts() });
+            }
+            Phase::WarmUp { .. } => self.ownership.clear(),
+        }
+    }
+
+    /// Append the summary of the game `winner` has won to the log, as
+    /// `player` saw it, ending with `state`. The server may tell us the
+    /// outcome more than once; we only write it the first time.
+    pub fn finish(&mut self, player: Player, state: &State, winner: Player, stats: &GameStats)
+                  -> Result<()>
+    {
+        if self.written {
+            return Ok(());
+        }
+        self.written = true;
+
+        let ended = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let summary = Summary {
+            ended,
+            written_by: player,
+            size: state.map.graph.size(),
+            sources: state.map.sources.clone(),
+            colors: state.map.player_colors.clone(),
+            joined: state.joined.clone(),
+            turns: stats.turns,
+            winner,
+            peak_nodes: stats.peak_nodes.clone(),
+            players: stats.players.clone(),
+            actions_per_minute: (0..stats.players.len())
+                .map(|player| stats.actions_per_minute(Player(player)))
+                .collect(),
+            ownership: take(&mut self.ownership),
+        };
+        self.append(&summary)
+            .chain_err(|| format!("writing game summary to {}", self.path.display()))
+    }
+
+    fn append(&self, summary: &Summary) -> Result<()> {
+        let csv = self.path.extension().is_some_and(|extension| extension == "csv");
+        let new = !self.path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut out = BufWriter::new(file);
+        if csv {
+            write_csv(&mut out, summary, new)?;
+        } else {
+            serde_json::to_writer(&mut out, summary)
+                .chain_err(|| "serializing game summary")?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Write `summary`'s counts to `out` as CSV rows, preceded by a header if
+/// `header` is set.
+fn write_csv<W: Write>(out: &mut W, summary: &Summary, header: bool) -> Result<()> {
+    if header {
+        writeln!(out, "ended,turn,player,nodes,winner")?;
+    }
+    for ownership in &summary.ownership {
+        for (player, nodes) in ownership.nodes.iter().enumerate() {
+            if summary.joined[player] {
+                writeln!(out, "{},{},{},{},{}",
+                         summary.ended, ownership.turn, player, nodes, summary.winner.0)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use map::MapParameters;
+    use state::{Action, GameParameters};
+
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn read_back(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    /// Play a short game in which player 0 wins, and log it to `path`.
+    fn play(path: PathBuf) {
+        let mut state = State::new(MapParameters {
+            size: (1, 4),
+            sources: vec![0, 3, 1],
+            player_colors: vec![(0xff, 0, 0), (0, 0, 0xff), (0, 0xff, 0)],
+        }, GameParameters { warmup_turns: 2, ..GameParameters::default() });
+        state.take_action(&Action::Join { player: Player(0) });
+        state.take_action(&Action::Join { player: Player(1) });
+        let mut log = StatsLog::new(path);
+        for turn in 0..6 {
+            if turn == 4 {
+                // Wipe out player 1.
+                state.nodes[3] = None;
+            }
+            state.advance();
+            log.record(&state);
+        }
+        let winner = state.winner().unwrap();
+        let mut players = vec![PlayerStats::default(); 3];
+        players[0].actions = 4;
+        let stats = GameStats { turns: 5, duration: Duration::from_secs(30),
+                                peak_nodes: vec![1, 1, 0], players, samples: vec![] };
+
+        // Being told twice doesn't log the game twice.
+        log.finish(Player(1), &state, winner, &stats).unwrap();
+        log.finish(Player(1), &state, winner, &stats).unwrap();
+    }
+
+    #[test]
+    fn logs() {
+        let dir = env::temp_dir().join(format!("rbattle-stats-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // A JSON log gets one line per game, and only one per game.
+        let path = dir.join("games.json");
+        play(path.clone());
+        let text = read_back(&path);
+        assert_eq!(text.lines().count(), 1);
+        let summary: serde_json::Value = serde_json::from_str(text.trim()).unwrap();
+        assert_eq!(summary["winner"], 0);
+        assert_eq!(summary["written_by"], 1);
+        assert_eq!(summary["size"], serde_json::json!([1, 4]));
+        assert_eq!(summary["joined"], serde_json::json!([true, true, false]));
+        assert_eq!(summary["players"][0]["actions"], 4);
+        assert_eq!(summary["actions_per_minute"][0], 8.0);
+
+        // Warm-up isn't counted, and player 1 is gone by the end.
+        let ownership = summary["ownership"].as_array().unwrap();
+        assert_eq!(ownership.len(), 5);
+        assert_eq!(ownership[0]["turn"], 0);
+        assert_eq!(ownership[0]["nodes"], serde_json::json!([1, 1, 0]));
+        assert_eq!(ownership[4]["nodes"], serde_json::json!([1, 0, 0]));
+
+        // A CSV log gets a header once, and rows for joined players only.
+        let path = dir.join("games.csv");
+        play(path.clone());
+        play(path.clone());
+        let text = read_back(&path);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "ended,turn,player,nodes,winner");
+        assert_eq!(lines.len(), 1 + 2 * 5 * 2);
+        assert!(lines[1..].iter().all(|line| !line.starts_with("ended")));
+        assert!(lines[1..].iter().all(|line| line.split(',').nth(2) != Some("2")));
+        assert!(lines[8].ends_with(",3,1,0,0"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Loading the game's look from a theme file.
+//!
+//! A theme sets the colors and line widths the drawer uses for everything
+//! other than the game's text: the backdrop and board, the grid, outflows,
+//! mouse highlights, and the colors of players' goop. A theme file is JSON, with any
+//! of the fields of `Theme`; fields left out keep their default values. Colors
+//! are written as hex strings, `RRGGBB` or `RRGGBBAA`, like this:
+//!
+//! ```json
+//! {
+//!     "background": "202020",
+//!     "grid": "606060",
+//!     "grid_width": 1.0,
+//!     "hover": "ffffff80",
+//!     "players": ["ff8000", "00a0ff"]
+//! }
+//! ```
+//!
+//! Player colors from the theme take the place of the map's, but a `--color`
+//! flag on the command line takes precedence over both.
+
+use errors::*;
+use palette::parse_color;
+
+use serde::de::{Deserialize, Deserializer, Error as DeError};
+
+use std::fs::File;
+use std::path::Path;
+
+/// A color, with transparency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub rgb: (u8, u8, u8),
+    pub alpha: u8,
+}
+
+impl Color {
+    const fn opaque(r: u8, g: u8, b: u8) -> Color {
+        Color { rgb: (r, g, b), alpha: 0xff }
+    }
+
+    /// Return this color as OpenGL likes it: red, green, blue, and alpha, each
+    /// from 0 to 1.
+    pub fn to_gl(self) -> [f32; 4] {
+        let (r, g, b) = self.rgb;
+        [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, self.alpha as f32 / 255.0]
+    }
+
+    /// Parse a color written as `RRGGBB` or `RRGGBBAA`, optionally preceded by
+    /// a `#`.
+    fn parse(text: &str) -> Option<Color> {
+        let text = text.trim_start_matches('#');
+        match text.len() {
+            6 => Some(Color { rgb: parse_color(text)?, alpha: 0xff }),
+            8 if text.is_ascii() => Some(Color {
+                rgb: parse_color(&text[..6])?,
+                alpha: u8::from_str_radix(&text[6..], 16).ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Color, D::Error>
+        where D: Deserializer<'de>
+    {
+        let text = String::deserialize(deserializer)?;
+        Color::parse(&text).ok_or_else(|| {
+            D::Error::custom(format!("bad color {:?}: expected RRGGBB or RRGGBBAA", text))
+        })
+    }
+}
+
+/// The colors and line widths to draw the game with.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// The color of the board, behind the nodes.
+    pub background: Color,
+
+    /// The colors at the top and bottom of the gradient filling the window
+    /// behind the board. When the window's shape doesn't match the board's,
+    /// this is what shows at the sides.
+    pub backdrop_top: Color,
+    pub backdrop_bottom: Color,
+
+    /// The color and width, in pixels, of the frame around the board.
+    pub frame: Color,
+    pub frame_width: f32,
+
+    /// The color and width, in pixels, of the lines between nodes.
+    pub grid: Color,
+    pub grid_width: f32,
+
+    /// The colors of outflow lines, and of the dashes that march along them,
+    /// and their width in pixels.
+    pub outflow: Color,
+    pub outflow_dash: Color,
+    pub outflow_width: f32,
+
+    /// The color of an outflow the mouse is over, and of one being clicked.
+    pub hover: Color,
+    pub active: Color,
+
+    /// The color of the outflow the hint overlay suggests opening.
+    pub hint: Color,
+
+    /// The colors to draw each player's goop in, by player number. Players
+    /// beyond the end of this list use the map's colors.
+    pub players: Vec<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            background: Color::opaque(0xff, 0xff, 0xff),
+            backdrop_top: Color::opaque(0xe4, 0xe8, 0xee),
+            backdrop_bottom: Color::opaque(0xb8, 0xc0, 0xcc),
+            frame: Color::opaque(0x40, 0x48, 0x58),
+            frame_width: 4.0,
+            grid: Color::opaque(0x00, 0x59, 0xff),
+            grid_width: 2.0,
+            outflow: Color::opaque(0x00, 0x00, 0x00),
+            outflow_dash: Color::opaque(0x99, 0x99, 0x99),
+            outflow_width: 5.0,
+            hover: Color { rgb: (0x00, 0x00, 0x00), alpha: 0x80 },
+            active: Color::opaque(0xf0, 0xf5, 0x00),
+            hint: Color { rgb: (0x10, 0xb0, 0x40), alpha: 0xc0 },
+            players: vec![],
+        }
+    }
+}
+
+impl Theme {
+    /// Read a theme from the JSON file at `path`.
+    pub fn load(path: &Path) -> Result<Theme> {
+        let file = File::open(path)
+            .chain_err(|| format!("opening theme file {}", path.display()))?;
+        ::serde_json::from_reader(file)
+            .chain_err(|| format!("reading theme file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let theme: Theme = ::serde_json::from_str(r##"{
+            "background": "#202020",
+            "grid_width": 1.5,
+            "hover": "ffffff80",
+            "players": ["ff8000"]
+        }"##).unwrap();
+        assert_eq!(theme.background, Color::opaque(0x20, 0x20, 0x20));
+        assert_eq!(theme.grid_width, 1.5);
+        assert_eq!(theme.hover, Color { rgb: (0xff, 0xff, 0xff), alpha: 0x80 });
+        assert_eq!(theme.players, vec![Color::opaque(0xff, 0x80, 0x00)]);
+
+        // Fields left out keep their defaults.
+        assert_eq!(theme.grid, Theme::default().grid);
+        assert_eq!(theme.outflow_width, Theme::default().outflow_width);
+
+        assert!(::serde_json::from_str::<Theme>(r#"{ "grid": "blue" }"#).is_err());
+        assert!(::serde_json::from_str::<Theme>(r#"{ "grud": "0000ff" }"#).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Buttons drawn in the game window.
+//!
+//! Keyboard shortcuts are quick, but nobody knows them at first, so we also
+//! put a row of buttons for the basic controls along the top of the window.
+//! This module only lays them out: `Input` decides which one the pointer is
+//! over, the drawer draws them, and the main loop carries out their commands.
+//!
+//! The start screen's menus use the same buttons, stacked in a column, with
+//! their own set of commands; see `menu`.
+//!
+//! Buttons are laid out in window coordinates: pixels, with the origin at the
+//! upper left.
+
+use font;
+
+/// Something a button can ask for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Give up the game.
+    Surrender,
+
+    /// Switch to the next style of drawing the grid.
+    ToggleGrid,
+
+    /// Show or hide the settings panel.
+    Settings,
+
+    /// Close the game.
+    Quit,
+}
+
+/// The buttons we show, and their labels, left to right.
+const BUTTONS: [(Command, &str); 4] = [
+    (Command::Surrender, "SURRENDER"),
+    (Command::ToggleGrid, "GRID"),
+    (Command::Settings, "SETTINGS"),
+    (Command::Quit, "QUIT"),
+];
+
+/// A button, placed in the window, that asks for a `C` when clicked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Button<C = Command> {
+    pub command: C,
+    pub label: String,
+
+    /// The button's upper left and lower right corners.
+    pub top_left: [f32; 2],
+    pub bottom_right: [f32; 2],
+
+    /// The size of a font pixel in the label.
+    pub pixel: f32,
+}
+
+impl<C> Button<C> {
+    /// Return true if `point` falls within this button.
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        self.top_left[0] <= point[0] && point[0] < self.bottom_right[0] &&
+        self.top_left[1] <= point[1] && point[1] < self.bottom_right[1]
+    }
+}
+
+/// Return the command of the button after the one for `current` in `buttons`,
+/// or before it if `backward`, wrapping around, for moving the keyboard focus.
+/// With no focus yet, or one not among `buttons`, start at the first button,
+/// or the last.
+pub fn next_focus<C: Copy + PartialEq>(buttons: &[Button<C>], current: Option<C>,
+                                       backward: bool) -> Option<C> {
+    let count = buttons.len();
+    if count == 0 {
+        return None;
+    }
+    let position = current.and_then(|current| {
+        buttons.iter().position(|button| button.command == current)
+    });
+    let next = match (position, backward) {
+        (Some(index), false) => (index + 1) % count,
+        (Some(index), true) => (index + count - 1) % count,
+        (None, false) => 0,
+        (None, true) => count - 1,
+    };
+    Some(buttons[next].command)
+}
+
+/// Return the size of a font pixel for button labels in a window `height`
+/// pixels tall.
+pub fn pixel_size(height: f32) -> f32 {
+    (height / 400.0).floor().max(1.0)
+}
+
+/// Return the space between a button's label and its edge, for labels drawn
+/// with font pixels `pixel` wide.
+pub fn padding(pixel: f32) -> f32 {
+    3.0 * pixel
+}
+
+/// Lay out the buttons for a window `width` by `height` pixels: a row centered
+/// along the top edge.
+pub fn layout(width: f32, height: f32) -> Vec<Button> {
+    let pixel = pixel_size(height);
+    let padding = padding(pixel);
+    let gap = 4.0 * pixel;
+    let button_height = font::GLYPH_HEIGHT as f32 * pixel + 2.0 * padding;
+    let button_width = |label: &str| font::width(label) as f32 * pixel + 2.0 * padding;
+
+    let total = BUTTONS.iter().map(|&(_, label)| button_width(label)).sum::<f32>()
+        + gap * (BUTTONS.len() - 1) as f32;
+    let mut left = ((width - total) / 2.0).max(0.0).floor();
+    BUTTONS.iter()
+        .map(|&(command, label)| {
+            let right = left + button_width(label);
+            let button = Button {
+                command,
+                label: label.to_string(),
+                top_left: [left, gap],
+                bottom_right: [right, gap + button_height],
+                pixel,
+            };
+            left = right + gap;
+            button
+        })
+        .collect()
+}
+
+/// Lay out `items` as a column of buttons, all as wide as the widest, centered
+/// in a window `width` by `height` pixels. Buttons are bigger than the ones
+/// along the top, since they're all there is to look at.
+pub fn column<C: Copy>(items: &[(C, String)], width: f32, height: f32) -> Vec<Button<C>> {
+    let pixel = 2.0 * pixel_size(height);
+    let padding = padding(pixel);
+    let gap = 4.0 * pixel;
+    let button_height = font::GLYPH_HEIGHT as f32 * pixel + 2.0 * padding;
+    let button_width = items.iter()
+        .map(|(_, label)| font::width(label) as f32 * pixel + 2.0 * padding)
+        .fold(0.0, f32::max);
+
+    let total = button_height * items.len() as f32
+        + gap * items.len().saturating_sub(1) as f32;
+    let left = ((width - button_width) / 2.0).max(0.0).floor();
+    let mut top = ((height - total) / 2.0).max(0.0).floor();
+    items.iter()
+        .map(|&(command, ref label)| {
+            let button = Button {
+                command,
+                label: label.clone(),
+                top_left: [left, top],
+                bottom_right: [left + button_width, top + button_height],
+                pixel,
+            };
+            top += button_height + gap;
+            button
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_row() {
+        let buttons = layout(800.0, 600.0);
+        assert_eq!(buttons.len(), BUTTONS.len());
+
+        // The row is centered, and the buttons don't overlap.
+        let left = buttons[0].top_left[0];
+        let right = buttons.last().unwrap().bottom_right[0];
+        assert!((left - (800.0 - right)).abs() <= 1.0);
+        for pair in buttons.windows(2) {
+            assert!(pair[0].bottom_right[0] < pair[1].top_left[0]);
+        }
+
+        let quit = &buttons[3];
+        assert_eq!(quit.command, Command::Quit);
+        assert!(quit.contains([quit.top_left[0] + 1.0, quit.top_left[1] + 1.0]));
+        assert!(!quit.contains([quit.bottom_right[0], quit.top_left[1] + 1.0]));
+        assert!(!quit.contains([quit.top_left[0] + 1.0, 0.0]));
+    }
+
+    #[test]
+    fn layout_column() {
+        let items = [(1, "ONE".to_string()), (2, "TWENTY-TWO".to_string())];
+        let buttons = column(&items, 800.0, 600.0);
+        assert_eq!(buttons.len(), 2);
+
+        // Both buttons are as wide as the widest, one above the other, and
+        // the column is centered.
+        assert_eq!(buttons[0].top_left[0], buttons[1].top_left[0]);
+        assert_eq!(buttons[0].bottom_right[0], buttons[1].bottom_right[0]);
+        assert!(buttons[0].bottom_right[1] < buttons[1].top_left[1]);
+        let (top, bottom) = (buttons[0].top_left[1], buttons[1].bottom_right[1]);
+        assert!((top - (600.0 - bottom)).abs() <= 1.0);
+        assert_eq!(buttons[1].command, 2);
+    }
+
+    #[test]
+    fn focus() {
+        let buttons = layout(800.0, 600.0);
+        assert_eq!(next_focus(&buttons, None, false), Some(Command::Surrender));
+        assert_eq!(next_focus(&buttons, None, true), Some(Command::Quit));
+        assert_eq!(next_focus(&buttons, Some(Command::Surrender), false),
+                   Some(Command::ToggleGrid));
+        assert_eq!(next_focus(&buttons, Some(Command::Quit), false), Some(Command::Surrender));
+        assert_eq!(next_focus(&buttons, Some(Command::Surrender), true), Some(Command::Quit));
+        assert_eq!(next_focus::<Command>(&[], None, false), None);
+    }
+}